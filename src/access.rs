@@ -0,0 +1,44 @@
+// src/access.rs
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// 累计多少次读取后批量写回数据库
+const ACCESS_FLUSH_THRESHOLD: i64 = 32;
+
+// --- 笔记访问计数器 ---
+// 读取笔记时只在内存中累加，达到阈值（或查询热门笔记）时再一次性写回，
+// 避免每次 GET 都产生一次写事务。进程退出时尚未写回的计数会丢失（尽力而为）。
+#[derive(Default)]
+pub struct AccessTracker {
+    pending: Mutex<HashMap<i64, i64>>,
+}
+
+impl AccessTracker {
+    // 记录一次访问；累计次数达到阈值时返回 true，调用方应 take_pending 后写回
+    pub fn record(&self, note_id: i64) -> bool {
+        match self.pending.lock() {
+            Ok(mut pending) => {
+                *pending.entry(note_id).or_insert(0) += 1;
+                pending.values().sum::<i64>() >= ACCESS_FLUSH_THRESHOLD
+            }
+            Err(_) => false,
+        }
+    }
+
+    // 取出并清空尚未写回的计数
+    pub fn take_pending(&self) -> HashMap<i64, i64> {
+        match self.pending.lock() {
+            Ok(mut pending) => std::mem::take(&mut *pending),
+            Err(_) => HashMap::new(),
+        }
+    }
+
+    // 写回失败时把取出的计数加回去，留待下次写回
+    pub fn restore(&self, counts: HashMap<i64, i64>) {
+        if let Ok(mut pending) = self.pending.lock() {
+            for (note_id, count) in counts {
+                *pending.entry(note_id).or_insert(0) += count;
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// src/allow.rs
+use rocket::http::{Method, Status};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response, Route};
+
+// --- OPTIONS 的 Allow 头 ---
+// Rocket 不会为每个路由自动响应 OPTIONS，这里根据已挂载的路由计算某个路径支持的方法。
+// 同一路径上静态段优先于动态段（与 Rocket 的默认排序一致），例如 /inbox/notes/popular
+// 只列出 GET，而不会把 /inbox/notes/<id> 的 PUT、DELETE 也算进来。
+
+// 路由模板中的一段
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Segment {
+    Rest,    // <name..>，匹配剩余的任意段
+    Dynamic, // <name>，匹配一段
+    Static,
+}
+
+// 按路径匹配路由（忽略查询参数与格式），返回匹配时每段的类型；不匹配时返回 None
+fn match_route(template: &str, path: &[&str]) -> Option<Vec<Segment>> {
+    let mut kinds = Vec::new();
+    let mut remaining = path.iter();
+    for part in template.split('/').filter(|p| !p.is_empty()) {
+        if part.starts_with('<') && part.ends_with("..>") {
+            kinds.push(Segment::Rest);
+            return Some(kinds);
+        }
+        let segment = remaining.next()?;
+        if part.starts_with('<') && part.ends_with('>') {
+            kinds.push(Segment::Dynamic);
+        } else if part == *segment {
+            kinds.push(Segment::Static);
+        } else {
+            return None;
+        }
+    }
+    remaining.next().is_none().then_some(kinds)
+}
+
+// 路径支持的方法（按 Method 的固定顺序，GET 存在时附带 HEAD，总是包含 OPTIONS）；没有匹配的路由时为空
+fn allowed_methods<'a>(routes: impl Iterator<Item = &'a Route>, path: &[&str]) -> Vec<Method> {
+    let matches: Vec<(Vec<Segment>, Method)> = routes
+        .filter(|route| route.method != Method::Options)
+        .filter_map(|route| match_route(route.uri.path(), path).map(|kinds| (kinds, route.method)))
+        .collect();
+    let Some(most_specific) = matches.iter().map(|(kinds, _)| kinds).max() else {
+        return Vec::new();
+    };
+
+    let mut methods: Vec<Method> = matches
+        .iter()
+        .filter(|(kinds, _)| kinds == most_specific)
+        .map(|(_, method)| *method)
+        .collect();
+    if methods.contains(&Method::Get) {
+        methods.push(Method::Head);
+    }
+    methods.push(Method::Options);
+    methods.sort_by_key(|method| ORDER.iter().position(|m| m == method));
+    methods.dedup();
+    methods
+}
+
+const ORDER: [Method; 9] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Patch,
+    Method::Delete,
+    Method::Options,
+    Method::Connect,
+    Method::Trace,
+];
+
+// OPTIONS 的响应：204 + 请求路径的 Allow 头；没有对应路由时返回 404
+pub struct Allow;
+
+impl<'r> Responder<'r, 'static> for Allow {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let path: Vec<&str> = request.uri().path().segments().collect();
+        let methods = allowed_methods(request.rocket().routes(), &path);
+        if methods.is_empty() {
+            return Err(Status::NotFound);
+        }
+        let allow = methods.iter().map(|method| method.as_str()).collect::<Vec<_>>().join(", ");
+        Response::build().status(Status::NoContent).raw_header("Allow", allow).ok()
+    }
+}
@@ -0,0 +1,42 @@
+// src/auth.rs
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::config::InboxConfig;
+
+// --- 管理接口鉴权 ---
+// 请求需携带 `X-API-Key: <key>` 或 `Authorization: Bearer <key>`，与 INBOX_API_KEY 一致。
+// 未配置 INBOX_API_KEY 时管理接口整体关闭 (403)，避免误暴露。
+pub struct AdminKey;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminKey {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let expected = match request.rocket().state::<InboxConfig>().and_then(|c| c.api_key.as_deref()) {
+            Some(key) => key,
+            None => return Outcome::Error((Status::Forbidden, "admin API disabled: INBOX_API_KEY is not set")),
+        };
+
+        let provided = request.headers().get_one("X-API-Key").or_else(|| {
+            request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+        });
+
+        match provided {
+            Some(key) if constant_time_eq(key.trim().as_bytes(), expected.as_bytes()) => Outcome::Success(AdminKey),
+            _ => Outcome::Error((Status::Unauthorized, "missing or invalid API key")),
+        }
+    }
+}
+
+// 逐字节比较全部内容，避免按前缀提前返回泄露密钥信息
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,73 @@
+// src/bundle.rs
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncRead, DuplexStream, ReadBuf};
+use tokio::task::JoinHandle;
+
+use crate::export;
+use crate::models::NoteResponse;
+
+// --- 单条笔记的分享包 ---
+// GET /inbox/notes/<id>/bundle 返回的 zip：note.md 为笔记本身，comments.md 为全部评论（没有评论时为空文件）。
+// 以后有附件时按原文件名加入同一个包。
+
+// 生成 zip 的任务与响应之间的管道缓冲区大小，写满后生成方等待客户端读取
+const BUNDLE_PIPE_BUFFER: usize = 64 * 1024;
+
+// 在后台任务中生成 zip，边生成边通过返回的流发送。需要时由 async_zip 切换为 ZIP64，不会截断大小或条目数
+pub fn note_bundle(note: NoteResponse, comments: Vec<NoteResponse>, modified: DateTime<Utc>, log_tag: String) -> BundleStream {
+    let (writer, reader) = tokio::io::duplex(BUNDLE_PIPE_BUFFER);
+    let task = tokio::spawn(async move {
+        let result = write_note_bundle(writer, &note, &comments, modified).await;
+        if let Err(e) = &result {
+            eprintln!("[ERROR] {}生成笔记包失败: {:?}", log_tag, e);
+        }
+        result
+    });
+    BundleStream { pipe: reader, task: Some(task) }
+}
+
+async fn write_note_bundle(writer: DuplexStream, note: &NoteResponse, comments: &[NoteResponse], modified: DateTime<Utc>) -> async_zip::error::Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    let files = [("note.md", export::note_to_markdown(note)), ("comments.md", export::notes_to_markdown(comments))];
+    for (name, content) in files {
+        let entry = ZipEntryBuilder::new(name.into(), Compression::Deflate).last_modification_date(modified.into());
+        zip.write_entry_whole(entry, content.as_bytes()).await?;
+    }
+    zip.close().await?;
+    Ok(())
+}
+
+// 管道的读端。生成失败时读到的是错误而不是 EOF，连接随之中断，客户端不会拿到被截断却看似完整的文件
+pub struct BundleStream {
+    pipe: DuplexStream,
+    task: Option<JoinHandle<async_zip::error::Result<()>>>,
+}
+
+impl AsyncRead for BundleStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.pipe).poll_read(cx, buf))?;
+        if buf.filled().len() > filled {
+            return Poll::Ready(Ok(()));
+        }
+        // 管道已关闭：等生成任务结束，确认 zip 完整写出
+        let Some(task) = this.task.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(Pin::new(task).poll(cx));
+        this.task = None;
+        match result {
+            Ok(Ok(())) => Poll::Ready(Ok(())),
+            Ok(Err(e)) => Poll::Ready(Err(io::Error::other(e))),
+            Err(e) => Poll::Ready(Err(io::Error::other(e))),
+        }
+    }
+}
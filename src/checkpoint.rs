@@ -0,0 +1,58 @@
+// src/checkpoint.rs
+use std::time::Duration;
+
+use r2d2::ManageConnection;
+use tokio::task;
+
+use crate::db;
+
+// --- WAL 定期检查点 ---
+// 持续写入时 WAL 文件只有在检查点之后才能被复用，长期运行的实例定期执行 PASSIVE 检查点，
+// 不阻塞读写。使用连接池之外的专用连接，不占用处理请求的连接。由 main 为每个数据库文件启动。
+pub async fn run_checkpoint_loop(database_url: String, interval: Duration) {
+    let conn = match db::SqliteConnectionManager::file(database_url.clone()).connect() {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("[ERROR] WAL 检查点：无法打开 {}: {}", database_url, e);
+            return;
+        }
+    };
+
+    let mut conn = Some(conn);
+    let mut ticker = tokio::time::interval(interval);
+    // 第一次 tick 立即完成，跳过它，避免启动时就执行一次
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(current) = conn.take() else {
+            return;
+        };
+        let result = task::spawn_blocking(move || {
+            let result = db::wal_checkpoint_db(&current);
+            (current, result)
+        })
+        .await;
+
+        match result {
+            Ok((current, result)) => {
+                conn = Some(current);
+                match result {
+                    // 未启用 WAL 模式时 log_pages 为 -1，没有可记录的内容
+                    Ok(checkpoint) if checkpoint.log_pages > 0 => println!(
+                        "[INFO] WAL 检查点 ({})：写回 {} / {} 页{}",
+                        database_url,
+                        checkpoint.checkpointed_pages,
+                        checkpoint.log_pages,
+                        if checkpoint.busy { "，部分页因读写冲突未写回" } else { "" }
+                    ),
+                    Ok(_) => {}
+                    Err(e) => eprintln!("[ERROR] WAL 检查点失败 ({}): {}", database_url, e),
+                }
+            }
+            Err(e) => {
+                eprintln!("[ERROR] WAL 检查点任务失败: {:?}", e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// src/compression.rs
+use std::io::{Cursor, Write};
+
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header};
+use rocket::{Request, Response};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+// --- 响应压缩 Fairing ---
+// 根据 Accept-Encoding 对较大的文本/JSON 响应做 gzip（优先）或 deflate 压缩。
+// 只处理已知大小的响应体，流式响应保持原样。
+pub struct ResponseCompression {
+    pub min_bytes: usize,
+}
+
+#[rocket::async_trait]
+impl Fairing for ResponseCompression {
+    fn info(&self) -> Info {
+        Info { name: "Response Compression", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let encoding = match negotiate(request.headers().get("Accept-Encoding")) {
+            Some(encoding) => encoding,
+            None => return,
+        };
+        if response.headers().contains("Content-Encoding") || !is_compressible(response.content_type()) {
+            return;
+        }
+        match response.body().preset_size() {
+            Some(size) if size >= self.min_bytes => {}
+            _ => return,
+        }
+
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                eprintln!("[ERROR] 读取响应体失败，无法压缩: {:?}", e);
+                return;
+            }
+        };
+        match compress(&body, encoding) {
+            Ok(compressed) => {
+                response.set_header(Header::new("Content-Encoding", encoding.as_str()));
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+            }
+            Err(e) => {
+                eprintln!("[ERROR] 压缩响应体失败: {:?}", e);
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+        // 提示缓存代理按 Accept-Encoding 区分缓存
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+    }
+}
+
+// 从 Accept-Encoding 中选择编码（忽略 q=0 的项）
+fn negotiate<'a>(accept_encodings: impl Iterator<Item = &'a str>) -> Option<Encoding> {
+    let mut chosen = None;
+    for value in accept_encodings {
+        for item in value.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let rejected = parts.any(|p| {
+                p.trim().strip_prefix("q=").and_then(|q| q.trim().parse::<f32>().ok()) == Some(0.0)
+            });
+            if rejected {
+                continue;
+            }
+            match name.as_str() {
+                "gzip" | "x-gzip" => return Some(Encoding::Gzip),
+                "deflate" => chosen = Some(Encoding::Deflate),
+                _ => {}
+            }
+        }
+    }
+    chosen
+}
+
+// 只压缩文本类内容，图片/压缩包等本身已压缩的内容不再处理
+fn is_compressible(content_type: Option<ContentType>) -> bool {
+    match content_type {
+        Some(ct) => {
+            ct.top() == "text"
+                || ct.sub() == "json"
+                || ct.sub() == "xml"
+                || ct.sub().as_str().ends_with("+xml")
+                || ct.sub().as_str().ends_with("+json")
+                || ct.sub() == "x-ndjson"
+        }
+        None => false,
+    }
+}
+
+fn compress(body: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+    }
+}
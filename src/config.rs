@@ -0,0 +1,432 @@
+// src/config.rs
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::crypto;
+use crate::db::{AutoLink, NoteWriteOptions};
+use crate::namespace;
+use crate::validation;
+
+// --- 环境变量名 ---
+const TRACK_ACCESS_ENV_VAR: &str = "INBOX_TRACK_ACCESS";
+const COMPRESSION_ENV_VAR: &str = "INBOX_COMPRESSION";
+const COMPRESS_MIN_BYTES_ENV_VAR: &str = "INBOX_COMPRESS_MIN_BYTES";
+const MAX_CONTENT_LEN_ENV_VAR: &str = "INBOX_MAX_CONTENT_LEN";
+const MAX_CLOCK_SKEW_SECS_ENV_VAR: &str = "INBOX_MAX_CLOCK_SKEW_SECS";
+const MIN_CREATED_AT_ENV_VAR: &str = "INBOX_MIN_CREATED_AT";
+const API_KEY_ENV_VAR: &str = "INBOX_API_KEY";
+const MAX_TAGS_ENV_VAR: &str = "INBOX_MAX_TAGS";
+const MAX_TAG_LEN_ENV_VAR: &str = "INBOX_MAX_TAG_LEN";
+const AUTO_TAG_ENV_VAR: &str = "INBOX_AUTO_TAG";
+const SOURCE_TAGGING_ENV_VAR: &str = "INBOX_SOURCE_TAGGING";
+const AUTO_LINK_ENV_VAR: &str = "INBOX_AUTO_LINK";
+const QUERY_TIMEOUT_MS_ENV_VAR: &str = "INBOX_QUERY_TIMEOUT_MS";
+const MAX_BATCH_SIZE_ENV_VAR: &str = "INBOX_MAX_BATCH_SIZE";
+const POOL_SIZE_ENV_VAR: &str = "INBOX_POOL_SIZE";
+const BUSY_RETRIES_ENV_VAR: &str = "INBOX_BUSY_RETRIES";
+const DEFAULT_LIMIT_ENV_VAR: &str = "INBOX_DEFAULT_LIMIT";
+const MAX_LIMIT_ENV_VAR: &str = "INBOX_MAX_LIMIT";
+const ENCRYPTION_KEY_ENV_VAR: &str = "INBOX_ENCRYPTION_KEY";
+const NORMALIZE_WHITESPACE_ENV_VAR: &str = "INBOX_NORMALIZE_WHITESPACE";
+const PRETTY_ENV_VAR: &str = "INBOX_PRETTY";
+const CORS_ORIGINS_ENV_VAR: &str = "INBOX_CORS_ORIGINS";
+const CORS_CREDENTIALS_ENV_VAR: &str = "INBOX_CORS_CREDENTIALS";
+const CORS_MAX_AGE_ENV_VAR: &str = "INBOX_CORS_MAX_AGE";
+const TRASH_RETENTION_DAYS_ENV_VAR: &str = "INBOX_TRASH_RETENTION_DAYS";
+const TRASH_PURGE_INTERVAL_SECS_ENV_VAR: &str = "INBOX_TRASH_PURGE_INTERVAL_SECS";
+const NAMESPACES_ENV_VAR: &str = "INBOX_NAMESPACES";
+const METRICS_PORT_ENV_VAR: &str = "INBOX_METRICS_PORT";
+const MAX_BODY_BYTES_ENV_VAR: &str = "INBOX_MAX_BODY_BYTES";
+const SEED_FILE_ENV_VAR: &str = "INBOX_SEED_FILE";
+const SLOW_MS_ENV_VAR: &str = "INBOX_SLOW_MS";
+const PREVIEW_LEN_ENV_VAR: &str = "INBOX_PREVIEW_LEN";
+const ALLOW_RESET_ENV_VAR: &str = "INBOX_ALLOW_RESET";
+const ID_SCHEME_ENV_VAR: &str = "INBOX_ID_SCHEME";
+const LOCATION_ID_ENV_VAR: &str = "INBOX_LOCATION_ID";
+const HISTORY_LIMIT_ENV_VAR: &str = "INBOX_HISTORY_LIMIT";
+const WAL_CHECKPOINT_SECS_ENV_VAR: &str = "INBOX_WAL_CHECKPOINT_SECS";
+const READ_TIMEOUT_MS_ENV_VAR: &str = "INBOX_READ_TIMEOUT_MS";
+const SEARCH_TIMEOUT_MS_ENV_VAR: &str = "INBOX_SEARCH_TIMEOUT_MS";
+const EXPORT_TIMEOUT_MS_ENV_VAR: &str = "INBOX_EXPORT_TIMEOUT_MS";
+const MAX_RELATION_DEPTH_ENV_VAR: &str = "INBOX_MAX_RELATION_DEPTH";
+const MAX_RELATIONS_PER_NOTE_ENV_VAR: &str = "INBOX_MAX_RELATIONS_PER_NOTE";
+const ALLOWED_TAGS_ENV_VAR: &str = "INBOX_ALLOWED_TAGS";
+
+// --- 默认值 ---
+const DEFAULT_COMPRESS_MIN_BYTES: usize = 1024;
+const DEFAULT_MAX_CONTENT_LEN: usize = 100_000;
+const DEFAULT_MAX_CLOCK_SKEW_SECS: i64 = 300;
+const DEFAULT_MAX_TAGS: usize = 50;
+const DEFAULT_MAX_TAG_LEN: usize = 64;
+const DEFAULT_QUERY_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_MAX_BATCH_SIZE: usize = 500;
+const DEFAULT_BUSY_RETRIES: u32 = 3;
+const DEFAULT_DEFAULT_LIMIT: i64 = 100;
+const DEFAULT_MAX_LIMIT: i64 = 1000;
+const DEFAULT_CORS_MAX_AGE: usize = 3600;
+const DEFAULT_TRASH_PURGE_INTERVAL_SECS: u64 = 3600;
+const DEFAULT_PREVIEW_LEN: usize = 140;
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+const DEFAULT_WAL_CHECKPOINT_SECS: u64 = 300;
+const DEFAULT_READ_TIMEOUT_MS: u64 = 2000;
+const DEFAULT_SEARCH_TIMEOUT_MS: u64 = 15_000;
+const DEFAULT_EXPORT_TIMEOUT_MS: u64 = 120_000;
+const DEFAULT_MAX_RELATION_DEPTH: u32 = 5;
+const DEFAULT_MAX_RELATIONS_PER_NOTE: usize = 1000;
+
+// --- 运行时配置 (启动时从环境变量读取一次，作为 Rocket 托管状态) ---
+#[derive(Debug, Clone)]
+pub struct InboxConfig {
+    // 是否统计 GET /inbox/notes/<id> 的访问次数（会额外产生写操作，默认关闭）
+    pub track_access: bool,
+    // 客户端支持时是否压缩响应体 (gzip/deflate)
+    pub compression: bool,
+    // 小于该字节数的响应体不压缩
+    pub compress_min_bytes: usize,
+    // 单条笔记内容的最大字符数
+    pub max_content_len: usize,
+    // 客户端指定的 created_at 最多允许超前服务器时间多少秒
+    pub max_clock_skew_secs: i64,
+    // 客户端指定的 created_at 不得早于该时间（默认 Unix 纪元）
+    pub min_created_at: DateTime<Utc>,
+    // 管理接口 (/inbox/admin/...) 的访问密钥，未设置时管理接口关闭
+    pub api_key: Option<String>,
+    // 单条笔记最多标签数
+    pub max_tags: usize,
+    // 单个标签的最大字符数
+    pub max_tag_len: usize,
+    // 创建/更新时把正文中的 #hashtag 自动并入标签
+    pub auto_tag: bool,
+    // 新建笔记时按请求头 X-Inbox-Source 自动添加 source:<来源> 标签
+    pub source_tagging: bool,
+    // 创建/更新时让笔记的 Link 关系跟随正文中的 [[id]] 引用
+    pub auto_link: bool,
+    // 单条 SQL 语句的最长执行时间（毫秒），超时返回 503；0 表示不限制
+    pub query_timeout_ms: u64,
+    // POST /inbox/notes/batch-get 单次最多请求的 id 数
+    pub max_batch_size: usize,
+    // 数据库连接池的最大连接数（默认为 CPU 核数）
+    pub pool_size: u32,
+    // 写操作遇到 SQLITE_BUSY 时的最大重试次数
+    pub busy_retries: u32,
+    // GET /inbox/notes 未指定 limit 时的条数
+    pub default_limit: i64,
+    // GET /inbox/notes 的 limit 上限，更多数据需要分页获取
+    pub max_limit: i64,
+    // 笔记内容的 AES-256-GCM 密钥；设置后内容加密存储，且 search 过滤不可用
+    pub encryption_key: Option<[u8; 32]>,
+    // 创建/更新时统一换行符为 \n 并去掉行尾空白
+    pub normalize_whitespace: bool,
+    // 默认以缩进格式输出 JSON（调试用；单个请求也可以用 ?pretty=true/false 覆盖）
+    pub pretty: bool,
+    // 允许跨域访问的来源（逗号分隔，"*" 表示任意来源）；未设置时不启用 CORS
+    pub cors_origins: Option<Vec<String>>,
+    // 是否返回 Access-Control-Allow-Credentials: true（不能与 "*" 同时使用）
+    pub cors_credentials: bool,
+    // 预检结果的缓存秒数 (Access-Control-Max-Age)，0 表示不发送
+    pub cors_max_age: usize,
+    // 软删除的笔记保留天数，超过后被后台任务彻底删除；未设置时不自动清理
+    pub trash_retention_days: Option<u32>,
+    // 后台清理任务的执行间隔（秒）
+    pub trash_purge_interval_secs: u64,
+    // 额外的命名空间及其数据库路径（name=path），各自挂载在 /inbox/<name> 下；
+    // 名为 default 的条目覆盖 DATABASE_URL
+    pub namespaces: Vec<(String, String)>,
+    // 在单独端口上提供 GET /metrics；未设置时与 API 共用端口
+    pub metrics_port: Option<u16>,
+    // JSON / 字节请求体的最大字节数（Rocket 的 json 与 bytes 限制）；未设置时使用 Rocket 默认值
+    pub max_body_bytes: Option<u64>,
+    // 示例笔记文件（CreateNotePayload 的 JSON 数组），默认命名空间为空库时启动写入
+    pub seed_file: Option<PathBuf>,
+    // 耗时超过该毫秒数的请求记录一条 WARN 日志；未设置时不记录
+    pub slow_ms: Option<u64>,
+    // 笔记响应中 preview 字段的最大字符数
+    pub preview_len: usize,
+    // 是否允许 POST /inbox/admin/reset 清空数据库（仅用于测试与演示环境）
+    pub allow_reset: bool,
+    // API 中笔记 id 的形式：integer（自增整数，默认）或 uuid（不可猜测的 UUID）
+    pub id_scheme: IdScheme,
+    // 创建笔记时 Location 头使用的标识：id（/inbox/notes/<id>，默认）或 slug（/inbox/n/<slug>，笔记没有 slug 时回退为 id）
+    pub location_id: LocationId,
+    // 每条笔记最多保留的历史版本数，超出时丢弃最早的；0 表示不记录历史
+    pub history_limit: usize,
+    // 后台执行 WAL 检查点的间隔（秒），0 表示不执行
+    pub wal_checkpoint_secs: u64,
+    // 各类路由数据库操作的最长时间（毫秒），超时返回 503，期间的语句也以此为上限（代替 query_timeout_ms）；
+    // 0 表示不单独限制（沿用 query_timeout_ms）。见 route_timeout
+    pub read_timeout_ms: u64,
+    pub search_timeout_ms: u64,
+    pub export_timeout_ms: u64,
+    // GET /inbox/notes/<id>/graph 的最大遍历深度，请求的 depth 超过时按此截断
+    pub max_relation_depth: u32,
+    // 单条笔记（作为源或目标）最多拥有的关系数，创建关系时超出返回 400；不含评论
+    pub max_relations_per_note: usize,
+    // 允许使用的标签（已按笔记标签的规则规范化）；设置后创建、更新笔记时出现其它标签返回 400，未设置时不限制
+    pub allowed_tags: Option<Vec<String>>,
+}
+
+// 笔记的规范 URL 使用的标识，见 InboxConfig::location_id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocationId {
+    Id,
+    Slug,
+}
+
+// 按开销区分的路由类别，各自有独立的超时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    Read,   // 单条笔记读取
+    Search, // 带内容搜索的列表、关联搜索
+    Export, // 全量导出
+}
+
+// 对外暴露的笔记 id 形式。数据库内部始终以整数主键关联，UUID 存在 notes.uid 列中
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdScheme {
+    Integer,
+    Uuid,
+}
+
+impl Default for InboxConfig {
+    fn default() -> Self {
+        InboxConfig {
+            track_access: false,
+            compression: true,
+            compress_min_bytes: DEFAULT_COMPRESS_MIN_BYTES,
+            max_content_len: DEFAULT_MAX_CONTENT_LEN,
+            max_clock_skew_secs: DEFAULT_MAX_CLOCK_SKEW_SECS,
+            min_created_at: DateTime::UNIX_EPOCH,
+            api_key: None,
+            max_tags: DEFAULT_MAX_TAGS,
+            max_tag_len: DEFAULT_MAX_TAG_LEN,
+            auto_tag: false,
+            source_tagging: false,
+            auto_link: false,
+            query_timeout_ms: DEFAULT_QUERY_TIMEOUT_MS,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            pool_size: default_pool_size(),
+            busy_retries: DEFAULT_BUSY_RETRIES,
+            default_limit: DEFAULT_DEFAULT_LIMIT,
+            max_limit: DEFAULT_MAX_LIMIT,
+            encryption_key: None,
+            normalize_whitespace: false,
+            pretty: false,
+            cors_origins: None,
+            cors_credentials: false,
+            cors_max_age: DEFAULT_CORS_MAX_AGE,
+            trash_retention_days: None,
+            trash_purge_interval_secs: DEFAULT_TRASH_PURGE_INTERVAL_SECS,
+            namespaces: Vec::new(),
+            metrics_port: None,
+            max_body_bytes: None,
+            seed_file: None,
+            slow_ms: None,
+            preview_len: DEFAULT_PREVIEW_LEN,
+            allow_reset: false,
+            id_scheme: IdScheme::Integer,
+            location_id: LocationId::Id,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            wal_checkpoint_secs: DEFAULT_WAL_CHECKPOINT_SECS,
+            read_timeout_ms: DEFAULT_READ_TIMEOUT_MS,
+            search_timeout_ms: DEFAULT_SEARCH_TIMEOUT_MS,
+            export_timeout_ms: DEFAULT_EXPORT_TIMEOUT_MS,
+            max_relation_depth: DEFAULT_MAX_RELATION_DEPTH,
+            max_relations_per_note: DEFAULT_MAX_RELATIONS_PER_NOTE,
+            allowed_tags: None,
+        }
+    }
+}
+
+impl InboxConfig {
+    pub fn from_env() -> Self {
+        let defaults = InboxConfig::default();
+        InboxConfig {
+            track_access: env_flag(TRACK_ACCESS_ENV_VAR, defaults.track_access),
+            compression: env_flag(COMPRESSION_ENV_VAR, defaults.compression),
+            compress_min_bytes: env_parse(COMPRESS_MIN_BYTES_ENV_VAR, defaults.compress_min_bytes),
+            max_content_len: env_parse(MAX_CONTENT_LEN_ENV_VAR, defaults.max_content_len),
+            max_clock_skew_secs: env_parse(MAX_CLOCK_SKEW_SECS_ENV_VAR, defaults.max_clock_skew_secs),
+            min_created_at: env_parse(MIN_CREATED_AT_ENV_VAR, defaults.min_created_at),
+            api_key: env::var(API_KEY_ENV_VAR).ok().filter(|key| !key.trim().is_empty()),
+            max_tags: env_parse(MAX_TAGS_ENV_VAR, defaults.max_tags),
+            max_tag_len: env_parse(MAX_TAG_LEN_ENV_VAR, defaults.max_tag_len),
+            auto_tag: env_flag(AUTO_TAG_ENV_VAR, defaults.auto_tag),
+            source_tagging: env_flag(SOURCE_TAGGING_ENV_VAR, defaults.source_tagging),
+            auto_link: env_flag(AUTO_LINK_ENV_VAR, defaults.auto_link),
+            query_timeout_ms: env_parse(QUERY_TIMEOUT_MS_ENV_VAR, defaults.query_timeout_ms),
+            max_batch_size: env_parse(MAX_BATCH_SIZE_ENV_VAR, defaults.max_batch_size),
+            pool_size: env_parse(POOL_SIZE_ENV_VAR, defaults.pool_size),
+            busy_retries: env_parse(BUSY_RETRIES_ENV_VAR, defaults.busy_retries),
+            default_limit: env_parse(DEFAULT_LIMIT_ENV_VAR, defaults.default_limit),
+            max_limit: env_max_limit(defaults.max_limit),
+            encryption_key: env_encryption_key(),
+            normalize_whitespace: env_flag(NORMALIZE_WHITESPACE_ENV_VAR, defaults.normalize_whitespace),
+            pretty: env_flag(PRETTY_ENV_VAR, defaults.pretty),
+            cors_origins: env_list(CORS_ORIGINS_ENV_VAR),
+            cors_credentials: env_flag(CORS_CREDENTIALS_ENV_VAR, defaults.cors_credentials),
+            cors_max_age: env_parse(CORS_MAX_AGE_ENV_VAR, defaults.cors_max_age),
+            trash_retention_days: env_parse_opt(TRASH_RETENTION_DAYS_ENV_VAR),
+            trash_purge_interval_secs: env_parse(TRASH_PURGE_INTERVAL_SECS_ENV_VAR, defaults.trash_purge_interval_secs),
+            namespaces: env_namespaces(),
+            metrics_port: env_parse_opt(METRICS_PORT_ENV_VAR),
+            max_body_bytes: env_parse_opt(MAX_BODY_BYTES_ENV_VAR),
+            seed_file: env_parse_opt::<PathBuf>(SEED_FILE_ENV_VAR).filter(|path| !path.as_os_str().is_empty()),
+            slow_ms: env_parse_opt(SLOW_MS_ENV_VAR),
+            preview_len: env_parse(PREVIEW_LEN_ENV_VAR, defaults.preview_len),
+            allow_reset: env_flag(ALLOW_RESET_ENV_VAR, defaults.allow_reset),
+            id_scheme: env_id_scheme(defaults.id_scheme),
+            location_id: env_location_id(defaults.location_id),
+            history_limit: env_parse(HISTORY_LIMIT_ENV_VAR, defaults.history_limit),
+            wal_checkpoint_secs: env_parse(WAL_CHECKPOINT_SECS_ENV_VAR, defaults.wal_checkpoint_secs),
+            read_timeout_ms: env_parse(READ_TIMEOUT_MS_ENV_VAR, defaults.read_timeout_ms),
+            search_timeout_ms: env_parse(SEARCH_TIMEOUT_MS_ENV_VAR, defaults.search_timeout_ms),
+            export_timeout_ms: env_parse(EXPORT_TIMEOUT_MS_ENV_VAR, defaults.export_timeout_ms),
+            max_relation_depth: env_parse(MAX_RELATION_DEPTH_ENV_VAR, defaults.max_relation_depth),
+            max_relations_per_note: env_parse(MAX_RELATIONS_PER_NOTE_ENV_VAR, defaults.max_relations_per_note),
+            allowed_tags: env_list(ALLOWED_TAGS_ENV_VAR).map(validation::normalize_tags),
+        }
+    }
+
+    pub fn query_timeout(&self) -> Option<Duration> {
+        (self.query_timeout_ms > 0).then(|| Duration::from_millis(self.query_timeout_ms))
+    }
+
+    // [[id]] 引用是内部整数 id，uuid 模式下不指向任何笔记，自动关联不生效；关闭时返回 None
+    pub fn auto_link_settings(&self) -> Option<AutoLink> {
+        (self.auto_link && self.id_scheme == IdScheme::Integer).then_some(AutoLink { max_relations: self.max_relations_per_note })
+    }
+
+    // 创建/更新笔记时交给 db 层执行的规则
+    pub fn note_write_options(&self) -> NoteWriteOptions {
+        NoteWriteOptions {
+            max_tags: Some(self.max_tags),
+            max_tag_len: Some(self.max_tag_len),
+            normalize_whitespace: self.normalize_whitespace,
+            auto_link: self.auto_link_settings(),
+        }
+    }
+
+    pub fn route_timeout(&self, class: RouteClass) -> Option<Duration> {
+        let ms = match class {
+            RouteClass::Read => self.read_timeout_ms,
+            RouteClass::Search => self.search_timeout_ms,
+            RouteClass::Export => self.export_timeout_ms,
+        };
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+}
+
+fn default_pool_size() -> u32 {
+    std::thread::available_parallelism().map_or(4, |n| n.get() as u32)
+}
+
+// 密钥写错时回退为明文会悄悄写入未加密数据，所以这里直接拒绝启动
+fn env_encryption_key() -> Option<[u8; 32]> {
+    let value = env::var(ENCRYPTION_KEY_ENV_VAR).ok().filter(|key| !key.trim().is_empty())?;
+    match crypto::parse_key(value.trim()) {
+        Some(key) => Some(key),
+        None => panic!("{} 必须是 64 位十六进制字符串 (32 字节 AES-256 密钥)", ENCRYPTION_KEY_ENV_VAR),
+    }
+}
+
+// 写错时回退为整数 id 会把本应隐藏的自增 id 暴露出去，所以和密钥一样直接拒绝启动
+fn env_id_scheme(default: IdScheme) -> IdScheme {
+    match env::var(ID_SCHEME_ENV_VAR) {
+        Ok(v) => match v.trim().to_ascii_lowercase().as_str() {
+            "integer" => IdScheme::Integer,
+            "uuid" => IdScheme::Uuid,
+            _ => panic!("{} 只能是 integer 或 uuid，实际为 {:?}", ID_SCHEME_ENV_VAR, v),
+        },
+        Err(_) => default,
+    }
+}
+
+// 各列表接口用它作为 clamp 的上界，小于 1 时请求会 panic，所以启动时直接拒绝
+fn env_max_limit(default: i64) -> i64 {
+    let limit = env_parse(MAX_LIMIT_ENV_VAR, default);
+    if limit < 1 {
+        panic!("{} 必须大于等于 1，实际为 {}", MAX_LIMIT_ENV_VAR, limit);
+    }
+    limit
+}
+
+fn env_location_id(default: LocationId) -> LocationId {
+    match env::var(LOCATION_ID_ENV_VAR) {
+        Ok(v) => match v.trim().to_ascii_lowercase().as_str() {
+            "id" => LocationId::Id,
+            "slug" => LocationId::Slug,
+            _ => panic!("{} 只能是 id 或 slug，实际为 {:?}", LOCATION_ID_ENV_VAR, v),
+        },
+        Err(_) => default,
+    }
+}
+
+// 解析布尔开关：1/true/yes/on 为开启，0/false/no/off 为关闭，未设置或无法识别时取默认值
+fn env_flag(name: &str, default: bool) -> bool {
+    match env::var(name) {
+        Ok(v) => match v.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "0" | "false" | "no" | "off" => false,
+            _ => {
+                eprintln!("[WARN] 无法识别的 {} 取值 {:?}，使用默认值 {}", name, v, default);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+// INBOX_NAMESPACES=work=/data/work.db,personal=/data/personal.db
+// 格式错误时拒绝启动，避免笔记被悄悄写进默认数据库
+fn env_namespaces() -> Vec<(String, String)> {
+    let mut namespaces: Vec<(String, String)> = Vec::new();
+    for entry in env_list(NAMESPACES_ENV_VAR).unwrap_or_default() {
+        let (name, path) = match entry.split_once('=') {
+            Some((name, path)) if namespace::is_valid_name(name.trim()) && !path.trim().is_empty() => {
+                (name.trim().to_string(), path.trim().to_string())
+            }
+            _ => panic!("{} 的条目 {:?} 无效：应为 name=path，name 只能包含字母、数字、'-' 和 '_'，且不能与路由同名（如 notes、tags、admin）", NAMESPACES_ENV_VAR, entry),
+        };
+        if namespaces.iter().any(|(existing, _)| *existing == name) {
+            panic!("{} 中命名空间 {:?} 重复", NAMESPACES_ENV_VAR, name);
+        }
+        namespaces.push((name, path));
+    }
+    namespaces
+}
+
+// 可选的数值配置：未设置或解析失败时为 None
+fn env_parse_opt<T: FromStr>(name: &str) -> Option<T> {
+    let v = env::var(name).ok()?;
+    let parsed = v.trim().parse().ok();
+    if parsed.is_none() {
+        eprintln!("[WARN] 无法解析 {} 取值 {:?}，忽略该配置", name, v);
+    }
+    parsed
+}
+
+// 解析逗号分隔的列表，忽略空项；未设置或为空时返回 None
+fn env_list(name: &str) -> Option<Vec<String>> {
+    let items: Vec<String> = env::var(name)
+        .ok()?
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect();
+    (!items.is_empty()).then_some(items)
+}
+
+// 解析数值等配置，未设置或解析失败时取默认值
+fn env_parse<T: FromStr + std::fmt::Debug>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(v) => v.trim().parse().unwrap_or_else(|_| {
+            eprintln!("[WARN] 无法解析 {} 取值 {:?}，使用默认值 {:?}", name, v, default);
+            default
+        }),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,32 @@
+// src/cors.rs
+use rocket_cors::{AllowedOrigins, Cors, CorsOptions};
+
+use crate::config::InboxConfig;
+
+// --- CORS ---
+// 未配置 INBOX_CORS_ORIGINS 时不处理跨域请求。
+// 按规范，允许携带凭据时不能放行任意来源 (*)，这种配置在启动时直接报错。
+pub fn build_cors(config: &InboxConfig) -> Result<Option<Cors>, rocket_cors::Error> {
+    let origins = match &config.cors_origins {
+        Some(origins) => origins,
+        None => return Ok(None),
+    };
+    let allowed_origins = if origins.iter().any(|origin| origin == "*") {
+        // rocket_cors 只在 send_wildcard 时拒绝这种组合，否则会把任意来源原样回显并允许凭据
+        if config.cors_credentials {
+            return Err(rocket_cors::Error::CredentialsWithWildcardOrigin);
+        }
+        AllowedOrigins::all()
+    } else {
+        AllowedOrigins::some_exact(origins)
+    };
+
+    CorsOptions {
+        allowed_origins,
+        allow_credentials: config.cors_credentials,
+        max_age: (config.cors_max_age > 0).then_some(config.cors_max_age),
+        ..Default::default()
+    }
+    .to_cors()
+    .map(Some)
+}
@@ -0,0 +1,47 @@
+// src/crypto.rs
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+// AES-GCM 使用 96 位 nonce
+pub const NONCE_LEN: usize = 12;
+
+// --- 笔记内容加密 (AES-256-GCM) ---
+// 每次加密生成随机 nonce，与密文分列存储
+pub struct ContentCipher {
+    cipher: Aes256Gcm,
+}
+
+impl ContentCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        ContentCipher { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)) }
+    }
+
+    // 返回 (nonce, 密文)
+    pub fn encrypt(&self, plaintext: &str) -> Result<(Vec<u8>, Vec<u8>), aes_gcm::Error> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext.as_bytes())?;
+        Ok((nonce.to_vec(), ciphertext))
+    }
+
+    // 密钥不匹配或数据被篡改时返回 None
+    pub fn decrypt(&self, nonce: &[u8], ciphertext: &[u8]) -> Option<String> {
+        if nonce.len() != NONCE_LEN {
+            return None;
+        }
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+// 密钥为 64 个十六进制字符（32 字节）
+pub fn parse_key(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
@@ -1,17 +1,99 @@
 // src/db.rs
-use rusqlite::{params, Connection, Error, Row, ToSql}; // Ensure rusqlite is in Cargo.toml!
+use rusqlite::{params, Connection, Error, OpenFlags, Row, ToSql, Transaction, TransactionBehavior}; // Ensure rusqlite is in Cargo.toml!
 use rusqlite::OptionalExtension; // 添加OptionalExtension trait
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::path::Path;
-use crate::models::{Note, CreateNotePayload, UpdateNotePayload, DetailedTag, NoteRelation, NoteRelationType, CreateNoteRelationPayload, CreateCommentPayload}; // Updated imports
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rusqlite::ffi;
+use rusqlite::functions::FunctionFlags;
+use std::os::raw::{c_int, c_uint, c_void};
+use rusqlite::types::{Value, ValueRef};
+use crate::config::IdScheme;
+use crate::crypto::{ContentCipher, NONCE_LEN};
+use crate::request_id;
+use crate::links;
+use crate::models::{Note, NoteKey, CreateNotePayload, UpdateNotePayload, DetailedTag, NoteRelation, NoteRelationType, CreateNoteRelationPayload, CreateCommentPayload, Template, TemplatePayload, ReindexSummary, RelationCounts, RelationTypeCount, ActivityDay};
+use crate::models::{TagCountsRebuild, BadTagsEntry, BadTagsReport, RepairSummary, TriageSummary, NoteMeta, NoteVersion, TagUsage, OrphanRelations, PruneSummary, PurgeSummary, RelatedTag, ResetSummary, IntegrityReport, ForeignKeyViolation, TagTreeNode}; // Updated imports
 use chrono::{DateTime, Utc};
 use serde_json;
+use sha2::{Digest, Sha256};
 
 // --- 错误处理助手 ---
 fn map_serde_error(e: serde_json::Error) -> Error {
     Error::InvalidParameterName(format!("JSON serialization/deserialization error: {}", e))
 }
 
+// 批量创建关系失败的原因：引用了不存在的笔记（附带出错条目的下标），或数据库错误
+#[derive(Debug, thiserror::Error)]
+pub enum BulkRelationError {
+    #[error("entry {index}: {role} note {note_id} does not exist")]
+    MissingNote { index: usize, role: &'static str, note_id: i64 },
+    #[error("entry {index}: Parent relation {source_id} -> {target_id} would form a cycle")]
+    ParentCycle { index: usize, source_id: i64, target_id: i64 },
+    #[error("entry {index}: note {note_id} already has {limit} relations (INBOX_MAX_RELATIONS_PER_NOTE)")]
+    TooManyRelations { index: usize, note_id: i64, limit: usize },
+    #[error(transparent)]
+    Db(#[from] Error),
+}
+
+// 创建单条关系失败的原因：源或目标笔记不存在，或数据库错误
+#[derive(Debug, thiserror::Error)]
+pub enum CreateRelationError {
+    #[error("source note {0} does not exist")]
+    SourceNotFound(i64),
+    #[error("target note {0} does not exist")]
+    TargetNotFound(i64),
+    #[error("Parent relation {0} -> {1} would form a cycle")]
+    ParentCycle(i64, i64),
+    #[error("note {0} already has {1} relations (INBOX_MAX_RELATIONS_PER_NOTE)")]
+    TooManyRelations(i64, usize),
+    #[error(transparent)]
+    Db(#[from] Error),
+}
+
+// 写入笔记内容失败的原因：标签超出 INBOX_MAX_TAGS / INBOX_MAX_TAG_LEN，或数据库错误
+#[derive(Debug, thiserror::Error)]
+pub enum WriteNoteError {
+    #[error("too many tags: {count} (INBOX_MAX_TAGS limit is {limit})")]
+    TooManyTags { count: usize, limit: usize },
+    #[error("tag {tag:?} is longer than {limit} characters (INBOX_MAX_TAG_LEN)")]
+    TagTooLong { tag: String, limit: usize },
+    #[error(transparent)]
+    Db(#[from] Error),
+}
+
+// 将评论提升为独立笔记失败的原因
+#[derive(Debug, thiserror::Error)]
+pub enum PromoteCommentError {
+    #[error("relation {0} does not exist")]
+    RelationNotFound(i64),
+    #[error("relation {0} is not a comment")]
+    NotAComment(i64),
+    #[error(transparent)]
+    Db(#[from] Error),
+}
+
+// 合并笔记失败的原因
+#[derive(Debug, thiserror::Error)]
+pub enum MergeNotesError {
+    #[error("note {0} does not exist")]
+    NoteNotFound(i64),
+    #[error(transparent)]
+    Db(#[from] Error),
+}
+
+// 迁移失败的原因
+#[derive(Debug, thiserror::Error)]
+pub enum MigrateError {
+    #[error("database schema version {db} is newer than this binary supports ({binary}); upgrade aw-inbox or point it at another database")]
+    SchemaTooNew { db: i64, binary: i64 },
+    #[error(transparent)]
+    Db(#[from] Error),
+}
+
 // --- 数据库连接类型 ---
 pub type DbConnection = Connection;
 
@@ -19,8 +101,86 @@ pub type DbConnection = Connection;
 const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
 const DEFAULT_DATABASE_URL: &str = "inbox.db";
 
+// --- 连接池 ---
+pub type DbPool = r2d2::Pool<SqliteConnectionManager>;
+
+// 连接池初始化失败的原因
+#[derive(Debug, thiserror::Error)]
+pub enum PoolInitError {
+    #[error(transparent)]
+    Db(#[from] Error),
+    #[error(transparent)]
+    Pool(#[from] r2d2::Error),
+}
+
+// r2d2 的 rusqlite 连接管理器：每个新连接都会开启外键约束、设置查询超时并注册内容加解密函数
+pub struct SqliteConnectionManager {
+    path: Option<String>, // None 表示内存数据库
+    query_timeout: Option<Duration>,
+    cipher: Option<Arc<ContentCipher>>,
+}
+
+impl SqliteConnectionManager {
+    pub fn file(path: impl Into<String>) -> Self {
+        SqliteConnectionManager { path: Some(path.into()), query_timeout: None, cipher: None }
+    }
+
+    // 内存数据库每个连接各自独立，只能配合大小为 1 的连接池使用
+    pub fn memory() -> Self {
+        SqliteConnectionManager { path: None, query_timeout: None, cipher: None }
+    }
+
+    pub fn with_query_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.query_timeout = timeout;
+        self
+    }
+
+    // 设置后新写入的笔记内容以 AES-256-GCM 加密存储
+    pub fn with_encryption_key(mut self, key: Option<[u8; 32]>) -> Self {
+        self.cipher = key.map(|key| Arc::new(ContentCipher::new(&key)));
+        self
+    }
+}
+
+impl r2d2::ManageConnection for SqliteConnectionManager {
+    type Connection = Connection;
+    type Error = Error;
+
+    fn connect(&self) -> Result<Connection, Error> {
+        let mut conn = match &self.path {
+            Some(path) => Connection::open(path)?,
+            None => Connection::open_in_memory()?,
+        };
+        conn.execute("PRAGMA foreign_keys = ON;", [])?;
+        register_content_functions(&conn, self.cipher.clone())?;
+        install_statement_hooks(&conn);
+        if let Some(timeout) = self.query_timeout {
+            set_query_timeout(&mut conn, timeout)?;
+        }
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<(), Error> {
+        conn.execute_batch("")
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+pub fn build_pool(manager: SqliteConnectionManager, size: u32) -> Result<DbPool, r2d2::Error> {
+    let size = if manager.path.is_none() { 1 } else { size.max(1) };
+    r2d2::Pool::builder().max_size(size).build(manager)
+}
+
 // --- 初始化 ---
-pub async fn init_pool() -> Result<DbConnection, Error> {
+pub async fn init_pool(size: u32, query_timeout: Option<Duration>, encryption_key: Option<[u8; 32]>) -> Result<DbPool, PoolInitError> {
+    init_pool_at(default_database_url()?, size, query_timeout, encryption_key).await
+}
+
+// 默认命名空间的数据库路径：DATABASE_URL，Android 下为应用数据目录中的 inbox.db
+pub fn default_database_url() -> Result<String, Error> {
     let database_url = if cfg!(target_os = "android") {
         // Android环境下使用应用私有数据目录
         let data_dir = std::env::var("DATA_DIR").unwrap_or_else(|_| ".".to_string());
@@ -42,17 +202,257 @@ pub async fn init_pool() -> Result<DbConnection, Error> {
         env::var(DATABASE_URL_ENV_VAR)
             .unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string())
     };
+    Ok(database_url)
+}
+
+pub async fn init_pool_at(database_url: String, size: u32, query_timeout: Option<Duration>, encryption_key: Option<[u8; 32]>) -> Result<DbPool, PoolInitError> {
+    println!("🗄️ 连接到数据库 (连接池大小 {}): {}", size, database_url);
+
+    let manager = SqliteConnectionManager::file(database_url)
+        .with_query_timeout(query_timeout)
+        .with_encryption_key(encryption_key);
+    Ok(build_pool(manager, size)?)
+}
+
+// --- 内容加密 ---
+// 加解密在 SQL 函数中完成，读取时 note_columns 统一用 inbox_open 解密，
+// 写入时由 seal_content 调用 inbox_seal。未配置密钥时两者都原样透传。
+fn register_content_functions(conn: &Connection, cipher: Option<Arc<ContentCipher>>) -> Result<(), Error> {
+    let seal_cipher = cipher.clone();
+    // inbox_seal(content) -> nonce || 密文；未配置密钥时为 NULL
+    conn.create_scalar_function("inbox_seal", 1, FunctionFlags::SQLITE_UTF8, move |ctx| {
+        let cipher = match &seal_cipher {
+            Some(cipher) => cipher,
+            None => return Ok(None),
+        };
+        let plaintext: String = ctx.get(0)?;
+        let (mut sealed, ciphertext) = cipher
+            .encrypt(&plaintext)
+            .map_err(|e| Error::UserFunctionError(format!("content encryption failed: {}", e).into()))?;
+        sealed.extend(ciphertext);
+        Ok(Some(sealed))
+    })?;
+
+    // inbox_open(content, content_nonce) -> 明文；nonce 为 NULL 表示明文存储
+    conn.create_scalar_function(
+        "inbox_open",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            let nonce = match ctx.get_raw(1) {
+                ValueRef::Null => return Ok(Value::from(ctx.get_raw(0))),
+                ValueRef::Blob(nonce) => nonce,
+                _ => return Err(Error::UserFunctionError("content_nonce must be a blob".into())),
+            };
+            let cipher = cipher.as_ref().ok_or_else(|| {
+                Error::UserFunctionError("note content is encrypted but INBOX_ENCRYPTION_KEY is not set".into())
+            })?;
+            let ciphertext = match ctx.get_raw(0) {
+                ValueRef::Blob(ciphertext) => ciphertext,
+                _ => return Err(Error::UserFunctionError("encrypted content must be a blob".into())),
+            };
+            cipher
+                .decrypt(nonce, ciphertext)
+                .map(Value::Text)
+                .ok_or_else(|| Error::UserFunctionError("failed to decrypt note content (wrong INBOX_ENCRYPTION_KEY?)".into()))
+        },
+    )
+}
+
+// 返回写入 content / content_nonce 两列的值
+fn seal_content(conn: &Connection, content: &str) -> Result<(Value, Option<Vec<u8>>), Error> {
+    let sealed: Option<Vec<u8>> = conn.query_row("SELECT inbox_seal(?1)", params![content], |row| row.get(0))?;
+    Ok(match sealed {
+        Some(mut sealed) => {
+            let ciphertext = sealed.split_off(NONCE_LEN);
+            (Value::Blob(ciphertext), Some(sealed))
+        }
+        None => (Value::Text(content.to_string()), None),
+    })
+}
+
+// --- 慢查询保护 ---
+// 每执行这么多条虚拟机指令检查一次是否超时
+const QUERY_PROGRESS_OPS: i32 = 1000;
+
+thread_local! {
+    // 当前线程上正在执行的语句的开始时间，由 trace 回调在语句开始执行时记录
+    static STATEMENT_STARTED: Cell<Option<Instant>> = const { Cell::new(None) };
+    // 按路由设置的语句超时，代替连接上的默认值（见 with_statement_timeout）
+    static STATEMENT_TIMEOUT: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+fn mark_statement_start() {
+    STATEMENT_STARTED.with(|started| started.set(Some(Instant::now())));
+}
+
+// 语句钩子：语句开始执行时记录时间（供超时检查），执行结束时把耗时计入 inbox_db_query_duration_seconds。
+// rusqlite 只封装了旧的 sqlite3_trace / sqlite3_profile，二者在 SQLite 内部共用同一个掩码、后注册的会关掉先注册的，
+// 因此直接用 sqlite3_trace_v2 注册一个同时处理两种事件的回调
+fn install_statement_hooks(conn: &DbConnection) {
+    unsafe extern "C" fn on_trace(event: c_uint, _ctx: *mut c_void, _stmt: *mut c_void, detail: *mut c_void) -> c_int {
+        if event == ffi::SQLITE_TRACE_STMT as c_uint {
+            mark_statement_start();
+        } else if event == ffi::SQLITE_TRACE_PROFILE as c_uint {
+            // PROFILE 事件的 detail 指向以纳秒计的执行时间 (sqlite3_int64)
+            let nanos = unsafe { *(detail as *const i64) };
+            crate::metrics::observe_query(Duration::from_nanos(nanos.max(0) as u64));
+        }
+        0
+    }
+    let mask = (ffi::SQLITE_TRACE_STMT | ffi::SQLITE_TRACE_PROFILE) as c_uint;
+    // SAFETY: handle() 在 conn 存活期间有效；回调不使用上下文指针，也不回调进 conn
+    unsafe {
+        ffi::sqlite3_trace_v2(conn.handle(), mask, Some(on_trace), std::ptr::null_mut());
+    }
+}
 
-    println!("🗄️ 连接到数据库 (同步): {}", database_url);
+// 运行超过 timeout 的语句会被中断（返回 SQLITE_INTERRUPT），等待数据库锁同样最多 timeout。
+// 避免单个病态查询长期占用阻塞线程。
+pub fn set_query_timeout(conn: &mut DbConnection, timeout: Duration) -> Result<(), Error> {
+    conn.busy_timeout(timeout)?;
+    install_statement_hooks(conn);
+    conn.progress_handler(QUERY_PROGRESS_OPS, Some(move || {
+        let limit = STATEMENT_TIMEOUT.with(|limit| limit.get()).unwrap_or(timeout);
+        STATEMENT_STARTED
+            .with(|started| started.get())
+            .is_some_and(|started| started.elapsed() > limit)
+    }));
+    Ok(())
+}
+
+// 在当前线程上执行 f，期间语句的超时改为 timeout（None 时沿用连接的默认值）。
+// 只对设置了 set_query_timeout 的连接生效
+pub fn with_statement_timeout<R>(timeout: Option<Duration>, f: impl FnOnce() -> R) -> R {
+    let previous = STATEMENT_TIMEOUT.with(|limit| limit.replace(timeout));
+    let result = f();
+    STATEMENT_TIMEOUT.with(|limit| limit.set(previous));
+    result
+}
+
+// 在一个 BEGIN DEFERRED 读事务中执行 f，使其中的多条语句看到同一份快照
+// （WAL 模式下并发写入不会插到两条语句之间）。f 只应读取；返回错误时回滚
+pub fn with_read_tx<T, E: From<Error>>(conn: &DbConnection, f: impl FnOnce(&DbConnection) -> Result<T, E>) -> Result<T, E> {
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Deferred)?;
+    let result = f(&tx)?;
+    tx.commit()?;
+    Ok(result)
+}
+
+// 是否为超时中断导致的错误
+pub fn is_query_timeout(err: &Error) -> bool {
+    matches!(err, Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::OperationInterrupted)
+}
+
+// --- WAL 检查点 ---
+// PRAGMA wal_checkpoint 的结果；未启用 WAL 模式时两个页数均为 -1
+#[derive(Debug, Clone, Copy)]
+pub struct WalCheckpoint {
+    pub busy: bool,              // 有页因其它连接正在读写而未能写回
+    pub log_pages: i64,          // WAL 中的总页数
+    pub checkpointed_pages: i64, // 已写回数据库文件的页数
+}
+
+pub fn wal_checkpoint_db(conn: &DbConnection) -> Result<WalCheckpoint, Error> {
+    conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |row| {
+        Ok(WalCheckpoint {
+            busy: row.get::<_, i64>(0)? != 0,
+            log_pages: row.get(1)?,
+            checkpointed_pages: row.get(2)?,
+        })
+    })
+}
+
+// --- SQLITE_BUSY 重试 ---
+// busy_timeout 之后仍可能在并发写入高峰时返回 SQLITE_BUSY；写操作整体重试，其它错误直接返回
+pub trait BusyError {
+    fn is_busy(&self) -> bool;
+}
+
+impl BusyError for Error {
+    fn is_busy(&self) -> bool {
+        matches!(self, Error::SqliteFailure(e, _) if e.code == rusqlite::ErrorCode::DatabaseBusy)
+    }
+}
+
+impl BusyError for BulkRelationError {
+    fn is_busy(&self) -> bool {
+        matches!(self, BulkRelationError::Db(e) if e.is_busy())
+    }
+}
+
+impl BusyError for CreateRelationError {
+    fn is_busy(&self) -> bool {
+        matches!(self, CreateRelationError::Db(e) if e.is_busy())
+    }
+}
+
+impl BusyError for WriteNoteError {
+    fn is_busy(&self) -> bool {
+        matches!(self, WriteNoteError::Db(e) if e.is_busy())
+    }
+}
+
+// 首次重试前的等待时间，之后每次翻倍
+const BUSY_RETRY_BASE_DELAY: Duration = Duration::from_millis(10);
+
+// 最多重试 retries 次（共执行 retries + 1 次），每次等待指数退避时间再加上随机抖动
+pub fn with_busy_retry<T, E: BusyError>(retries: u32, mut op: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(e) if e.is_busy() && attempt < retries => {
+                let backoff = BUSY_RETRY_BASE_DELAY * 2u32.saturating_pow(attempt);
+                std::thread::sleep(backoff + jitter(backoff));
+                attempt += 1;
+                eprintln!("[WARN] {}数据库忙，第 {} 次重试", request_id::tag(), attempt);
+            }
+            result => return result,
+        }
+    }
+}
 
-    let db_path = Path::new(&database_url);
-    let conn = Connection::open(db_path)?;
-    conn.execute("PRAGMA foreign_keys = ON;", [])?;
-    Ok(conn)
+// [0, max) 内的抖动，避免多个写入方同时重试；不需要密码学强度的随机数
+fn jitter(max: Duration) -> Duration {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos() as u64);
+    let max_nanos = max.as_nanos().max(1) as u64;
+    Duration::from_nanos(nanos % max_nanos)
 }
 
 // --- 迁移 ---
-pub fn migrate(conn: &DbConnection) -> Result<(), Error> {
+// 按版本排列的迁移步骤：第 i 步把结构从版本 i 升级到 i + 1，版本号记录在 PRAGMA user_version。
+// 已发布的步骤不能再修改，结构变更只能追加新步骤。
+type Migration = fn(&DbConnection) -> Result<(), Error>;
+const MIGRATIONS: &[Migration] = &[migrate_v1, migrate_v2, migrate_v3, migrate_v4, migrate_v5, migrate_v6, migrate_v7, migrate_v8, migrate_v9, migrate_v10, migrate_v11, migrate_v12];
+
+// 当前程序能理解的数据库结构版本
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+pub fn schema_version(conn: &DbConnection) -> Result<i64, Error> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+// 执行尚未应用的迁移；数据库版本比程序新时拒绝继续，避免旧代码按旧结构写坏数据
+pub fn migrate(conn: &DbConnection) -> Result<(), MigrateError> {
+    let current = schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        return Err(MigrateError::SchemaTooNew { db: current, binary: SCHEMA_VERSION });
+    }
+    for (version, step) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        step(conn)?;
+        conn.pragma_update(None, "user_version", version as i64 + 1)?;
+    }
+    backfill_note_uids(conn)?;
+    backfill_relation_uids(conn)?;
+
+    println!("✅ 数据库迁移完成 (schema_version {})", SCHEMA_VERSION);
+    Ok(())
+}
+
+// 版本 1：引入版本号之前的全部结构。旧数据库 user_version 为 0，这里的语句都可重复执行
+fn migrate_v1(conn: &DbConnection) -> Result<(), Error> {
     conn.execute_batch(
         r#"
         BEGIN;
@@ -82,55 +482,515 @@ pub fn migrate(conn: &DbConnection) -> Result<(), Error> {
         CREATE INDEX IF NOT EXISTS idx_note_relations_source ON note_relations(source_note_id);
         CREATE INDEX IF NOT EXISTS idx_note_relations_target ON note_relations(target_note_id);
         CREATE INDEX IF NOT EXISTS idx_note_relations_type ON note_relations(relation_type);
+
+        -- 笔记模板表
+        CREATE TABLE IF NOT EXISTS templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            content TEXT NOT NULL,
+            tags TEXT NOT NULL DEFAULT '[]'
+        );
         COMMIT;
         "#
     )?;
-    
-    println!("✅ 数据库迁移完成");
+
+    // 新增列（对已有数据库执行 ALTER TABLE）
+    add_column_if_missing(conn, "notes", "access_count", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "notes", "color", "TEXT")?;
+    // 软删除时间；非空的笔记对所有读取接口不可见
+    add_column_if_missing(conn, "notes", "deleted_at", "TEXT")?;
+    // 加密存储时每行的 nonce；为 NULL 表示 content 为明文
+    add_column_if_missing(conn, "notes", "content_nonce", "BLOB")?;
+    Ok(())
+}
+
+// 版本 2：永久链接 slug。已有笔记保持 NULL（唯一索引允许多个 NULL）
+fn migrate_v2(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "notes", "slug", "TEXT")?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_notes_slug ON notes(slug)", [])?;
+    Ok(())
+}
+
+// 版本 3：笔记语言（ISO 639-1），已有笔记为 NULL
+fn migrate_v3(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "notes", "lang", "TEXT")?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_lang ON notes(lang)", [])?;
+    Ok(())
+}
+
+// 版本 4：对外的 UUID (INBOX_ID_SCHEME=uuid)。内部主键和关系外键仍是整数，
+// 已有笔记由 migrate 末尾的 backfill_note_uids 补齐
+fn migrate_v4(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "notes", "uid", "TEXT")?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_notes_uid ON notes(uid)", [])?;
+    Ok(())
+}
+
+// 版本 5：笔记被 PUT 更新的次数（GET /inbox/notes/<id>/meta），已有笔记从 0 开始计
+fn migrate_v5(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "notes", "update_count", "INTEGER NOT NULL DEFAULT 0")
+}
+
+// 版本 6：笔记历史版本。每次更新前把旧内容存入 note_history（加密存储时保留密文和 nonce），
+// 笔记被彻底删除时随外键级联删除
+fn migrate_v6(conn: &DbConnection) -> Result<(), Error> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS note_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            content_nonce BLOB,
+            tags TEXT NOT NULL DEFAULT '[]',
+            edited_at TEXT NOT NULL, -- 该版本被写入的时间（即被替换前的 updated_at）
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_note_history_note ON note_history(note_id, id);
+        "#,
+    )
+}
+
+// 版本 7：置顶与归档（POST /inbox/triage）
+fn migrate_v7(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "notes", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "notes", "archived_at", "TEXT")
+}
+
+// 版本 8：关系的软删除。笔记移入回收站时其关系一并隐藏，恢复笔记时随之恢复
+fn migrate_v8(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "note_relations", "deleted_at", "TEXT")
+}
+
+// 版本 9：标签计数汇总表，由触发器随笔记的增删改增量维护（GET /inbox/tags/detailed 读取），
+// 创建后按现有数据计算一次。计入的笔记与实时扫描一致：未删除且 tags 是合法的 JSON 数组。
+// 同一笔记中重复的标签只计一次，删除触发器按标签名各减一，两边才能抵消。
+// last_modified 只增不减：删除笔记或去掉标签后可能晚于实时扫描的结果，重建后恢复一致
+fn migrate_v9(conn: &DbConnection) -> Result<(), Error> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS tag_counts (
+            name TEXT PRIMARY KEY,
+            count INTEGER NOT NULL,
+            last_modified TEXT
+        );
+
+        CREATE TRIGGER IF NOT EXISTS tag_counts_insert AFTER INSERT ON notes
+        WHEN NEW.deleted_at IS NULL AND json_valid(NEW.tags) AND json_type(NEW.tags) = 'array'
+        BEGIN
+            INSERT INTO tag_counts (name, count, last_modified)
+            SELECT DISTINCT value, 1, NEW.updated_at FROM json_each(NEW.tags) WHERE true
+            ON CONFLICT(name) DO UPDATE SET count = count + 1, last_modified = MAX(COALESCE(last_modified, ''), excluded.last_modified);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS tag_counts_delete AFTER DELETE ON notes
+        WHEN OLD.deleted_at IS NULL AND json_valid(OLD.tags) AND json_type(OLD.tags) = 'array'
+        BEGIN
+            UPDATE tag_counts SET count = count - 1 WHERE name IN (SELECT value FROM json_each(OLD.tags));
+            DELETE FROM tag_counts WHERE count <= 0;
+        END;
+
+        -- 更新拆成“移出旧值”和“计入新值”两个触发器，各自只在对应的行会被计入时执行
+        CREATE TRIGGER IF NOT EXISTS tag_counts_update_old AFTER UPDATE OF tags, deleted_at, updated_at ON notes
+        WHEN OLD.deleted_at IS NULL AND json_valid(OLD.tags) AND json_type(OLD.tags) = 'array'
+        BEGIN
+            UPDATE tag_counts SET count = count - 1 WHERE name IN (SELECT value FROM json_each(OLD.tags));
+            DELETE FROM tag_counts WHERE count <= 0;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS tag_counts_update_new AFTER UPDATE OF tags, deleted_at, updated_at ON notes
+        WHEN NEW.deleted_at IS NULL AND json_valid(NEW.tags) AND json_type(NEW.tags) = 'array'
+        BEGIN
+            INSERT INTO tag_counts (name, count, last_modified)
+            SELECT DISTINCT value, 1, NEW.updated_at FROM json_each(NEW.tags) WHERE true
+            ON CONFLICT(name) DO UPDATE SET count = count + 1, last_modified = MAX(COALESCE(last_modified, ''), excluded.last_modified);
+        END;
+        "#,
+    )?;
+    rebuild_tag_counts(conn).map(|_| ())
+}
+
+// 版本 10：外部系统的 ID（POST /inbox/notes/upsert）。已有笔记为 NULL（唯一索引允许多个 NULL）
+fn migrate_v10(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "notes", "external_id", "TEXT")?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_notes_external_id ON notes(external_id)", [])?;
+    Ok(())
+}
+
+// 版本 11：关系对外的 UUID（INBOX_ID_SCHEME=uuid 时作为 API 中的关系 id），已有关系由 backfill_relation_uids 补齐
+fn migrate_v11(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "note_relations", "uid", "TEXT")?;
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS idx_note_relations_uid ON note_relations(uid)", [])?;
+    Ok(())
+}
+
+// 版本 12：标记由 INBOX_AUTO_LINK 同步创建的 Link 关系，同步只删除带标记的关系。
+// 升级前同步创建的关系无从区分，一律按手动创建处理（auto_link = 0）
+fn migrate_v12(conn: &DbConnection) -> Result<(), Error> {
+    add_column_if_missing(conn, "note_relations", "auto_link", "INTEGER NOT NULL DEFAULT 0")
+}
+
+// 为 uid 为空的笔记生成 UUID。每次启动都执行，旧版本程序或外部工具写入的行也会被补齐，
+// 之后随时可以切换到 INBOX_ID_SCHEME=uuid
+fn backfill_note_uids(conn: &DbConnection) -> Result<(), Error> {
+    let ids = conn
+        .prepare("SELECT id FROM notes WHERE uid IS NULL")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<i64>, Error>>()?;
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare("UPDATE notes SET uid = ?1 WHERE id = ?2")?;
+    for id in &ids {
+        stmt.execute(params![new_note_uid(), id])?;
+    }
+    println!("✅ 已为 {} 条笔记生成 uid", ids.len());
+    Ok(())
+}
+
+// 同 backfill_note_uids，补齐关系的 uid
+fn backfill_relation_uids(conn: &DbConnection) -> Result<(), Error> {
+    let ids = conn
+        .prepare("SELECT id FROM note_relations WHERE uid IS NULL")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<i64>, Error>>()?;
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let mut stmt = conn.prepare("UPDATE note_relations SET uid = ?1 WHERE id = ?2")?;
+    for id in &ids {
+        stmt.execute(params![new_note_uid(), id])?;
+    }
+    println!("✅ 已为 {} 条关系生成 uid", ids.len());
+    Ok(())
+}
+
+// 旧数据库的表结构已经存在，CREATE TABLE IF NOT EXISTS 不会补列，这里按需 ALTER TABLE
+fn add_column_if_missing(conn: &DbConnection, table: &str, column: &str, definition: &str) -> Result<(), Error> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let exists = stmt
+        .query_map(params![table], |row| row.get::<_, String>("name"))?
+        .collect::<Result<Vec<String>, Error>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
     Ok(())
 }
 
 // --- 笔记的 CRUD 操作 ---
 
+// 映射到 Note 的列；notes 表新增字段时只需同时修改这里和 map_row_to_note
+const NOTE_COLUMNS: &[&str] = &["id", "content", "tags", "created_at", "updated_at", "color", "slug", "lang", "uid", "pinned", "archived_at"];
+
+// 生成 SELECT 列表，alias 非空时加上表别名前缀（用于 JOIN 查询）
+fn note_columns(alias: &str) -> String {
+    NOTE_COLUMNS
+        .iter()
+        .map(|column| {
+            let prefix = if alias.is_empty() { String::new() } else { format!("{}.", alias) };
+            if *column == "content" {
+                // 加密存储的内容在读取时解密
+                format!("inbox_open({0}content, {0}content_nonce) AS content", prefix)
+            } else {
+                format!("{}{}", prefix, column)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// 列表查询的过滤条件
+#[derive(Debug, Default, Clone)]
+pub struct NoteFilter {
+    pub tag: Option<String>,
+    // 层级标签：同时匹配 tag 下的子标签（tag/...）
+    pub tag_prefix: bool,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub search: Option<String>,
+    pub color: Option<String>,
+    pub lang: Option<String>,
+    // 内容长度范围（UTF-8 字节数，闭区间）
+    pub min_len: Option<i64>,
+    pub max_len: Option<i64>,
+}
+
+// 标签统计的排序：count 按笔记数（默认），name 按名称，recent 按最近修改时间；并列时按名称
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TagSort {
+    #[default]
+    Count,
+    Name,
+    Recent,
+}
+
+impl TagSort {
+    pub fn parse(value: &str) -> Option<TagSort> {
+        match value {
+            "count" => Some(TagSort::Count),
+            "name" => Some(TagSort::Name),
+            "recent" => Some(TagSort::Recent),
+            _ => None,
+        }
+    }
+
+    // name_column 为结果中标签名的列名
+    fn order_by(self, name_column: &str) -> String {
+        match self {
+            TagSort::Count => format!("count DESC, {}", name_column),
+            TagSort::Name => name_column.to_string(),
+            TagSort::Recent => format!("last_modified DESC, {}", name_column),
+        }
+    }
+}
+
+// 标签统计的排序与分页；limit 为 None 时不限
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TagPage {
+    pub sort: TagSort,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+// 标签统计的范围；都为空时统计全部笔记
+#[derive(Debug, Default, Clone)]
+pub struct TagStatsFilter {
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub prefix: Option<String>,
+}
+
 fn map_row_to_note(row: &Row) -> Result<Note, Error> {
-    let tags_json: String = row.get("tags")?;
-    // Assuming Note in models.rs has tags: Vec<String>
-    let tags: Vec<String> = serde_json::from_str(&tags_json).map_err(map_serde_error)?;
+    let id: i64 = row.get("id")?;
+    // 单条笔记的 tags 损坏时不让整个查询失败：记录日志并按空标签处理，
+    // 可以通过 GET /inbox/admin/bad-tags 找出这些笔记
+    let tags = parse_tags(row.get_ref("tags")?).unwrap_or_else(|| {
+        eprintln!("[WARN] {}笔记 {} 的 tags 无法解析，按空标签处理: {:?}", request_id::tag(), id, raw_tags(row.get_ref("tags").ok()));
+        Vec::new()
+    });
     let created_at: DateTime<Utc> = row.get("created_at")?;
     let updated_at: DateTime<Utc> = row.get("updated_at")?;
 
     Ok(Note {
-        id: row.get("id")?,
+        id,
         content: row.get("content")?,
         tags, // Store parsed Vec<String>
         created_at,
         updated_at,
+        color: row.get("color")?,
+        slug: row.get("slug")?,
+        lang: row.get("lang")?,
+        uid: row.get("uid")?,
+        pinned: row.get("pinned")?,
+        archived_at: row.get("archived_at")?,
     })
 }
 
-pub fn create_note_db(conn: &mut DbConnection, payload: CreateNotePayload) -> Result<Note, Error> {
+// tags 列应为字符串数组的 JSON；NULL、非文本或无法解析时返回 None
+fn parse_tags(value: ValueRef) -> Option<Vec<String>> {
+    match value {
+        ValueRef::Text(text) => serde_json::from_slice(text).ok(),
+        _ => None,
+    }
+}
+
+// 原始 tags 值的文本形式（用于日志和报告）
+fn raw_tags(value: Option<ValueRef>) -> Option<String> {
+    match value? {
+        ValueRef::Null => None,
+        ValueRef::Integer(n) => Some(n.to_string()),
+        ValueRef::Real(f) => Some(f.to_string()),
+        ValueRef::Text(text) | ValueRef::Blob(text) => Some(String::from_utf8_lossy(text).into_owned()),
+    }
+}
+
+// tags 无法解析的笔记（含回收站中的），按 id 排序
+pub fn get_bad_tags_db(conn: &DbConnection) -> Result<BadTagsReport, Error> {
+    let mut stmt = conn.prepare("SELECT id, tags FROM notes ORDER BY id")?;
+    let mut rows = stmt.query([])?;
+    let mut notes = Vec::new();
+    while let Some(row) = rows.next()? {
+        let value = row.get_ref("tags")?;
+        if parse_tags(value).is_none() {
+            notes.push(BadTagsEntry { id: row.get("id")?, tags: raw_tags(Some(value)) });
+        }
+    }
+    Ok(BadTagsReport { count: notes.len(), notes })
+}
+
+fn new_note_uid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+// 把路径中的笔记标识解析为内部 id。uuid 模式下不接受整数 id（否则隐藏自增 id 就没有意义）；
+// 整数模式下两种都接受。笔记不存在（含已软删除）时返回 QueryReturnedNoRows
+pub fn resolve_note_key(conn: &DbConnection, key: &NoteKey, scheme: IdScheme) -> Result<i64, Error> {
+    match (key, scheme) {
+        (NoteKey::Id(_), IdScheme::Uuid) => Err(Error::QueryReturnedNoRows),
+        (NoteKey::Id(id), IdScheme::Integer) => Ok(*id),
+        (NoteKey::Uid(uid), _) => conn
+            .prepare_cached("SELECT id FROM notes WHERE uid = ?1 AND deleted_at IS NULL")?
+            .query_row(params![uid], |row| row.get(0)),
+    }
+}
+
+// 关系标识解析为内部 id，规则同 resolve_note_key（关系的 uid 见 migrate_v11）
+pub fn resolve_relation_key(conn: &DbConnection, key: &NoteKey, scheme: IdScheme) -> Result<i64, Error> {
+    match (key, scheme) {
+        (NoteKey::Id(_), IdScheme::Uuid) => Err(Error::QueryReturnedNoRows),
+        (NoteKey::Id(id), IdScheme::Integer) => Ok(*id),
+        (NoteKey::Uid(uid), _) => conn
+            .prepare_cached("SELECT id FROM note_relations WHERE uid = ?1 AND deleted_at IS NULL")?
+            .query_row(params![uid], |row| row.get(0)),
+    }
+}
+
+// 内部 id 对应的 uid（含回收站中的笔记），用于 uuid 模式下把响应中的 id 换成对外的 id；
+// 已不存在的笔记不在结果中
+pub fn note_uids_db(conn: &DbConnection, ids: &[i64]) -> Result<HashMap<i64, String>, Error> {
+    let ids_json = serde_json::to_string(ids).map_err(map_serde_error)?;
+    let mut stmt = conn.prepare_cached("SELECT id, uid FROM notes WHERE uid IS NOT NULL AND id IN (SELECT value FROM json_each(?1))")?;
+    let rows = stmt.query_map(params![ids_json], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+// --- slug ---
+// 由首行生成的 slug 的最大长度
+const AUTO_SLUG_MAX_LEN: usize = 60;
+
+// 首个非空行转为 kebab-case（只保留 ASCII 字母数字），没有可用字符时用 "note"
+fn slugify(content: &str) -> String {
+    let first_line = content.lines().find(|line| !line.trim().is_empty()).unwrap_or("");
+    let mut slug = String::new();
+    for c in first_line.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(AUTO_SLUG_MAX_LEN);
+    match slug.trim_matches('-') {
+        "" => "note".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+// base 已被占用时依次尝试 base-2、base-3 …
+fn unique_slug(conn: &Connection, base: &str) -> Result<String, Error> {
+    let taken = conn
+        .prepare_cached("SELECT slug FROM notes WHERE slug = ?1 OR slug LIKE ?1 || '-%'")?
+        .query_map(params![base], |row| row.get::<_, String>(0))?
+        .collect::<Result<HashSet<String>, Error>>()?;
+    if !taken.contains(base) {
+        return Ok(base.to_string());
+    }
+    Ok((2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|candidate| !taken.contains(candidate))
+        .expect("unbounded suffix range"))
+}
+
+// 新笔记的 slug：自定义的原样使用（冲突时插入触发唯一约束，返回 409），否则由首行生成。
+// 内容加密存储时不自动生成，以免首行以明文出现在 slug 中。
+fn resolve_slug(conn: &Connection, custom: Option<String>, content: &str, encrypted: bool) -> Result<Option<String>, Error> {
+    match custom {
+        Some(slug) => Ok(Some(slug)),
+        None if encrypted => Ok(None),
+        None => unique_slug(conn, &slugify(content)).map(Some),
+    }
+}
+
+pub fn get_note_by_slug_db(conn: &DbConnection, slug: &str) -> Result<Option<Note>, Error> {
+    let result = conn
+        .prepare_cached(&format!("SELECT {} FROM notes WHERE slug = ?1 AND deleted_at IS NULL", note_columns("")))?
+        .query_row(params![slug], map_row_to_note);
+    match result {
+        Ok(note) => Ok(Some(note)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// create_note_db / update_note_db / upsert_note_db 对所有调用方统一执行的写入规则，由 InboxConfig::note_write_options 生成。
+// 默认值不限制标签、不规范化空白、不自动关联
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoteWriteOptions {
+    pub max_tags: Option<usize>,
+    pub max_tag_len: Option<usize>,
+    pub normalize_whitespace: bool,
+    pub auto_link: Option<AutoLink>,
+}
+
+// 标签数量和单个标签长度的限制（标签应已规范化去重），返回第一个违反的限制
+pub fn check_tag_limits(tags: &[String], options: &NoteWriteOptions) -> Result<(), WriteNoteError> {
+    if let Some(limit) = options.max_tags.filter(|&limit| tags.len() > limit) {
+        return Err(WriteNoteError::TooManyTags { count: tags.len(), limit });
+    }
+    if let Some(limit) = options.max_tag_len {
+        if let Some(tag) = tags.iter().find(|tag| tag.chars().count() > limit) {
+            return Err(WriteNoteError::TagTooLong { tag: tag.clone(), limit });
+        }
+    }
+    Ok(())
+}
+
+// INBOX_NORMALIZE_WHITESPACE：\r\n 统一为 \n，去掉每行行尾空白；空行和行首缩进保持不变
+pub fn normalize_whitespace(content: &str) -> String {
+    content
+        .replace("\r\n", "\n")
+        .split('\n')
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// 传入 auto_link 时在同一事务中按正文的 [[id]] 引用创建 Link 关系（INBOX_AUTO_LINK）
+pub fn create_note_db(conn: &mut DbConnection, mut payload: CreateNotePayload, options: NoteWriteOptions) -> Result<Note, WriteNoteError> {
+    if options.normalize_whitespace {
+        payload.content = normalize_whitespace(&payload.content);
+    }
+    check_tag_limits(payload.tags.as_deref().unwrap_or_default(), &options)?;
+    let tx = conn.transaction()?;
+    let note = insert_note_tx(&tx, payload, None)?;
+    if let Some(auto_link) = options.auto_link {
+        sync_auto_links_tx(&tx, note.id, None, &note.content, auto_link)?;
+    }
+    tx.commit()?;
+    Ok(note)
+}
+
+fn insert_note_tx(tx: &Connection, payload: CreateNotePayload, external_id: Option<&str>) -> Result<Note, Error> {
     let created_at = payload.created_at.unwrap_or_else(Utc::now);
     let updated_at = created_at;
     let tags_json = serde_json::to_string(&payload.tags.unwrap_or_default())
         .map_err(map_serde_error)?;
 
-    let tx = conn.transaction()?;
+    let (stored_content, nonce) = seal_content(tx, &payload.content)?;
+    let slug = resolve_slug(tx, payload.slug, &payload.content, nonce.is_some())?;
+    let uid = new_note_uid();
     tx.execute(
         r#"
-        INSERT INTO notes (content, tags, created_at, updated_at)
-        VALUES (?1, ?2, ?3, ?4)
+        INSERT INTO notes (content, tags, created_at, updated_at, color, content_nonce, slug, lang, uid, external_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
         "#,
         params![
-            payload.content,
+            stored_content,
             tags_json,
             created_at,
             updated_at,
+            payload.color,
+            nonce,
+            slug,
+            payload.lang,
+            uid,
+            external_id,
         ],
     )?;
 
     let id = tx.last_insert_rowid();
-    tx.commit()?;
-
     let parsed_tags: Vec<String> = serde_json::from_str(&tags_json).map_err(map_serde_error)?;
 
     Ok(Note {
@@ -139,12 +999,122 @@ pub fn create_note_db(conn: &mut DbConnection, payload: CreateNotePayload) -> Re
         tags: parsed_tags, // Ensure Note struct expects Vec<String>
         created_at,
         updated_at,
+        color: payload.color,
+        slug,
+        lang: payload.lang,
+        uid: Some(uid),
+        pinned: false,
+        archived_at: None,
     })
 }
 
+// POST /inbox/notes/upsert 的结果；Updated 带上更新前的状态，供撤销使用
+pub enum Upserted {
+    Created(Note),
+    Updated { before: Note, note: Note },
+}
+
+// 按外部 ID 写入：已有未删除的笔记时更新其内容和标签（颜色保持不变，内容和标签都未变化时不写入），
+// 否则新建笔记并记下外部 ID。回收站中的笔记让出外部 ID，由新笔记接管。
+// 查找与写入在同一个事务中，唯一索引保证同一外部 ID 最多对应一条笔记
+pub fn upsert_note_db(
+    conn: &mut DbConnection,
+    external_id: &str,
+    mut payload: CreateNotePayload,
+    history_limit: usize,
+    options: NoteWriteOptions,
+) -> Result<Upserted, WriteNoteError> {
+    if options.normalize_whitespace {
+        payload.content = normalize_whitespace(&payload.content);
+    }
+    check_tag_limits(payload.tags.as_deref().unwrap_or_default(), &options)?;
+    let tx = conn.transaction()?;
+    let existing: Option<(i64, Option<String>)> = tx
+        .query_row(
+            "SELECT id, deleted_at FROM notes WHERE external_id = ?1",
+            params![external_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    let upserted = match existing {
+        Some((id, None)) => {
+            let before = get_note_db(&tx, id)?.ok_or(Error::QueryReturnedNoRows)?;
+            let tags = payload.tags.unwrap_or_default();
+            if before.content != payload.content || before.tags != tags {
+                let tags_json = serde_json::to_string(&tags).map_err(map_serde_error)?;
+                update_note_tx(&tx, id, &payload.content, &tags_json, before.color.as_deref(), history_limit)?;
+                if let Some(auto_link) = options.auto_link {
+                    sync_auto_links_tx(&tx, id, Some(&before.content), &payload.content, auto_link)?;
+                }
+            }
+            let note = get_note_db(&tx, id)?.ok_or(Error::QueryReturnedNoRows)?;
+            Upserted::Updated { before, note }
+        }
+        existing => {
+            if let Some((id, _)) = existing {
+                tx.execute("UPDATE notes SET external_id = NULL WHERE id = ?1", params![id])?;
+            }
+            let note = insert_note_tx(&tx, payload, Some(external_id))?;
+            if let Some(auto_link) = options.auto_link {
+                sync_auto_links_tx(&tx, note.id, None, &note.content, auto_link)?;
+            }
+            Upserted::Created(note)
+        }
+    };
+    tx.commit()?;
+    Ok(upserted)
+}
+
+// 批量创建笔记（导入等场景），全部在一个事务中完成，任一失败则整体回滚。
+// 传入 auto_link 时在全部写入后同步 [[id]] 引用，同一批中前后互相引用的笔记也能关联上
+pub fn create_notes_bulk_db(conn: &mut DbConnection, payloads: Vec<CreateNotePayload>, auto_link: Option<AutoLink>) -> Result<Vec<Note>, Error> {
+    let tx = conn.transaction()?;
+    let mut notes = Vec::with_capacity(payloads.len());
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO notes (content, tags, created_at, updated_at, color, content_nonce, slug, lang, uid) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+        )?;
+        for payload in payloads {
+            let created_at = payload.created_at.unwrap_or_else(Utc::now);
+            let tags = payload.tags.unwrap_or_default();
+            let tags_json = serde_json::to_string(&tags).map_err(map_serde_error)?;
+
+            let (stored_content, nonce) = seal_content(&tx, &payload.content)?;
+            // 导入的 slug 已被占用时（如导回同一个库）追加数字后缀，而不是让整批失败
+            let slug = match payload.slug {
+                Some(slug) => Some(unique_slug(&tx, &slug)?),
+                None => resolve_slug(&tx, None, &payload.content, nonce.is_some())?,
+            };
+            let uid = new_note_uid();
+            stmt.execute(params![stored_content, tags_json, created_at, created_at, payload.color, nonce, slug, payload.lang, uid])?;
+            notes.push(Note {
+                id: tx.last_insert_rowid(),
+                content: payload.content,
+                tags,
+                created_at,
+                updated_at: created_at,
+                color: payload.color,
+                slug,
+                lang: payload.lang,
+                uid: Some(uid),
+                pinned: false,
+                archived_at: None,
+            });
+        }
+    }
+    if let Some(auto_link) = auto_link {
+        for note in &notes {
+            sync_auto_links_tx(&tx, note.id, None, &note.content, auto_link)?;
+        }
+    }
+    tx.commit()?;
+    Ok(notes)
+}
+
 pub fn get_note_db(conn: &DbConnection, note_id: i64) -> Result<Option<Note>, Error> {
     let mut stmt = conn.prepare(
-        "SELECT id, content, tags, created_at, updated_at FROM notes WHERE id = ?1"
+        &format!("SELECT {} FROM notes WHERE id = ?1 AND deleted_at IS NULL", note_columns(""))
     )?;
     let result = stmt.query_row(params![note_id], map_row_to_note);
 
@@ -155,41 +1125,270 @@ pub fn get_note_db(conn: &DbConnection, note_id: i64) -> Result<Option<Note>, Er
     }
 }
 
+// 按 id 批量获取笔记；结果按 ids 的顺序排列，不存在的 id 直接跳过
+pub fn get_notes_by_ids_db(conn: &DbConnection, ids: &[i64]) -> Result<Vec<Note>, Error> {
+    let ids_json = serde_json::to_string(ids).map_err(map_serde_error)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM notes WHERE id IN (SELECT value FROM json_each(?1)) AND deleted_at IS NULL",
+        note_columns("")
+    ))?;
+    let mut found: HashMap<i64, Note> = stmt
+        .query_map(params![ids_json], map_row_to_note)?
+        .map(|note| note.map(|n| (n.id, n)))
+        .collect::<Result<_, _>>()?;
+
+    Ok(ids.iter().filter_map(|id| found.remove(id)).collect())
+}
+
 pub fn get_notes_db(
     conn: &DbConnection,
     limit: Option<i64>,
-    tag: Option<String>,
-    created_after: Option<DateTime<Utc>>,
-    created_before: Option<DateTime<Utc>>,
-    search: Option<String>,
+    offset: Option<i64>,
+    filter: NoteFilter,
 ) -> Result<Vec<Note>, Error> {
-    let mut query_str = "SELECT id, content, tags, created_at, updated_at FROM notes WHERE 1=1".to_string();
+    let (query_str, params_vec) = filtered_notes_query(limit, offset, filter);
+
+    let mut stmt = conn.prepare_cached(&query_str)?;
+    let params_ref: Vec<&dyn ToSql> = params_vec.iter().map(|b| b.as_ref()).collect();
+    let notes_iter = stmt.query_map(&params_ref[..], map_row_to_note)?;
+
+    let mut notes = Vec::new();
+    for note_result in notes_iter {
+        notes.push(note_result?);
+    }
+
+    Ok(notes)
+}
+
+// 同 get_notes_db，并附带每条笔记的关系数量。
+// 在分页后的结果上做一次 LEFT JOIN + GROUP BY，而不是逐条查询。
+// relation_count 统计该笔记作为任意一端的所有关系，comment_count 只统计指向它的评论。
+pub fn get_notes_with_counts_db(
+    conn: &DbConnection,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    filter: NoteFilter,
+) -> Result<Vec<(Note, RelationCounts)>, Error> {
+    let (inner_query, params_vec) = filtered_notes_query(limit, offset, filter);
+    let query_str = format!(
+        "SELECT n.*,
+                COUNT(r.id) AS relation_count,
+                COUNT(CASE WHEN r.relation_type = 'Comment' AND r.target_note_id = n.id THEN 1 END) AS comment_count
+         FROM ({}) n
+         LEFT JOIN note_relations r ON (r.source_note_id = n.id OR r.target_note_id = n.id) AND r.deleted_at IS NULL
+         GROUP BY n.id
+         ORDER BY n.created_at DESC, n.id DESC",
+        inner_query
+    );
+
+    let mut stmt = conn.prepare(&query_str)?;
+    let params_ref: Vec<&dyn ToSql> = params_vec.iter().map(|b| b.as_ref()).collect();
+    let rows = stmt.query_map(&params_ref[..], |row| {
+        let counts = RelationCounts {
+            relation_count: row.get("relation_count")?,
+            comment_count: row.get("comment_count")?,
+        };
+        Ok((map_row_to_note(row)?, counts))
+    })?;
+    rows.collect()
+}
+
+// 按过滤条件构造笔记查询（含排序与分页），返回带编号占位符的 SQL 及其参数
+fn filtered_notes_query(
+    limit: Option<i64>,
+    offset: Option<i64>,
+    filter: NoteFilter,
+) -> (String, Vec<Box<dyn ToSql>>) {
+    let (where_clause, mut params_vec) = note_filter_clause(filter);
+    let mut query_str = format!("SELECT {} FROM notes{}", note_columns(""), where_clause);
+
+    // 同一时刻创建的笔记（批量导入时很常见）按 id 排序，保证分页结果稳定
+    query_str.push_str(" ORDER BY created_at DESC, id DESC");
+
+    // LIMIT/OFFSET 同样作为绑定参数，相同形状的查询可以复用缓存的预编译语句
+    if limit.is_some() || offset.is_some() {
+        // SQLite 要求 OFFSET 前必须有 LIMIT，未指定 limit 时用 -1 表示不限
+        query_str.push_str(" LIMIT ?");
+        params_vec.push(Box::new(limit.unwrap_or(-1)));
+    }
+    if let Some(o) = offset {
+        query_str.push_str(" OFFSET ?");
+        params_vec.push(Box::new(o));
+    }
+
+    (number_placeholders(&query_str), params_vec)
+}
+
+// 按 id 升序分页读取 id 大于 after_id 的笔记（键集分页）。
+// 流式导出用它分批读取：每批是一条独立的短语句，不受 INBOX_QUERY_TIMEOUT_MS 对整个导出时长的限制，
+// 也不会在客户端读得慢时长期持有读事务。
+pub fn get_notes_page_after_db(conn: &DbConnection, after_id: i64, limit: i64) -> Result<Vec<Note>, Error> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM notes WHERE id > ?1 AND deleted_at IS NULL ORDER BY id LIMIT ?2",
+        note_columns("")
+    ))?;
+    let notes = stmt
+        .query_map(params![after_id, limit], map_row_to_note)?
+        .collect::<Result<Vec<Note>, Error>>()?;
+    Ok(notes)
+}
+
+// 内容匹配 query 的笔记，以及通过任意方向的关系与它们直接相连的笔记。
+// 两组各自最多 limit 条、按创建时间倒序；已出现在匹配结果中的笔记不再列入 linked。
+pub fn search_linked_notes_db(conn: &DbConnection, query: &str, limit: i64) -> Result<(Vec<Note>, Vec<Note>), Error> {
+    const MATCHED: &str = "SELECT id FROM notes
+         WHERE deleted_at IS NULL AND content LIKE ?1
+         ORDER BY created_at DESC LIMIT ?2";
+    let pattern = format!("%{}%", query);
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM notes WHERE id IN ({}) ORDER BY created_at DESC",
+        note_columns(""),
+        MATCHED
+    ))?;
+    let matched = stmt
+        .query_map(params![pattern, limit], map_row_to_note)?
+        .collect::<Result<Vec<Note>, Error>>()?;
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH matched AS ({})
+         SELECT {} FROM notes n
+         WHERE n.deleted_at IS NULL
+           AND n.id NOT IN (SELECT id FROM matched)
+           AND n.id IN (
+               SELECT r.target_note_id FROM note_relations r JOIN matched m ON r.source_note_id = m.id WHERE r.deleted_at IS NULL
+               UNION
+               SELECT r.source_note_id FROM note_relations r JOIN matched m ON r.target_note_id = m.id WHERE r.deleted_at IS NULL
+           )
+         ORDER BY n.created_at DESC LIMIT ?2",
+        MATCHED,
+        note_columns("n")
+    ))?;
+    let linked = stmt
+        .query_map(params![pattern, limit], map_row_to_note)?
+        .collect::<Result<Vec<Note>, Error>>()?;
+
+    Ok((matched, linked))
+}
+
+// 按 created_at 相邻的方向（next 为更晚创建的笔记）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjacent {
+    Next,
+    Prev,
+}
+
+// 与 note_id 相邻的笔记（同样满足过滤条件），按 (created_at, id) 排序以区分同一时刻创建的笔记。
+// note_id 不存在或已经在两端时返回 None。
+pub fn get_adjacent_note_db(conn: &DbConnection, note_id: i64, direction: Adjacent, filter: NoteFilter) -> Result<Option<Note>, Error> {
+    let (where_clause, mut params_vec) = note_filter_clause(filter);
+    let (op, order) = match direction {
+        Adjacent::Next => (">", "ASC"),
+        Adjacent::Prev => ("<", "DESC"),
+    };
+    let query_str = number_placeholders(&format!(
+        "SELECT {} FROM notes{} AND (created_at, id) {} (SELECT created_at, id FROM notes WHERE id = ?)
+         ORDER BY created_at {order}, id {order} LIMIT 1",
+        note_columns(""),
+        where_clause,
+        op,
+        order = order
+    ));
+    params_vec.push(Box::new(note_id));
+
+    let params_ref: Vec<&dyn ToSql> = params_vec.iter().map(|b| b.as_ref()).collect();
+    match conn.prepare_cached(&query_str)?.query_row(&params_ref[..], map_row_to_note) {
+        Ok(note) => Ok(Some(note)),
+        Err(Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// notes 表中没有任何行（包括软删除的笔记），用于判断是否写入示例数据
+pub fn notes_table_is_empty(conn: &DbConnection) -> Result<bool, Error> {
+    conn.query_row("SELECT NOT EXISTS (SELECT 1 FROM notes)", [], |row| row.get(0))
+}
+
+// 符合过滤条件的笔记总数（用于分页元数据）
+pub fn count_notes_db(conn: &DbConnection, filter: NoteFilter) -> Result<i64, Error> {
+    let (where_clause, params_vec) = note_filter_clause(filter);
+    let query_str = number_placeholders(&format!("SELECT COUNT(*) FROM notes{}", where_clause));
+    let params_ref: Vec<&dyn ToSql> = params_vec.iter().map(|b| b.as_ref()).collect();
+    conn.prepare_cached(&query_str)?.query_row(&params_ref[..], |row| row.get(0))
+}
+
+// 未归档、未删除的笔记数（GET /inbox/count），可按标签精确匹配
+pub fn count_inbox_db(conn: &DbConnection, tag: Option<&str>) -> Result<i64, Error> {
+    conn.prepare_cached(&format!(
+        "SELECT COUNT(*) FROM notes WHERE deleted_at IS NULL AND archived_at IS NULL
+         AND (?1 IS NULL OR ({} AND EXISTS (SELECT 1 FROM json_each(notes.tags) WHERE json_each.value = ?1)))",
+        NOTE_TAGS_ARE_ARRAY
+    ))?
+    .query_row(params![tag], |row| row.get(0))
+}
+
+// 只有 tags 是合法的 JSON 数组时才对它调用 json_each：损坏的行会让 json_each 报错，使整条查询失败。
+// 放在 json_each 之前，SQLite 按顺序短路求值
+const NOTE_TAGS_ARE_ARRAY: &str = "json_valid(notes.tags) AND json_type(notes.tags) = 'array'";
+
+// 过滤条件对应的 WHERE 子句（使用未编号的 '?' 占位符）
+fn note_filter_clause(filter: NoteFilter) -> (String, Vec<Box<dyn ToSql>>) {
+    let mut clause = String::from(" WHERE deleted_at IS NULL");
     let mut params_vec: Vec<Box<dyn ToSql>> = Vec::new();
 
-    if let Some(t) = tag {
-        query_str.push_str(" AND tags LIKE ?");
-        params_vec.push(Box::new(format!("%\"{}\"%", t)));
+    if let Some(t) = filter.tag {
+        // 精确匹配 JSON 数组中的元素（LIKE 会把标签里的 % 和 _ 当作通配符，且可能误匹配子串）
+        if filter.tag_prefix {
+            // work 匹配 work 与 work/...，但不匹配 workshop；substr 按字符计数
+            let t = t.trim_end_matches('/');
+            clause.push_str(&format!(
+                " AND {} AND EXISTS (SELECT 1 FROM json_each(notes.tags) WHERE json_each.value = ? OR substr(json_each.value, 1, ?) = ?)",
+                NOTE_TAGS_ARE_ARRAY
+            ));
+            params_vec.push(Box::new(t.to_string()));
+            params_vec.push(Box::new(t.chars().count() as i64 + 1));
+            params_vec.push(Box::new(format!("{}/", t)));
+        } else {
+            clause.push_str(&format!(" AND {} AND EXISTS (SELECT 1 FROM json_each(notes.tags) WHERE json_each.value = ?)", NOTE_TAGS_ARE_ARRAY));
+            params_vec.push(Box::new(t));
+        }
     }
-    if let Some(after) = created_after {
-        query_str.push_str(" AND created_at >= ?");
+    if let Some(after) = filter.created_after {
+        clause.push_str(" AND created_at >= ?");
         params_vec.push(Box::new(after));
     }
-    if let Some(before) = created_before {
-        query_str.push_str(" AND created_at < ?");
+    if let Some(before) = filter.created_before {
+        clause.push_str(" AND created_at < ?");
         params_vec.push(Box::new(before));
     }
-    if let Some(s) = search {
+    if let Some(s) = filter.search {
         // 使用 LIKE 在内容中搜索（将搜索词包裹在通配符 % 中）
-        query_str.push_str(" AND content LIKE ?");
+        clause.push_str(" AND content LIKE ?");
         params_vec.push(Box::new(format!("%{}%", s)));
     }
-
-    query_str.push_str(" ORDER BY created_at DESC");
-
-    if let Some(l) = limit {
-        query_str.push_str(&format!(" LIMIT {}", l));
+    if let Some(c) = filter.color {
+        clause.push_str(" AND color = ?");
+        params_vec.push(Box::new(c));
+    }
+    if let Some(lang) = filter.lang {
+        clause.push_str(" AND lang = ?");
+        params_vec.push(Box::new(lang));
+    }
+    // 按解密后的明文计算字节数（转为 BLOB 后 LENGTH 返回字节数而不是字符数）
+    if let Some(min_len) = filter.min_len {
+        clause.push_str(" AND LENGTH(CAST(inbox_open(notes.content, notes.content_nonce) AS BLOB)) >= ?");
+        params_vec.push(Box::new(min_len));
     }
+    if let Some(max_len) = filter.max_len {
+        clause.push_str(" AND LENGTH(CAST(inbox_open(notes.content, notes.content_nonce) AS BLOB)) <= ?");
+        params_vec.push(Box::new(max_len));
+    }
+
+    (clause, params_vec)
+}
 
+// 把 '?' 依次替换为 ?1, ?2, ...
+fn number_placeholders(query_str: &str) -> String {
     let mut final_query_str = String::new();
     let mut param_index = 1;
     for c in query_str.chars() {
@@ -200,49 +1399,226 @@ pub fn get_notes_db(
             final_query_str.push(c);
         }
     }
+    final_query_str
+}
 
-    let mut stmt = conn.prepare(&final_query_str)?;
-    let params_ref: Vec<&dyn ToSql> = params_vec.iter().map(|b| b.as_ref()).collect();
-
-    // *** MUST FIX THIS LINE LOCALLY: Remove '¶', use 'params_ref' ***
-    let notes_iter = stmt.query_map(&params_ref[..], map_row_to_note)?;
+// 更新笔记。history_limit > 0 时在同一事务内先把旧版本存入 note_history，并只保留最近 history_limit 个版本；
+// 与当前内容完全相同的更新不写入，直接返回当前笔记
+pub fn update_note_db(
+    conn: &mut DbConnection,
+    note_id: i64,
+    mut payload: UpdateNotePayload,
+    history_limit: usize,
+    options: NoteWriteOptions,
+) -> Result<Option<Note>, WriteNoteError> {
+    if options.normalize_whitespace {
+        payload.content = normalize_whitespace(&payload.content);
+    }
+    let tags = payload.tags.unwrap_or_default();
+    check_tag_limits(&tags, &options)?;
+    let tags_json = serde_json::to_string(&tags).map_err(map_serde_error)?;
 
-    let mut notes = Vec::new();
-    for note_result in notes_iter {
-        notes.push(note_result?);
+    let tx = conn.transaction()?;
+    // 内容、标签和颜色都与当前一致时不写入：updated_at、更新次数和历史版本保持不变
+    let current = match get_note_db(&tx, note_id)? {
+        None => return Ok(None),
+        Some(current) if current.content == payload.content && current.tags == tags && current.color == payload.color => {
+            return Ok(Some(current));
+        }
+        Some(current) => current,
+    };
+    let updated = update_note_tx(&tx, note_id, &payload.content, &tags_json, payload.color.as_deref(), history_limit)?;
+    if let Some(auto_link) = options.auto_link.filter(|_| updated) {
+        sync_auto_links_tx(&tx, note_id, Some(&current.content), &payload.content, auto_link)?;
     }
+    tx.commit()?;
 
-    Ok(notes)
+    if updated {
+        Ok(get_note_db(conn, note_id)?)
+    } else {
+        Ok(None)
+    }
 }
 
-pub fn update_note_db(
-    conn: &mut DbConnection,
+fn update_note_tx(
+    tx: &Connection,
     note_id: i64,
-    payload: UpdateNotePayload,
-) -> Result<Option<Note>, Error> {
-    let updated_at = Utc::now();
-    let tags_json = serde_json::to_string(&payload.tags.unwrap_or_default())
-        .map_err(map_serde_error)?;
+    content: &str,
+    tags_json: &str,
+    color: Option<&str>,
+    history_limit: usize,
+) -> Result<bool, Error> {
+    if history_limit > 0 {
+        tx.execute(
+            "INSERT INTO note_history (note_id, content, content_nonce, tags, edited_at)
+             SELECT id, content, content_nonce, tags, updated_at FROM notes WHERE id = ?1 AND deleted_at IS NULL",
+            params![note_id],
+        )?;
+        tx.execute(
+            "DELETE FROM note_history WHERE note_id = ?1 AND id NOT IN (
+                 SELECT id FROM note_history WHERE note_id = ?1 ORDER BY id DESC LIMIT ?2
+             )",
+            params![note_id, history_limit as i64],
+        )?;
+    }
 
-    let rows_affected = conn.execute(
+    let (stored_content, nonce) = seal_content(tx, content)?;
+    let rows_affected = tx.execute(
         r#"
         UPDATE notes
-        SET content = ?1, tags = ?2, updated_at = ?3
-        WHERE id = ?4
+        SET content = ?1, tags = ?2, updated_at = ?3, color = ?4, content_nonce = ?5, update_count = update_count + 1
+        WHERE id = ?6 AND deleted_at IS NULL
         "#,
         params![
-            payload.content,
+            stored_content,
             tags_json,
-            updated_at,
+            Utc::now(),
+            color,
+            nonce,
             note_id
         ],
     )?;
+    Ok(rows_affected > 0)
+}
 
-    if rows_affected == 0 {
-        Ok(None)
-    } else {
-        get_note_db(conn, note_id)
+// 笔记的历史版本，最新的在前；笔记不存在（含已软删除）时返回 None
+pub fn get_note_history_db(conn: &DbConnection, note_id: i64) -> Result<Option<Vec<NoteVersion>>, Error> {
+    if !note_exists(conn, note_id)? {
+        return Ok(None);
+    }
+    let versions = conn
+        .prepare(
+            "SELECT id, inbox_open(content, content_nonce) AS content, tags, edited_at
+             FROM note_history WHERE note_id = ?1 ORDER BY id DESC",
+        )?
+        .query_map(params![note_id], map_row_to_version)?
+        .collect::<Result<Vec<NoteVersion>, Error>>()?;
+    Ok(Some(versions))
+}
+
+fn map_row_to_version(row: &Row) -> Result<NoteVersion, Error> {
+    let tags_json: String = row.get("tags")?;
+    Ok(NoteVersion {
+        version_id: row.get("id")?,
+        content: row.get("content")?,
+        tags: serde_json::from_str(&tags_json).map_err(map_serde_error)?,
+        edited_at: row.get("edited_at")?,
+    })
+}
+
+// 把笔记的内容和标签恢复为某个历史版本（颜色不变）。恢复本身也是一次更新，
+// 当前内容会先存为新的历史版本，因此恢复可以再被恢复回来。
+// 笔记或该笔记下的版本不存在时返回 None
+pub fn restore_note_version_db(
+    conn: &mut DbConnection,
+    note_id: i64,
+    version_id: i64,
+    history_limit: usize,
+    auto_link: Option<AutoLink>,
+) -> Result<Option<Note>, Error> {
+    let tx = conn.transaction()?;
+    let Some(current) = get_note_db(&tx, note_id)? else {
+        return Ok(None);
+    };
+    let version = tx
+        .query_row(
+            "SELECT id, inbox_open(content, content_nonce) AS content, tags, edited_at
+             FROM note_history WHERE id = ?1 AND note_id = ?2",
+            params![version_id, note_id],
+            map_row_to_version,
+        )
+        .optional()?;
+    let Some(version) = version else {
+        return Ok(None);
+    };
+
+    let tags_json = serde_json::to_string(&version.tags).map_err(map_serde_error)?;
+    let updated = update_note_tx(&tx, note_id, &version.content, &tags_json, current.color.as_deref(), history_limit)?;
+    if let Some(auto_link) = auto_link.filter(|_| updated) {
+        sync_auto_links_tx(&tx, note_id, Some(&current.content), &version.content, auto_link)?;
+    }
+    tx.commit()?;
+    get_note_db(conn, note_id)
+}
+
+// 笔记的修改元数据；content_hash 为明文内容的 SHA-256（十六进制），与是否加密存储无关
+pub fn get_note_meta_db(conn: &DbConnection, note_id: i64) -> Result<Option<NoteMeta>, Error> {
+    conn.query_row(
+        "SELECT inbox_open(content, content_nonce), created_at, updated_at, update_count
+         FROM notes WHERE id = ?1 AND deleted_at IS NULL",
+        params![note_id],
+        |row| {
+            let content: String = row.get(0)?;
+            Ok(NoteMeta {
+                created_at: row.get(1)?,
+                updated_at: row.get(2)?,
+                update_count: row.get(3)?,
+                content_hash: format!("{:x}", Sha256::digest(content.as_bytes())),
+            })
+        },
+    )
+    .optional()
+}
+
+// 合并笔记时各段内容之间的分隔
+const MERGE_SEPARATOR: &str = "\n\n---\n\n";
+
+// 把 others 合并进 primary：依次追加内容、合并标签、把关系改指向 primary，然后软删除 others。
+// 全部在一个事务内完成。others 应已去重且不含 primary。
+// 传入 auto_link 时按合并后的正文同步 primary 的 [[id]] 引用（others 的关系此时已改指向 primary）
+pub fn merge_notes_db(conn: &mut DbConnection, primary_id: i64, other_ids: &[i64], auto_link: Option<AutoLink>) -> Result<Note, MergeNotesError> {
+    let tx = conn.transaction()?;
+
+    let mut primary = get_note_db(&tx, primary_id)?.ok_or(MergeNotesError::NoteNotFound(primary_id))?;
+    let mut others = Vec::with_capacity(other_ids.len());
+    for &id in other_ids {
+        others.push(get_note_db(&tx, id)?.ok_or(MergeNotesError::NoteNotFound(id))?);
+    }
+
+    let original_content = primary.content.clone();
+    for other in &others {
+        primary.content.push_str(MERGE_SEPARATOR);
+        primary.content.push_str(&other.content);
+        for tag in &other.tags {
+            if !primary.tags.contains(tag) {
+                primary.tags.push(tag.clone());
+            }
+        }
+    }
+
+    let now = Utc::now();
+    let tags_json = serde_json::to_string(&primary.tags).map_err(map_serde_error)?;
+    let (stored_content, nonce) = seal_content(&tx, &primary.content)?;
+    tx.execute(
+        "UPDATE notes SET content = ?1, tags = ?2, updated_at = ?3, content_nonce = ?4 WHERE id = ?5",
+        params![stored_content, tags_json, now, nonce, primary_id],
+    )?;
+
+    let ids_json = serde_json::to_string(other_ids).map_err(map_serde_error)?;
+    tx.execute(
+        "UPDATE note_relations SET source_note_id = ?1 WHERE source_note_id IN (SELECT value FROM json_each(?2))",
+        params![primary_id, ids_json],
+    )?;
+    tx.execute(
+        "UPDATE note_relations SET target_note_id = ?1 WHERE target_note_id IN (SELECT value FROM json_each(?2))",
+        params![primary_id, ids_json],
+    )?;
+    // 被合并笔记之间（或与 primary 之间）的关系改指向后变成自引用，直接删除
+    tx.execute(
+        "DELETE FROM note_relations WHERE source_note_id = ?1 AND target_note_id = ?1",
+        params![primary_id],
+    )?;
+    tx.execute(
+        "UPDATE notes SET deleted_at = ?1 WHERE id IN (SELECT value FROM json_each(?2))",
+        params![now, ids_json],
+    )?;
+    if let Some(auto_link) = auto_link {
+        sync_auto_links_tx(&tx, primary_id, Some(&original_content), &primary.content, auto_link)?;
     }
+
+    let merged = get_note_db(&tx, primary_id)?.ok_or(MergeNotesError::NoteNotFound(primary_id))?;
+    tx.commit()?;
+    Ok(merged)
 }
 
 pub fn delete_note_db(conn: &mut DbConnection, note_id: i64) -> Result<bool, Error> {
@@ -253,10 +1629,202 @@ pub fn delete_note_db(conn: &mut DbConnection, note_id: i64) -> Result<bool, Err
     Ok(rows_affected > 0)
 }
 
+// 在一个事务中批量归档、删除、置顶笔记（POST /inbox/triage），返回各操作实际影响的笔记数。
+// 删除与 DELETE /inbox/notes/<id> 一样是彻底删除；已归档的笔记保留原归档时间
+pub fn triage_notes_db(conn: &mut DbConnection, archive: &[i64], delete: &[i64], pin: &[i64]) -> Result<TriageSummary, Error> {
+    let tx = conn.transaction()?;
+    let ids_json = |ids: &[i64]| serde_json::to_string(ids).map_err(map_serde_error);
+    let archived = tx.execute(
+        "UPDATE notes SET archived_at = COALESCE(archived_at, ?1)
+         WHERE deleted_at IS NULL AND id IN (SELECT value FROM json_each(?2))",
+        params![Utc::now(), ids_json(archive)?],
+    )?;
+    let pinned = tx.execute(
+        "UPDATE notes SET pinned = 1 WHERE deleted_at IS NULL AND id IN (SELECT value FROM json_each(?1))",
+        params![ids_json(pin)?],
+    )?;
+    let deleted = tx.execute(
+        "DELETE FROM notes WHERE deleted_at IS NULL AND id IN (SELECT value FROM json_each(?1))",
+        params![ids_json(delete)?],
+    )?;
+    tx.commit()?;
+    Ok(TriageSummary { archived, deleted, pinned })
+}
+
+// --- 撤销 ---
+
+// 删除前的快照：笔记本身及其出入关系（删除时随外键级联删除）
+pub fn get_note_snapshot_db(conn: &DbConnection, note_id: i64) -> Result<Option<(Note, Vec<NoteRelation>)>, Error> {
+    let Some(note) = get_note_db(conn, note_id)? else {
+        return Ok(None);
+    };
+    let mut relations = get_relations_for_note_db(conn, note_id, None)?;
+    relations.extend(get_outgoing_relations_db(conn, note_id)?);
+    relations.sort_by_key(|relation| relation.id);
+    relations.dedup_by_key(|relation| relation.id);
+    Ok(Some((note, relations)))
+}
+
+// 把笔记的内容、标签、颜色和更新时间恢复为 before；笔记已不存在时返回 false
+pub fn restore_note_db(conn: &mut DbConnection, before: &Note, auto_link: Option<AutoLink>) -> Result<bool, Error> {
+    let tags_json = serde_json::to_string(&before.tags).map_err(map_serde_error)?;
+    let tx = conn.transaction()?;
+    let Some(current) = get_note_db(&tx, before.id)? else {
+        return Ok(false);
+    };
+    let (stored_content, nonce) = seal_content(&tx, &before.content)?;
+    tx.execute(
+        "UPDATE notes SET content = ?1, tags = ?2, updated_at = ?3, color = ?4, content_nonce = ?5 WHERE id = ?6 AND deleted_at IS NULL",
+        params![stored_content, tags_json, before.updated_at, before.color, nonce, before.id],
+    )?;
+    if let Some(auto_link) = auto_link {
+        sync_auto_links_tx(&tx, before.id, Some(&current.content), &before.content, auto_link)?;
+    }
+    tx.commit()?;
+    Ok(true)
+}
+
+// 以原 id 重新插入被删除的笔记及其关系；另一端笔记已不存在的关系跳过
+pub fn reinsert_note_db(conn: &mut DbConnection, note: &Note, relations: &[NoteRelation]) -> Result<(), Error> {
+    let tx = conn.transaction()?;
+    let tags_json = serde_json::to_string(&note.tags).map_err(map_serde_error)?;
+    let (stored_content, nonce) = seal_content(&tx, &note.content)?;
+    tx.execute(
+        "INSERT INTO notes (id, content, tags, created_at, updated_at, color, content_nonce, slug, lang, uid, pinned, archived_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![note.id, stored_content, tags_json, note.created_at, note.updated_at, note.color, nonce, note.slug, note.lang, note.uid, note.pinned, note.archived_at],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO note_relations (id, source_note_id, target_note_id, relation_type, created_at, uid)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6
+             WHERE EXISTS (SELECT 1 FROM notes WHERE id = ?2) AND EXISTS (SELECT 1 FROM notes WHERE id = ?3)"
+        )?;
+        for relation in relations {
+            stmt.execute(params![
+                relation.id,
+                relation.source_note_id,
+                relation.target_note_id,
+                relation_type_to_str(&relation.relation_type),
+                relation.created_at,
+                relation.uid,
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+// --- 回收站 ---
+// 把笔记移入回收站（软删除），其出入关系一并软删除，从关系与评论查询中隐藏。笔记不存在或已在回收站中时返回 false
+pub fn trash_note_db(conn: &mut DbConnection, note_id: i64) -> Result<bool, Error> {
+    let tx = conn.transaction()?;
+    let now = Utc::now();
+    let trashed = tx.execute("UPDATE notes SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL", params![now, note_id])?;
+    if trashed == 0 {
+        return Ok(false);
+    }
+    tx.execute(
+        "UPDATE note_relations SET deleted_at = ?1
+         WHERE (source_note_id = ?2 OR target_note_id = ?2) AND deleted_at IS NULL",
+        params![now, note_id],
+    )?;
+    tx.commit()?;
+    Ok(true)
+}
+
+// 从回收站恢复笔记，并恢复另一端笔记也未被删除的关系（另一端仍在回收站中的关系等它恢复时再恢复）。
+// 笔记不在回收站中时返回 None
+pub fn restore_trashed_note_db(conn: &mut DbConnection, note_id: i64) -> Result<Option<Note>, Error> {
+    let tx = conn.transaction()?;
+    let restored = tx.execute("UPDATE notes SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL", params![note_id])?;
+    if restored == 0 {
+        return Ok(None);
+    }
+    tx.execute(
+        "UPDATE note_relations SET deleted_at = NULL
+         WHERE deleted_at IS NOT NULL AND (source_note_id = ?1 OR target_note_id = ?1)
+           AND EXISTS (SELECT 1 FROM notes WHERE id = note_relations.source_note_id AND deleted_at IS NULL)
+           AND EXISTS (SELECT 1 FROM notes WHERE id = note_relations.target_note_id AND deleted_at IS NULL)",
+        params![note_id],
+    )?;
+    let note = get_note_db(&tx, note_id)?;
+    tx.commit()?;
+    Ok(note)
+}
+
+// 回收站中笔记的标识解析为内部 id（同 resolve_note_key，但只匹配已软删除的笔记）
+pub fn resolve_trashed_note_key(conn: &DbConnection, key: &NoteKey, scheme: IdScheme) -> Result<i64, Error> {
+    match (key, scheme) {
+        (NoteKey::Id(_), IdScheme::Uuid) => Err(Error::QueryReturnedNoRows),
+        (NoteKey::Id(id), IdScheme::Integer) => Ok(*id),
+        (NoteKey::Uid(uid), _) => conn
+            .prepare_cached("SELECT id FROM notes WHERE uid = ?1 AND deleted_at IS NOT NULL")?
+            .query_row(params![uid], |row| row.get(0)),
+    }
+}
+
+// 彻底删除 deleted_at 早于 cutoff 的笔记（关系随外键级联删除）
+pub fn purge_trash_db(conn: &mut DbConnection, cutoff: DateTime<Utc>) -> Result<PurgeSummary, Error> {
+    let tx = conn.transaction()?;
+    let purged = tx.execute(
+        "DELETE FROM notes WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
+        params![cutoff],
+    )?;
+    tx.commit()?;
+    Ok(PurgeSummary { purged })
+}
+
+// --- 访问统计 ---
+
+// 批量写回内存中累计的访问次数（note_id -> 增量）
+pub fn increment_access_counts_db(conn: &mut DbConnection, counts: &HashMap<i64, i64>) -> Result<(), Error> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare("UPDATE notes SET access_count = access_count + ?1 WHERE id = ?2")?;
+        for (note_id, delta) in counts {
+            stmt.execute(params![delta, note_id])?;
+        }
+    }
+    tx.commit()
+}
+
+// 按访问次数倒序获取被访问过的笔记
+pub fn get_popular_notes_db(conn: &DbConnection, limit: i64) -> Result<Vec<Note>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM notes
+         WHERE access_count > 0 AND deleted_at IS NULL
+         ORDER BY access_count DESC, updated_at DESC
+         LIMIT ?1",
+        note_columns("")
+    ))?;
+    let notes_iter = stmt.query_map(params![limit], map_row_to_note)?;
+
+    let mut notes = Vec::new();
+    for note_result in notes_iter {
+        notes.push(note_result?);
+    }
+    Ok(notes)
+}
+
+// 没有标签且 updated_at 早于 cutoff 的笔记，最久未动的排在最前（整理收件箱用）
+pub fn get_stale_untagged_notes_db(conn: &DbConnection, cutoff: DateTime<Utc>, limit: i64) -> Result<Vec<Note>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM notes
+         WHERE (tags IS NULL OR json_array_length(tags) = 0)
+           AND updated_at < ?1 AND deleted_at IS NULL
+         ORDER BY updated_at ASC, id ASC
+         LIMIT ?2",
+        note_columns("")
+    ))?;
+    let notes = stmt
+        .query_map(params![cutoff, limit], map_row_to_note)?
+        .collect::<Result<Vec<Note>, Error>>()?;
+    Ok(notes)
+}
+
 // --- 标签操作 ---
 
 pub fn get_all_tags_db(conn: &DbConnection) -> Result<Vec<String>, Error> {
-    let mut stmt = conn.prepare("SELECT tags FROM notes WHERE json_valid(tags) AND json_type(tags) = 'array'")?;
+    let mut stmt = conn.prepare("SELECT tags FROM notes WHERE deleted_at IS NULL AND json_valid(tags) AND json_type(tags) = 'array'")?;
     let rows_iter = stmt.query_map(params![], |row| row.get::<_, String>(0))?;
 
     // *** Attempt to fix E0277 by collecting results first ***
@@ -271,7 +1839,7 @@ pub fn get_all_tags_db(conn: &DbConnection) -> Result<Vec<String>, Error> {
                         tag_set.insert(tag);
                     }
                 } else {
-                     eprintln!("警告：无法从数据库解析标签 JSON：{}", tags_json);
+                     eprintln!("{}警告：无法从数据库解析标签 JSON：{}", request_id::tag(), tags_json);
                 }
             }
             Err(e) => {
@@ -284,24 +1852,48 @@ pub fn get_all_tags_db(conn: &DbConnection) -> Result<Vec<String>, Error> {
 }
 
 
-pub fn get_detailed_tags_db(conn: &DbConnection) -> Result<Vec<DetailedTag>, Error> {
-    let mut stmt = conn.prepare(
+// 实时扫描全部笔记的标签统计；标签计数汇总表（get_tag_counts_db）无法满足的过滤条件以及校验时使用
+pub fn get_detailed_tags_db(conn: &DbConnection, filter: TagStatsFilter, page: TagPage) -> Result<Vec<DetailedTag>, Error> {
+    let mut where_clause = String::from("n.deleted_at IS NULL AND json_valid(n.tags) AND json_type(n.tags) = 'array'");
+    let mut params_vec: Vec<Box<dyn ToSql>> = Vec::new();
+    if let Some(after) = filter.created_after {
+        where_clause.push_str(" AND n.created_at >= ?");
+        params_vec.push(Box::new(after));
+    }
+    if let Some(before) = filter.created_before {
+        where_clause.push_str(" AND n.created_at < ?");
+        params_vec.push(Box::new(before));
+    }
+    if let Some(prefix) = filter.prefix {
+        // instr 做字面前缀匹配，不受 LIKE 通配符影响
+        where_clause.push_str(" AND instr(jt.value, ?) = 1");
+        params_vec.push(Box::new(prefix));
+    }
+
+    let query_str = number_placeholders(&format!(
         r#"
         SELECT
             jt.value as tag_name,
-            COUNT(*) as count,
+            COUNT(DISTINCT n.id) as count,
             MAX(n.updated_at) as last_modified
         FROM
             notes n, json_each(n.tags) jt
-        WHERE json_valid(n.tags) AND json_type(n.tags) = 'array'
+        WHERE {}
         GROUP BY
             jt.value
         ORDER BY
-            count DESC;
-        "#
-    )?;
+            {}
+        LIMIT ? OFFSET ?;
+        "#,
+        where_clause,
+        page.sort.order_by("tag_name")
+    ));
+    params_vec.push(Box::new(page.limit.unwrap_or(-1)));
+    params_vec.push(Box::new(page.offset.unwrap_or(0)));
+    let mut stmt = conn.prepare(&query_str)?;
 
-    let tag_iter = stmt.query_map(params![], |row| {
+    let params_ref: Vec<&dyn ToSql> = params_vec.iter().map(|b| b.as_ref()).collect();
+    let tag_iter = stmt.query_map(&params_ref[..], |row| {
         let last_modified: Option<DateTime<Utc>> = row.get("last_modified")?;
         Ok(DetailedTag {
             name: row.get("tag_name")?,
@@ -310,23 +1902,219 @@ pub fn get_detailed_tags_db(conn: &DbConnection) -> Result<Vec<DetailedTag>, Err
         })
     })?;
 
-    let mut result = Vec::new();
-    for tag_result in tag_iter {
-        result.push(tag_result?);
-    }
-    Ok(result)
+    let mut result = Vec::new();
+    for tag_result in tag_iter {
+        result.push(tag_result?);
+    }
+    Ok(result)
+}
+
+// 按实时扫描的结果重新填充 tag_counts，返回标签数
+fn rebuild_tag_counts(conn: &Connection) -> Result<usize, Error> {
+    conn.execute("DELETE FROM tag_counts", [])?;
+    conn.execute(
+        "INSERT INTO tag_counts (name, count, last_modified)
+         SELECT jt.value, COUNT(DISTINCT n.id), MAX(n.updated_at)
+         FROM notes n, json_each(n.tags) jt
+         WHERE n.deleted_at IS NULL AND json_valid(n.tags) AND json_type(n.tags) = 'array'
+         GROUP BY jt.value",
+        [],
+    )
+}
+
+// 在一个事务中从头重建标签计数汇总表（POST /inbox/admin/tag-counts/rebuild）
+pub fn rebuild_tag_counts_db(conn: &mut DbConnection) -> Result<TagCountsRebuild, Error> {
+    let tx = conn.transaction()?;
+    let tags = rebuild_tag_counts(&tx)?;
+    tx.commit()?;
+    Ok(TagCountsRebuild { tags })
+}
+
+// 从汇总表读取标签统计（次数相同时按名称排序），只支持前缀过滤；按创建时间过滤需要实时扫描
+pub fn get_tag_counts_db(conn: &DbConnection, prefix: Option<&str>, page: TagPage) -> Result<Vec<DetailedTag>, Error> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT name, count, last_modified FROM tag_counts
+         WHERE ?1 IS NULL OR instr(name, ?1) = 1
+         ORDER BY {}
+         LIMIT ?2 OFFSET ?3",
+        page.sort.order_by("name")
+    ))?;
+    let rows = stmt.query_map(params![prefix, page.limit.unwrap_or(-1), page.offset.unwrap_or(0)], |row| {
+        Ok(DetailedTag { name: row.get("name")?, count: row.get("count")?, last_modified: row.get("last_modified")? })
+    })?;
+    rows.collect()
+}
+
+// 按 '/' 分层的标签树（GET /inbox/tags/tree），同层按名称排序。
+// count 为直接使用该完整路径的笔记数，只作为中间层出现的路径为 0；空的层级（如 a//b 中间）被忽略
+pub fn get_tag_tree_db(conn: &DbConnection) -> Result<Vec<TagTreeNode>, Error> {
+    let mut roots = Vec::new();
+    for tag in get_tag_counts_db(conn, None, TagPage::default())? {
+        let segments: Vec<&str> = tag.name.split('/').filter(|segment| !segment.is_empty()).collect();
+        insert_tag_path(&mut roots, "", &segments, tag.count);
+    }
+    sort_tag_tree(&mut roots);
+    Ok(roots)
+}
+
+fn insert_tag_path(level: &mut Vec<TagTreeNode>, parent: &str, segments: &[&str], count: i64) {
+    let Some((name, rest)) = segments.split_first() else {
+        return;
+    };
+    let path = if parent.is_empty() { name.to_string() } else { format!("{}/{}", parent, name) };
+    let index = match level.iter().position(|node| node.name == *name) {
+        Some(index) => index,
+        None => {
+            level.push(TagTreeNode { name: name.to_string(), path: path.clone(), count: 0, children: Vec::new() });
+            level.len() - 1
+        }
+    };
+    let node = &mut level[index];
+    if rest.is_empty() {
+        node.count += count;
+    } else {
+        insert_tag_path(&mut node.children, &path, rest, count);
+    }
+}
+
+fn sort_tag_tree(level: &mut [TagTreeNode]) {
+    level.sort_by(|a, b| a.name.cmp(&b.name));
+    for node in level {
+        sort_tag_tree(&mut node.children);
+    }
+}
+
+// 每个标签的笔记数和内容总字节数（按存储的字节计，加密时为密文长度），字节数多的在前。
+// 一条笔记的内容会计入它的每个标签
+pub fn get_tag_usage_db(conn: &DbConnection) -> Result<Vec<TagUsage>, Error> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT
+            jt.value AS tag_name,
+            COUNT(*) AS count,
+            SUM(LENGTH(CAST(n.content AS BLOB))) AS bytes
+        FROM
+            notes n, json_each(n.tags) jt
+        WHERE n.deleted_at IS NULL AND json_valid(n.tags) AND json_type(n.tags) = 'array'
+        GROUP BY
+            jt.value
+        ORDER BY
+            bytes DESC, tag_name
+        "#,
+    )?;
+    let usage = stmt
+        .query_map([], |row| {
+            Ok(TagUsage {
+                name: row.get("tag_name")?,
+                count: row.get("count")?,
+                bytes: row.get("bytes")?,
+            })
+        })?
+        .collect::<Result<Vec<TagUsage>, Error>>()?;
+    Ok(usage)
+}
+
+// --- 标签改名 / 合并 ---
+// 受影响的笔记：未删除、且标签中含任一 sources 的笔记（id 升序）。预览与实际改写共用这一查询，保证两者一致
+fn select_tag_rewrite(conn: &DbConnection, sources: &[String]) -> Result<Vec<(i64, Vec<String>)>, Error> {
+    let sources_json = serde_json::to_string(sources).map_err(map_serde_error)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, tags FROM notes
+         WHERE deleted_at IS NULL AND json_valid(tags) AND json_type(tags) = 'array'
+           AND EXISTS (SELECT 1 FROM json_each(notes.tags) WHERE json_each.value IN (SELECT value FROM json_each(?1)))
+         ORDER BY id",
+    )?;
+    let rows = stmt.query_map(params![sources_json], |row| {
+        Ok((row.get::<_, i64>("id")?, parse_tags(row.get_ref("tags")?).unwrap_or_default()))
+    })?;
+    rows.collect()
+}
+
+// 改名/合并会修改的笔记 id，不写入任何数据
+pub fn preview_tag_rewrite_db(conn: &DbConnection, sources: &[String]) -> Result<Vec<i64>, Error> {
+    Ok(select_tag_rewrite(conn, sources)?.into_iter().map(|(id, _)| id).collect())
+}
+
+// 在一个事务中把 sources 中的标签替换为 target（已有 target 的笔记去重，保持原有顺序），
+// 并更新 updated_at。返回被修改的笔记 id
+pub fn rewrite_tags_db(conn: &mut DbConnection, sources: &[String], target: &str) -> Result<Vec<i64>, Error> {
+    let tx = conn.transaction()?;
+    let rows = select_tag_rewrite(&tx, sources)?;
+    let now = Utc::now();
+    {
+        let mut stmt = tx.prepare("UPDATE notes SET tags = ?1, updated_at = ?2 WHERE id = ?3")?;
+        for (id, tags) in &rows {
+            let mut rewritten: Vec<&str> = Vec::with_capacity(tags.len());
+            for tag in tags {
+                let tag = if sources.contains(tag) { target } else { tag.as_str() };
+                if !rewritten.contains(&tag) {
+                    rewritten.push(tag);
+                }
+            }
+            let tags_json = serde_json::to_string(&rewritten).map_err(map_serde_error)?;
+            stmt.execute(params![tags_json, now, id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(rows.into_iter().map(|(id, _)| id).collect())
+}
+
+// 与 tag 共同出现的其它标签，按共同出现的笔记数降序（同数按名称）
+pub fn get_related_tags_db(conn: &DbConnection, tag: &str, limit: i64) -> Result<Vec<RelatedTag>, Error> {
+    let mut stmt = conn.prepare_cached(
+        r#"
+        SELECT other.value AS tag_name, COUNT(DISTINCT n.id) AS count
+        FROM notes n, json_each(n.tags) target, json_each(n.tags) other
+        WHERE n.deleted_at IS NULL AND json_valid(n.tags) AND json_type(n.tags) = 'array'
+          AND target.value = ?1 AND other.value != ?1
+        GROUP BY other.value
+        ORDER BY count DESC, tag_name
+        LIMIT ?2
+        "#
+    )?;
+    let tags = stmt.query_map(params![tag, limit], |row| {
+        Ok(RelatedTag { name: row.get("tag_name")?, count: row.get("count")? })
+    })?;
+    tags.collect()
+}
+
+// 每个标签下最新的 per_tag 条笔记；标签按最近一条笔记的时间倒序，最多 max_tags 个。
+// 返回 (标签, 笔记) 列表，同一标签内按 created_at 倒序
+pub fn get_overview_db(conn: &DbConnection, per_tag: i64, max_tags: i64) -> Result<Vec<(String, Note)>, Error> {
+    let mut stmt = conn.prepare_cached(&format!(
+        r#"
+        WITH tagged AS (
+            SELECT t.value AS tag_name, n.id AS note_id,
+                   ROW_NUMBER() OVER (PARTITION BY t.value ORDER BY n.created_at DESC, n.id DESC) AS rn,
+                   MAX(n.created_at) OVER (PARTITION BY t.value) AS latest
+            FROM notes n, json_each(n.tags) t
+            WHERE n.deleted_at IS NULL AND json_valid(n.tags) AND json_type(n.tags) = 'array'
+        ),
+        top_tags AS (
+            SELECT DISTINCT tag_name, latest FROM tagged
+            ORDER BY latest DESC, tag_name
+            LIMIT ?2
+        )
+        SELECT tagged.tag_name AS overview_tag, {}
+        FROM tagged
+        JOIN top_tags ON top_tags.tag_name = tagged.tag_name
+        JOIN notes n ON n.id = tagged.note_id
+        WHERE tagged.rn <= ?1
+        ORDER BY top_tags.latest DESC, tagged.tag_name, tagged.rn
+        "#,
+        note_columns("n")
+    ))?;
+    let rows = stmt.query_map(params![per_tag, max_tags], |row| {
+        Ok((row.get("overview_tag")?, map_row_to_note(row)?))
+    })?;
+    rows.collect()
 }
 
 // --- 笔记关系操作 ---
 
 fn map_row_to_relation(row: &Row) -> Result<NoteRelation, Error> {
     let relation_type_str: String = row.get("relation_type")?;
-    let relation_type = match relation_type_str.as_str() {
-        "Comment" => NoteRelationType::Comment,
-        "Reference" => NoteRelationType::Reference,
-        "Link" => NoteRelationType::Link,
-        _ => NoteRelationType::Reference, // 默认值
-    };
+    let relation_type = str_to_relation_type(&relation_type_str);
 
     Ok(NoteRelation {
         id: row.get("id")?,
@@ -334,32 +2122,100 @@ fn map_row_to_relation(row: &Row) -> Result<NoteRelation, Error> {
         target_note_id: row.get("target_note_id")?,
         relation_type,
         created_at: row.get("created_at")?,
+        uid: row.get("uid")?,
     })
 }
 
+// 未知的类型字符串按 Reference 处理
+fn str_to_relation_type(relation_type: &str) -> NoteRelationType {
+    match relation_type {
+        "Comment" => NoteRelationType::Comment,
+        "Reference" => NoteRelationType::Reference,
+        "Link" => NoteRelationType::Link,
+        "Parent" => NoteRelationType::Parent,
+        _ => NoteRelationType::Reference, // 默认值
+    }
+}
+
+// 各关系类型的使用次数（按次数降序），未使用的已知类型计为 0
+pub fn get_relation_type_counts_db(conn: &DbConnection) -> Result<Vec<RelationTypeCount>, Error> {
+    let mut counts: Vec<RelationTypeCount> = [
+        NoteRelationType::Comment,
+        NoteRelationType::Reference,
+        NoteRelationType::Link,
+        NoteRelationType::Parent,
+    ]
+    .into_iter()
+    .map(|relation_type| RelationTypeCount { relation_type, count: 0 })
+    .collect();
+
+    let mut stmt = conn.prepare(
+        "SELECT relation_type, COUNT(*) AS count FROM note_relations WHERE deleted_at IS NULL GROUP BY relation_type"
+    )?;
+    let rows = stmt.query_map(params![], |row| {
+        Ok((row.get::<_, String>("relation_type")?, row.get::<_, i64>("count")?))
+    })?;
+    for row in rows {
+        let (relation_type, count) = row?;
+        let relation_type = str_to_relation_type(&relation_type);
+        if let Some(entry) = counts.iter_mut().find(|c| c.relation_type == relation_type) {
+            entry.count += count;
+        }
+    }
+
+    // 稳定排序：次数相同时保持上面的已知类型顺序
+    counts.sort_by_key(|c| std::cmp::Reverse(c.count));
+    Ok(counts)
+}
+
+// 指定年份内每天创建的笔记数，按日期升序；没有笔记的日期不返回，由客户端补齐
+pub fn get_activity_db(conn: &DbConnection, year: i32) -> Result<Vec<ActivityDay>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT strftime('%Y-%m-%d', created_at) AS date, COUNT(*) AS count
+         FROM notes
+         WHERE deleted_at IS NULL AND strftime('%Y', created_at) = ?1
+         GROUP BY date
+         ORDER BY date",
+    )?;
+    let rows = stmt.query_map(params![format!("{:04}", year)], |row| {
+        Ok(ActivityDay { date: row.get("date")?, count: row.get("count")? })
+    })?;
+    rows.collect()
+}
+
+fn relation_type_to_str(relation_type: &NoteRelationType) -> &'static str {
+    match relation_type {
+        NoteRelationType::Comment => "Comment",
+        NoteRelationType::Reference => "Reference",
+        NoteRelationType::Link => "Link",
+        NoteRelationType::Parent => "Parent",
+    }
+}
+
+fn note_exists(conn: &Connection, note_id: i64) -> Result<bool, Error> {
+    Ok(conn.query_row(
+        "SELECT 1 FROM notes WHERE id = ? AND deleted_at IS NULL LIMIT 1",
+        params![note_id],
+        |_| Ok(true)
+    ).optional()?.unwrap_or(false))
+}
+
 // 获取指向特定笔记的所有关系
 pub fn get_relations_for_note_db(conn: &DbConnection, note_id: i64, relation_type: Option<NoteRelationType>) -> Result<Vec<NoteRelation>, Error> {
     let mut query = String::from(
-        "SELECT id, source_note_id, target_note_id, relation_type, created_at 
+        "SELECT id, source_note_id, target_note_id, relation_type, created_at, uid
          FROM note_relations 
-         WHERE target_note_id = ?"
+         WHERE target_note_id = ? AND deleted_at IS NULL"
     );
     
     let mut params_vec: Vec<Box<dyn ToSql>> = Vec::new();
     params_vec.push(Box::new(note_id));
     
-    let relation_type_str = match &relation_type {
-        Some(rt) => match rt {
-            NoteRelationType::Comment => Some("Comment"),
-            NoteRelationType::Reference => Some("Reference"),
-            NoteRelationType::Link => Some("Link"),
-        },
-        None => None,
-    };
+    let relation_type_str = relation_type.as_ref().map(relation_type_to_str);
     
-    if relation_type_str.is_some() {
+    if let Some(rt) = relation_type_str {
         query.push_str(" AND relation_type = ?");
-        params_vec.push(Box::new(relation_type_str.unwrap()));
+        params_vec.push(Box::new(rt));
     }
     
     query.push_str(" ORDER BY created_at");
@@ -377,28 +2233,32 @@ pub fn get_relations_for_note_db(conn: &DbConnection, note_id: i64, relation_typ
     Ok(relations)
 }
 
+// 获取从特定笔记出发的所有关系（该笔记为源笔记）
+pub fn get_outgoing_relations_db(conn: &DbConnection, note_id: i64) -> Result<Vec<NoteRelation>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, source_note_id, target_note_id, relation_type, created_at, uid
+         FROM note_relations
+         WHERE source_note_id = ?1 AND deleted_at IS NULL
+         ORDER BY created_at"
+    )?;
+    let relations = stmt.query_map(params![note_id], map_row_to_relation)?;
+    relations.collect()
+}
+
 // 获取特定笔记的所有评论（作为关系的源笔记）
 pub fn get_comments_for_note_db(conn: &DbConnection, note_id: i64) -> Result<Vec<(Note, NoteRelation)>, Error> {
-    let mut stmt = conn.prepare(
-        "SELECT n.id, n.content, n.tags, n.created_at, n.updated_at, 
-                r.id as relation_id, r.source_note_id, r.target_note_id, r.relation_type, r.created_at as relation_created_at
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, 
+                r.id as relation_id, r.source_note_id, r.target_note_id, r.relation_type, r.created_at as relation_created_at, r.uid as relation_uid
          FROM notes n
          JOIN note_relations r ON n.id = r.source_note_id
-         WHERE r.target_note_id = ? AND r.relation_type = 'Comment'
-         ORDER BY r.created_at"
-    )?;
+         WHERE r.target_note_id = ? AND r.relation_type = 'Comment' AND r.deleted_at IS NULL AND n.deleted_at IS NULL
+         ORDER BY r.created_at",
+        note_columns("n")
+    ))?;
     
     let results_iter = stmt.query_map(params![note_id], |row| {
-        let tags_json: String = row.get("tags")?;
-        let tags: Vec<String> = serde_json::from_str(&tags_json).map_err(map_serde_error)?;
-        
-        let note = Note {
-            id: row.get("id")?,
-            content: row.get("content")?,
-            tags,
-            created_at: row.get("created_at")?,
-            updated_at: row.get("updated_at")?,
-        };
+        let note = map_row_to_note(row)?;
         
         let relation = NoteRelation {
             id: row.get("relation_id")?,
@@ -406,6 +2266,7 @@ pub fn get_comments_for_note_db(conn: &DbConnection, note_id: i64) -> Result<Vec
             target_note_id: row.get("target_note_id")?,
             relation_type: NoteRelationType::Comment,
             created_at: row.get("relation_created_at")?,
+            uid: row.get("relation_uid")?,
         };
         
         Ok((note, relation))
@@ -419,60 +2280,241 @@ pub fn get_comments_for_note_db(conn: &DbConnection, note_id: i64) -> Result<Vec
     Ok(results)
 }
 
+// 笔记的评论数（与 get_comments_for_note_db 一致，不计已删除的评论）
+pub fn count_comments_for_note_db(conn: &DbConnection, note_id: i64) -> Result<i64, Error> {
+    conn.query_row(
+        "SELECT COUNT(*)
+         FROM note_relations r
+         JOIN notes n ON n.id = r.source_note_id
+         WHERE r.target_note_id = ?1 AND r.relation_type = 'Comment' AND r.deleted_at IS NULL AND n.deleted_at IS NULL",
+        params![note_id],
+        |row| row.get(0),
+    )
+}
+
 // 创建笔记关系
-pub fn create_note_relation_db(conn: &mut DbConnection, source_note_id: i64, target_note_id: i64, payload: CreateNoteRelationPayload) -> Result<NoteRelation, Error> {
-    // 先检查两个笔记是否存在
-    let source_exists = conn.query_row(
-        "SELECT 1 FROM notes WHERE id = ? LIMIT 1",
-        params![source_note_id],
-        |_| Ok(true)
-    ).optional()?.unwrap_or(false);
-    
-    let target_exists = conn.query_row(
-        "SELECT 1 FROM notes WHERE id = ? LIMIT 1",
-        params![target_note_id],
-        |_| Ok(true)
-    ).optional()?.unwrap_or(false);
-    
-    if !source_exists || !target_exists {
-        return Err(Error::QueryReturnedNoRows);
+pub fn create_note_relation_db(conn: &mut DbConnection, source_note_id: i64, target_note_id: i64, payload: CreateNoteRelationPayload, max_relations: usize) -> Result<NoteRelation, CreateRelationError> {
+    // 先检查两个笔记是否存在，分别报告缺少的是哪一个
+    if !note_exists(conn, source_note_id)? {
+        return Err(CreateRelationError::SourceNotFound(source_note_id));
+    }
+    if !note_exists(conn, target_note_id)? {
+        return Err(CreateRelationError::TargetNotFound(target_note_id));
     }
     
-    let relation_type_str = match payload.relation_type {
-        NoteRelationType::Comment => "Comment",
-        NoteRelationType::Reference => "Reference",
-        NoteRelationType::Link => "Link",
-    };
-    
-    let created_at = Utc::now();
-    
+    if payload.relation_type == NoteRelationType::Parent && creates_parent_cycle(conn, source_note_id, target_note_id)? {
+        return Err(CreateRelationError::ParentCycle(source_note_id, target_note_id));
+    }
+
+    insert_relation(conn, source_note_id, target_note_id, payload.relation_type, Utc::now(), max_relations)
+}
+
+// 除撤销恢复外，所有关系都经由这里插入：非评论关系先检查两端笔记是否已达 INBOX_MAX_RELATIONS_PER_NOTE
+fn insert_relation(conn: &Connection, source_note_id: i64, target_note_id: i64, relation_type: NoteRelationType, created_at: DateTime<Utc>, max_relations: usize) -> Result<NoteRelation, CreateRelationError> {
+    if relation_type != NoteRelationType::Comment {
+        for note_id in [source_note_id, target_note_id] {
+            if relation_count(conn, note_id)? >= max_relations {
+                return Err(CreateRelationError::TooManyRelations(note_id, max_relations));
+            }
+        }
+    }
+
+    let uid = new_note_uid();
     conn.execute(
-        "INSERT INTO note_relations (source_note_id, target_note_id, relation_type, created_at) VALUES (?, ?, ?, ?)",
-        params![source_note_id, target_note_id, relation_type_str, created_at],
+        "INSERT INTO note_relations (source_note_id, target_note_id, relation_type, created_at, uid) VALUES (?, ?, ?, ?, ?)",
+        params![source_note_id, target_note_id, relation_type_to_str(&relation_type), created_at, uid],
     )?;
-    
-    let id = conn.last_insert_rowid();
-    
+
     Ok(NoteRelation {
-        id,
+        id: conn.last_insert_rowid(),
         source_note_id,
         target_note_id,
-        relation_type: payload.relation_type,
+        relation_type,
         created_at,
+        uid: Some(uid),
     })
 }
 
+// INBOX_AUTO_LINK 开启时传给创建/更新函数；同步创建的 Link 关系同样受 INBOX_MAX_RELATIONS_PER_NOTE 限制
+#[derive(Debug, Clone, Copy)]
+pub struct AutoLink {
+    pub max_relations: usize,
+}
+
+// INBOX_AUTO_LINK：让 note_id 发出的 Link 关系跟随正文中的 [[id]] 引用。
+// 新出现的引用补上关系（已有 Link 关系、引用自身、引用不存在的笔记或任一端已达关系上限时跳过）；
+// 从正文中删掉的引用只删除同步创建的 Link 关系（auto_link = 1），手动创建的关系保持不变
+fn sync_auto_links_tx(tx: &Connection, note_id: i64, old_content: Option<&str>, new_content: &str, auto_link: AutoLink) -> Result<(), Error> {
+    let new_refs = links::note_refs(new_content);
+    let old_refs = old_content.map(links::note_refs).unwrap_or_default();
+    for removed in old_refs.iter().filter(|id| !new_refs.contains(id)) {
+        tx.execute(
+            "DELETE FROM note_relations WHERE source_note_id = ?1 AND target_note_id = ?2 AND relation_type = 'Link' AND auto_link = 1",
+            params![note_id, removed],
+        )?;
+    }
+
+    let created_at = Utc::now();
+    for target in new_refs.into_iter().filter(|id| *id != note_id) {
+        let linked: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM note_relations WHERE source_note_id = ?1 AND target_note_id = ?2 AND relation_type = 'Link')",
+            params![note_id, target],
+            |row| row.get(0),
+        )?;
+        if linked || !note_exists(tx, target)? {
+            continue;
+        }
+        match insert_relation(tx, note_id, target, NoteRelationType::Link, created_at, auto_link.max_relations) {
+            Ok(relation) => {
+                tx.execute("UPDATE note_relations SET auto_link = 1 WHERE id = ?1", params![relation.id])?;
+            }
+            Err(CreateRelationError::Db(e)) => return Err(e),
+            // 两端笔记都已确认存在，其余错误只可能是达到关系上限：跳过这条引用
+            Err(_) => {}
+        }
+    }
+    Ok(())
+}
+
+// 笔记作为源或目标的未删除关系数（不含评论），用于 INBOX_MAX_RELATIONS_PER_NOTE
+fn relation_count(conn: &Connection, note_id: i64) -> Result<usize, Error> {
+    conn.prepare_cached(
+        "SELECT COUNT(*) FROM note_relations
+         WHERE (source_note_id = ?1 OR target_note_id = ?1) AND relation_type != 'Comment' AND deleted_at IS NULL",
+    )?
+    .query_row(params![note_id], |row| row.get::<_, i64>(0))
+    .map(|count| count as usize)
+}
+
+// 新增 source -> target 的 Parent 关系是否会形成环：即 source 是否已经是 target 自身或其祖先。
+// UNION 去重，已有数据中即使存在环（如外部写入）查询也会终止
+fn creates_parent_cycle(conn: &Connection, source_note_id: i64, target_note_id: i64) -> Result<bool, Error> {
+    conn.query_row(
+        "WITH RECURSIVE ancestors(id) AS (
+             SELECT ?1
+             UNION
+             SELECT r.target_note_id FROM note_relations r JOIN ancestors a ON r.source_note_id = a.id
+             WHERE r.relation_type = 'Parent'
+         )
+         SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = ?2)",
+        params![target_note_id, source_note_id],
+        |row| row.get(0),
+    )
+}
+
+// 从 root 出发沿关系（双向、任意类型）广度优先遍历至多 max_depth 层。记录已访问的笔记，
+// 因此关系中存在环时也会终止；指向已删除笔记的关系不返回。节点按距离、边按 id 排序
+pub fn get_note_graph_db(conn: &DbConnection, root: i64, max_depth: u32) -> Result<RelationGraph, Error> {
+    let mut nodes = vec![(root, 0)];
+    let mut visited = HashSet::from([root]);
+    let mut edges: HashMap<i64, NoteRelation> = HashMap::new();
+    let mut frontier = vec![root];
+    for depth in 1..=max_depth {
+        let mut next = Vec::new();
+        for note_id in frontier {
+            let mut relations = get_outgoing_relations_db(conn, note_id)?;
+            relations.extend(get_relations_for_note_db(conn, note_id, None)?);
+            for relation in relations {
+                let neighbor = if relation.source_note_id == note_id { relation.target_note_id } else { relation.source_note_id };
+                if edges.contains_key(&relation.id) || !note_exists(conn, neighbor)? {
+                    continue;
+                }
+                if visited.insert(neighbor) {
+                    nodes.push((neighbor, depth));
+                    next.push(neighbor);
+                }
+                edges.insert(relation.id, relation);
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        frontier = next;
+    }
+    let mut edges: Vec<NoteRelation> = edges.into_values().collect();
+    edges.sort_by_key(|relation| relation.id);
+    Ok(RelationGraph { nodes, edges })
+}
+
+// get_note_graph_db 的结果，对外 id 由调用方换算
+pub struct RelationGraph {
+    pub nodes: Vec<(i64, u32)>, // (笔记 id, 与起点之间的最少关系数)
+    pub edges: Vec<NoteRelation>,
+}
+
+// 批量创建关系：所有条目在同一事务中校验并插入，任一笔记不存在则整体回滚。
+// 每条为 (源笔记 id, 目标笔记 id, 关系类型)
+pub fn create_relations_bulk_db(conn: &mut DbConnection, entries: &[(i64, i64, NoteRelationType)], max_relations: usize) -> Result<Vec<NoteRelation>, BulkRelationError> {
+    let tx = conn.transaction()?;
+    let created_at = Utc::now();
+    let mut relations = Vec::with_capacity(entries.len());
+
+    for (index, (source_id, target_id, relation_type)) in entries.iter().enumerate() {
+        let (source_id, target_id) = (*source_id, *target_id);
+        if !note_exists(&tx, source_id)? {
+            return Err(BulkRelationError::MissingNote { index, role: "source", note_id: source_id });
+        }
+        if !note_exists(&tx, target_id)? {
+            return Err(BulkRelationError::MissingNote { index, role: "target", note_id: target_id });
+        }
+        // 同一批中先插入的关系也参与检查
+        if *relation_type == NoteRelationType::Parent && creates_parent_cycle(&tx, source_id, target_id)? {
+            return Err(BulkRelationError::ParentCycle { index, source_id, target_id });
+        }
+        let relation = insert_relation(&tx, source_id, target_id, relation_type.clone(), created_at, max_relations).map_err(|e| match e {
+            CreateRelationError::TooManyRelations(note_id, limit) => BulkRelationError::TooManyRelations { index, note_id, limit },
+            CreateRelationError::Db(e) => BulkRelationError::Db(e),
+            other => unreachable!("insert_relation only checks the relation cap: {}", other),
+        })?;
+        relations.push(relation);
+    }
+
+    tx.commit()?;
+    Ok(relations)
+}
+
+// 将评论提升为独立笔记：评论本身就是笔记，只需删除 Comment 关系
+pub fn promote_comment_db(conn: &mut DbConnection, relation_id: i64) -> Result<Note, PromoteCommentError> {
+    let tx = conn.transaction()?;
+
+    let relation = tx.query_row(
+        "SELECT id, source_note_id, target_note_id, relation_type, created_at, uid FROM note_relations WHERE id = ?1 AND deleted_at IS NULL",
+        params![relation_id],
+        map_row_to_relation,
+    ).optional()?.ok_or(PromoteCommentError::RelationNotFound(relation_id))?;
+
+    if relation.relation_type != NoteRelationType::Comment {
+        return Err(PromoteCommentError::NotAComment(relation_id));
+    }
+
+    tx.execute("DELETE FROM note_relations WHERE id = ?1", params![relation_id])?;
+    let note = get_note_db(&tx, relation.source_note_id)?.ok_or(Error::QueryReturnedNoRows)?;
+    tx.commit()?;
+
+    Ok(note)
+}
+
 // 添加评论（创建一个笔记并建立评论关系）
-pub fn add_comment_db(conn: &mut DbConnection, target_note_id: i64, payload: CreateCommentPayload) -> Result<(Note, NoteRelation), Error> {
+pub fn add_comment_db(
+    conn: &mut DbConnection,
+    target_note_id: i64,
+    mut payload: CreateCommentPayload,
+    max_relations: usize,
+    options: NoteWriteOptions,
+) -> Result<(Note, NoteRelation), WriteNoteError> {
+    if options.normalize_whitespace {
+        payload.content = normalize_whitespace(&payload.content);
+    }
+    check_tag_limits(payload.tags.as_deref().unwrap_or_default(), &options)?;
     // 检查目标笔记是否存在
     let target_exists = conn.query_row(
-        "SELECT 1 FROM notes WHERE id = ? LIMIT 1",
+        "SELECT 1 FROM notes WHERE id = ? AND deleted_at IS NULL LIMIT 1",
         params![target_note_id],
         |_| Ok(true)
     ).optional()?.unwrap_or(false);
     
     if !target_exists {
-        return Err(Error::QueryReturnedNoRows);
+        return Err(Error::QueryReturnedNoRows.into());
     }
     
     // 开始事务
@@ -484,20 +2526,24 @@ pub fn add_comment_db(conn: &mut DbConnection, target_note_id: i64, payload: Cre
     let tags = payload.tags.unwrap_or_default();
     let tags_json = serde_json::to_string(&tags).map_err(map_serde_error)?;
     
+    let (stored_content, nonce) = seal_content(&tx, &payload.content)?;
+    let slug = resolve_slug(&tx, None, &payload.content, nonce.is_some())?;
+    let uid = new_note_uid();
     tx.execute(
-        "INSERT INTO notes (content, tags, created_at, updated_at) VALUES (?, ?, ?, ?)",
-        params![payload.content, tags_json, created_at, updated_at],
+        "INSERT INTO notes (content, tags, created_at, updated_at, content_nonce, slug, uid) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![stored_content, tags_json, created_at, updated_at, nonce, slug, uid],
     )?;
     
     let comment_note_id = tx.last_insert_rowid();
     
-    // 2. 创建评论关系
-    tx.execute(
-        "INSERT INTO note_relations (source_note_id, target_note_id, relation_type, created_at) VALUES (?, ?, ?, ?)",
-        params![comment_note_id, target_note_id, "Comment", created_at],
-    )?;
-    
-    let relation_id = tx.last_insert_rowid();
+    // 2. 创建评论关系（评论不计入关系上限）
+    let relation = insert_relation(&tx, comment_note_id, target_note_id, NoteRelationType::Comment, created_at, max_relations).map_err(|e| match e {
+        CreateRelationError::Db(e) => e,
+        other => unreachable!("comment relations are not capped: {}", other),
+    })?;
+    if let Some(auto_link) = options.auto_link {
+        sync_auto_links_tx(&tx, comment_note_id, None, &payload.content, auto_link)?;
+    }
     
     // 提交事务
     tx.commit()?;
@@ -510,13 +2556,266 @@ pub fn add_comment_db(conn: &mut DbConnection, target_note_id: i64, payload: Cre
             tags,
             created_at,
             updated_at,
+            color: None,
+            slug,
+            lang: None,
+            uid: Some(uid),
+            pinned: false,
+            archived_at: None,
         },
-        NoteRelation {
-            id: relation_id,
-            source_note_id: comment_note_id,
-            target_note_id,
-            relation_type: NoteRelationType::Comment,
+        relation,
+    ))
+}
+
+// 创建新笔记并从 source_note_id 指向它（任意关系类型），同一事务内完成；
+// 源笔记不存在或已达关系上限时回滚，不留下孤立的新笔记。正文规范化和 [[id]] 自动关联按 options，与 create_note_db 一致
+pub fn create_linked_note_db(
+    conn: &mut DbConnection,
+    source_note_id: i64,
+    mut payload: CreateNotePayload,
+    relation_type: NoteRelationType,
+    max_relations: usize,
+    options: NoteWriteOptions,
+) -> Result<(Note, NoteRelation), CreateRelationError> {
+    if options.normalize_whitespace {
+        payload.content = normalize_whitespace(&payload.content);
+    }
+    let tx = conn.transaction()?;
+    if !note_exists(&tx, source_note_id)? {
+        return Err(CreateRelationError::SourceNotFound(source_note_id));
+    }
+
+    let created_at = payload.created_at.unwrap_or_else(Utc::now);
+    let tags = payload.tags.unwrap_or_default();
+    let tags_json = serde_json::to_string(&tags).map_err(map_serde_error)?;
+    let (stored_content, nonce) = seal_content(&tx, &payload.content)?;
+    let slug = resolve_slug(&tx, payload.slug, &payload.content, nonce.is_some())?;
+    let uid = new_note_uid();
+    tx.execute(
+        "INSERT INTO notes (content, tags, created_at, updated_at, color, content_nonce, slug, lang, uid) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![stored_content, tags_json, created_at, created_at, payload.color, nonce, slug, payload.lang, uid],
+    )?;
+    let target_note_id = tx.last_insert_rowid();
+
+    let relation = insert_relation(&tx, source_note_id, target_note_id, relation_type, created_at, max_relations)?;
+    if let Some(auto_link) = options.auto_link {
+        sync_auto_links_tx(&tx, target_note_id, None, &payload.content, auto_link)?;
+    }
+    tx.commit()?;
+
+    Ok((
+        Note {
+            id: target_note_id,
+            content: payload.content,
+            tags,
             created_at,
-        }
+            updated_at: created_at,
+            color: payload.color,
+            slug,
+            lang: payload.lang,
+            uid: Some(uid),
+            pinned: false,
+            archived_at: None,
+        },
+        relation,
     ))
-}
\ No newline at end of file
+}
+
+// --- 模板操作 ---
+
+fn map_row_to_template(row: &Row) -> Result<Template, Error> {
+    let tags_json: String = row.get("tags")?;
+    let tags: Vec<String> = serde_json::from_str(&tags_json).map_err(map_serde_error)?;
+
+    Ok(Template {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        content: row.get("content")?,
+        tags,
+    })
+}
+
+pub fn create_template_db(conn: &mut DbConnection, payload: TemplatePayload) -> Result<Template, Error> {
+    let tags = payload.tags.unwrap_or_default();
+    let tags_json = serde_json::to_string(&tags).map_err(map_serde_error)?;
+
+    conn.execute(
+        "INSERT INTO templates (name, content, tags) VALUES (?1, ?2, ?3)",
+        params![payload.name, payload.content, tags_json],
+    )?;
+
+    Ok(Template {
+        id: conn.last_insert_rowid(),
+        name: payload.name,
+        content: payload.content,
+        tags,
+    })
+}
+
+pub fn get_templates_db(conn: &DbConnection) -> Result<Vec<Template>, Error> {
+    let mut stmt = conn.prepare("SELECT id, name, content, tags FROM templates ORDER BY name")?;
+    let templates_iter = stmt.query_map(params![], map_row_to_template)?;
+
+    let mut templates = Vec::new();
+    for template_result in templates_iter {
+        templates.push(template_result?);
+    }
+    Ok(templates)
+}
+
+pub fn get_template_db(conn: &DbConnection, template_id: i64) -> Result<Option<Template>, Error> {
+    conn.query_row(
+        "SELECT id, name, content, tags FROM templates WHERE id = ?1",
+        params![template_id],
+        map_row_to_template,
+    ).optional()
+}
+
+pub fn update_template_db(conn: &mut DbConnection, template_id: i64, payload: TemplatePayload) -> Result<Option<Template>, Error> {
+    let tags_json = serde_json::to_string(&payload.tags.unwrap_or_default())
+        .map_err(map_serde_error)?;
+
+    let rows_affected = conn.execute(
+        "UPDATE templates SET name = ?1, content = ?2, tags = ?3 WHERE id = ?4",
+        params![payload.name, payload.content, tags_json, template_id],
+    )?;
+
+    if rows_affected == 0 {
+        Ok(None)
+    } else {
+        get_template_db(conn, template_id)
+    }
+}
+
+pub fn delete_template_db(conn: &mut DbConnection, template_id: i64) -> Result<bool, Error> {
+    let rows_affected = conn.execute(
+        "DELETE FROM templates WHERE id = ?1",
+        params![template_id],
+    )?;
+    Ok(rows_affected > 0)
+}
+
+// --- 维护操作 ---
+
+// 重建所有索引；若存在 FTS5 虚表则一并执行 'rebuild'（手工修改数据库或批量导入后使用）
+pub fn reindex_db(conn: &mut DbConnection) -> Result<ReindexSummary, Error> {
+    let indexes = query_names(
+        conn,
+        "SELECT name FROM sqlite_master WHERE type = 'index' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let fts_tables = query_names(
+        conn,
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND sql LIKE '%USING fts%' ORDER BY name",
+    )?;
+
+    let tx = conn.transaction()?;
+    tx.execute_batch("REINDEX;")?;
+    for table in &fts_tables {
+        tx.execute(&format!("INSERT INTO \"{0}\"(\"{0}\") VALUES ('rebuild')", table), [])?;
+    }
+    tx.commit()?;
+
+    Ok(ReindexSummary { indexes, fts_tables })
+}
+
+// 清空笔记与关系（模板保留），自增 id 从 1 重新开始；存在 FTS5 虚表时一并重建
+pub fn reset_db(conn: &mut DbConnection) -> Result<ResetSummary, Error> {
+    let fts_tables = query_names(
+        conn,
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND sql LIKE '%USING fts%' ORDER BY name",
+    )?;
+
+    let tx = conn.transaction()?;
+    let relations = tx.execute("DELETE FROM note_relations", [])?;
+    tx.execute("DELETE FROM note_history", [])?;
+    let notes = tx.execute("DELETE FROM notes", [])?;
+    tx.execute("DELETE FROM sqlite_sequence WHERE name IN ('notes', 'note_relations', 'note_history')", [])?;
+    for table in &fts_tables {
+        tx.execute(&format!("INSERT INTO \"{0}\"(\"{0}\") VALUES ('rebuild')", table), [])?;
+    }
+    tx.commit()?;
+
+    Ok(ResetSummary { notes, relations })
+}
+
+// PRAGMA integrity_check 与 PRAGMA foreign_key_check 的结果；两者都没有发现问题时 ok 为 true
+pub fn check_integrity_db(conn: &DbConnection) -> Result<IntegrityReport, Error> {
+    let integrity_errors = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .filter(|line| !matches!(line.as_deref(), Ok("ok")))
+        .collect::<Result<Vec<String>, Error>>()?;
+    let foreign_key_violations = conn
+        .prepare("PRAGMA foreign_key_check")?
+        .query_map([], |row| {
+            Ok(ForeignKeyViolation { table: row.get(0)?, rowid: row.get(1)?, parent: row.get(2)? })
+        })?
+        .collect::<Result<Vec<ForeignKeyViolation>, Error>>()?;
+    Ok(IntegrityReport {
+        ok: integrity_errors.is_empty() && foreign_key_violations.is_empty(),
+        integrity_errors,
+        foreign_key_violations,
+    })
+}
+
+// 主数据库文件的路径；内存数据库为 None
+pub fn database_path(conn: &DbConnection) -> Result<Option<String>, Error> {
+    let file: String = conn.query_row("SELECT file FROM pragma_database_list WHERE name = 'main'", [], |row| row.get(0))?;
+    Ok(Some(file).filter(|file| !file.is_empty()))
+}
+
+// 连接池之外的只读连接，用于耗时的检查（不占用池中的连接）
+pub fn open_read_only(path: &str) -> Result<Connection, Error> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+}
+
+// 源或目标笔记已不存在的关系（外键约束曾被关闭时可能出现）。
+// 软删除的笔记行仍然存在，其关系不算孤立。
+const ORPHAN_RELATIONS_WHERE: &str =
+    "NOT EXISTS (SELECT 1 FROM notes WHERE notes.id = note_relations.source_note_id)
+     OR NOT EXISTS (SELECT 1 FROM notes WHERE notes.id = note_relations.target_note_id)";
+
+pub fn get_orphan_relations_db(conn: &DbConnection) -> Result<OrphanRelations, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, source_note_id, target_note_id, relation_type, created_at, uid
+         FROM note_relations WHERE {} ORDER BY id",
+        ORPHAN_RELATIONS_WHERE
+    ))?;
+    let relations = stmt
+        .query_map(params![], map_row_to_relation)?
+        .collect::<Result<Vec<NoteRelation>, Error>>()?;
+    Ok(OrphanRelations { count: relations.len(), relations })
+}
+
+pub fn prune_orphan_relations_db(conn: &mut DbConnection) -> Result<PruneSummary, Error> {
+    let tx = conn.transaction()?;
+    let pruned = tx.execute(&format!("DELETE FROM note_relations WHERE {}", ORPHAN_RELATIONS_WHERE), [])?;
+    tx.commit()?;
+    Ok(PruneSummary { pruned })
+}
+
+// 在一个事务中修复无法解析的 tags：是 JSON 数组时保留其中的字符串元素，否则置为 '[]'
+pub fn repair_bad_tags_db(conn: &mut DbConnection) -> Result<RepairSummary, Error> {
+    let tx = conn.transaction()?;
+    let bad = get_bad_tags_db(&tx)?;
+    for entry in &bad.notes {
+        let recovered: Vec<String> = match entry.tags.as_deref().map(serde_json::from_str::<serde_json::Value>) {
+            Some(Ok(serde_json::Value::Array(items))) => {
+                items.into_iter().filter_map(|item| item.as_str().map(str::to_string)).collect()
+            }
+            _ => Vec::new(),
+        };
+        let tags_json = serde_json::to_string(&recovered).map_err(map_serde_error)?;
+        tx.execute("UPDATE notes SET tags = ?1 WHERE id = ?2", params![tags_json, entry.id])?;
+    }
+    tx.commit()?;
+    Ok(RepairSummary { repaired: bad.count })
+}
+
+fn query_names(conn: &DbConnection, sql: &str) -> Result<Vec<String>, Error> {
+    let mut stmt = conn.prepare(sql)?;
+    let names = stmt
+        .query_map(params![], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>, Error>>()?;
+    Ok(names)
+}
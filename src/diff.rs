@@ -0,0 +1,36 @@
+// src/diff.rs
+use std::collections::BTreeSet;
+
+use similar::{ChangeTag, TextDiff};
+
+use crate::models::{DiffLine, Note, NoteDiff, NoteId, TagDiff};
+
+// --- 两条笔记的比较 ---
+// 内容按行比较；标签给出对称差（只在 a 中 / 只在 b 中）。a_id / b_id 为响应中使用的对外 id
+pub fn diff_notes(a: &Note, b: &Note, a_id: NoteId, b_id: NoteId) -> NoteDiff {
+    let lines = TextDiff::from_lines(&a.content, &b.content)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            op: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Delete => "delete",
+                ChangeTag::Insert => "insert",
+            },
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect();
+
+    let tags_a: BTreeSet<&String> = a.tags.iter().collect();
+    let tags_b: BTreeSet<&String> = b.tags.iter().collect();
+
+    NoteDiff {
+        a: a_id,
+        b: b_id,
+        identical: a.content == b.content && tags_a == tags_b,
+        lines,
+        tags: TagDiff {
+            only_a: tags_a.difference(&tags_b).map(|t| t.to_string()).collect(),
+            only_b: tags_b.difference(&tags_a).map(|t| t.to_string()).collect(),
+        },
+    }
+}
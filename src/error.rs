@@ -0,0 +1,82 @@
+// src/error.rs
+use rocket::http::Status;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::{catch, catchers, Catcher, Request};
+
+use crate::models::FieldError;
+
+// --- 带 JSON 错误信息的 API 错误 ---
+// 响应体形如 { "error": "...", "status": 422 }，便于客户端直接展示原因。
+// 仅返回状态码的旧处理函数仍可通过 From<Status> 与之混用。
+// 请求体校验失败时额外带上 "fields": [{ "field": ..., "message": ... }]。
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+    pub fields: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        ApiError { status, message: message.into(), fields: Vec::new() }
+    }
+
+    // 400，message 取第一个字段错误，便于只读 "error" 的客户端
+    pub fn validation(fields: Vec<FieldError>) -> Self {
+        let message = fields
+            .first()
+            .map(|f| f.message.clone())
+            .unwrap_or_else(|| "validation failed".to_string());
+        ApiError { status: Status::BadRequest, message, fields }
+    }
+}
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        ApiError::new(status, status.reason().unwrap_or("Unknown Error"))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut body = serde_json::json!({
+            "error": self.message,
+            "status": self.status.code,
+        });
+        if !self.fields.is_empty() {
+            body["fields"] = serde_json::json!(self.fields);
+        }
+        Response::build_from(Json(body).respond_to(request)?)
+            .status(self.status)
+            .ok()
+    }
+}
+
+// --- JSON 错误捕获器 ---
+// 未匹配的路由（含多余的尾部斜杠）、请求体无法解析以及内部错误默认返回 Rocket 的 HTML 页面，
+// 这里统一改为与 ApiError 相同的 { "error": ..., "status": ... } 格式。
+#[catch(404)]
+fn not_found(request: &Request<'_>) -> ApiError {
+    ApiError::new(Status::NotFound, format!("not found: {}", request.uri().path()))
+}
+
+#[catch(422)]
+fn unprocessable_entity() -> ApiError {
+    ApiError::from(Status::UnprocessableEntity)
+}
+
+#[catch(500)]
+fn internal_error() -> ApiError {
+    ApiError::from(Status::InternalServerError)
+}
+
+// 查询超时 (INBOX_QUERY_TIMEOUT_MS) 或连接池耗尽时返回 503
+#[catch(503)]
+fn service_unavailable() -> ApiError {
+    ApiError::new(Status::ServiceUnavailable, "database is busy or the query timed out, try again later")
+}
+
+pub fn json_catchers() -> Vec<Catcher> {
+    catchers![not_found, unprocessable_entity, internal_error, service_unavailable]
+}
@@ -0,0 +1,52 @@
+// src/export.rs
+use crate::models::NoteResponse;
+
+// --- 笔记列表的文本导出格式 ---
+
+// Markdown：每条笔记一个小节，标签以 #tag 形式列在正文后
+pub fn notes_to_markdown(notes: &[NoteResponse]) -> String {
+    let mut md = String::new();
+    for note in notes {
+        md.push_str(&note_to_markdown(note));
+        md.push_str("---\n\n");
+    }
+    md
+}
+
+// 单条笔记的 Markdown 小节（列表导出与 Accept: text/markdown 共用）
+pub fn note_to_markdown(note: &NoteResponse) -> String {
+    let mut md = format!("## Note {} ({})\n\n", note.id, note.created_at);
+    md.push_str(note.content.trim_end());
+    md.push_str("\n\n");
+    if !note.tags.is_empty() {
+        let tags: Vec<String> = note.tags.iter().map(|t| format!("#{}", t)).collect();
+        md.push_str(&tags.join(" "));
+        md.push_str("\n\n");
+    }
+    md
+}
+
+// CSV：标签以分号连接；字段含逗号、引号或换行时按 RFC 4180 加引号
+pub fn notes_to_csv(notes: &[NoteResponse]) -> String {
+    let mut csv = String::from("id,content,tags,created_at,updated_at\r\n");
+    for note in notes {
+        let row = [
+            note.id.to_string(),
+            escape_csv(&note.content),
+            escape_csv(&note.tags.join(";")),
+            escape_csv(&note.created_at),
+            escape_csv(&note.updated_at),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
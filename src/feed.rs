@@ -0,0 +1,72 @@
+// src/feed.rs
+use chrono::Utc;
+use crate::models::{Note, NoteId};
+
+// 条目标题取内容首行，过长时截断
+const ENTRY_TITLE_MAX_CHARS: usize = 80;
+
+// --- Atom 订阅源生成 ---
+// feed_id 需保持稳定，阅读器用它识别同一个订阅源；public_id 给出条目中使用的对外笔记 id
+pub fn notes_to_atom(feed_id: &str, title: &str, self_path: &str, notes: &[Note], public_id: impl Fn(&Note) -> NoteId) -> String {
+    // 订阅源的更新时间取最近一次修改的笔记，没有笔记时用当前时间
+    let updated = notes
+        .iter()
+        .map(|note| note.updated_at)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <id>{}</id>\n", escape_xml(feed_id)));
+    xml.push_str(&format!("  <title>{}</title>\n", escape_xml(title)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    xml.push_str(&format!("  <link rel=\"self\" href=\"{}\"/>\n", escape_xml(self_path)));
+    xml.push_str("  <author><name>aw-inbox</name></author>\n");
+
+    for note in notes {
+        let id = public_id(note);
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>urn:aw-inbox:note:{}</id>\n", id));
+        xml.push_str(&format!("    <title>{}</title>\n", escape_xml(&entry_title(&note.content))));
+        xml.push_str(&format!("    <link href=\"/inbox/notes/{}\"/>\n", id));
+        xml.push_str(&format!("    <published>{}</published>\n", note.created_at.to_rfc3339()));
+        xml.push_str(&format!("    <updated>{}</updated>\n", note.updated_at.to_rfc3339()));
+        for tag in &note.tags {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", escape_xml(tag)));
+        }
+        xml.push_str(&format!("    <content type=\"text\">{}</content>\n", escape_xml(&note.content)));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn entry_title(content: &str) -> String {
+    let first_line = content.lines().next().unwrap_or("").trim();
+    if first_line.chars().count() > ENTRY_TITLE_MAX_CHARS {
+        let truncated: String = first_line.chars().take(ENTRY_TITLE_MAX_CHARS).collect();
+        format!("{}…", truncated)
+    } else {
+        first_line.to_string()
+    }
+}
+
+// 转义 XML 特殊字符（同时适用于文本节点和属性值）
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // XML 1.0 不允许除制表/换行/回车以外的控制字符，直接丢弃
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
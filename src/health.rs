@@ -0,0 +1,20 @@
+// src/health.rs
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// --- 就绪状态 ---
+// 进程启动后先迁移数据库，迁移完成前 /inbox/readyz 返回 503，
+// 编排系统（如 Kubernetes）据此暂缓转发流量。
+#[derive(Default)]
+pub struct Readiness {
+    migrated: AtomicBool,
+}
+
+impl Readiness {
+    pub fn mark_migrated(&self) {
+        self.migrated.store(true, Ordering::Release);
+    }
+
+    pub fn is_migrated(&self) -> bool {
+        self.migrated.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,21 @@
+// src/lang.rs
+use isolang::Language;
+
+// --- 笔记语言 ---
+// 统一使用 ISO 639-1 两字母代码（如 en、zh）；客户端未指定时按内容自动检测。
+
+pub fn is_valid_code(code: &str) -> bool {
+    code.len() == 2 && Language::from_639_1(code).is_some()
+}
+
+// 检测结果不可靠（如内容过短）时返回 None
+pub fn detect(content: &str) -> Option<String> {
+    let info = whatlang::detect(content).filter(|info| info.is_reliable())?;
+    let code = match info.lang().code() {
+        // whatlang 用具体语言（普通话、伊朗波斯语）的三字母代码，对应的两字母代码属于其宏语言
+        "cmn" => "zh",
+        "pes" => "fa",
+        other => Language::from_639_3(other)?.to_639_1()?,
+    };
+    Some(code.to_string())
+}
@@ -1,64 +1,348 @@
 // src/lib.rs 或 src/main.rs
-use rocket::{Build, Rocket, get, post, put, delete, routes, State};
+use rocket::{Build, Rocket, get, head, post, put, delete, options, routes, State};
 use rocket::serde::json::Json;
-use rocket::http::Status;
+use rocket::http::{ContentType, Header, Status};
+use rocket::http::uri::Origin;
 // Remove unused NotFound import
 use rocket::response::status::Created;
-use std::sync::Arc;
-use std::sync::Mutex; // Use std::sync::Mutex
+use rocket::response::{self, Responder};
+use rocket::response::stream::TextStream;
+use rocket::Request;
+use rocket::request::{FromParam, FromRequest, Outcome};
+use rocket::fairing::AdHoc;
 use tokio::task; // For spawn_blocking
-use rocket::form::FromForm;
+use rocket::form::{Form, FromForm};
+use rocket::fs::TempFile;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+use rocket::data::{Data, Limits, ToByteUnit};
+use sha2::{Digest, Sha256};
 
 pub mod db;
-mod models;
+pub mod models;
+pub mod config;
+pub mod trash;
+pub mod checkpoint;
+pub mod namespace;
+mod access;
+mod feed;
+mod compression;
+mod pretty;
+mod cors;
+mod metrics;
+mod error;
+mod validation;
+mod export;
+mod diff;
+mod auth;
+mod health;
+mod crypto;
+mod markdown_import;
+mod seed;
+mod undo;
+mod slow_log;
+mod lang;
+mod allow;
+mod request_id;
+mod bundle;
+mod links;
 // Ensure models.rs has correct Note/NoteResponse definitions (tags: Vec<String>)
-use models::{Note, CreateNotePayload, NoteResponse, DetailedTag};
-use crate::models::UpdateNotePayload;
+use models::{Note, NoteId, NoteKey, CreateNotePayload, NoteResponse, DetailedTag};
+use crate::models::{NoteMeta, NoteVersion, UpdateNotePayload, UpsertNotePayload, UpsertNoteResponse, FieldError};
 // 添加评论相关模型
-use crate::models::{NoteRelation, NoteRelationType, CreateNoteRelationPayload, CreateCommentPayload};
+use crate::models::{NoteRelation, RelationResponse, NoteRelationType, CreateNoteRelationPayload, CreateCommentPayload, BulkRelationEntry, RelationTypeCount, NoteGraph, GraphNode, TagUsage, CreateLinkedNotePayload, LinkedNoteResponse, UndoResponse, RelatedTag, TagOverview};
+use crate::models::{Template, TemplatePayload, ReindexSummary, ImportSummary, StreamImportSummary, NoteDiff, BatchGetResponse, PoolStats, MergeNotesPayload, TriagePayload, TriageSummary, TagRenamePayload, TagMergePayload, TagRewriteSummary, CountResponse, FullNoteResponse, ActivityDay, OrphanRelations, PruneSummary, LinkedSearchResponse, PurgeSummary, ResetSummary, BadTagsReport, RepairSummary, TagCountsRebuild, ServiceInfo, AllowedTags, IntegrityReport, TagTreeNode, BrokenLinks};
+use crate::auth::AdminKey;
+use crate::config::{IdScheme, InboxConfig, LocationId, RouteClass};
+use crate::namespace::{Db, Namespaces, RequestPool};
+use crate::metrics::Metrics;
+use crate::health::Readiness;
+use crate::error::ApiError;
 // 删除未使用的导入
 // use crate::db::DbConnection;
 
 // --- Use correct DbConnection type ---
-pub type SharedDb = Arc<Mutex<db::DbConnection>>;
+pub type SharedDb = db::DbPool;
 
 // --- note_to_response expects Note with tags: Vec<String> ---
-fn note_to_response(note: &Note) -> NoteResponse {
+fn note_to_response(note: &Note, config: &InboxConfig) -> NoteResponse {
     NoteResponse {
-        id: note.id,
+        id: public_note_id(note, config),
         content: note.content.clone(),
+        preview: content_preview(&note.content, config.preview_len),
         tags: note.tags.clone(), // Directly clone Vec<String>
         created_at: note.created_at.to_rfc3339(),
         updated_at: note.updated_at.to_rfc3339(),
+        color: note.color.clone(),
+        slug: note.slug.clone(),
+        lang: note.lang.clone(),
+        pinned: note.pinned,
+        archived_at: note.archived_at.map(|at| at.to_rfc3339()),
     }
 }
 
+// API 中的笔记 id：uuid 模式下为 uid（缺失时回退为整数 id，正常情况下启动时已补齐）
+fn public_note_id(note: &Note, config: &InboxConfig) -> NoteId {
+    match (config.id_scheme, &note.uid) {
+        (IdScheme::Uuid, Some(uid)) => NoteId::Uuid(uid.clone()),
+        _ => NoteId::Int(note.id),
+    }
+}
+
+// 响应中引用的其它笔记（及关系）的 id 换成对外 id：整数模式下原样返回；
+// uuid 模式下先用 db::note_uids_db 一次查出涉及笔记的 uid
+struct PublicIds {
+    scheme: IdScheme,
+    uids: std::collections::HashMap<i64, String>,
+}
+
+impl PublicIds {
+    fn load(conn: &db::DbConnection, scheme: IdScheme, ids: impl IntoIterator<Item = i64>) -> Result<Self, rusqlite::Error> {
+        let uids = match scheme {
+            IdScheme::Integer => std::collections::HashMap::new(),
+            IdScheme::Uuid => db::note_uids_db(conn, &ids.into_iter().collect::<Vec<i64>>())?,
+        };
+        Ok(PublicIds { scheme, uids })
+    }
+
+    fn for_relations(conn: &db::DbConnection, scheme: IdScheme, relations: &[NoteRelation]) -> Result<Self, rusqlite::Error> {
+        Self::load(conn, scheme, relations.iter().flat_map(|relation| [relation.source_note_id, relation.target_note_id]))
+    }
+
+    // 查不到 uid（笔记已被彻底删除）时回退为整数 id，同 public_note_id
+    fn note(&self, id: i64) -> NoteId {
+        match self.uids.get(&id) {
+            Some(uid) => NoteId::Uuid(uid.clone()),
+            None => NoteId::Int(id),
+        }
+    }
+
+    fn relation(&self, relation: &NoteRelation) -> RelationResponse {
+        let id = match (self.scheme, &relation.uid) {
+            (IdScheme::Uuid, Some(uid)) => NoteId::Uuid(uid.clone()),
+            _ => NoteId::Int(relation.id),
+        };
+        RelationResponse {
+            id,
+            source_note_id: self.note(relation.source_note_id),
+            target_note_id: self.note(relation.target_note_id),
+            relation_type: relation.relation_type.clone(),
+            created_at: relation.created_at,
+        }
+    }
+
+    fn relations(&self, relations: &[NoteRelation]) -> Vec<RelationResponse> {
+        relations.iter().map(|relation| self.relation(relation)).collect()
+    }
+}
+
+// 新建笔记的 Location：按 INBOX_LOCATION_ID 指向 /inbox/n/<slug> 或 /inbox/notes/<id>，两者都能解析到这条笔记
+fn note_location(note: &Note, config: &InboxConfig) -> String {
+    match (config.location_id, &note.slug) {
+        (LocationId::Slug, Some(slug)) => format!("/inbox/n/{}", slug),
+        _ => format!("/inbox/notes/{}", public_note_id(note, config)),
+    }
+}
+
+// 路径（及查询参数）中的笔记标识：纯数字按整数 id 解析，否则必须是 UUID（统一为小写带连字符的形式）
+impl<'a> FromParam<'a> for NoteKey {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        if let Ok(id) = param.parse::<i64>() {
+            return Ok(NoteKey::Id(id));
+        }
+        uuid::Uuid::parse_str(param)
+            .map(|uid| NoteKey::Uid(uid.hyphenated().to_string()))
+            .map_err(|_| param)
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> rocket::form::FromFormField<'v> for NoteKey {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        NoteKey::from_param(field.value).map_err(|value| rocket::form::Error::validation(format!("invalid note id {:?}", value)).into())
+    }
+}
+
+// 列表展示用的摘要：前 max_chars 个字符（按 Unicode 字符计），截断时加省略号
+fn content_preview(content: &str, max_chars: usize) -> String {
+    match content.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", content[..end].trim_end()),
+        None => content.to_string(),
+    }
+}
+
+// 校验语言过滤参数（ISO 639-1，不区分大小写），非法值返回 400
+fn validate_lang(lang: Option<String>) -> Result<Option<String>, Status> {
+    match lang {
+        Some(l) => {
+            let code = l.trim().to_ascii_lowercase();
+            if lang::is_valid_code(&code) {
+                Ok(Some(code))
+            } else {
+                eprintln!("[WARN] 非法的语言代码: {}", l);
+                Err(Status::BadRequest)
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+// 校验并规范化颜色过滤参数，非法值返回 400
+fn validate_color(color: Option<String>) -> Result<Option<String>, Status> {
+    match color {
+        Some(c) => validation::normalize_color(&c).map(Some).ok_or_else(|| {
+            eprintln!("[WARN] 非法的颜色值: {}", c);
+            Status::BadRequest
+        }),
+        None => Ok(None),
+    }
+}
+
+// 解析时间过滤参数：RFC 3339 或 Unix 时间戳（秒），两者都无法解析时返回 400
+fn parse_time_filter(name: &str, value: Option<String>) -> Result<Option<chrono::DateTime<chrono::Utc>>, Status> {
+    let v = match value {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let parsed = match v.trim().parse::<i64>() {
+        Ok(secs) => chrono::DateTime::from_timestamp(secs, 0),
+        Err(_) => chrono::DateTime::parse_from_rfc3339(v.trim()).ok().map(|t| t.with_timezone(&chrono::Utc)),
+    };
+    parsed.map(Some).ok_or_else(|| {
+        eprintln!("[WARN] 非法的 {} 取值: {}", name, v);
+        Status::BadRequest
+    })
+}
+
+// --- 字段投影 (?fields=id,content,tags) ---
+const NOTE_FIELDS: &[&str] = &["id", "content", "tags", "created_at", "updated_at", "color", "slug", "lang", "preview", "pinned", "archived_at"];
+
+// 解析逗号分隔的字段列表，出现白名单以外的字段时返回 400
+fn parse_note_fields(raw: &str) -> Result<Vec<String>, Status> {
+    let fields: Vec<String> = raw
+        .split(',')
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty())
+        .collect();
+
+    if fields.is_empty() {
+        return Err(Status::BadRequest);
+    }
+    if let Some(unknown) = fields.iter().find(|f| !NOTE_FIELDS.contains(&f.as_str())) {
+        eprintln!("[WARN] 未知的投影字段: {}", unknown);
+        return Err(Status::BadRequest);
+    }
+    Ok(fields)
+}
+
+// 未指定 fields 时返回完整的 NoteResponse
+fn project_note_fields(response: &NoteResponse, fields: Option<&[String]>) -> Result<serde_json::Value, Status> {
+    let mut value = serde_json::to_value(response).map_err(|_| Status::InternalServerError)?;
+    if let (Some(fields), Some(object)) = (fields, value.as_object_mut()) {
+        object.retain(|key, _| fields.iter().any(|f| f == key));
+    }
+    Ok(value)
+}
+
 // --- 辅助函数处理 DB 错误 (uses rusqlite::Error) ---
 fn handle_db_error(db_err: rusqlite::Error) -> Status { // Use full path
     let msg = format!("DB function failed: {:?}", db_err);
-    eprintln!("[ERROR] {}", msg);
+    eprintln!("[ERROR] {}{}", request_id::tag(), msg);
     match db_err {
         e if e.to_string().contains("no such table") => Status::BadRequest,
+        // 超过 INBOX_QUERY_TIMEOUT_MS 被中断的查询
+        ref e if db::is_query_timeout(e) => Status::ServiceUnavailable,
         // Use full path for QueryReturnedNoRows
         rusqlite::Error::QueryReturnedNoRows => Status::NotFound,
+        // 唯一约束等冲突（如模板重名）
+        rusqlite::Error::SqliteFailure(ref err, _) if err.code == rusqlite::ErrorCode::ConstraintViolation => Status::Conflict,
         _ => Status::InternalServerError,
     }
 }
 
+// 标签超出限制按字段校验错误返回 400，与请求体校验的响应一致
+fn handle_write_note_error(err: db::WriteNoteError) -> ApiError {
+    match err {
+        db::WriteNoteError::Db(db_err) => ApiError::from(handle_db_error(db_err)),
+        e => ApiError::validation(vec![FieldError::new("tags", e.to_string())]),
+    }
+}
+
+// 连接池取连接超时（连接全部被占用）
+fn handle_pool_error(pool_err: r2d2::Error) -> Status {
+    eprintln!("[ERROR] 无法从连接池获取连接: {:?}", pool_err);
+    Status::ServiceUnavailable
+}
+
 // --- 辅助函数处理 spawn_blocking 错误 (returns Status) ---
 fn handle_spawn_error(spawn_err: task::JoinError) -> Status { // Return Status directly
      eprintln!("[ERROR] Spawn blocking task failed: {:?}", spawn_err);
      Status::InternalServerError
 }
 
+// 在阻塞线程池中执行数据库操作，最长 timeout（按路由类别配置，见 InboxConfig::route_timeout），
+// 超时返回 503。期间执行的语句以 timeout 为上限被中断，不会在响应返回后继续长期占用连接
+async fn run_blocking<T, E>(timeout: Option<std::time::Duration>, f: impl FnOnce() -> Result<T, E> + Send + 'static) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<Status> + Send + 'static,
+{
+    let handle = task::spawn_blocking(move || db::with_statement_timeout(timeout, f));
+    let joined = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, handle).await.map_err(|_| {
+            eprintln!("[WARN] 数据库操作超过 {:?}，返回 503", timeout);
+            Status::ServiceUnavailable
+        })?,
+        None => handle.await,
+    };
+    joined.map_err(handle_spawn_error)?
+}
+
+
+// GET /tags/detailed 的查询参数
+#[derive(FromForm)]
+struct DetailedTagsQuery {
+    created_after: Option<String>,
+    created_before: Option<String>,
+    prefix: Option<String>,
+    live: Option<bool>,
+    // count（默认）、name 或 recent
+    sort: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
 
-#[get("/tags/detailed")]
-async fn get_detailed_tags(db_state: &State<SharedDb>) -> Result<Json<Vec<DetailedTag>>, Status> {
+// 标签统计，可按创建时间范围（同 GET /notes 的 created_after/created_before）和标签前缀缩小，
+// 按 sort 排序并用 limit/offset 分页。
+// 默认读取标签计数汇总表；指定时间范围或 live=true 时实时扫描全部笔记（用于校验汇总表）
+#[get("/tags/detailed?<query..>")]
+async fn get_detailed_tags(db_state: Db<'_>, query: DetailedTagsQuery) -> Result<Json<Vec<DetailedTag>>, Status> {
     let db_arc = db_state.inner().clone();
+    let filter = db::TagStatsFilter {
+        created_after: parse_time_filter("created_after", query.created_after)?,
+        created_before: parse_time_filter("created_before", query.created_before)?,
+        prefix: query.prefix.filter(|p| !p.is_empty()),
+    };
+    let sort = match query.sort.as_deref() {
+        Some(sort) => db::TagSort::parse(sort).ok_or(Status::BadRequest)?,
+        None => db::TagSort::default(),
+    };
+    let page = db::TagPage {
+        sort,
+        limit: query.limit.map(|limit| limit.max(0)),
+        offset: query.offset.map(|offset| offset.max(0)),
+    };
+    let live = query.live.unwrap_or(false) || filter.created_after.is_some() || filter.created_before.is_some();
 
     let tags = task::spawn_blocking(move || {
-        let conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        match db::get_detailed_tags_db(&conn) {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let tags = if live {
+            db::get_detailed_tags_db(&conn, filter, page)
+        } else {
+            db::get_tag_counts_db(&conn, filter.prefix.as_deref(), page)
+        };
+        match tags {
             Ok(tags) => Ok(tags),
             Err(e) => Err(handle_db_error(e))
         }
@@ -69,13 +353,119 @@ async fn get_detailed_tags(db_state: &State<SharedDb>) -> Result<Json<Vec<Detail
     Ok(Json(tags))
 }
 
+// --- 标签改名 / 合并 ---
+// 改名是只有一个来源的合并。/preview 返回将被修改的笔记但不写入，与实际操作使用同一选择逻辑
+
+#[post("/tags/rename", data = "<payload>", format = "json")]
+async fn rename_tag(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<TagRenamePayload>) -> Result<Json<TagRewriteSummary>, ApiError> {
+    let TagRenamePayload { from, to } = payload.into_inner();
+    rewrite_tags(db_state, config, vec![from], to, false).await
+}
+
+#[post("/tags/rename/preview", data = "<payload>", format = "json")]
+async fn rename_tag_preview(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<TagRenamePayload>) -> Result<Json<TagRewriteSummary>, ApiError> {
+    let TagRenamePayload { from, to } = payload.into_inner();
+    rewrite_tags(db_state, config, vec![from], to, true).await
+}
+
+#[post("/tags/merge", data = "<payload>", format = "json")]
+async fn merge_tags(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<TagMergePayload>) -> Result<Json<TagRewriteSummary>, ApiError> {
+    let TagMergePayload { sources, target } = payload.into_inner();
+    rewrite_tags(db_state, config, sources, target, false).await
+}
+
+#[post("/tags/merge/preview", data = "<payload>", format = "json")]
+async fn merge_tags_preview(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<TagMergePayload>) -> Result<Json<TagRewriteSummary>, ApiError> {
+    let TagMergePayload { sources, target } = payload.into_inner();
+    rewrite_tags(db_state, config, sources, target, true).await
+}
+
+// 标签按创建笔记时的规则规范化；来源为空、目标为空或过长、目标同时是来源时返回 400
+async fn rewrite_tags(db_state: Db<'_>, config: &InboxConfig, sources: Vec<String>, target: String, dry_run: bool) -> Result<Json<TagRewriteSummary>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let retries = config.busy_retries;
+    let sources = validation::normalize_tags(sources);
+    let target = match validation::normalize_tags(vec![target]).pop() {
+        Some(target) if target.chars().count() <= config.max_tag_len => target,
+        _ => return Err(ApiError::new(Status::BadRequest, format!("target tag must be 1 to {} characters", config.max_tag_len))),
+    };
+    if sources.is_empty() {
+        return Err(ApiError::new(Status::BadRequest, "no source tags given"));
+    }
+    if sources.contains(&target) {
+        return Err(ApiError::new(Status::BadRequest, format!("tag {:?} is both a source and the target", target)));
+    }
+    if validation::disallowed_tag(std::slice::from_ref(&target), config).is_some() {
+        return Err(ApiError::new(Status::BadRequest, format!("tag {:?} is not in INBOX_ALLOWED_TAGS", target)));
+    }
+
+    let id_scheme = config.id_scheme;
+
+    let note_ids = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let note_ids = if dry_run {
+            db::preview_tag_rewrite_db(&conn, &sources)
+        } else {
+            db::with_busy_retry(retries, || db::rewrite_tags_db(&mut conn, &sources, &target))
+        }
+        .map_err(handle_db_error)?;
+        let public_ids = PublicIds::load(&conn, id_scheme, note_ids.iter().copied()).map_err(handle_db_error)?;
+        Ok::<_, Status>(note_ids.into_iter().map(|id| public_ids.note(id)).collect::<Vec<NoteId>>())
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(TagRewriteSummary { count: note_ids.len(), note_ids }))
+}
+
+// 允许使用的标签（INBOX_ALLOWED_TAGS）；未限制时 restricted 为 false、tags 为空
+#[get("/tags/allowed")]
+fn get_allowed_tags(config: &State<InboxConfig>) -> Json<AllowedTags> {
+    Json(AllowedTags {
+        restricted: config.allowed_tags.is_some(),
+        tags: config.allowed_tags.clone().unwrap_or_default(),
+    })
+}
+
+// 按 '/' 分层的标签树，例如 work/projects/x 嵌套在 work 和 work/projects 下
+#[get("/tags/tree")]
+async fn get_tag_tree(db_state: Db<'_>) -> Result<Json<Vec<TagTreeNode>>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let tree = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_tag_tree_db(&conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(tree))
+}
+
+// 各标签的笔记数与内容字节数，字节数多的在前（归档前找出占用最大的标签）
+#[get("/tags/usage")]
+async fn get_tag_usage(db_state: Db<'_>) -> Result<Json<Vec<TagUsage>>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let usage = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_tag_usage_db(&conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(usage))
+}
+
 
 #[get("/tags")]
-async fn get_tags(db_state: &State<SharedDb>) -> Result<Json<Vec<String>>, Status> {
+async fn get_tags(db_state: Db<'_>) -> Result<Json<Vec<String>>, Status> {
     let db_arc = db_state.inner().clone();
 
     task::spawn_blocking(move || {
-        let conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
+        let conn = db_arc.get().map_err(handle_pool_error)?;
         db::get_all_tags_db(&conn)
             .map_err(handle_db_error)
     })
@@ -84,211 +474,2305 @@ async fn get_tags(db_state: &State<SharedDb>) -> Result<Json<Vec<String>>, Statu
     .map(Json)
 }
 
+// 按输出格式 (json/md/csv) 序列化笔记列表
+fn render_notes(notes: &[NoteResponse], format: Option<&str>) -> Result<(ContentType, String), Status> {
+    match format.unwrap_or("json") {
+        "json" => serde_json::to_string(notes)
+            .map(|body| (ContentType::JSON, body))
+            .map_err(|_| Status::InternalServerError),
+        "md" | "markdown" => Ok((ContentType::new("text", "markdown"), export::notes_to_markdown(notes))),
+        "csv" => Ok((ContentType::CSV, export::notes_to_csv(notes))),
+        _ => Err(Status::BadRequest),
+    }
+}
+
+// 经常与 name 一起出现的标签（用于标签推荐），默认返回前 20 个
+#[get("/tags/<name>/related?<limit>")]
+async fn get_related_tags(db_state: Db<'_>, config: &State<InboxConfig>, name: String, limit: Option<i64>) -> Result<Json<Vec<RelatedTag>>, Status> {
+    let db_arc = db_state.inner().clone();
+    let limit = limit.unwrap_or(20).clamp(0, config.max_limit);
+
+    let tags = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_related_tags_db(&conn, &name, limit)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(tags))
+}
+
+// 某个标签下的笔记（等价于 /notes?tag=<name>），标签从未使用过时返回 404
+#[get("/tags/<name>/notes?<limit>&<offset>&<format>")]
+async fn get_tag_notes(db_state: Db<'_>, config: &State<InboxConfig>, name: String, limit: Option<i64>, offset: Option<i64>, format: Option<String>) -> Result<(ContentType, String), Status> {
+    let db_arc = db_state.inner().clone();
+    // 与 /notes?tag= 相同：未指定 limit 时使用 INBOX_DEFAULT_LIMIT，不超过 INBOX_MAX_LIMIT
+    let limit = limit.unwrap_or(config.default_limit).clamp(0, config.max_limit);
+    let filter = db::NoteFilter { tag: Some(name), ..Default::default() };
+
+    let notes = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_notes_db(&conn, Some(limit), offset, filter)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    // 翻页越界或 limit=0 时返回空列表，只有第一页为空才说明标签不存在
+    if notes.is_empty() && offset.unwrap_or(0) == 0 && limit > 0 {
+        return Err(Status::NotFound);
+    }
+
+    let response: Vec<NoteResponse> = notes.iter().map(|note| note_to_response(note, config)).collect();
+    render_notes(&response, format.as_deref())
+}
+
+const OVERVIEW_DEFAULT_PER_TAG: i64 = 3;
+const OVERVIEW_MAX_PER_TAG: i64 = 20;
+const OVERVIEW_MAX_TAGS: i64 = 20;
+
+// 仪表盘总览：最近活跃的标签（最多 OVERVIEW_MAX_TAGS 个）各自最新的 per_tag 条笔记，
+// 标签按最近一条笔记的时间倒序排列（用数组而不是对象，保证客户端看到的顺序）
+#[get("/overview?<per_tag>")]
+async fn get_overview(db_state: Db<'_>, config: &State<InboxConfig>, per_tag: Option<i64>) -> Result<Json<Vec<TagOverview>>, Status> {
+    let db_arc = db_state.inner().clone();
+    let per_tag = per_tag.unwrap_or(OVERVIEW_DEFAULT_PER_TAG).clamp(1, OVERVIEW_MAX_PER_TAG);
+
+    let rows = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_overview_db(&conn, per_tag, OVERVIEW_MAX_TAGS)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    // 同一标签的行是连续的，按出现顺序分组即可保持标签的排序
+    let mut overview: Vec<TagOverview> = Vec::new();
+    for (tag, note) in &rows {
+        let note = note_to_response(note, config);
+        match overview.last_mut() {
+            Some(last) if last.tag == *tag => last.notes.push(note),
+            _ => overview.push(TagOverview { tag: tag.clone(), notes: vec![note] }),
+        }
+    }
+    Ok(Json(overview))
+}
+
 // 获取笔记的评论
 #[get("/notes/<note_id>/comments")]
-async fn get_comments(db_state: &State<SharedDb>, note_id: i64) -> Result<Json<Vec<NoteResponse>>, Status> {
+async fn get_comments(db_state: Db<'_>, config: &State<InboxConfig>, note_id: NoteKey) -> Result<Json<Vec<NoteResponse>>, Status> {
     let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
     
     let comments_with_relations = task::spawn_blocking(move || {
-        let conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::get_comments_for_note_db(&conn, note_id)
-            .map_err(handle_db_error)
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::with_read_tx(&conn, |conn| {
+            let note_id = db::resolve_note_key(conn, &note_id, id_scheme)?;
+            db::get_comments_for_note_db(conn, note_id)
+        })
+        .map_err(handle_db_error)
     })
     .await
     .map_err(handle_spawn_error)??;
     
     // 转换为NoteResponse，只返回笔记部分
     let response = comments_with_relations.iter()
-        .map(|(note, _relation)| note_to_response(note))
+        .map(|(note, _relation)| note_to_response(note, config))
         .collect();
         
     Ok(Json(response))
 }
 
+// 评论数（用于显示 "N 条评论"，无需下载全部评论）；没有评论或笔记不存在时为 0
+#[get("/notes/<note_id>/comments/count")]
+async fn count_comments(db_state: Db<'_>, config: &State<InboxConfig>, note_id: NoteKey) -> Result<Json<CountResponse>, Status> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+
+    let count = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let note_id = match db::resolve_note_key(&conn, &note_id, id_scheme) {
+            Ok(note_id) => note_id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(0),
+            Err(e) => return Err(handle_db_error(e)),
+        };
+        db::count_comments_for_note_db(&conn, note_id)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(CountResponse { count }))
+}
+
 // 添加评论
 #[post("/notes/<note_id>/comments", data = "<payload>", format = "json")]
-async fn add_comment(db_state: &State<SharedDb>, note_id: i64, payload: Json<CreateCommentPayload>) -> Result<Created<Json<NoteResponse>>, Status> {
+async fn add_comment(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, note_id: NoteKey, payload: Json<CreateCommentPayload>) -> Result<Created<Json<NoteResponse>>, ApiError> {
     let db_arc = db_state.inner().clone();
-    let comment_payload = payload.into_inner();
+    let comment_payload = validation::validate_comment_payload(payload.into_inner(), config)
+        .map_err(ApiError::validation)?;
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+    let max_relations = config.max_relations_per_note;
+    let write_options = config.note_write_options();
+    let location = format!("/inbox/notes/{}/comments", note_id);
     
     let (created_note, _relation) = task::spawn_blocking(move || {
-        let mut conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::add_comment_db(&mut conn, note_id, comment_payload)
-            .map_err(handle_db_error)
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let note_id = db::resolve_note_key(&conn, &note_id, id_scheme).map_err(handle_db_error)?;
+        db::with_busy_retry(retries, || db::add_comment_db(&mut conn, note_id, comment_payload.clone(), max_relations, write_options))
+            .map_err(handle_write_note_error)
     })
     .await
     .map_err(handle_spawn_error)??;
+    metrics.notes_created.inc();
     
-    Ok(Created::new(format!("/inbox/notes/{}/comments", note_id))
-       .body(Json(note_to_response(&created_note))))
+    Ok(Created::new(location)
+       .body(Json(note_to_response(&created_note, config))))
+}
+
+// 将评论提升为独立笔记（删除评论关系，保留笔记本身）
+#[post("/comments/<relation_id>/promote")]
+async fn promote_comment(db_state: Db<'_>, config: &State<InboxConfig>, relation_id: NoteKey) -> Result<Json<NoteResponse>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+
+    let note = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let relation_id = match db::resolve_relation_key(&conn, &relation_id, id_scheme) {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::new(Status::NotFound, format!("relation {} does not exist", relation_id))),
+            Err(e) => return Err(ApiError::from(handle_db_error(e))),
+        };
+        db::promote_comment_db(&mut conn, relation_id).map_err(|e| match e {
+            db::PromoteCommentError::RelationNotFound(_) => ApiError::new(Status::NotFound, e.to_string()),
+            db::PromoteCommentError::NotAComment(_) => ApiError::new(Status::Conflict, e.to_string()),
+            db::PromoteCommentError::Db(db_err) => ApiError::from(handle_db_error(db_err)),
+        })
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(note_to_response(&note, config)))
 }
 
 // 创建笔记关系
 #[post("/notes/<source_id>/relations/<target_id>", data = "<payload>", format = "json")]
-async fn create_relation(db_state: &State<SharedDb>, source_id: i64, target_id: i64, payload: Json<CreateNoteRelationPayload>) -> Result<Created<Json<NoteRelation>>, Status> {
+async fn create_relation(db_state: Db<'_>, config: &State<InboxConfig>, source_id: NoteKey, target_id: NoteKey, payload: Json<CreateNoteRelationPayload>) -> Result<Created<Json<RelationResponse>>, ApiError> {
     let db_arc = db_state.inner().clone();
     let relation_payload = payload.into_inner();
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+    let max_relations = config.max_relations_per_note;
+    let location = format!("/inbox/notes/{}/relations/{}", source_id, target_id);
     
     let created_relation = task::spawn_blocking(move || {
-        let mut conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::create_note_relation_db(&mut conn, source_id, target_id, relation_payload)
-            .map_err(handle_db_error)
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let resolve = |key: &NoteKey, role: &str| {
+            db::resolve_note_key(&conn, key, id_scheme).map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => ApiError::new(Status::UnprocessableEntity, format!("{} note {} does not exist", role, key)),
+                e => ApiError::from(handle_db_error(e)),
+            })
+        };
+        let (source, target) = (resolve(&source_id, "source")?, resolve(&target_id, "target")?);
+        // 错误信息中使用请求里的标识，uuid 模式下不暴露内部 id
+        let key_of = |note_id: i64| if note_id == source { &source_id } else { &target_id };
+        let relation = db::with_busy_retry(retries, || db::create_note_relation_db(&mut conn, source, target, relation_payload.clone(), max_relations))
+            .map_err(|e| match e {
+                db::CreateRelationError::Db(db_err) => ApiError::from(handle_db_error(db_err)),
+                db::CreateRelationError::ParentCycle(..) => ApiError::new(Status::Conflict, format!("Parent relation {} -> {} would form a cycle", source_id, target_id)),
+                db::CreateRelationError::TooManyRelations(note_id, limit) => ApiError::new(
+                    Status::BadRequest,
+                    format!("note {} already has {} relations (INBOX_MAX_RELATIONS_PER_NOTE)", key_of(note_id), limit),
+                ),
+                db::CreateRelationError::SourceNotFound(_) => ApiError::new(Status::UnprocessableEntity, format!("source note {} does not exist", source_id)),
+                db::CreateRelationError::TargetNotFound(_) => ApiError::new(Status::UnprocessableEntity, format!("target note {} does not exist", target_id)),
+            })?;
+        let public_ids = PublicIds::for_relations(&conn, id_scheme, std::slice::from_ref(&relation)).map_err(handle_db_error)?;
+        Ok::<_, ApiError>(public_ids.relation(&relation))
     })
     .await
     .map_err(handle_spawn_error)??;
     
-    Ok(Created::new(format!("/inbox/notes/{}/relations/{}", source_id, target_id))
+    Ok(Created::new(location)
        .body(Json(created_relation)))
 }
 
-// 获取笔记的所有关系
-#[get("/notes/<note_id>/relations")]
-async fn get_relations(db_state: &State<SharedDb>, note_id: i64) -> Result<Json<Vec<NoteRelation>>, Status> {
+// 创建新笔记并从 source_id 关联到它（类似添加评论，但关系类型任意），源笔记不存在时返回 404
+#[post("/notes/<source_id>/link", data = "<payload>", format = "json")]
+async fn create_linked_note(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, source_id: NoteKey, payload: Json<CreateLinkedNotePayload>) -> Result<Created<Json<LinkedNoteResponse>>, ApiError> {
     let db_arc = db_state.inner().clone();
-    
-    let relations = task::spawn_blocking(move || {
-        let conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::get_relations_for_note_db(&conn, note_id, None)
-            .map_err(handle_db_error)
+    let CreateLinkedNotePayload { content, tags, relation_type } = payload.into_inner();
+    let note_payload = CreateNotePayload { content, tags, created_at: None, color: None, slug: None, lang: None };
+    let note_payload = validation::validate_create_payload(note_payload, config).map_err(ApiError::validation)?;
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+    let max_relations = config.max_relations_per_note;
+    let write_options = config.note_write_options();
+
+    let (note, relation) = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let source = db::resolve_note_key(&conn, &source_id, id_scheme).map_err(handle_db_error)?;
+        // 新笔记还没有任何关系，达到上限的只可能是源笔记
+        let (note, relation) = db::with_busy_retry(retries, || db::create_linked_note_db(&mut conn, source, note_payload.clone(), relation_type.clone(), max_relations, write_options))
+            .map_err(|e| match e {
+                db::CreateRelationError::Db(db_err) => ApiError::from(handle_db_error(db_err)),
+                db::CreateRelationError::TooManyRelations(_, limit) => ApiError::new(
+                    Status::BadRequest,
+                    format!("note {} already has {} relations (INBOX_MAX_RELATIONS_PER_NOTE)", source_id, limit),
+                ),
+                _ => ApiError::new(Status::NotFound, format!("source note {} does not exist", source_id)),
+            })?;
+        let public_ids = PublicIds::for_relations(&conn, id_scheme, std::slice::from_ref(&relation)).map_err(handle_db_error)?;
+        Ok::<_, ApiError>((note, public_ids.relation(&relation)))
     })
     .await
     .map_err(handle_spawn_error)??;
-    
-    Ok(Json(relations))
-}
+    metrics.notes_created.inc();
 
-// mount_rocket remains the same
-pub fn mount_rocket(rocket: Rocket<Build>, db: SharedDb) -> Rocket<Build> {
-    println!("[INFO] 开始注册 Inbox Server 路由...");
-    println!("[INFO] 注册数据库连接池 (同步包装)...");
-    let rocket = rocket.manage(db);
+    Ok(Created::new(note_location(&note, config))
+       .body(Json(LinkedNoteResponse { note: note_to_response(&note, config), relation })))
+}
 
-    println!("[INFO] 注册 API 路由:");
-    // ... (routes) ...
+// 批量创建关系（全部成功或全部回滚）
+#[post("/relations/bulk", data = "<payload>", format = "json")]
+async fn create_relations_bulk(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<Vec<BulkRelationEntry>>) -> Result<Created<Json<Vec<RelationResponse>>>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let entries = payload.into_inner();
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+    let max_relations = config.max_relations_per_note;
+    if entries.is_empty() {
+        return Err(ApiError::new(Status::BadRequest, "relation list must not be empty"));
+    }
 
-    let rocket = rocket.mount("/inbox", routes![
-        root,
-        create_note,
-        get_notes,
-        get_note,
-        update_note,
-        delete_note,
-        get_tags,
-        get_detailed_tags,
-        // 评论和关系相关路由
-        get_comments,
-        add_comment,
-        create_relation,
-        get_relations,
-    ]);
+    let created_relations = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let mut resolved = Vec::with_capacity(entries.len());
+        for (index, entry) in entries.iter().enumerate() {
+            let resolve = |key: &NoteKey, role: &str| match db::resolve_note_key(&conn, key, id_scheme) {
+                Ok(id) => Ok(id),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Err(ApiError::new(Status::UnprocessableEntity, format!("entry {}: {} note {} does not exist", index, role, key))),
+                Err(e) => Err(ApiError::from(handle_db_error(e))),
+            };
+            resolved.push((resolve(&entry.source_id, "source")?, resolve(&entry.target_id, "target")?, entry.relation_type.clone()));
+        }
+        // 错误信息中使用请求里的标识，uuid 模式下不暴露内部 id
+        let relations = db::with_busy_retry(retries, || db::create_relations_bulk_db(&mut conn, &resolved, max_relations)).map_err(|e| match e {
+            db::BulkRelationError::MissingNote { index, role, .. } => {
+                let key = if role == "source" { &entries[index].source_id } else { &entries[index].target_id };
+                ApiError::new(Status::UnprocessableEntity, format!("entry {}: {} note {} does not exist", index, role, key))
+            }
+            db::BulkRelationError::ParentCycle { index, .. } => ApiError::new(
+                Status::Conflict,
+                format!("entry {}: Parent relation {} -> {} would form a cycle", index, entries[index].source_id, entries[index].target_id),
+            ),
+            db::BulkRelationError::TooManyRelations { index, note_id, limit } => {
+                let key = if note_id == resolved[index].0 { &entries[index].source_id } else { &entries[index].target_id };
+                ApiError::new(Status::BadRequest, format!("entry {}: note {} already has {} relations (INBOX_MAX_RELATIONS_PER_NOTE)", index, key, limit))
+            }
+            db::BulkRelationError::Db(db_err) => ApiError::from(handle_db_error(db_err)),
+        })?;
+        let public_ids = PublicIds::for_relations(&conn, id_scheme, &relations).map_err(handle_db_error)?;
+        Ok::<_, ApiError>(public_ids.relations(&relations))
+    })
+    .await
+    .map_err(handle_spawn_error)??;
 
-    println!("[INFO] Inbox Server 路由注册完成");
-    rocket
+    Ok(Created::new("/inbox/relations/bulk").body(Json(created_relations)))
 }
 
-#[get("/")]
-fn root() -> &'static str {
-    "📥 Welcome to Inbox Inbox Server (Rust Version)"
+// 列出所有关系类型及其数量（关系版的 get_detailed_tags）
+#[get("/relations/types")]
+async fn get_relation_types(db_state: Db<'_>) -> Result<Json<Vec<RelationTypeCount>>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let counts = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_relation_type_counts_db(&conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(counts))
 }
 
-#[post("/notes", data = "<payload>", format = "json")]
-async fn create_note(db_state: &State<SharedDb>, payload: Json<CreateNotePayload>) -> Result<Created<Json<NoteResponse>>, Status> {
+// 日历热力图数据：指定年份（默认今年，UTC）每天创建的笔记数
+#[get("/activity?<year>")]
+async fn get_activity(db_state: Db<'_>, year: Option<i32>) -> Result<Json<Vec<ActivityDay>>, Status> {
     let db_arc = db_state.inner().clone();
-    let note_payload = payload.into_inner();
+    let year = year.unwrap_or_else(|| chrono::Datelike::year(&chrono::Utc::now()));
+    if !(1..=9999).contains(&year) {
+        return Err(Status::BadRequest);
+    }
 
-    let created_note = task::spawn_blocking(move || {
-        let mut conn_guard = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::create_note_db(&mut conn_guard, note_payload)
+    let days = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_activity_db(&conn, year)
             .map_err(handle_db_error)
     })
     .await
-    .map_err(handle_spawn_error)??; // Double '?' handles JoinError and then DB Result
+    .map_err(handle_spawn_error)??;
 
-    Ok(Created::new("/inbox/notes").body(Json(note_to_response(&created_note))))
+    Ok(Json(days))
 }
 
-#[derive(FromForm)]
-struct NotesQuery {
-    limit: Option<i64>,
-    offset: Option<i64>,
-    tag: Option<String>,
-    search: Option<String>,
-    sort_by: Option<String>,
+// --- 按时间段浏览（UTC）---
+// 边界在服务端计算后复用列表的 created_after/created_before 过滤，最新的在前，支持 limit/offset 分页；
+// 没有笔记的时间段返回空数组。年份超出 1..=9999、月份或 ISO 周号不存在时返回 400
+
+#[get("/archive/<year>?<limit>&<offset>")]
+async fn archive_year(db_state: Db<'_>, config: &State<InboxConfig>, year: i32, limit: Option<i64>, offset: Option<i64>) -> Result<Json<Vec<NoteResponse>>, Status> {
+    let start = archive_date(chrono::NaiveDate::from_ymd_opt(year, 1, 1))?;
+    let end = archive_date(chrono::NaiveDate::from_ymd_opt(year + 1, 1, 1))?;
+    archive_notes(db_state, config, start, end, limit, offset).await
 }
 
-#[get("/notes?<query..>")]
-async fn get_notes(db_state: &State<SharedDb>, query: NotesQuery) -> Result<Json<Vec<NoteResponse>>, Status> {
+#[get("/archive/<year>/<month>?<limit>&<offset>")]
+async fn archive_month(db_state: Db<'_>, config: &State<InboxConfig>, year: i32, month: u32, limit: Option<i64>, offset: Option<i64>) -> Result<Json<Vec<NoteResponse>>, Status> {
+    let start = archive_date(chrono::NaiveDate::from_ymd_opt(year, month, 1))?;
+    let end = archive_date(start.checked_add_months(chrono::Months::new(1)))?;
+    archive_notes(db_state, config, start, end, limit, offset).await
+}
+
+// ISO 8601 周：从该周的星期一开始，跨年的周按 ISO 周年计算（如 2021 年第 1 周从 2021-01-04 开始）
+#[get("/archive/<year>/week/<week>?<limit>&<offset>")]
+async fn archive_week(db_state: Db<'_>, config: &State<InboxConfig>, year: i32, week: u32, limit: Option<i64>, offset: Option<i64>) -> Result<Json<Vec<NoteResponse>>, Status> {
+    let start = archive_date(chrono::NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Mon))?;
+    let end = archive_date(start.checked_add_days(chrono::Days::new(7)))?;
+    archive_notes(db_state, config, start, end, limit, offset).await
+}
+
+fn archive_date(date: Option<chrono::NaiveDate>) -> Result<chrono::NaiveDate, Status> {
+    date.filter(|date| (1..=9999).contains(&chrono::Datelike::year(date))).ok_or(Status::BadRequest)
+}
+
+async fn archive_notes(
+    db_state: Db<'_>,
+    config: &InboxConfig,
+    start: chrono::NaiveDate,
+    end: chrono::NaiveDate,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Json<Vec<NoteResponse>>, Status> {
     let db_arc = db_state.inner().clone();
-    
-    // 接收查询参数
-    let limit = query.limit;
-    let tag = query.tag;
-    let search = query.search;
-    
+    let limit = limit.unwrap_or(config.default_limit).clamp(0, config.max_limit);
+    let offset = offset.unwrap_or(0).max(0);
+    let filter = db::NoteFilter {
+        created_after: Some(start.and_time(chrono::NaiveTime::MIN).and_utc()),
+        created_before: Some(end.and_time(chrono::NaiveTime::MIN).and_utc()),
+        ..Default::default()
+    };
+
     let notes = task::spawn_blocking(move || {
-        let conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::get_notes_db(&conn, limit, tag, None, None, search)
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_notes_db(&conn, Some(limit), Some(offset), filter)
             .map_err(handle_db_error)
     })
     .await
-    .map_err(handle_spawn_error)??; // Double '?'
+    .map_err(handle_spawn_error)??;
 
-    let response = notes.iter().map(note_to_response).collect();
-    Ok(Json(response))
+    Ok(Json(notes.iter().map(|note| note_to_response(note, config)).collect()))
 }
 
-
-#[get("/notes/<id>")]
-async fn get_note(db_state: &State<SharedDb>, id: i64) -> Result<Json<NoteResponse>, Status> {
+// 关联搜索：内容匹配 q 的笔记及其一跳关联笔记，每组最多 limit 条（默认 50，不超过 INBOX_MAX_LIMIT）
+#[get("/search/linked?<q>&<limit>")]
+async fn search_linked(db_state: Db<'_>, config: &State<InboxConfig>, q: String, limit: Option<i64>) -> Result<Json<LinkedSearchResponse>, Status> {
     let db_arc = db_state.inner().clone();
+    // 加密存储时无法在 SQL 中匹配内容
+    if q.trim().is_empty() || config.encryption_key.is_some() {
+        return Err(Status::BadRequest);
+    }
+    let limit = limit.unwrap_or(50).clamp(0, config.max_limit);
 
-    let maybe_note = task::spawn_blocking(move || {
-        let conn = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::get_note_db(&conn, id)
+    let (matched, linked) = run_blocking(config.route_timeout(RouteClass::Search), move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::search_linked_notes_db(&conn, &q, limit)
             .map_err(handle_db_error)
     })
-    .await
-    .map_err(handle_spawn_error)??; // Double '?'
+    .await?;
 
-    match maybe_note {
-        Some(note) => Ok(Json(note_to_response(&note))),
-        None => Err(Status::NotFound),
-    }
+    Ok(Json(LinkedSearchResponse {
+        matched: matched.iter().map(|note| note_to_response(note, config)).collect(),
+        linked: linked.iter().map(|note| note_to_response(note, config)).collect(),
+    }))
 }
 
-
-#[put("/notes/<id>", data = "<payload>", format = "json")]
-async fn update_note(db_state: &State<SharedDb>, id: i64, payload: Json<UpdateNotePayload>) -> Result<Json<NoteResponse>, Status> {
+// 从笔记出发按关系（双向、任意类型）遍历得到的子图，depth 默认 1，不超过 INBOX_MAX_RELATION_DEPTH。
+// 遍历记录已访问的笔记，关系成环时也会终止
+#[get("/notes/<id>/graph?<depth>")]
+async fn get_note_graph(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey, depth: Option<u32>) -> Result<Json<NoteGraph>, Status> {
     let db_arc = db_state.inner().clone();
-    let note_payload = payload.into_inner();
+    let id_scheme = config.id_scheme;
+    let depth = depth.unwrap_or(1).min(config.max_relation_depth);
 
-    let updated_note_option = task::spawn_blocking(move || {
-        let mut conn_guard = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::update_note_db(&mut conn_guard, id, note_payload)
-             .map_err(handle_db_error)
+    let graph = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        let graph = db::get_note_graph_db(&conn, id, depth).map_err(handle_db_error)?;
+        let public_ids = PublicIds::load(&conn, id_scheme, graph.nodes.iter().map(|(id, _)| *id)).map_err(handle_db_error)?;
+        Ok::<_, Status>(NoteGraph {
+            nodes: graph.nodes.into_iter().map(|(id, depth)| GraphNode { id: public_ids.note(id), depth }).collect(),
+            edges: public_ids.relations(&graph.edges),
+        })
     })
     .await
-    .map_err(handle_spawn_error)??; // Double '?'
+    .map_err(handle_spawn_error)??;
 
-    match updated_note_option {
-        Some(note) => Ok(Json(note_to_response(&note))),
+    Ok(Json(graph))
+}
+
+// 获取笔记的所有关系
+#[get("/notes/<note_id>/relations")]
+async fn get_relations(db_state: Db<'_>, config: &State<InboxConfig>, note_id: NoteKey) -> Result<Json<Vec<RelationResponse>>, Status> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+    
+    let relations = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let note_id = db::resolve_note_key(&conn, &note_id, id_scheme).map_err(handle_db_error)?;
+        let relations = db::get_relations_for_note_db(&conn, note_id, None).map_err(handle_db_error)?;
+        let public_ids = PublicIds::for_relations(&conn, id_scheme, &relations).map_err(handle_db_error)?;
+        Ok::<_, Status>(public_ids.relations(&relations))
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+    
+    Ok(Json(relations))
+}
+
+// --- 导出 / 导入 ---
+
+// 导出全部笔记（JSON 数组），可直接作为 POST /inbox/import 的请求体
+#[get("/export")]
+async fn export_notes(db_state: Db<'_>, config: &State<InboxConfig>) -> Result<Json<Vec<NoteResponse>>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let notes = run_blocking(config.route_timeout(RouteClass::Export), move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_notes_db(&conn, None, None, db::NoteFilter::default())
+            .map_err(handle_db_error)
+    })
+    .await?;
+
+    Ok(Json(notes.iter().map(|note| note_to_response(note, config)).collect()))
+}
+
+// 流式导出每批读取的笔记数，以及读取线程最多领先客户端的行数
+const EXPORT_STREAM_BATCH: i64 = 500;
+const EXPORT_STREAM_BUFFER: usize = 256;
+
+// 流式导出（NDJSON：每行一个与 GET /export 元素相同的笔记对象，按 id 升序），内存占用与笔记总数无关。
+// 读取线程分批读取并逐行发送；客户端断开后停止读取。中途出错时响应被截断并记录日志
+#[get("/export/stream")]
+async fn export_notes_stream(db_state: Db<'_>, config: &State<InboxConfig>) -> Result<(ContentType, TextStream![String]), Status> {
+    let db_arc = db_state.inner().clone();
+    let config = config.inner().clone();
+    // 先读出第一批，连接池耗尽或查询出错时仍能返回 503/500，而不是一个空的 200 响应
+    let pool = db_arc.clone();
+    let first = task::spawn_blocking(move || {
+        let conn = pool.get().map_err(handle_pool_error)?;
+        db::get_notes_page_after_db(&conn, 0, EXPORT_STREAM_BATCH).map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let (sender, mut receiver) = tokio::sync::mpsc::channel::<String>(EXPORT_STREAM_BUFFER);
+    task::spawn_blocking(move || {
+        let _scope = db_arc.enter();
+        let mut notes = first;
+        loop {
+            for note in &notes {
+                let line = match serde_json::to_string(&note_to_response(note, &config)) {
+                    Ok(json) => json + "\n",
+                    Err(e) => {
+                        eprintln!("[ERROR] {}流式导出中断: {:?}", request_id::tag(), e);
+                        return;
+                    }
+                };
+                if sender.blocking_send(line).is_err() {
+                    return; // 客户端已断开
+                }
+            }
+            let after_id = match notes.last() {
+                Some(last) if notes.len() as i64 == EXPORT_STREAM_BATCH => last.id,
+                _ => return,
+            };
+            // 每批单独取连接，读完即归还：客户端读得慢时发送会阻塞，期间不占用连接池
+            let next = db_arc
+                .get()
+                .map_err(|e| format!("{:?}", e))
+                .and_then(|conn| db::get_notes_page_after_db(&conn, after_id, EXPORT_STREAM_BATCH).map_err(|e| format!("{:?}", e)));
+            notes = match next {
+                Ok(notes) => notes,
+                Err(e) => {
+                    eprintln!("[ERROR] {}流式导出中断: {}", request_id::tag(), e);
+                    return;
+                }
+            };
+        }
+    });
+
+    let stream = TextStream! {
+        while let Some(line) = receiver.recv().await {
+            yield line;
+        }
+    };
+    Ok((ContentType::new("application", "x-ndjson"), stream))
+}
+
+// 解析导入文件。lenient 模式下严格 JSON 解析失败后再用 JSON5 解析，
+// 以容忍手工编辑留下的尾逗号和注释；入库的数据始终是规范 JSON。
+fn parse_import_body(body: &str, lenient: bool) -> Result<Vec<CreateNotePayload>, ApiError> {
+    match serde_json::from_str(body) {
+        Ok(payloads) => Ok(payloads),
+        Err(strict_err) if lenient => json5::from_str(body).map_err(|e| {
+            ApiError::new(Status::BadRequest, format!("invalid import file (JSON: {}; JSON5: {})", strict_err, e))
+        }),
+        Err(e) => Err(ApiError::new(Status::BadRequest, format!("invalid import file: {}", e))),
+    }
+}
+
+// 导入笔记（JSON 数组，字段同创建笔记），全部校验通过后在一个事务中写入
+#[post("/import?<lenient>", data = "<data>")]
+async fn import_notes(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, limits: &Limits, lenient: Option<bool>, data: Data<'_>) -> Result<Created<Json<ImportSummary>>, ApiError> {
+    let body = data
+        .open(limits.get("json").unwrap_or(1.mebibytes()))
+        .into_string()
+        .await
+        .map_err(|_| Status::BadRequest)?;
+    if !body.is_complete() {
+        return Err(ApiError::new(Status::PayloadTooLarge, "import file exceeds the JSON size limit"));
+    }
+
+    let payloads = parse_import_body(&body, lenient.unwrap_or(false))?
+        .into_iter()
+        .enumerate()
+        .map(|(index, payload)| {
+            validation::validate_create_payload(payload, config).map_err(|fields| {
+                let mut err = ApiError::validation(fields);
+                err.message = format!("entry {}: {}", index, err.message);
+                err
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let db_arc = db_state.inner().clone();
+    let auto_link = config.auto_link_settings();
+    let notes = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::create_notes_bulk_db(&mut conn, payloads, auto_link)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let ids: Vec<NoteId> = notes.iter().map(|note| public_note_id(note, config)).collect();
+    metrics.notes_created.inc_by(ids.len() as u64);
+    Ok(Created::new("/inbox/notes").body(Json(ImportSummary { imported: ids.len(), ids })))
+}
+
+// 流式导入每个事务写入的笔记数，未配置 ndjson 限制时请求体的上限，以及最多列出的跳过行号
+const IMPORT_STREAM_BATCH: usize = 500;
+const IMPORT_STREAM_DEFAULT_LIMIT_GIB: u64 = 1;
+const IMPORT_STREAM_MAX_REPORTED_LINES: usize = 1000;
+
+// 流式导入 NDJSON（GET /export/stream 的格式）：逐行读取请求体，每 IMPORT_STREAM_BATCH 条在一个事务中写入，
+// 不缓存整个请求体。无法解析或校验失败的行跳过并返回其行号（从 1 开始）；
+// 中途出错时此前的批次已经提交，错误信息中给出已导入的条数
+#[post("/import/stream", data = "<data>")]
+async fn import_notes_stream(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, limits: &Limits, data: Data<'_>) -> Result<Created<Json<StreamImportSummary>>, ApiError> {
+    let limit = limits.get("ndjson").unwrap_or(IMPORT_STREAM_DEFAULT_LIMIT_GIB.gibibytes());
+    let mut reader = tokio::io::BufReader::new(data.open(limit));
+    let mut summary = StreamImportSummary { imported: 0, skipped: 0, skipped_lines: Vec::new() };
+    let mut batch = Vec::with_capacity(IMPORT_STREAM_BATCH);
+    let auto_link = config.auto_link_settings();
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut bytes_read = 0u64;
+
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).await.map_err(|e| {
+            ApiError::new(Status::BadRequest, format!("line {}: {} ({} notes imported)", line_number + 1, e, summary.imported))
+        })?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        bytes_read += read as u64;
+        if bytes_read >= limit.as_u64() {
+            return Err(ApiError::new(
+                Status::PayloadTooLarge,
+                format!("request body exceeds the ndjson size limit ({} notes imported)", summary.imported),
+            ));
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let payload = serde_json::from_str::<CreateNotePayload>(&line)
+            .ok()
+            .and_then(|payload| validation::validate_create_payload(payload, config).ok());
+        match payload {
+            Some(payload) => batch.push(payload),
+            None => {
+                summary.skipped += 1;
+                if summary.skipped_lines.len() < IMPORT_STREAM_MAX_REPORTED_LINES {
+                    summary.skipped_lines.push(line_number);
+                }
+            }
+        }
+        if batch.len() >= IMPORT_STREAM_BATCH {
+            import_stream_batch(&db_state.inner(), std::mem::take(&mut batch), auto_link, &mut summary, metrics).await?;
+        }
+    }
+    if !batch.is_empty() {
+        import_stream_batch(&db_state.inner(), batch, auto_link, &mut summary, metrics).await?;
+    }
+
+    Ok(Created::new("/inbox/notes").body(Json(summary)))
+}
+
+async fn import_stream_batch(db: &RequestPool, payloads: Vec<CreateNotePayload>, auto_link: Option<db::AutoLink>, summary: &mut StreamImportSummary, metrics: &Metrics) -> Result<(), ApiError> {
+    let db_arc = db.clone();
+    let imported = summary.imported;
+    let count = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::create_notes_bulk_db(&mut conn, payloads, auto_link).map(|notes| notes.len()).map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map_err(|status| ApiError::new(status, format!("import failed after {} notes were imported", imported)))?;
+
+    summary.imported += count;
+    metrics.notes_created.inc_by(count as u64);
+    println!("[INFO] 流式导入：已写入 {} 条笔记", summary.imported);
+    Ok(())
+}
+
+// Markdown 文件批量导入（multipart/form-data）：每个 files 字段是一个 .md 文件。
+// 可选的 mtime 字段按顺序对应各文件（Unix 秒或 RFC 3339，留空表示未知），作为笔记的 created_at。
+#[derive(FromForm)]
+struct MarkdownUpload<'r> {
+    files: Vec<TempFile<'r>>,
+    mtime: Vec<String>,
+}
+
+#[post("/import/markdown", data = "<upload>")]
+async fn import_markdown(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, upload: Form<MarkdownUpload<'_>>) -> Result<Created<Json<ImportSummary>>, ApiError> {
+    let upload = upload.into_inner();
+    if upload.files.is_empty() {
+        return Err(ApiError::new(Status::BadRequest, "no files uploaded"));
+    }
+
+    let mut payloads = Vec::with_capacity(upload.files.len());
+    for (index, file) in upload.files.iter().enumerate() {
+        // 不写入磁盘，直接使用原始文件名（Rocket 的安全文件名会在第一个 '.' 处截断）
+        let raw_name = file.raw_name().map(|name| name.dangerous_unsafe_unsanitized_raw().as_str()).unwrap_or("");
+        let path = std::path::Path::new(raw_name);
+        let is_markdown = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"));
+        let title = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(title) if is_markdown => title,
+            _ => return Err(ApiError::new(Status::BadRequest, format!("file {}: not a .md file: {:?}", index, raw_name))),
+        };
+
+        let mut text = String::new();
+        file.open()
+            .await
+            .map_err(|_| Status::InternalServerError)?
+            .read_to_string(&mut text)
+            .await
+            .map_err(|_| ApiError::new(Status::BadRequest, format!("file {}: not valid UTF-8", index)))?;
+        let created_at = parse_time_filter("mtime", upload.mtime.get(index).filter(|m| !m.is_empty()).cloned())?;
+
+        let note = markdown_import::parse_markdown_file(title, &text);
+        let payload = CreateNotePayload { content: note.content, tags: Some(note.tags), created_at, color: None, slug: None, lang: None };
+        payloads.push(validation::validate_create_payload(payload, config).map_err(|fields| {
+            let mut err = ApiError::validation(fields);
+            err.message = format!("file {}: {}", index, err.message);
+            err
+        })?);
+    }
+
+    let db_arc = db_state.inner().clone();
+    let auto_link = config.auto_link_settings();
+    let notes = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::create_notes_bulk_db(&mut conn, payloads, auto_link)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let ids: Vec<NoteId> = notes.iter().map(|note| public_note_id(note, config)).collect();
+    metrics.notes_created.inc_by(ids.len() as u64);
+    Ok(Created::new("/inbox/notes").body(Json(ImportSummary { imported: ids.len(), ids })))
+}
+
+// 最近笔记的 Atom 订阅源
+#[get("/feed.xml?<limit>")]
+async fn get_feed(db_state: Db<'_>, config: &State<InboxConfig>, limit: Option<i64>) -> Result<(ContentType, String), Status> {
+    let db_arc = db_state.inner().clone();
+    let limit = limit.unwrap_or(50).clamp(1, config.max_limit);
+
+    let notes = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_notes_db(&conn, Some(limit), None, db::NoteFilter::default())
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let xml = feed::notes_to_atom("urn:aw-inbox:feed", "aw-inbox", "/inbox/feed.xml", &notes, |note| public_note_id(note, config));
+    Ok((ContentType::new("application", "atom+xml"), xml))
+}
+
+// 单个标签的 Atom 订阅源（标签精确匹配）；没有笔记使用该标签时返回 404
+#[get("/tags/<name>/feed.xml?<limit>")]
+async fn get_tag_feed(db_state: Db<'_>, config: &State<InboxConfig>, name: String, limit: Option<i64>) -> Result<(ContentType, String), Status> {
+    let db_arc = db_state.inner().clone();
+    let limit = limit.unwrap_or(50).clamp(1, config.max_limit);
+    let filter = db::NoteFilter { tag: Some(name.clone()), ..Default::default() };
+
+    let notes = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_notes_db(&conn, Some(limit), None, filter)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+    if notes.is_empty() {
+        return Err(Status::NotFound);
+    }
+
+    // id 只由标签名决定，笔记增减不影响阅读器识别订阅源
+    let encoded = rocket::http::RawStr::new(&name).percent_encode();
+    let xml = feed::notes_to_atom(
+        &format!("urn:aw-inbox:feed:tag:{}", encoded),
+        &format!("aw-inbox #{}", name),
+        &format!("/inbox/tags/{}/feed.xml", encoded),
+        &notes,
+        |note| public_note_id(note, config),
+    );
+    Ok((ContentType::new("application", "atom+xml"), xml))
+}
+
+// mount_rocket remains the same
+pub fn mount_rocket(rocket: Rocket<Build>, db: SharedDb) -> Rocket<Build> {
+    mount_rocket_with_config(rocket, db, InboxConfig::from_env())
+}
+
+// 使用显式配置挂载（测试中用来开启/关闭各项功能，而不依赖进程环境变量）
+pub fn mount_rocket_with_config(rocket: Rocket<Build>, db: SharedDb, config: InboxConfig) -> Rocket<Build> {
+    let namespaces = Namespaces::new([(namespace::DEFAULT_NAMESPACE.to_string(), db)]);
+    mount_rocket_with_namespaces(rocket, namespaces, config)
+}
+
+// 挂载多个命名空间：default 在 /inbox 下，其余在 /inbox/<ns> 下，各自使用独立的数据库
+pub fn mount_rocket_with_namespaces(rocket: Rocket<Build>, namespaces: Namespaces, config: InboxConfig) -> Rocket<Build> {
+    println!("[INFO] 开始注册 Inbox Server 路由...");
+    println!("[INFO] 注册数据库连接池 (同步包装)...");
+    let compression = config.compression.then_some(compression::ResponseCompression {
+        min_bytes: config.compress_min_bytes,
+    });
+    let pretty = pretty::PrettyJson { always: config.pretty };
+    let cors = cors::build_cors(&config);
+    let mount_points: Vec<String> = namespaces.iter().map(|(name, _)| Namespaces::mount_point(name)).collect();
+    // JSON 请求体（含导入）、纯文本和原始字节请求体的上限，未配置时沿用 Rocket 默认值
+    let rocket = match config.max_body_bytes {
+        Some(max) => {
+            let limits = std::collections::HashMap::from([("json", max), ("bytes", max), ("string", max)]);
+            let figment = rocket.figment().clone().merge((rocket::Config::LIMITS, limits));
+            rocket.configure(figment)
+        }
+        None => rocket,
+    };
+    let metrics = Metrics::new(namespaces.iter().map(|(name, ns)| (name.clone(), ns.db.clone())).collect());
+    let metrics_port = config.metrics_port;
+    let slow_ms = config.slow_ms;
+    let trash_retention_days = config.trash_retention_days;
+    let trash_purge_interval = std::time::Duration::from_secs(config.trash_purge_interval_secs.max(1));
+    let rocket = rocket
+        .manage(namespaces)
+        .manage(metrics.clone())
+        .attach(metrics::RequestMetrics)
+        .attach(request_id::RequestIdFairing)
+        .manage(config)
+        .manage(Readiness::default())
+        .attach(AdHoc::try_on_ignite("Database Migration", run_migrations))
+        .attach(AdHoc::try_on_ignite("Seed Notes", seed_notes))
+        // 响应 Fairing 按挂载顺序执行：先格式化再压缩
+        .attach(pretty);
+
+    let rocket = match compression {
+        Some(fairing) => {
+            println!("[INFO] 启用响应压缩 (>= {} 字节)", fairing.min_bytes);
+            rocket.attach(fairing)
+        }
+        None => rocket,
+    };
+
+    let rocket = match cors {
+        Ok(Some(cors)) => {
+            println!("[INFO] 启用 CORS");
+            rocket.attach(cors)
+        }
+        Ok(None) => rocket,
+        // 配置错误（如凭据与 "*" 同时开启）时拒绝启动
+        Err(e) => rocket.attach(AdHoc::try_on_ignite("CORS", move |rocket| async move {
+            eprintln!("[ERROR] CORS 配置无效: {}", e);
+            Err(rocket)
+        })),
+    };
+
+    // 最后挂载，使计时包含前面各响应 Fairing（格式化、压缩）的耗时
+    let rocket = match slow_ms {
+        Some(ms) => {
+            println!("[INFO] 记录耗时超过 {} ms 的请求", ms);
+            rocket.attach(slow_log::SlowRequestLog::new(ms))
+        }
+        None => rocket,
+    };
+
+    println!("[INFO] 注册 API 路由:");
+    // ... (routes) ...
+
+    let rocket = mount_points.into_iter().fold(rocket, |rocket, base| {
+        println!("[INFO] 挂载命名空间: {}", base);
+        rocket.mount(base, inbox_routes())
+    });
+
+    // 任意路径的 OPTIONS 都由同一个路由处理（各命名空间共用），按已挂载的路由计算 Allow
+    let rocket = rocket.mount("/", routes![options_allow]);
+
+    // 所有错误响应都使用 JSON 格式
+    let rocket = rocket.register("/", error::json_catchers());
+
+    // 指标默认与 API 共用端口；配置 INBOX_METRICS_PORT 后改为在单独端口上提供
+    let rocket = match metrics_port {
+        None => rocket.mount("/", routes![metrics_endpoint]),
+        Some(port) => rocket.attach(AdHoc::on_liftoff("Metrics Server", move |rocket| {
+            let figment = rocket.figment().clone().merge(("port", port));
+            Box::pin(async move {
+                let server = rocket::custom(figment).manage(metrics).mount("/", routes![metrics_endpoint]);
+                tokio::spawn(async move {
+                    if let Err(e) = server.launch().await {
+                        eprintln!("[ERROR] 指标服务启动失败: {}", e);
+                    }
+                });
+            })
+        })),
+    };
+
+    // 回收站自动清理（未设置保留天数时不启动）。liftoff 在点火之后，此时各命名空间的迁移已经执行完毕
+    let rocket = match trash_retention_days {
+        None => rocket,
+        Some(days) => rocket.attach(AdHoc::on_liftoff("Trash Purge", move |rocket| {
+            Box::pin(async move {
+                println!("[INFO] 回收站保留 {} 天，每 {:?} 清理一次", days, trash_purge_interval);
+                if let Some(namespaces) = rocket.state::<Namespaces>() {
+                    for (_, ns) in namespaces.iter() {
+                        tokio::spawn(trash::run_purge_loop(ns.db.clone(), days, trash_purge_interval));
+                    }
+                }
+            })
+        })),
+    };
+
+    println!("[INFO] Inbox Server 路由注册完成");
+    rocket
+}
+
+// 每个命名空间挂载同一组路由
+fn inbox_routes() -> Vec<rocket::Route> {
+    routes![
+        root,
+        livez,
+        readyz,
+        get_schema,
+        create_note,
+        create_note_raw,
+        upsert_note,
+        validate_note,
+        get_notes,
+        count_notes,
+        inbox_count,
+        get_popular_notes,
+        get_broken_links,
+        get_stale_untagged_notes,
+        diff_notes,
+        batch_get_notes,
+        merge_notes,
+        triage_notes,
+        get_note,
+        head_note,
+        get_note_by_slug,
+        get_next_note,
+        get_prev_note,
+        get_full_note,
+        get_note_bundle,
+        get_note_meta,
+        get_note_history,
+        restore_note_version,
+        update_note,
+        delete_note,
+        undo_last,
+        purge_trash,
+        trash_note,
+        restore_trashed_note,
+        get_tags,
+        get_detailed_tags,
+        get_tag_usage,
+        get_tag_tree,
+        get_overview,
+        get_allowed_tags,
+        rename_tag,
+        rename_tag_preview,
+        merge_tags,
+        merge_tags_preview,
+        get_tag_notes,
+        get_related_tags,
+        // 评论和关系相关路由
+        get_comments,
+        count_comments,
+        add_comment,
+        promote_comment,
+        create_relation,
+        create_linked_note,
+        get_relations,
+        get_note_graph,
+        create_relations_bulk,
+        get_relation_types,
+        get_activity,
+        archive_year,
+        archive_month,
+        archive_week,
+        search_linked,
+        get_feed,
+        get_tag_feed,
+        export_notes,
+        export_notes_stream,
+        import_notes_stream,
+        import_notes,
+        import_markdown,
+        // 模板相关路由
+        get_templates,
+        create_template,
+        get_template,
+        update_template,
+        delete_template,
+        create_note_from_template,
+        // 管理接口（需 API Key）
+        admin_reindex,
+        admin_pool,
+        admin_integrity,
+        admin_orphan_relations,
+        admin_bad_tags,
+        admin_repair_bad_tags,
+        admin_rebuild_tag_counts,
+        admin_prune_orphan_relations,
+        admin_reset,
+    ]
+}
+
+// 启动时逐个迁移各命名空间的数据库，全部成功后标记为就绪；失败时服务照常启动但 readyz 保持 503
+async fn run_migrations(rocket: Rocket<Build>) -> rocket::fairing::Result {
+    let (Some(namespaces), Some(readiness)) = (rocket.state::<Namespaces>(), rocket.state::<Readiness>()) else {
+        return Ok(rocket);
+    };
+    let pools: Vec<(String, SharedDb)> = namespaces.iter().map(|(name, ns)| (name.clone(), ns.db.clone())).collect();
+
+    let mut all_migrated = true;
+    for (name, db) in pools {
+        let result = task::spawn_blocking(move || {
+            let conn = db.get().map_err(|e| e.to_string())?;
+            Ok::<_, String>(db::migrate(&conn))
+        })
+        .await;
+
+        match result {
+            Ok(Ok(Ok(()))) => continue,
+            // 数据库由更新版本的程序创建：拒绝启动
+            Ok(Ok(Err(e @ db::MigrateError::SchemaTooNew { .. }))) => {
+                eprintln!("[ERROR] 命名空间 {}: {}", name, e);
+                return Err(rocket);
+            }
+            Ok(Ok(Err(e))) => eprintln!("[ERROR] 命名空间 {} 数据库迁移失败: {}", name, e),
+            Ok(Err(e)) => eprintln!("[ERROR] 命名空间 {} 无法获取数据库连接: {}", name, e),
+            Err(e) => eprintln!("[ERROR] 命名空间 {} 数据库迁移任务失败: {:?}", name, e),
+        }
+        all_migrated = false;
+    }
+    if all_migrated {
+        readiness.mark_migrated();
+    }
+    Ok(rocket)
+}
+
+// 迁移完成后，若配置了 INBOX_SEED_FILE 且默认命名空间为空库，写入示例笔记；
+// 示例文件有误时拒绝启动（与其它配置错误一致）
+async fn seed_notes(rocket: Rocket<Build>) -> rocket::fairing::Result {
+    let (Some(config), Some(namespaces), Some(readiness)) =
+        (rocket.state::<InboxConfig>(), rocket.state::<Namespaces>(), rocket.state::<Readiness>())
+    else {
+        return Ok(rocket);
+    };
+    let (Some(path), Some(namespace)) = (config.seed_file.clone(), namespaces.get(namespace::DEFAULT_NAMESPACE)) else {
+        return Ok(rocket);
+    };
+    if !readiness.is_migrated() {
+        return Ok(rocket);
+    }
+
+    let db = namespace.db.clone();
+    let config = config.clone();
+    let result = task::spawn_blocking(move || seed::seed_if_empty(&db, &path, &config).map(|count| (count, path))).await;
+    match result {
+        Ok(Ok((0, _))) => Ok(rocket),
+        Ok(Ok((count, path))) => {
+            println!("[INFO] 已从 {} 写入 {} 条示例笔记", path.display(), count);
+            Ok(rocket)
+        }
+        Ok(Err(e)) => {
+            eprintln!("[ERROR] 写入示例笔记失败: {}", e);
+            Err(rocket)
+        }
+        Err(e) => {
+            eprintln!("[ERROR] 写入示例笔记任务失败: {:?}", e);
+            Err(rocket)
+        }
+    }
+}
+
+// OPTIONS：返回该路径上各路由支持的方法，没有对应路由时返回 404
+#[options("/<_..>")]
+fn options_allow() -> allow::Allow {
+    allow::Allow
+}
+
+// Prometheus 文本格式的指标（不需要认证）
+#[get("/metrics")]
+async fn metrics_endpoint(metrics: &State<Metrics>) -> Result<(ContentType, String), Status> {
+    let metrics = metrics.inner().clone();
+    let body = task::spawn_blocking(move || metrics.render())
+        .await
+        .map_err(handle_spawn_error)?;
+    Ok((ContentType::new("text", "plain").with_params(("version", "0.0.4")), body))
+}
+
+// 笔记模型的 JSON Schema（创建请求体与响应体），供动态表单等客户端自省。
+// 结构在运行时不会变化，首次请求时生成后缓存。
+#[get("/schema")]
+fn get_schema() -> Json<&'static serde_json::Value> {
+    static SCHEMA: std::sync::OnceLock<serde_json::Value> = std::sync::OnceLock::new();
+    Json(SCHEMA.get_or_init(|| {
+        serde_json::json!({
+            "create": schemars::schema_for!(CreateNotePayload),
+            "note": schemars::schema_for!(NoteResponse),
+        })
+    }))
+}
+
+// 存活探针：进程能响应即可
+#[get("/livez")]
+fn livez() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "alive" }))
+}
+
+// 就绪探针：迁移已完成且能从连接池取到可用连接
+#[get("/readyz")]
+async fn readyz(db_state: Db<'_>, readiness: &State<Readiness>) -> Result<Json<serde_json::Value>, ApiError> {
+    if !readiness.is_migrated() {
+        return Err(ApiError::new(Status::ServiceUnavailable, "database migrations have not completed"));
+    }
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        conn.query_row("SELECT 1", [], |_| Ok(())).map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map_err(|status| ApiError::new(status, "database is not available"))?;
+
+    Ok(Json(serde_json::json!({ "status": "ready" })))
+}
+
+// 服务信息：名称、版本和已挂载的路由（"GET /inbox/notes/<id>" 形式，按路径排序），用于能力发现
+#[get("/")]
+fn root() -> ServiceInfoResponse {
+    ServiceInfoResponse
+}
+
+struct ServiceInfoResponse;
+
+impl<'r> Responder<'r, 'static> for ServiceInfoResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut routes: Vec<(String, String)> = request
+            .rocket()
+            .routes()
+            .map(|route| (route.uri.as_str().to_string(), route.method.as_str().to_string()))
+            .collect();
+        routes.sort();
+        routes.dedup();
+        let info = ServiceInfo {
+            name: "aw-inbox",
+            version: env!("CARGO_PKG_VERSION"),
+            routes: routes.into_iter().map(|(uri, method)| format!("{} {}", method, uri)).collect(),
+        };
+        Json(info).respond_to(request)
+    }
+}
+
+// 开启 INBOX_SOURCE_TAGGING 时由请求头 X-Inbox-Source 得到的 source:<来源> 标签（来源转为小写）
+struct SourceTag(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SourceTag {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let enabled = request.rocket().state::<InboxConfig>().is_some_and(|config| config.source_tagging);
+        let source = request
+            .headers()
+            .get_one("X-Inbox-Source")
+            .map(|source| source.trim().to_lowercase())
+            .filter(|source| enabled && !source.is_empty());
+        Outcome::Success(SourceTag(source.map(|source| format!("source:{}", source))))
+    }
+}
+
+impl SourceTag {
+    // 来源标签与请求中的标签一起校验（计入 INBOX_MAX_TAGS，也受 INBOX_ALLOWED_TAGS 限制）
+    fn apply(self, payload: &mut CreateNotePayload) {
+        if let Some(tag) = self.0 {
+            payload.tags.get_or_insert_with(Vec::new).push(tag);
+        }
+    }
+}
+
+#[post("/notes", data = "<payload>", format = "json")]
+async fn create_note(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, source: SourceTag, payload: Json<CreateNotePayload>) -> Result<Created<Json<NoteResponse>>, ApiError> {
+    let mut payload = payload.into_inner();
+    source.apply(&mut payload);
+    insert_note(db_state, config, metrics, payload).await
+}
+
+// 纯文本请求体即为笔记内容，标签通过 ?tag= 指定（可重复），便于 `curl --data-binary @-` 直接捕获
+#[post("/notes/raw?<tag>", data = "<content>", format = "plain")]
+async fn create_note_raw(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, source: SourceTag, tag: Vec<String>, content: &str) -> Result<Created<Json<NoteResponse>>, ApiError> {
+    let mut payload = CreateNotePayload {
+        content: content.to_string(),
+        tags: (!tag.is_empty()).then_some(tag),
+        created_at: None,
+        color: None,
+        slug: None,
+        lang: None,
+    };
+    source.apply(&mut payload);
+    insert_note(db_state, config, metrics, payload).await
+}
+
+// 校验并写入一条新笔记，记录到撤销日志
+async fn insert_note(db_state: Db<'_>, config: &InboxConfig, metrics: &Metrics, payload: CreateNotePayload) -> Result<Created<Json<NoteResponse>>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let note_payload = validation::validate_create_payload(payload, config)
+        .map_err(ApiError::validation)?;
+
+    let retries = config.busy_retries;
+    let write_options = config.note_write_options();
+
+    let created_note = task::spawn_blocking(move || {
+        let mut conn_guard = db_arc.get().map_err(handle_pool_error)?;
+        db::with_busy_retry(retries, || db::create_note_db(&mut conn_guard, note_payload.clone(), write_options))
+            .map_err(handle_write_note_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??; // Double '?' handles JoinError and then DB Result
+    metrics.notes_created.inc();
+    db_state.undo_log().record(undo::Operation::Created { note: created_note.clone() });
+
+    Ok(Created::new(note_location(&created_note, config)).body(Json(note_to_response(&created_note, config))))
+}
+
+// 按外部系统的 ID 同步笔记：external_id 已存在时更新内容和标签（200），否则新建（201）
+#[post("/notes/upsert", data = "<payload>", format = "json")]
+async fn upsert_note(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, payload: Json<UpsertNotePayload>) -> Result<UpsertResult, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let (external_id, note_payload) = validation::validate_upsert_payload(payload.into_inner(), config)
+        .map_err(ApiError::validation)?;
+
+    let retries = config.busy_retries;
+    let history_limit = config.history_limit;
+    let write_options = config.note_write_options();
+
+    let upserted = task::spawn_blocking(move || {
+        let mut conn_guard = db_arc.get().map_err(handle_pool_error)?;
+        db::with_busy_retry(retries, || db::upsert_note_db(&mut conn_guard, &external_id, note_payload.clone(), history_limit, write_options))
+            .map_err(handle_write_note_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    match upserted {
+        db::Upserted::Created(note) => {
+            metrics.notes_created.inc();
+            db_state.undo_log().record(undo::Operation::Created { note: note.clone() });
+            let body = UpsertNoteResponse { note: note_to_response(&note, config), created: true };
+            Ok(UpsertResult::Created(note_location(&note, config), body))
+        }
+        db::Upserted::Updated { before, note } => {
+            // 内容未变化时没有写入，也就没有可撤销的操作
+            if note.updated_at != before.updated_at {
+                db_state.undo_log().record(undo::Operation::Updated { before });
+            }
+            Ok(UpsertResult::Updated(UpsertNoteResponse { note: note_to_response(&note, config), created: false }))
+        }
+    }
+}
+
+// 新建时与 POST /notes 一样返回 201 和 Location，更新时返回 200
+enum UpsertResult {
+    Created(String, UpsertNoteResponse),
+    Updated(UpsertNoteResponse),
+}
+
+impl<'r> Responder<'r, 'static> for UpsertResult {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            UpsertResult::Created(location, body) => Created::new(location).body(Json(body)).respond_to(request),
+            UpsertResult::Updated(body) => Json(body).respond_to(request),
+        }
+    }
+}
+
+// 只校验不写入：返回规范化后的请求体，或 400 + 字段错误
+#[post("/notes/validate", data = "<payload>", format = "json")]
+fn validate_note(config: &State<InboxConfig>, payload: Json<CreateNotePayload>) -> Result<Json<CreateNotePayload>, ApiError> {
+    validation::validate_create_payload(payload.into_inner(), config)
+        .map(Json)
+        .map_err(ApiError::validation)
+}
+
+#[derive(FromForm)]
+struct NotesQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    tag: Option<String>,
+    // 为 true 时 tag 同时匹配其子标签（tag=work 匹配 work 与 work/...）
+    prefix: Option<bool>,
+    search: Option<String>,
+    // search 的简写，同时给出时两者必须相同
+    q: Option<String>,
+    fields: Option<String>,
+    color: Option<String>,
+    created_after: Option<String>,
+    created_before: Option<String>,
+    lang: Option<String>,
+    // 内容的字节数范围（含两端）
+    min_len: Option<i64>,
+    max_len: Option<i64>,
+    // 附带 relation_count / comment_count（多一次 JOIN，默认关闭）
+    with_counts: Option<bool>,
+    // 早期版本就有的参数，目前未使用：列表固定按创建时间倒序
+    #[allow(dead_code)]
+    sort_by: Option<String>,
+}
+
+impl NotesQuery {
+    // 列表和 GET /notes/count 共用同一套过滤参数，保证两者结果一致
+    fn filter(&self, config: &InboxConfig) -> Result<db::NoteFilter, Status> {
+        let search = match (&self.search, &self.q) {
+            (Some(search), Some(q)) if search != q => return Err(Status::BadRequest),
+            (search, q) => search.clone().or_else(|| q.clone()),
+        };
+        // 加密存储时无法在 SQL 中匹配内容
+        if search.is_some() && config.encryption_key.is_some() {
+            return Err(Status::BadRequest);
+        }
+        let (min_len, max_len) = match (self.min_len, self.max_len) {
+            (Some(min), _) if min < 0 => return Err(Status::BadRequest),
+            (_, Some(max)) if max < 0 => return Err(Status::BadRequest),
+            (Some(min), Some(max)) if min > max => return Err(Status::BadRequest),
+            range => range,
+        };
+        Ok(db::NoteFilter {
+            tag: self.tag.clone(),
+            tag_prefix: self.prefix.unwrap_or(false),
+            created_after: parse_time_filter("created_after", self.created_after.clone())?,
+            created_before: parse_time_filter("created_before", self.created_before.clone())?,
+            search,
+            color: validate_color(self.color.clone())?,
+            lang: validate_lang(self.lang.clone())?,
+            min_len,
+            max_len,
+        })
+    }
+}
+
+// 符合过滤条件的笔记数（不返回笔记本身），分页参数被忽略
+#[get("/notes/count?<query..>")]
+async fn count_notes(db_state: Db<'_>, config: &State<InboxConfig>, query: NotesQuery) -> Result<Json<CountResponse>, Status> {
+    let db_arc = db_state.inner().clone();
+    let filter = query.filter(config)?;
+
+    let count = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::count_notes_db(&conn, filter)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(CountResponse { count }))
+}
+
+// 收件箱中待处理（未归档、未删除）的笔记数，供菜单栏等频繁轮询的角标使用。
+// 只做一次 COUNT(*)，并允许客户端缓存几秒
+#[get("/count?<tag>")]
+async fn inbox_count(db_state: Db<'_>, tag: Option<String>) -> Result<InboxCount, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let count = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::count_inbox_db(&conn, tag.as_deref())
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(InboxCount(CountResponse { count }))
+}
+
+// 角标计数允许缓存的秒数
+const INBOX_COUNT_MAX_AGE: u32 = 5;
+
+struct InboxCount(CountResponse);
+
+impl<'r> Responder<'r, 'static> for InboxCount {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Json(self.0).respond_to(request)?;
+        response.set_header(Header::new("Cache-Control", format!("private, max-age={}", INBOX_COUNT_MAX_AGE)));
+        Ok(response)
+    }
+}
+
+// 笔记列表响应；指定 limit 分页时附带 RFC 5988 风格的 Link 头（响应体不变）
+struct NotesPage {
+    notes: Vec<serde_json::Value>,
+    link: Option<String>,
+}
+
+impl<'r> Responder<'r, 'static> for NotesPage {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Json(self.notes).respond_to(request)?;
+        if let Some(link) = self.link {
+            response.set_header(Header::new("Link", link));
+        }
+        Ok(response)
+    }
+}
+
+// 生成 first/prev/next/last 链接，保留 limit/offset 以外的查询参数
+fn pagination_links(origin: &Origin<'_>, limit: i64, offset: i64, total: i64) -> String {
+    let mut base_query: Vec<&str> = origin
+        .query()
+        .map(|q| q.raw_segments().map(|seg| seg.as_str()).collect())
+        .unwrap_or_default();
+    base_query.retain(|seg| {
+        let key = seg.split('=').next().unwrap_or("");
+        key != "limit" && key != "offset"
+    });
+    let path = origin.path().as_str();
+    let link = |offset: i64, rel: &str| {
+        let mut query = base_query.clone();
+        let paging = format!("limit={}&offset={}", limit, offset);
+        query.push(&paging);
+        format!("<{}?{}>; rel=\"{}\"", path, query.join("&"), rel)
+    };
+
+    let last_offset = if total > 0 { (total - 1) / limit * limit } else { 0 };
+    let mut links = vec![link(0, "first")];
+    if offset > 0 {
+        links.push(link((offset - limit).max(0), "prev"));
+    }
+    if offset + limit < total {
+        links.push(link(offset + limit, "next"));
+    }
+    links.push(link(last_offset, "last"));
+    links.join(", ")
+}
+
+#[get("/notes?<query..>")]
+async fn get_notes(db_state: Db<'_>, config: &State<InboxConfig>, origin: &Origin<'_>, query: NotesQuery) -> Result<NotesPage, Status> {
+    let db_arc = db_state.inner().clone();
+    // 先校验字段投影，避免无效请求白白查库
+    let fields = match query.fields.as_deref() {
+        Some(raw) => Some(parse_note_fields(raw)?),
+        None => None,
+    };
+    
+    // 接收查询参数：未指定 limit 时使用 INBOX_DEFAULT_LIMIT，显式指定的也不能超过 INBOX_MAX_LIMIT
+    let paged = query.limit.is_some() || query.offset.is_some();
+    let limit = query.limit.unwrap_or(config.default_limit).clamp(0, config.max_limit);
+    let offset = query.offset;
+    let filter = query.filter(config)?;
+    
+    let with_counts = query.with_counts.unwrap_or(false);
+    // 分页链接需要总数（多一次 COUNT 查询）
+    let count_filter = (limit > 0).then(|| filter.clone());
+
+    // 带内容搜索的列表按搜索类路由计时，其余沿用 INBOX_QUERY_TIMEOUT_MS
+    let timeout = filter.search.as_ref().and_then(|_| config.route_timeout(RouteClass::Search));
+
+    let (notes, total) = run_blocking(timeout, move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        // 总数与当前页在同一快照中读取，避免两者不一致
+        db::with_read_tx(&conn, |conn| {
+            let total = match count_filter {
+                Some(count_filter) => Some(db::count_notes_db(conn, count_filter)?),
+                None => None,
+            };
+            let notes = if with_counts {
+                db::get_notes_with_counts_db(conn, Some(limit), offset, filter)
+                    .map(|rows| rows.into_iter().map(|(note, counts)| (note, Some(counts))).collect::<Vec<_>>())
+            } else {
+                db::get_notes_db(conn, Some(limit), offset, filter)
+                    .map(|notes| notes.into_iter().map(|note| (note, None)).collect())
+            }?;
+            Ok((notes, total))
+        })
+        .map_err(handle_db_error)
+    })
+    .await?;
+
+    let response = notes
+        .iter()
+        .map(|(note, counts)| {
+            let mut value = project_note_fields(&note_to_response(note, config), fields.as_deref())?;
+            // 计数是显式请求的，不受 fields 投影影响
+            if let (Some(counts), Some(object)) = (counts, value.as_object_mut()) {
+                object.insert("relation_count".to_string(), counts.relation_count.into());
+                object.insert("comment_count".to_string(), counts.comment_count.into());
+            }
+            Ok(value)
+        })
+        .collect::<Result<_, Status>>()?;
+
+    // 客户端分页，或默认 limit 截断了结果时，附带分页链接
+    let link = match total {
+        Some(total) if paged || total > limit => Some(pagination_links(origin, limit, offset.unwrap_or(0).max(0), total)),
+        _ => None,
+    };
+    Ok(NotesPage { notes: response, link })
+}
+
+
+// 一次获取多条笔记（避免逐条请求）；重复的 id 只返回一次
+#[post("/notes/batch-get", data = "<payload>", format = "json")]
+async fn batch_get_notes(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<Vec<NoteKey>>) -> Result<Json<BatchGetResponse>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+    let mut ids = payload.into_inner();
+    let mut seen = std::collections::HashSet::new();
+    ids.retain(|id| seen.insert(id.clone()));
+    if ids.len() > config.max_batch_size {
+        return Err(ApiError::new(
+            Status::BadRequest,
+            format!("too many ids: {} (INBOX_MAX_BATCH_SIZE limit is {})", ids.len(), config.max_batch_size),
+        ));
+    }
+
+    let (notes, missing) = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        // 解析不到的标识（含 uuid 模式下的整数 id）原样列入 missing
+        let mut resolved = Vec::with_capacity(ids.len());
+        let mut missing = Vec::new();
+        for key in ids {
+            match db::resolve_note_key(&conn, &key, id_scheme) {
+                Ok(id) => resolved.push((id, key)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => missing.push(key),
+                Err(e) => return Err(handle_db_error(e)),
+            }
+        }
+        let note_ids: Vec<i64> = resolved.iter().map(|(id, _)| *id).collect();
+        let notes = db::get_notes_by_ids_db(&conn, &note_ids).map_err(handle_db_error)?;
+        missing.extend(resolved.into_iter().filter(|(id, _)| !notes.iter().any(|note| note.id == *id)).map(|(_, key)| key));
+        Ok::<_, Status>((notes, missing))
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let missing = missing.into_iter().map(NoteId::from).collect();
+    Ok(Json(BatchGetResponse {
+        notes: notes.iter().map(|note| note_to_response(note, config)).collect(),
+        missing,
+    }))
+}
+
+// 把多条笔记合并为一条：内容追加到 primary，标签取并集，关系改指向 primary，其余笔记软删除
+#[post("/notes/merge", data = "<payload>", format = "json")]
+async fn merge_notes(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<MergeNotesPayload>) -> Result<Json<NoteResponse>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+    let auto_link = config.auto_link_settings();
+    let MergeNotesPayload { primary, mut others } = payload.into_inner();
+    let mut seen = std::collections::HashSet::new();
+    others.retain(|id| seen.insert(id.clone()));
+    if others.is_empty() {
+        return Err(ApiError::new(Status::BadRequest, "others must not be empty"));
+    }
+    if others.contains(&primary) {
+        return Err(ApiError::new(Status::BadRequest, format!("primary note {} is also listed in others", primary)));
+    }
+
+    let merged = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let resolve = |key: &NoteKey| match db::resolve_note_key(&conn, key, id_scheme) {
+            Ok(id) => Ok(id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(ApiError::new(Status::NotFound, format!("note {} does not exist", key))),
+            Err(e) => Err(ApiError::from(handle_db_error(e))),
+        };
+        let primary = resolve(&primary)?;
+        let others = others.iter().map(resolve).collect::<Result<Vec<i64>, ApiError>>()?;
+        db::merge_notes_db(&mut conn, primary, &others, auto_link).map_err(|e| match e {
+            db::MergeNotesError::NoteNotFound(_) => ApiError::new(Status::NotFound, e.to_string()),
+            db::MergeNotesError::Db(db_err) => ApiError::from(handle_db_error(db_err)),
+        })
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(note_to_response(&merged, config)))
+}
+
+// 整理收件箱：在一个事务中批量归档、删除、置顶笔记，返回各操作影响的笔记数
+#[post("/triage", data = "<payload>", format = "json")]
+async fn triage_notes(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, payload: Json<TriagePayload>) -> Result<Json<TriageSummary>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let retries = config.busy_retries;
+    let payload = payload.into_inner();
+
+    let mut seen = std::collections::HashMap::new();
+    for (action, ids) in [("archive", &payload.archive), ("delete", &payload.delete), ("pin", &payload.pin)] {
+        for id in ids {
+            match seen.insert(id.clone(), action) {
+                Some(other) if other != action => {
+                    return Err(ApiError::new(Status::BadRequest, format!("note {} is listed in both {} and {}", id, other, action)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let id_scheme = config.id_scheme;
+
+    let summary = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        // 解析不到的标识同不存在的 id 一样跳过
+        let resolve = |keys: &[NoteKey]| -> Result<Vec<i64>, Status> {
+            let mut ids = Vec::with_capacity(keys.len());
+            for key in keys {
+                match db::resolve_note_key(&conn, key, id_scheme) {
+                    Ok(id) => ids.push(id),
+                    Err(rusqlite::Error::QueryReturnedNoRows) => {}
+                    Err(e) => return Err(handle_db_error(e)),
+                }
+            }
+            Ok(ids)
+        };
+        let (archive, delete, pin) = (resolve(&payload.archive)?, resolve(&payload.delete)?, resolve(&payload.pin)?);
+        db::with_busy_retry(retries, || db::triage_notes_db(&mut conn, &archive, &delete, &pin)).map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    metrics.notes_deleted.inc_by(summary.deleted as u64);
+    Ok(Json(summary))
+}
+
+// 逐行比较两条笔记的内容及标签差异（用于检查近似重复）
+#[get("/notes/diff?<a>&<b>")]
+async fn diff_notes(db_state: Db<'_>, config: &State<InboxConfig>, a: NoteKey, b: NoteKey) -> Result<Json<NoteDiff>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+    let (key_a, key_b) = (a.clone(), b.clone());
+
+    let (note_a, note_b) = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::with_read_tx(&conn, |conn| {
+            let get = |key: &NoteKey| match db::resolve_note_key(conn, key, id_scheme) {
+                Ok(id) => db::get_note_db(conn, id),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e),
+            };
+            Ok((get(&key_a)?, get(&key_b)?))
+        })
+        .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    match (note_a, note_b) {
+        (Some(note_a), Some(note_b)) => Ok(Json(diff::diff_notes(&note_a, &note_b, public_note_id(&note_a, config), public_note_id(&note_b, config)))),
+        (None, _) => Err(ApiError::new(Status::NotFound, format!("note {} does not exist", a))),
+        (_, None) => Err(ApiError::new(Status::NotFound, format!("note {} does not exist", b))),
+    }
+}
+
+// 整理用：没有标签、且超过 days 天（默认 30）未修改的笔记，最久未动的在前
+#[get("/notes/stale-untagged?<days>&<limit>")]
+async fn get_stale_untagged_notes(db_state: Db<'_>, config: &State<InboxConfig>, days: Option<u32>, limit: Option<i64>) -> Result<Json<Vec<NoteResponse>>, Status> {
+    let db_arc = db_state.inner().clone();
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(i64::from(days.unwrap_or(30)));
+    let limit = limit.unwrap_or(config.default_limit).clamp(0, config.max_limit);
+
+    let notes = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_stale_untagged_notes_db(&conn, cutoff, limit)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Json(notes.iter().map(|note| note_to_response(note, config)).collect()))
+}
+
+// 内容中 [[id]] 引用了不存在（或已移入回收站）的笔记的笔记，最新的在前；需要扫描全部笔记
+#[get("/notes/broken-links")]
+async fn get_broken_links(db_state: Db<'_>, config: &State<InboxConfig>) -> Result<Json<Vec<BrokenLinks>>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let notes = run_blocking(config.route_timeout(RouteClass::Export), move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_notes_db(&conn, None, None, db::NoteFilter::default())
+            .map_err(handle_db_error)
+    })
+    .await?;
+
+    // uuid 模式下整数 id 不指向任何笔记，[[id]] 引用一律视为断链
+    let resolvable = config.id_scheme == IdScheme::Integer;
+    Ok(Json(
+        links::find_broken_links(&notes, resolvable)
+            .into_iter()
+            .map(|(note, missing_refs)| BrokenLinks { note_id: public_note_id(note, config), missing_refs })
+            .collect(),
+    ))
+}
+
+// 按访问次数排序的热门笔记（需开启 INBOX_TRACK_ACCESS 才会有数据）
+#[get("/notes/popular?<limit>")]
+async fn get_popular_notes(db_state: Db<'_>, config: &State<InboxConfig>, limit: Option<i64>) -> Result<Json<Vec<NoteResponse>>, Status> {
+    let db_arc = db_state.inner().clone();
+    let tracker = db_state.tracker();
+    let limit = limit.unwrap_or(20).clamp(1, config.max_limit);
+
+    // 先把内存中尚未写回的计数落库，保证排序反映最新访问；写回失败时计数放回内存，不会丢失
+    let pending = tracker.take_pending();
+    if !pending.is_empty() {
+        let flush_db = db_arc.clone();
+        let flushed = task::spawn_blocking(move || {
+            flush_db
+                .get()
+                .map_err(handle_pool_error)
+                .and_then(|mut conn| db::increment_access_counts_db(&mut conn, &pending).map_err(handle_db_error))
+                .map_err(|status| (status, pending))
+        })
+        .await
+        .map_err(handle_spawn_error)?;
+        if let Err((status, pending)) = flushed {
+            tracker.restore(pending);
+            return Err(status);
+        }
+    }
+
+    let notes = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_popular_notes_db(&conn, limit)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let response = notes.iter().map(|note| note_to_response(note, config)).collect();
+    Ok(Json(response))
+}
+
+#[get("/notes/<id>")]
+async fn get_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<NoteRepresentation, Status> {
+    let db_arc = db_state.inner().clone();
+    let tracker = db_state.tracker();
+    let id_scheme = config.id_scheme;
+
+    let maybe_note = run_blocking(config.route_timeout(RouteClass::Read), move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        db::get_note_db(&conn, id)
+            .map_err(handle_db_error)
+    })
+    .await?;
+
+    match maybe_note {
+        Some(note) => {
+            if config.track_access && tracker.record(note.id) {
+                // 达到批量阈值：后台写回，不阻塞本次响应（失败只记录日志）
+                let pending = tracker.take_pending();
+                let db_arc = db_state.inner().clone();
+                task::spawn_blocking(move || {
+                    if let Ok(mut conn) = db_arc.get() {
+                        if let Err(e) = db::increment_access_counts_db(&mut conn, &pending) {
+                            eprintln!("[WARN] 写回访问计数失败: {:?}", e);
+                        }
+                    }
+                });
+            }
+            Ok(NoteRepresentation(note_to_response(&note, config)))
+        }
         None => Err(Status::NotFound),
     }
 }
 
+// 供监控等场景低成本地检查笔记是否存在：状态码和头与 GET /notes/<id> 一致，不返回正文，也不计入访问次数
+#[head("/notes/<id>")]
+async fn head_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<NoteHead, Status> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+
+    let maybe_note = run_blocking(config.route_timeout(RouteClass::Read), move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        db::get_note_db(&conn, id)
+            .map_err(handle_db_error)
+    })
+    .await?;
+
+    maybe_note
+        .map(|note| NoteHead(NoteRepresentation(note_to_response(&note, config))))
+        .ok_or(Status::NotFound)
+}
+
+// 单条笔记按 Accept 头协商格式：application/json（默认）、text/markdown、text/plain（仅正文）。
+// 按 q 值从高到低取第一个支持的类型，都不支持时返回 406。
+struct NoteRepresentation(NoteResponse);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NoteFormat {
+    Json,
+    Markdown,
+    Plain,
+}
+
+fn negotiate_note_format(accept: Option<&rocket::http::Accept>) -> Option<NoteFormat> {
+    let Some(accept) = accept else {
+        return Some(NoteFormat::Json);
+    };
+    let mut candidates: Vec<_> = accept.iter().filter(|m| m.weight_or(1.0) > 0.0).collect();
+    // sort_by 是稳定排序，同权重时保持客户端给出的顺序
+    candidates.sort_by(|a, b| b.weight_or(1.0).total_cmp(&a.weight_or(1.0)));
+    candidates.into_iter().find_map(|m| {
+        let media = m.media_type();
+        match (media.top().as_str(), media.sub().as_str()) {
+            ("*", "*") | ("application", "*") | ("application", "json") => Some(NoteFormat::Json),
+            ("text", "markdown") => Some(NoteFormat::Markdown),
+            ("text", "plain") | ("text", "*") => Some(NoteFormat::Plain),
+            _ => None,
+        }
+    })
+}
+
+impl<'r> Responder<'r, 'static> for NoteRepresentation {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let etag = note_etag(&self.0);
+        let mut response = match negotiate_note_format(request.accept()).ok_or(Status::NotAcceptable)? {
+            NoteFormat::Json => Json(self.0).respond_to(request)?,
+            NoteFormat::Markdown => {
+                (ContentType::new("text", "markdown"), export::note_to_markdown(&self.0)).respond_to(request)?
+            }
+            NoteFormat::Plain => (ContentType::Plain, self.0.content).respond_to(request)?,
+        };
+        response.adjoin_header(Header::new("Vary", "Accept"));
+        response.set_header(Header::new("ETag", etag));
+        Ok(response)
+    }
+}
+
+// 弱 ETag：由笔记的 JSON 表示计算，三种格式共用同一个值（格式由 Accept 决定，见 Vary）
+fn note_etag(note: &NoteResponse) -> String {
+    let json = serde_json::to_vec(note).unwrap_or_default();
+    let digest = format!("{:x}", Sha256::digest(&json));
+    format!("W/\"{}\"", &digest[..32])
+}
+
+// HEAD /notes/<id> 的响应：与 GET 相同的头（含 ETag 和 Content-Length），但不带响应体。
+// 这里照常生成完整的响应体，让格式化、压缩等 Fairing 像处理 GET 一样处理它；
+// Rocket 在所有 Fairing 之后才去掉 HEAD 的响应体，Content-Length 取处理后的大小，因此与 GET 一致
+struct NoteHead(NoteRepresentation);
+
+impl<'r> Responder<'r, 'static> for NoteHead {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        self.0.respond_to(request)
+    }
+}
+
+
+// 按 slug 访问笔记（永久链接），与 GET /notes/<id> 一样支持按 Accept 协商格式
+#[get("/n/<slug>")]
+async fn get_note_by_slug(db_state: Db<'_>, config: &State<InboxConfig>, slug: String) -> Result<NoteRepresentation, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let note = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_note_by_slug_db(&conn, &slug)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    note.map(|note| NoteRepresentation(note_to_response(&note, config))).ok_or(Status::NotFound)
+}
+
+// 按创建时间前后翻阅笔记（可用 ?tag= 限定范围），到达两端时返回 404
+#[get("/notes/<id>/next?<tag>")]
+async fn get_next_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey, tag: Option<String>) -> Result<Json<NoteResponse>, Status> {
+    adjacent_note(db_state, config, id, db::Adjacent::Next, tag).await
+}
+
+#[get("/notes/<id>/prev?<tag>")]
+async fn get_prev_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey, tag: Option<String>) -> Result<Json<NoteResponse>, Status> {
+    adjacent_note(db_state, config, id, db::Adjacent::Prev, tag).await
+}
+
+async fn adjacent_note(db_state: Db<'_>, config: &InboxConfig, id: NoteKey, direction: db::Adjacent, tag: Option<String>) -> Result<Json<NoteResponse>, Status> {
+    let db_arc = db_state.inner().clone();
+    let filter = db::NoteFilter { tag, ..Default::default() };
+    let id_scheme = config.id_scheme;
+
+    let note = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        db::get_adjacent_note_db(&conn, id, direction, filter)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    note.map(|note| Json(note_to_response(&note, config))).ok_or(Status::NotFound)
+}
+
+// 详情页所需的全部数据，一次取连接、一次往返
+#[get("/notes/<id>/full")]
+async fn get_full_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<Json<FullNoteResponse>, Status> {
+    let db_arc = db_state.inner().clone();
+    let config = config.inner().clone();
+
+    let full = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        // 笔记、评论与关系在同一快照中读取
+        db::with_read_tx(&conn, |conn| {
+            let id = match db::resolve_note_key(conn, &id, config.id_scheme) {
+                Ok(id) => id,
+                Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let note = match db::get_note_db(conn, id)? {
+                Some(note) => note,
+                None => return Ok(None),
+            };
+            let comments = db::get_comments_for_note_db(conn, id)?;
+            let relations = db::get_outgoing_relations_db(conn, id)?;
+            let backlinks: Vec<NoteRelation> = db::get_relations_for_note_db(conn, id, None)?
+                .into_iter()
+                .filter(|relation| relation.relation_type != NoteRelationType::Comment)
+                .collect();
+            let public_ids = PublicIds::for_relations(conn, config.id_scheme, &[relations.as_slice(), backlinks.as_slice()].concat())?;
+
+            Ok(Some(FullNoteResponse {
+                note: note_to_response(&note, &config),
+                comments: comments.iter().map(|(note, _relation)| note_to_response(note, &config)).collect(),
+                relations: public_ids.relations(&relations),
+                backlinks: public_ids.relations(&backlinks),
+            }))
+        })
+        .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    full.map(Json).ok_or(Status::NotFound)
+}
+
+// 笔记及其评论打包为 zip 下载（note.md + comments.md），便于单独分享一条笔记
+#[get("/notes/<id>/bundle")]
+async fn get_note_bundle(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<NoteBundle, Status> {
+    let db_arc = db_state.inner().clone();
+    let config = config.inner().clone();
+
+    let (response, comments, modified, log_tag) = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let (note, comments) = db::with_read_tx(&conn, |conn| {
+            let id = db::resolve_note_key(conn, &id, config.id_scheme)?;
+            let note = db::get_note_db(conn, id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+            Ok((note, db::get_comments_for_note_db(conn, id)?))
+        })
+        .map_err(handle_db_error)?;
+
+        let response = note_to_response(&note, &config);
+        let comments: Vec<NoteResponse> = comments.iter().map(|(note, _relation)| note_to_response(note, &config)).collect();
+        Ok::<_, Status>((response, comments, note.updated_at, request_id::tag()))
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let filename = format!("note-{}.zip", response.id);
+    Ok(NoteBundle { filename, zip: bundle::note_bundle(response, comments, modified, log_tag) })
+}
+
+// zip 边生成边发送，没有 Content-Length
+struct NoteBundle {
+    filename: String,
+    zip: bundle::BundleStream,
+}
+
+impl<'r> Responder<'r, 'static> for NoteBundle {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        rocket::Response::build()
+            .header(ContentType::ZIP)
+            .header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", self.filename)))
+            .streamed_body(self.zip)
+            .ok()
+    }
+}
+
+// 笔记的修改元数据（创建/更新时间、更新次数、内容哈希），不返回正文
+#[get("/notes/<id>/meta")]
+async fn get_note_meta(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<Json<NoteMeta>, Status> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+
+    let meta = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        db::get_note_meta_db(&conn, id)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    meta.map(Json).ok_or(Status::NotFound)
+}
+
+#[put("/notes/<id>", data = "<payload>", format = "json")]
+async fn update_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey, payload: Json<UpdateNotePayload>) -> Result<Json<NoteResponse>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let note_payload = validation::validate_update_payload(payload.into_inner(), config)
+        .map_err(ApiError::validation)?;
+
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+    let history_limit = config.history_limit;
+    let write_options = config.note_write_options();
+
+    // 同时取回更新前的状态，供撤销使用
+    let (before, updated_note_option) = task::spawn_blocking(move || {
+        let mut conn_guard = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn_guard, &id, id_scheme).map_err(handle_db_error)?;
+        let before = db::get_note_db(&conn_guard, id).map_err(handle_db_error)?;
+        db::with_busy_retry(retries, || db::update_note_db(&mut conn_guard, id, note_payload.clone(), history_limit, write_options))
+             .map(|updated| (before, updated))
+             .map_err(handle_write_note_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??; // Double '?'
+
+    match (before, updated_note_option) {
+        (Some(before), Some(note)) => {
+            // 内容未变化时没有写入，也就没有可撤销的操作
+            if note.updated_at != before.updated_at {
+                db_state.undo_log().record(undo::Operation::Updated { before });
+            }
+            Ok(Json(note_to_response(&note, config)))
+        }
+        _ => Err(Status::NotFound.into()),
+    }
+}
+
+
+// 笔记的历史版本（最新的在前），每次 PUT 更新前自动保存
+#[get("/notes/<id>/history")]
+async fn get_note_history(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<Json<Vec<NoteVersion>>, Status> {
+    let db_arc = db_state.inner().clone();
+    let id_scheme = config.id_scheme;
+
+    let history = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        db::get_note_history_db(&conn, id)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    history.map(Json).ok_or(Status::NotFound)
+}
+
+// 恢复到某个历史版本（内容与标签），当前内容先存为新版本；笔记或版本不存在时返回 404
+#[post("/notes/<id>/restore/<version_id>")]
+async fn restore_note_version(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey, version_id: i64) -> Result<Json<NoteResponse>, Status> {
+    let db_arc = db_state.inner().clone();
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+    let history_limit = config.history_limit;
+    let auto_link = config.auto_link_settings();
+
+    let (before, restored) = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        let before = db::get_note_db(&conn, id).map_err(handle_db_error)?;
+        db::with_busy_retry(retries, || db::restore_note_version_db(&mut conn, id, version_id, history_limit, auto_link))
+            .map(|restored| (before, restored))
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    match (before, restored) {
+        (Some(before), Some(note)) => {
+            db_state.undo_log().record(undo::Operation::Updated { before });
+            Ok(Json(note_to_response(&note, config)))
+        }
+        _ => Err(Status::NotFound),
+    }
+}
 
 #[delete("/notes/<id>")]
-async fn delete_note(db_state: &State<SharedDb>, id: i64) -> Result<Status, Status> {
+async fn delete_note(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, id: NoteKey) -> Result<Status, Status> {
     let db_arc = db_state.inner().clone();
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
 
-    let deleted = task::spawn_blocking(move || {
-        let mut conn_guard = db_arc.lock().map_err(|_| Status::InternalServerError)?;
-        db::delete_note_db(&mut conn_guard, id)
+    // 删除前保存快照，供撤销使用
+    let (snapshot, deleted) = task::spawn_blocking(move || {
+        let mut conn_guard = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn_guard, &id, id_scheme).map_err(handle_db_error)?;
+        let snapshot = db::get_note_snapshot_db(&conn_guard, id).map_err(handle_db_error)?;
+        db::with_busy_retry(retries, || db::delete_note_db(&mut conn_guard, id))
+             .map(|deleted| (snapshot, deleted))
              .map_err(handle_db_error)
     })
     .await
     .map_err(handle_spawn_error)??; // Double '?'
 
+    if deleted {
+        metrics.notes_deleted.inc();
+        // 已软删除的笔记没有快照，不记录（撤销也无法恢复其软删除状态）
+        if let Some((note, relations)) = snapshot {
+            db_state.undo_log().record(undo::Operation::Deleted { note, relations });
+        }
+        Ok(Status::NoContent)
+    } else {
+        Err(Status::NotFound)
+    }
+}
+
+// 把笔记移入回收站（软删除），其关系一并隐藏；可通过 POST /inbox/trash/<id>/restore 恢复，
+// 超过 INBOX_TRASH_RETENTION_DAYS 后被彻底删除
+#[post("/notes/<id>/trash")]
+async fn trash_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<Status, Status> {
+    let db_arc = db_state.inner().clone();
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+
+    let trashed = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        db::with_busy_retry(retries, || db::trash_note_db(&mut conn, id))
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    if trashed { Ok(Status::NoContent) } else { Err(Status::NotFound) }
+}
+
+// 从回收站恢复笔记及其关系，返回恢复后的笔记；笔记不在回收站中时返回 404
+#[post("/trash/<id>/restore")]
+async fn restore_trashed_note(db_state: Db<'_>, config: &State<InboxConfig>, id: NoteKey) -> Result<Json<NoteResponse>, Status> {
+    let db_arc = db_state.inner().clone();
+    let retries = config.busy_retries;
+    let id_scheme = config.id_scheme;
+
+    let restored = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let id = db::resolve_trashed_note_key(&conn, &id, id_scheme).map_err(handle_db_error)?;
+        db::with_busy_retry(retries, || db::restore_trashed_note_db(&mut conn, id))
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    restored.map(|note| Json(note_to_response(&note, config))).ok_or(Status::NotFound)
+}
+
+// 撤销本命名空间最近一次笔记创建/更新/删除（仅限本进程启动后的操作）
+#[post("/undo")]
+async fn undo_last(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>) -> Result<Json<UndoResponse>, ApiError> {
+    let operation = db_state
+        .undo_log()
+        .pop()
+        .ok_or_else(|| ApiError::new(Status::NotFound, "nothing to undo"))?;
+    let (undone, note_id, public_id) = (operation.kind(), operation.note().id, public_note_id(operation.note(), config));
+    let db_arc = db_state.inner().clone();
+    let gone_id = public_id.clone();
+    let auto_link = config.auto_link_settings();
+
+    let note = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        let gone = || ApiError::new(Status::Conflict, format!("note {} no longer exists", gone_id));
+        match operation {
+            undo::Operation::Created { .. } => {
+                if !db::delete_note_db(&mut conn, note_id).map_err(handle_db_error)? {
+                    return Err(gone());
+                }
+                Ok(None)
+            }
+            undo::Operation::Updated { before } => {
+                if !db::restore_note_db(&mut conn, &before, auto_link).map_err(handle_db_error)? {
+                    return Err(gone());
+                }
+                Ok(db::get_note_db(&conn, note_id).map_err(handle_db_error)?)
+            }
+            undo::Operation::Deleted { note, relations } => {
+                // 原 id 已被占用时触发主键冲突，返回 409
+                db::reinsert_note_db(&mut conn, &note, &relations).map_err(handle_db_error)?;
+                Ok(db::get_note_db(&conn, note_id).map_err(handle_db_error)?)
+            }
+        }
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    match undone {
+        "create" => metrics.notes_deleted.inc(),
+        "delete" => metrics.notes_created.inc(),
+        _ => {}
+    }
+    Ok(Json(UndoResponse { undone, note_id: public_id, note: note.as_ref().map(|note| note_to_response(note, config)) }))
+}
+
+// 手动清理回收站：彻底删除软删除超过 days 天的笔记（默认使用 INBOX_TRASH_RETENTION_DAYS）
+#[post("/trash/purge?<days>")]
+async fn purge_trash(db_state: Db<'_>, config: &State<InboxConfig>, days: Option<u32>) -> Result<Json<PurgeSummary>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let days = days.or(config.trash_retention_days).ok_or_else(|| {
+        ApiError::new(Status::BadRequest, "pass ?days=N or set INBOX_TRASH_RETENTION_DAYS")
+    })?;
+    let cutoff = trash::retention_cutoff(days);
+
+    let summary = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::purge_trash_db(&mut conn, cutoff)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    println!("[INFO] 手动清理回收站：彻底删除 {} 条笔记", summary.purged);
+    Ok(Json(summary))
+}
+
+// --- 模板 ---
+
+#[get("/templates")]
+async fn get_templates(db_state: Db<'_>) -> Result<Json<Vec<Template>>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_templates_db(&conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map(Json)
+}
+
+// 配置了 INBOX_ALLOWED_TAGS 时，按规范化后的标签检查，第一个不在允许列表中的标签返回 400
+fn check_allowed_tags(tags: Vec<String>, config: &InboxConfig) -> Result<(), ApiError> {
+    match validation::disallowed_tag(&validation::normalize_tags(tags), config) {
+        Some(tag) => Err(ApiError::new(Status::BadRequest, format!("tag {:?} is not in INBOX_ALLOWED_TAGS", tag))),
+        None => Ok(()),
+    }
+}
+
+#[post("/templates", data = "<payload>", format = "json")]
+async fn create_template(db_state: Db<'_>, config: &State<InboxConfig>, payload: Json<TemplatePayload>) -> Result<Created<Json<Template>>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let mut template_payload = payload.into_inner();
+    template_payload.tags = template_payload.tags.map(validation::normalize_tags);
+    check_allowed_tags(template_payload.tags.clone().unwrap_or_default(), config)?;
+
+    let created_template = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::create_template_db(&mut conn, template_payload)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    Ok(Created::new(format!("/inbox/templates/{}", created_template.id)).body(Json(created_template)))
+}
+
+#[get("/templates/<id>")]
+async fn get_template(db_state: Db<'_>, id: i64) -> Result<Json<Template>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let maybe_template = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_template_db(&conn, id)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    maybe_template.map(Json).ok_or(Status::NotFound)
+}
+
+#[put("/templates/<id>", data = "<payload>", format = "json")]
+async fn update_template(db_state: Db<'_>, config: &State<InboxConfig>, id: i64, payload: Json<TemplatePayload>) -> Result<Json<Template>, ApiError> {
+    let db_arc = db_state.inner().clone();
+    let mut template_payload = payload.into_inner();
+    template_payload.tags = template_payload.tags.map(validation::normalize_tags);
+    check_allowed_tags(template_payload.tags.clone().unwrap_or_default(), config)?;
+
+    let maybe_template = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::update_template_db(&mut conn, id, template_payload)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    maybe_template.map(Json).ok_or(Status::NotFound.into())
+}
+
+#[delete("/templates/<id>")]
+async fn delete_template(db_state: Db<'_>, id: i64) -> Result<Status, Status> {
+    let db_arc = db_state.inner().clone();
+
+    let deleted = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::delete_template_db(&mut conn, id)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
     if deleted {
         Ok(Status::NoContent)
     } else {
@@ -296,6 +2780,192 @@ async fn delete_note(db_state: &State<SharedDb>, id: i64) -> Result<Status, Stat
     }
 }
 
+// 用模板内容和标签创建新笔记，{{date}} 替换为当天日期 (UTC, YYYY-MM-DD)
+fn render_template_content(content: &str, now: chrono::DateTime<chrono::Utc>) -> String {
+    content.replace("{{date}}", &now.format("%Y-%m-%d").to_string())
+}
+
+// 与 /notes/<note_id>/comments 形状冲突，降低优先级（非数字的 note_id 会转发到这里）
+#[post("/notes/from-template/<template_id>", rank = 2)]
+async fn create_note_from_template(db_state: Db<'_>, config: &State<InboxConfig>, metrics: &State<Metrics>, template_id: i64) -> Result<Created<Json<NoteResponse>>, ApiError> {
+    let db_arc = db_state.inner().clone();
+
+    let template = task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_template_db(&conn, template_id)
+            .map_err(handle_db_error)?
+            .ok_or(Status::NotFound)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    // 渲染结果按创建笔记的规则校验后写入（模板可能创建于 INBOX_ALLOWED_TAGS 等限制之前），与 POST /notes 同一流程
+    let now = chrono::Utc::now();
+    let payload = CreateNotePayload {
+        content: render_template_content(&template.content, now),
+        tags: Some(template.tags),
+        created_at: Some(now),
+        color: None,
+        slug: None,
+        lang: None,
+    };
+    insert_note(db_state, config, metrics, payload).await
+}
+
+// --- 管理接口 ---
+
+// 连接池使用情况，用于排查连接耗尽
+#[get("/admin/pool")]
+fn admin_pool(_admin: AdminKey, db_state: Db<'_>) -> Json<PoolStats> {
+    let state = db_state.state();
+    Json(PoolStats {
+        size: db_state.max_size(),
+        idle: state.idle_connections,
+        in_use: state.connections - state.idle_connections,
+    })
+}
+
+// 重建索引及 FTS 表，适用于手工修改数据库或批量导入之后
+#[post("/admin/reindex")]
+async fn admin_reindex(_admin: AdminKey, db_state: Db<'_>) -> Result<Json<ReindexSummary>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::reindex_db(&mut conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map(Json)
+}
+
+// 运行 integrity_check 与 foreign_key_check；发现问题时返回 500 和详情。
+// 文件数据库在连接池之外单独打开只读连接执行，检查再久也不会占用池中的连接
+#[get("/admin/integrity")]
+async fn admin_integrity(_admin: AdminKey, db_state: Db<'_>) -> Result<(Status, Json<IntegrityReport>), Status> {
+    let db_arc = db_state.inner().clone();
+
+    let report = task::spawn_blocking(move || {
+        let path = {
+            let conn = db_arc.get().map_err(handle_pool_error)?;
+            db::database_path(&conn).map_err(handle_db_error)?
+        };
+        match path {
+            Some(path) => db::open_read_only(&path).and_then(|conn| db::check_integrity_db(&conn)),
+            None => db::check_integrity_db(&*db_arc.get().map_err(handle_pool_error)?),
+        }
+        .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    let status = if report.ok { Status::Ok } else { Status::InternalServerError };
+    Ok((status, Json(report)))
+}
+
+// 列出源或目标笔记已不存在的关系
+#[get("/admin/orphan-relations")]
+async fn admin_orphan_relations(_admin: AdminKey, db_state: Db<'_>) -> Result<Json<OrphanRelations>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_orphan_relations_db(&conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map(Json)
+}
+
+// tags 列损坏（非法 JSON 或不是字符串数组）的笔记；读取时这些笔记按空标签处理
+#[get("/admin/bad-tags")]
+async fn admin_bad_tags(_admin: AdminKey, db_state: Db<'_>) -> Result<Json<BadTagsReport>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let conn = db_arc.get().map_err(handle_pool_error)?;
+        db::get_bad_tags_db(&conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map(Json)
+}
+
+// 在一个事务中修复损坏的 tags（尽量保留数组中的字符串标签，否则置为空数组），返回修复的行数
+#[post("/admin/bad-tags/repair")]
+async fn admin_repair_bad_tags(_admin: AdminKey, db_state: Db<'_>) -> Result<Json<RepairSummary>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::repair_bad_tags_db(&mut conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map(Json)
+}
+
+// 从头重建标签计数汇总表（手工修改数据库后，或让 last_modified 与实时扫描重新一致）
+#[post("/admin/tag-counts/rebuild")]
+async fn admin_rebuild_tag_counts(_admin: AdminKey, db_state: Db<'_>) -> Result<Json<TagCountsRebuild>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::rebuild_tag_counts_db(&mut conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map(Json)
+}
+
+// 在一个事务中删除所有孤立关系
+#[post("/admin/orphan-relations/prune")]
+async fn admin_prune_orphan_relations(_admin: AdminKey, db_state: Db<'_>) -> Result<Json<PruneSummary>, Status> {
+    let db_arc = db_state.inner().clone();
+
+    task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::prune_orphan_relations_db(&mut conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)?
+    .map(Json)
+}
+
+// 清空本命名空间的笔记与关系（测试与演示用）。除管理密钥外还要求 INBOX_ALLOW_RESET=true
+// 且显式带上 ?confirm=true，避免在生产环境误触发
+#[post("/admin/reset?<confirm>")]
+async fn admin_reset(_admin: AdminKey, db_state: Db<'_>, config: &State<InboxConfig>, confirm: Option<bool>) -> Result<Json<ResetSummary>, ApiError> {
+    if !config.allow_reset {
+        return Err(ApiError::new(Status::Forbidden, "reset is disabled (set INBOX_ALLOW_RESET=true to enable)"));
+    }
+    if confirm != Some(true) {
+        return Err(ApiError::new(Status::BadRequest, "reset requires ?confirm=true"));
+    }
+    let db_arc = db_state.inner().clone();
+
+    let summary = task::spawn_blocking(move || {
+        let mut conn = db_arc.get().map_err(handle_pool_error)?;
+        db::reset_db(&mut conn)
+            .map_err(handle_db_error)
+    })
+    .await
+    .map_err(handle_spawn_error)??;
+
+    // 内存中引用旧笔记的状态一并丢弃
+    db_state.undo_log().clear();
+    db_state.tracker().take_pending();
+    eprintln!("[WARN] 数据库已重置：删除 {} 条笔记、{} 条关系", summary.notes, summary.relations);
+    Ok(Json(summary))
+}
+
 // 修改migrate_db函数，解决借用问题
 pub async fn migrate_db(db_path: &str) -> Result<(), Status> {
     // 复制路径字符串，以便在闭包中使用
@@ -312,7 +2982,10 @@ pub async fn migrate_db(db_path: &str) -> Result<(), Status> {
         // 执行迁移
         db::migrate(&conn).map_err(|e| {
             eprintln!("数据库迁移操作失败: {:?}", e);
-            handle_db_error(e)
+            match e {
+                db::MigrateError::Db(e) => handle_db_error(e),
+                db::MigrateError::SchemaTooNew { .. } => Status::InternalServerError,
+            }
         })
     }).await.map_err(|_| Status::InternalServerError)?
 }
\ No newline at end of file
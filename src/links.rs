@@ -0,0 +1,39 @@
+// src/links.rs
+use std::collections::HashSet;
+
+use crate::models::Note;
+
+// --- 笔记内容中的 [[<id>]] 引用 ---
+// 只识别双方括号内全部为 ASCII 数字的写法（如 [[12]]）；[[ 12 ]]、[[abc]] 和超出 i64 的数字都不算引用。
+
+// 内容中引用的笔记 id，按首次出现的顺序去重
+pub fn note_refs(content: &str) -> Vec<i64> {
+    let mut refs = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 && rest[digits..].starts_with("]]") {
+            if let Ok(id) = rest[..digits].parse::<i64>() {
+                if !refs.contains(&id) {
+                    refs.push(id);
+                }
+            }
+            rest = &rest[digits + 2..];
+        }
+    }
+    refs
+}
+
+// 引用了不存在笔记的笔记及其断开的引用（GET /inbox/notes/broken-links）。notes 为全部可见笔记，
+// 引用回收站中的笔记也算断链；resolvable 为 false 时（uuid 模式）所有引用都算断链。结果按 notes 的顺序排列
+pub fn find_broken_links(notes: &[Note], resolvable: bool) -> Vec<(&Note, Vec<i64>)> {
+    let existing: HashSet<i64> = if resolvable { notes.iter().map(|note| note.id).collect() } else { HashSet::new() };
+    notes
+        .iter()
+        .filter_map(|note| {
+            let missing_refs: Vec<i64> = note_refs(&note.content).into_iter().filter(|id| !existing.contains(id)).collect();
+            (!missing_refs.is_empty()).then_some((note, missing_refs))
+        })
+        .collect()
+}
@@ -1,7 +1,9 @@
-use aw_inbox_rust::{mount_rocket, db};
-use std::sync::{Arc, Mutex};
+use aw_inbox_rust::config::InboxConfig;
+use aw_inbox_rust::namespace::{Namespaces, DEFAULT_NAMESPACE};
+use aw_inbox_rust::{mount_rocket_with_namespaces, checkpoint, db};
 
 #[rocket::main]
+#[allow(clippy::result_large_err)] // rocket::Error 本身较大，main 直接返回即可
 async fn main() -> Result<(), rocket::Error> {
     let config = rocket::Config {
         port: 5600,
@@ -10,17 +12,34 @@ async fn main() -> Result<(), rocket::Error> {
     };
     println!("[DEBUG] Rocket config: address={:?}, port={:?}", config.address, config.port);
 
-    // 使用固定的数据库路径
-    let db_path = "inbox.db";
-    
-    // 先迁移数据库
-    aw_inbox_rust::migrate_db(db_path).await.expect("数据库迁移失败");
-    
-    // 初始化数据库连接池
-    let pool = db::init_pool().await.expect("数据库连接失败");
-    let db = Arc::new(Mutex::new(pool));
+    // 初始化各命名空间的数据库连接池（迁移在 Rocket 点火阶段执行，完成前 /inbox/readyz 返回 503）
+    let inbox_config = InboxConfig::from_env();
+    let mut databases = vec![(DEFAULT_NAMESPACE.to_string(), db::default_database_url().expect("无法确定数据库路径"))];
+    for (name, path) in &inbox_config.namespaces {
+        match databases.iter_mut().find(|(existing, _)| existing == name) {
+            Some(entry) => entry.1 = path.clone(),
+            None => databases.push((name.clone(), path.clone())),
+        }
+    }
 
-    let _ = mount_rocket(rocket::custom(config), db)
+    let mut pools = Vec::with_capacity(databases.len());
+    for (name, path) in &databases {
+        let pool = db::init_pool_at(path.clone(), inbox_config.pool_size, inbox_config.query_timeout(), inbox_config.encryption_key)
+            .await
+            .expect("数据库连接失败");
+        pools.push((name.clone(), pool));
+    }
+
+    // WAL 定期检查点（间隔为 0 时不启动），每个数据库文件一个任务
+    if inbox_config.wal_checkpoint_secs > 0 {
+        let interval = std::time::Duration::from_secs(inbox_config.wal_checkpoint_secs);
+        println!("[INFO] 每 {:?} 执行一次 WAL 检查点", interval);
+        for (_, path) in &databases {
+            tokio::spawn(checkpoint::run_checkpoint_loop(path.clone(), interval));
+        }
+    }
+
+    let _ = mount_rocket_with_namespaces(rocket::custom(config), Namespaces::new(pools), inbox_config)
         .launch()
         .await?;
     Ok(())
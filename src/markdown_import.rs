@@ -0,0 +1,70 @@
+// src/markdown_import.rs
+
+// --- Markdown 文件导入 ---
+// 每个文件生成一条笔记：文件名（去掉扩展名）作为一级标题，
+// 文件开头的 front matter（--- 包围的 YAML）中的 tags 并入标签，front matter 本身不保留。
+
+pub struct MarkdownNote {
+    pub content: String,
+    pub tags: Vec<String>,
+}
+
+pub fn parse_markdown_file(title: &str, text: &str) -> MarkdownNote {
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let (front_matter, body) = split_front_matter(&text);
+    let tags = front_matter.map(front_matter_tags).unwrap_or_default();
+
+    let body = body.trim();
+    let heading = format!("# {}", title);
+    // 正文已经以同名标题开头时不再重复
+    let content = if body.lines().next().map(str::trim_end) == Some(heading.as_str()) {
+        body.to_string()
+    } else if body.is_empty() {
+        heading
+    } else {
+        format!("{}\n\n{}", heading, body)
+    };
+    MarkdownNote { content, tags }
+}
+
+// front matter 必须从第一行的 --- 开始，到单独一行的 --- 或 ... 结束；未闭合时视为普通正文
+fn split_front_matter(text: &str) -> (Option<&str>, &str) {
+    let Some(rest) = text.strip_prefix("---\n") else {
+        return (None, text);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, text)
+}
+
+// 支持 YAML 中常见的三种写法：tags: [a, b]、tags: a, b、以及逐行的 "- a" 列表
+fn front_matter_tags(front_matter: &str) -> Vec<String> {
+    let mut lines = front_matter.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some(value) = line.strip_prefix("tags:") else {
+            continue;
+        };
+        let value = value.trim();
+        let items: Vec<&str> = if value.is_empty() {
+            let mut items = Vec::new();
+            while let Some(item) = lines.peek().and_then(|l| l.trim_start().strip_prefix('-')) {
+                items.push(item);
+                lines.next();
+            }
+            items
+        } else {
+            value.trim_start_matches('[').trim_end_matches(']').split(',').collect()
+        };
+        return items
+            .into_iter()
+            .map(|item| item.trim().trim_matches(|c| c == '"' || c == '\'').trim_start_matches('#').to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+    }
+    Vec::new()
+}
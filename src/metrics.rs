@@ -0,0 +1,103 @@
+// src/metrics.rs
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Request, Response};
+
+use crate::{db, SharedDb};
+
+// --- Prometheus 指标 ---
+// 作为 Rocket 托管状态；GET /metrics 以文本格式导出。
+// 克隆共享同一组指标（prometheus 的指标类型内部是 Arc）。
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    pub notes_created: IntCounter,
+    pub notes_deleted: IntCounter,
+    notes_total: IntGaugeVec,
+    // 笔记总数在抓取时按命名空间实时统计
+    databases: Vec<(String, SharedDb)>,
+}
+
+impl Metrics {
+    pub fn new(databases: Vec<(String, SharedDb)>) -> Self {
+        let registry = Registry::new_custom(Some("inbox".to_string()), None).expect("valid metrics prefix");
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route, method and status"),
+            &["route", "method", "status"],
+        )
+        .expect("valid metric");
+        let notes_created = IntCounter::new("notes_created_total", "Notes created").expect("valid metric");
+        let notes_deleted = IntCounter::new("notes_deleted_total", "Notes deleted").expect("valid metric");
+        let notes_total = IntGaugeVec::new(Opts::new("notes", "Current number of notes"), &["namespace"]).expect("valid metric");
+
+        registry.register(Box::new(http_requests.clone())).expect("unique metric");
+        registry.register(Box::new(notes_created.clone())).expect("unique metric");
+        registry.register(Box::new(notes_deleted.clone())).expect("unique metric");
+        registry.register(Box::new(notes_total.clone())).expect("unique metric");
+        registry.register(Box::new(query_histogram().clone())).expect("unique metric");
+
+        Metrics { registry, http_requests, notes_created, notes_deleted, notes_total, databases }
+    }
+
+    // 刷新笔记总数后导出全部指标
+    pub fn render(&self) -> String {
+        for (namespace, db) in &self.databases {
+            let count = db
+                .get()
+                .ok()
+                .and_then(|conn| db::count_notes_db(&conn, db::NoteFilter::default()).ok());
+            if let Some(count) = count {
+                self.notes_total.with_label_values(&[namespace]).set(count);
+            }
+        }
+
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            eprintln!("[ERROR] 导出指标失败: {:?}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+// SQL 语句耗时。由 SQLite 的 profile 回调（只能是函数指针）记录，因此是进程级全局指标，
+// 每个 Metrics 的 registry 都注册同一个实例。
+fn query_histogram() -> &'static Histogram {
+    static QUERY_SECONDS: OnceLock<Histogram> = OnceLock::new();
+    QUERY_SECONDS.get_or_init(|| {
+        Histogram::with_opts(
+            HistogramOpts::new("db_query_duration_seconds", "SQLite statement execution time")
+                .buckets(vec![0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0]),
+        )
+        .expect("valid metric")
+    })
+}
+
+pub fn observe_query(elapsed: Duration) {
+    query_histogram().observe(elapsed.as_secs_f64());
+}
+
+// --- 请求计数 Fairing ---
+// 按路由模板（而不是实际路径）计数，避免 id 等参数导致标签数量无限增长
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info { name: "Request Metrics", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(metrics) = request.rocket().state::<Metrics>() else {
+            return;
+        };
+        let route = request.route().map_or_else(|| "unmatched".to_string(), |route| route.uri.to_string());
+        metrics
+            .http_requests
+            .with_label_values(&[&route, request.method().as_str(), &response.status().code.to_string()])
+            .inc();
+    }
+}
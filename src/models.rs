@@ -1,5 +1,6 @@
 // src/models.rs
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 // Removed: use sqlx::FromRow;
 
@@ -12,31 +13,239 @@ pub struct Note {
     pub tags: Vec<String>, // <<< Changed from String to Vec<String>
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub color: Option<String>, // 展示用颜色：#rrggbb 或预设颜色名
+    pub slug: Option<String>,  // 永久链接 GET /inbox/n/<slug>
+    pub lang: Option<String>,  // ISO 639-1 语言代码
+    pub uid: Option<String>,   // 对外的 UUID（INBOX_ID_SCHEME=uuid 时作为 API 中的 id）
+    pub pinned: bool,
+    pub archived_at: Option<DateTime<Utc>>,
 }
 
-// 用于创建新笔记的请求体结构 (Remains the same)
-#[derive(Deserialize, Debug)]
+// API 中的笔记 id：整数或 UUID，取决于 INBOX_ID_SCHEME
+#[derive(Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum NoteId {
+    Int(i64),
+    Uuid(String),
+}
+
+impl std::fmt::Display for NoteId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoteId::Int(id) => write!(f, "{}", id),
+            NoteId::Uuid(uid) => f.write_str(uid),
+        }
+    }
+}
+
+impl From<NoteKey> for NoteId {
+    fn from(key: NoteKey) -> Self {
+        match key {
+            NoteKey::Id(id) => NoteId::Int(id),
+            NoteKey::Uid(uid) => NoteId::Uuid(uid),
+        }
+    }
+}
+
+// 路径参数或请求体中的笔记（及关系）标识，由 db::resolve_note_key 解析为内部整数 id。
+// 请求体中可以是整数或 UUID 字符串
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NoteKey {
+    Id(i64),
+    Uid(String),
+}
+
+impl<'de> Deserialize<'de> for NoteKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Id(i64),
+            Uid(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Id(id) => Ok(NoteKey::Id(id)),
+            Raw::Uid(uid) => uuid::Uuid::parse_str(&uid)
+                .map(|uid| NoteKey::Uid(uid.hyphenated().to_string()))
+                .map_err(|_| serde::de::Error::custom(format!("invalid note id {:?}", uid))),
+        }
+    }
+}
+
+impl std::fmt::Display for NoteKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoteKey::Id(id) => write!(f, "{}", id),
+            NoteKey::Uid(uid) => f.write_str(uid),
+        }
+    }
+}
+
+// 用于创建新笔记的请求体结构 (Serialize 用于 /notes/validate 返回规范化结果；JsonSchema 用于 GET /inbox/schema)
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct CreateNotePayload {
     pub content: String,
     pub tags: Option<Vec<String>>,
     pub created_at: Option<DateTime<Utc>>,
+    pub color: Option<String>,
+    pub slug: Option<String>, // 自定义 slug（[a-z0-9-]+）；未指定时由首行生成
+    pub lang: Option<String>, // ISO 639-1 语言代码；未指定时按内容自动检测
+}
+
+// POST /inbox/notes/upsert 的请求体：按 external_id 更新已有笔记，没有时新建
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpsertNotePayload {
+    pub external_id: String,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
 }
 
 // 用于更新笔记的请求体结构 (Remains the same)
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct UpdateNotePayload {
     pub content: String,
     pub tags: Option<Vec<String>>,
+    pub color: Option<String>,
 }
 
 // 用于 API 响应的笔记结构 (Remains the same, tags is Vec<String>)
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct NoteResponse {
-   pub id: i64,
+   pub id: NoteId,
    pub content: String,
+   pub preview: String, // content 的前 INBOX_PREVIEW_LEN 个字符，截断时以 … 结尾
    pub tags: Vec<String>, // API 层面返回 Vec<String>
+   #[schemars(with = "DateTime<Utc>")]
    pub created_at: String, // ISO 8601 格式字符串
+   #[schemars(with = "DateTime<Utc>")]
    pub updated_at: String, // ISO 8601 格式字符串
+   pub color: Option<String>,
+   pub slug: Option<String>,
+   pub lang: Option<String>,
+   pub pinned: bool,
+   #[schemars(with = "Option<DateTime<Utc>>")]
+   pub archived_at: Option<String>, // 未归档时为 null
+}
+
+// POST /inbox/notes/upsert 的响应：笔记本身，外加是否为新建
+#[derive(Serialize, Debug)]
+pub struct UpsertNoteResponse {
+    #[serde(flatten)]
+    pub note: NoteResponse,
+    pub created: bool,
+}
+
+// GET /inbox/notes/<id>/meta：修改时间、更新次数和内容哈希，用于审计视图与变更检测
+#[derive(Serialize, Debug)]
+pub struct NoteMeta {
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub update_count: i64,
+    pub content_hash: String,
+}
+
+// 笔记的一个历史版本（GET /inbox/notes/<id>/history），version_id 用于
+// POST /inbox/notes/<id>/restore/<version_id>
+#[derive(Serialize, Debug)]
+pub struct NoteVersion {
+    pub version_id: i64,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub edited_at: DateTime<Utc>, // 该版本被保存的时间
+}
+
+// 笔记的关系数量（GET /inbox/notes?with_counts=true）
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct RelationCounts {
+    pub relation_count: i64,
+    pub comment_count: i64,
+}
+
+// GET /inbox/notes/diff 的响应
+#[derive(Serialize, Debug)]
+pub struct NoteDiff {
+    pub a: NoteId,
+    pub b: NoteId,
+    pub identical: bool,
+    pub lines: Vec<DiffLine>,
+    pub tags: TagDiff,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffLine {
+    pub op: &'static str, // "equal" / "delete"（仅在 a 中）/ "insert"（仅在 b 中）
+    pub text: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TagDiff {
+    pub only_a: Vec<String>,
+    pub only_b: Vec<String>,
+}
+
+// POST /inbox/notes/batch-get 的响应：找到的笔记（按请求顺序）和不存在的 id
+#[derive(Serialize, Debug)]
+pub struct BatchGetResponse {
+    pub notes: Vec<NoteResponse>,
+    pub missing: Vec<NoteId>,
+}
+
+// 连接池状态（GET /inbox/admin/pool）
+#[derive(Serialize, Debug)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: u32,
+    pub in_use: u32,
+}
+
+// GET /inbox/notes/<id>/full：笔记及其评论、出链和反向链接（反向链接不含评论）
+#[derive(Serialize, Debug)]
+pub struct FullNoteResponse {
+    pub note: NoteResponse,
+    pub comments: Vec<NoteResponse>,
+    pub relations: Vec<RelationResponse>,
+    pub backlinks: Vec<RelationResponse>,
+}
+
+// GET /inbox/search/linked：内容匹配的笔记及与它们直接相连的笔记（不重复，不含已匹配的笔记）
+#[derive(Serialize, Debug)]
+pub struct LinkedSearchResponse {
+    pub matched: Vec<NoteResponse>,
+    pub linked: Vec<NoteResponse>,
+}
+
+// 只返回数量的响应，如 { "count": 12 }
+#[derive(Serialize, Debug)]
+pub struct CountResponse {
+    pub count: i64,
+}
+
+// 导入结果
+#[derive(Serialize, Debug)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub ids: Vec<NoteId>,
+}
+
+// POST /inbox/import/stream 的结果：skipped_lines 为被跳过的行号（从 1 开始，最多列出前 1000 个）
+#[derive(Serialize, Debug)]
+pub struct StreamImportSummary {
+    pub imported: usize,
+    pub skipped: usize,
+    pub skipped_lines: Vec<usize>,
+}
+
+// 请求体校验失败时的字段级错误
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        FieldError { field, message: message.into() }
+    }
 }
 
 // 用于数据库交互和 API 响应的 Tag 结构体
@@ -59,15 +268,89 @@ pub struct DetailedTag {
     pub last_modified: Option<DateTime<Utc>>, // <<< Changed from Option<String>
 }
 
+// GET /inbox/tags/allowed：restricted 为 false 时任何标签都可以使用
+#[derive(Serialize, Debug)]
+pub struct AllowedTags {
+    pub restricted: bool,
+    pub tags: Vec<String>,
+}
+
+// GET /inbox/notes/broken-links 的一项：note_id 的内容中 [[id]] 引用了不存在的笔记
+#[derive(Serialize, Debug)]
+pub struct BrokenLinks {
+    pub note_id: NoteId,
+    pub missing_refs: Vec<i64>,
+}
+
+// 层级标签树的一个节点（GET /inbox/tags/tree）：work/projects/x 依次嵌套在 work、work/projects 下
+#[derive(Serialize, Debug)]
+pub struct TagTreeNode {
+    pub name: String,  // 本层的名称，如 projects
+    pub path: String,  // 完整路径，如 work/projects
+    pub count: i64,    // 直接使用该完整路径作为标签的笔记数
+    pub children: Vec<TagTreeNode>,
+}
+
+// 标签的存储占用（GET /inbox/tags/usage）
+#[derive(Serialize, Debug)]
+pub struct TagUsage {
+    pub name: String,
+    pub count: i64,
+    pub bytes: i64, // 使用该标签的笔记内容总字节数
+}
+
 // 笔记关系类型枚举
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum NoteRelationType {
     Comment,  // 评论关系
     Reference, // 引用关系
     Link,      // 链接关系
+    Parent,    // 层级关系：target 是 source 的父笔记，不允许形成环
     // 可以根据需要添加更多关系类型
 }
 
+// 从某条笔记出发按关系遍历得到的子图（GET /inbox/notes/<id>/graph）
+#[derive(Serialize, Debug)]
+pub struct NoteGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<RelationResponse>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GraphNode {
+    pub id: NoteId,
+    pub depth: u32, // 与起点之间的最少关系数（忽略方向）
+}
+
+// 某一天创建的笔记数（GET /inbox/activity，用于日历热力图）
+#[derive(Serialize, Debug)]
+pub struct ActivityDay {
+    pub date: String, // YYYY-MM-DD (UTC)
+    pub count: i64,
+}
+
+// 标签总览中的一栏（GET /inbox/overview）：标签及其最新的几条笔记
+#[derive(Serialize, Debug)]
+pub struct TagOverview {
+    pub tag: String,
+    pub notes: Vec<NoteResponse>,
+}
+
+// 与某个标签出现在同一笔记上的标签及共同出现的笔记数（GET /inbox/tags/<name>/related）
+#[derive(Serialize, Debug)]
+pub struct RelatedTag {
+    pub name: String,
+    pub count: i64,
+}
+
+// 关系类型及其使用次数（GET /inbox/relations/types）
+#[derive(Serialize, Debug)]
+pub struct RelationTypeCount {
+    #[serde(rename = "type")]
+    pub relation_type: NoteRelationType,
+    pub count: i64,
+}
+
 // 用于数据库交互的笔记关系结构体
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NoteRelation {
@@ -76,17 +359,207 @@ pub struct NoteRelation {
     pub target_note_id: i64,  // 目标笔记ID（如被评论的笔记）
     pub relation_type: NoteRelationType, // 关系类型
     pub created_at: DateTime<Utc>,
+    pub uid: Option<String>,  // 对外的 UUID（INBOX_ID_SCHEME=uuid 时作为 API 中的关系 id）
+}
+
+// 用于 API 响应的笔记关系：id 及两端笔记的 id 均为对外 id（见 NoteId）
+#[derive(Serialize, Debug)]
+pub struct RelationResponse {
+    pub id: NoteId,
+    pub source_note_id: NoteId,
+    pub target_note_id: NoteId,
+    pub relation_type: NoteRelationType,
+    pub created_at: DateTime<Utc>,
 }
 
 // 用于创建笔记关系的请求体结构
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct CreateNoteRelationPayload {
     pub relation_type: NoteRelationType,  // 关系类型（默认为Comment）
 }
 
-// 用于创建评论的请求体结构 (与CreateNotePayload结合)
+// POST /inbox/notes/merge 的请求体
+#[derive(Deserialize, Debug)]
+pub struct MergeNotesPayload {
+    pub primary: NoteKey,
+    pub others: Vec<NoteKey>,
+}
+
+// POST /inbox/tags/rename（及 /preview）的请求体
 #[derive(Deserialize, Debug)]
+pub struct TagRenamePayload {
+    pub from: String,
+    pub to: String,
+}
+
+// POST /inbox/tags/merge（及 /preview）的请求体：把 sources 中的标签都改为 target
+#[derive(Deserialize, Debug)]
+pub struct TagMergePayload {
+    pub sources: Vec<String>,
+    pub target: String,
+}
+
+// 标签改名/合并修改（预览时为将要修改）的笔记
+#[derive(Serialize, Debug)]
+pub struct TagRewriteSummary {
+    pub count: usize,
+    pub note_ids: Vec<NoteId>,
+}
+
+// GET /inbox/ 的服务信息
+#[derive(Serialize, Debug)]
+pub struct ServiceInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub routes: Vec<String>,
+}
+
+// POST /inbox/triage 的请求体：每组为笔记 id，省略的组视为空；同一 id 只能出现在一组中
+#[derive(Deserialize, Debug, Default)]
+pub struct TriagePayload {
+    #[serde(default)]
+    pub archive: Vec<NoteKey>,
+    #[serde(default)]
+    pub delete: Vec<NoteKey>,
+    #[serde(default)]
+    pub pin: Vec<NoteKey>,
+}
+
+// POST /inbox/triage 的结果：各操作实际影响的笔记数（不存在的 id 不计入）
+#[derive(Serialize, Debug)]
+pub struct TriageSummary {
+    pub archived: usize,
+    pub deleted: usize,
+    pub pinned: usize,
+}
+
+// 批量创建关系时的单条记录
+#[derive(Deserialize, Debug, Clone)]
+pub struct BulkRelationEntry {
+    pub source_id: NoteKey,
+    pub target_id: NoteKey,
+    pub relation_type: NoteRelationType,
+}
+
+// POST /inbox/notes/<source_id>/link 的请求体：新笔记的内容与标签，以及从源笔记指向它的关系类型
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateLinkedNotePayload {
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+    pub relation_type: NoteRelationType,
+}
+
+// 创建并关联的结果
+#[derive(Serialize, Debug)]
+pub struct LinkedNoteResponse {
+    pub note: NoteResponse,
+    pub relation: RelationResponse,
+}
+
+// POST /inbox/undo 的结果：被撤销的操作类型 (create/update/delete) 及撤销后的笔记（撤销创建时为 null）
+#[derive(Serialize, Debug)]
+pub struct UndoResponse {
+    pub undone: &'static str,
+    pub note_id: NoteId,
+    pub note: Option<NoteResponse>,
+}
+
+// 用于创建评论的请求体结构 (与CreateNotePayload结合)
+#[derive(Deserialize, Debug, Clone)]
 pub struct CreateCommentPayload {
     pub content: String,        // 评论内容
     pub tags: Option<Vec<String>>, // 评论标签（可选）
-}
\ No newline at end of file
+}
+
+// 笔记模板（如会议记录、日志），content 中的 {{date}} 在实例化时替换为当天日期
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Template {
+    pub id: i64,
+    pub name: String,
+    pub content: String,
+    pub tags: Vec<String>,
+}
+
+// 用于创建/更新模板的请求体结构
+#[derive(Deserialize, Debug)]
+pub struct TemplatePayload {
+    pub name: String,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+}
+
+// POST /inbox/admin/reindex 的执行结果
+#[derive(Serialize, Debug)]
+pub struct ReindexSummary {
+    pub indexes: Vec<String>,    // 重建的索引
+    pub fts_tables: Vec<String>, // 重建的全文检索表
+}
+
+// 引用了不存在笔记的关系（GET /inbox/admin/orphan-relations）
+#[derive(Serialize, Debug)]
+pub struct OrphanRelations {
+    pub count: usize,
+    pub relations: Vec<NoteRelation>,
+}
+
+// 数据库完整性检查结果（GET /inbox/admin/integrity）；ok 为 false 时列出发现的问题
+#[derive(Serialize, Debug)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub integrity_errors: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+}
+
+// PRAGMA foreign_key_check 的一行：table 中 rowid 这一行引用了 parent 中不存在的行
+#[derive(Serialize, Debug)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+// tags 列无法解析为字符串数组的笔记（GET /inbox/admin/bad-tags）；tags 为原始值，NULL 时为 null
+#[derive(Serialize, Debug)]
+pub struct BadTagsReport {
+    pub count: usize,
+    pub notes: Vec<BadTagsEntry>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BadTagsEntry {
+    pub id: i64,
+    pub tags: Option<String>,
+}
+
+// POST /inbox/trash/purge 的结果
+#[derive(Serialize, Debug)]
+pub struct PurgeSummary {
+    pub purged: usize,
+}
+
+// POST /inbox/admin/orphan-relations/prune 的结果
+#[derive(Serialize, Debug)]
+pub struct PruneSummary {
+    pub pruned: usize,
+}
+
+// POST /inbox/admin/bad-tags/repair 的结果
+#[derive(Serialize, Debug)]
+pub struct RepairSummary {
+    pub repaired: usize,
+}
+
+// POST /inbox/admin/tag-counts/rebuild 的结果：重建后的标签数
+#[derive(Serialize, Debug)]
+pub struct TagCountsRebuild {
+    pub tags: usize,
+}
+
+// POST /inbox/admin/reset 删除的行数
+#[derive(Serialize, Debug)]
+pub struct ResetSummary {
+    pub notes: usize,
+    pub relations: usize,
+}
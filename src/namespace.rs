@@ -0,0 +1,170 @@
+// src/namespace.rs
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::access::AccessTracker;
+use crate::request_id;
+use crate::undo::UndoLog;
+use crate::SharedDb;
+
+// 默认命名空间挂载在 /inbox 下（保持原有路径），其余命名空间挂载在 /inbox/<ns> 下
+pub const DEFAULT_NAMESPACE: &str = "default";
+pub const BASE_PATH: &str = "/inbox";
+
+// --- 命名空间 ---
+// 每个命名空间对应一个独立的数据库（各自的连接池、访问计数与撤销日志），作为 Rocket 托管状态。
+pub struct Namespace {
+    pub db: SharedDb,
+    tracker: AccessTracker,
+    undo_log: UndoLog,
+}
+
+#[derive(Default)]
+pub struct Namespaces {
+    by_name: BTreeMap<String, Namespace>,
+}
+
+impl Namespaces {
+    pub fn new(pools: impl IntoIterator<Item = (String, SharedDb)>) -> Self {
+        let by_name = pools
+            .into_iter()
+            .map(|(name, db)| (name, Namespace { db, tracker: AccessTracker::default(), undo_log: UndoLog::default() }))
+            .collect();
+        Namespaces { by_name }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Namespace> {
+        self.by_name.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Namespace)> {
+        self.by_name.iter()
+    }
+
+    // 命名空间对应的挂载点
+    pub fn mount_point(name: &str) -> String {
+        if name == DEFAULT_NAMESPACE {
+            BASE_PATH.to_string()
+        } else {
+            format!("{}/{}", BASE_PATH, name)
+        }
+    }
+}
+
+// /inbox 下各路由的第一段。命名空间挂载在 /inbox/<ns>，同名时会与默认命名空间的路由冲突，
+// 新增顶层路由时要一并加到这里（tests/namespace_api_test.rs 会检查）
+const RESERVED_NAMES: &[&str] = &[
+    "activity", "admin", "archive", "comments", "count", "export", "import", "livez", "metrics", "n", "notes",
+    "overview", "readyz", "relations", "schema", "search", "tags", "templates", "trash", "triage", "undo",
+];
+
+// 命名空间只能由字母、数字、'-' 和 '_' 组成（会出现在 URL 路径中），且不能是保留的路由名
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !RESERVED_NAMES.contains(&name)
+}
+
+// --- 请求守卫 ---
+// 按匹配到的路由挂载点选出当前命名空间的数据库，用法与 &State<SharedDb> 相同。
+pub struct Db<'r> {
+    namespace: &'r Namespace,
+    request_id: Option<&'r str>,
+}
+
+impl<'r> Db<'r> {
+    // 带有当前请求 ID 的连接池句柄，可以 clone 后移入 spawn_blocking
+    pub fn inner(&self) -> RequestPool {
+        RequestPool { pool: self.namespace.db.clone(), request_id: self.request_id.map(str::to_string) }
+    }
+
+    pub fn tracker(&self) -> &'r AccessTracker {
+        &self.namespace.tracker
+    }
+
+    pub fn undo_log(&self) -> &'r UndoLog {
+        &self.namespace.undo_log
+    }
+}
+
+impl Deref for Db<'_> {
+    type Target = SharedDb;
+
+    fn deref(&self) -> &SharedDb {
+        &self.namespace.db
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Db<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let (Some(namespaces), Some(route)) = (request.rocket().state::<Namespaces>(), request.route()) else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        let base = route.uri.base();
+        let name = match base.strip_prefix(BASE_PATH).map(|rest| rest.trim_start_matches('/')) {
+            Some("") => DEFAULT_NAMESPACE,
+            Some(name) => name,
+            None => return Outcome::Error((Status::NotFound, ())),
+        };
+        match namespaces.get(name) {
+            Some(namespace) => Outcome::Success(Db { namespace, request_id: request_id::get(request) }),
+            None => Outcome::Error((Status::NotFound, ())),
+        }
+    }
+}
+
+// --- 带请求 ID 的连接 ---
+// get() 取得的连接存活期间，当前线程的请求 ID（request_id::tag）为发起请求的 ID，
+// 因此 spawn_blocking 中数据库层的日志不需要显式传递 ID。其余用法与 SharedDb 相同。
+// ScopedConnection 不能跨线程移动：应在每个阻塞任务内各自 get()，用完即释放。
+#[derive(Clone)]
+pub struct RequestPool {
+    pool: SharedDb,
+    request_id: Option<String>,
+}
+
+impl RequestPool {
+    pub fn get(&self) -> Result<ScopedConnection, r2d2::Error> {
+        let scope = request_id::enter(self.request_id.clone());
+        let conn = self.pool.get()?;
+        Ok(ScopedConnection { conn, _scope: scope })
+    }
+
+    // 不取连接，只在当前线程上进入请求 ID 作用域（如持有连接池之外取得的连接时）
+    pub fn enter(&self) -> request_id::Scope {
+        request_id::enter(self.request_id.clone())
+    }
+}
+
+impl Deref for RequestPool {
+    type Target = SharedDb;
+
+    fn deref(&self) -> &SharedDb {
+        &self.pool
+    }
+}
+
+pub struct ScopedConnection {
+    conn: r2d2::PooledConnection<crate::db::SqliteConnectionManager>,
+    _scope: request_id::Scope,
+}
+
+impl Deref for ScopedConnection {
+    type Target = crate::db::DbConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conn
+    }
+}
+
+impl DerefMut for ScopedConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.conn
+    }
+}
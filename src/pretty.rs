@@ -0,0 +1,43 @@
+// src/pretty.rs
+use std::io::Cursor;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::{Request, Response};
+
+// --- JSON 美化输出 Fairing ---
+// 请求带 ?pretty=true（或开启 INBOX_PRETTY）时把 JSON 响应体重新序列化为缩进格式，
+// 便于用 curl 调试。经 serde_json::Value 中转，对象的键会按字母序排列。
+// 必须在压缩 Fairing 之前挂载，否则拿到的是已压缩的响应体。
+pub struct PrettyJson {
+    pub always: bool,
+}
+
+#[rocket::async_trait]
+impl Fairing for PrettyJson {
+    fn info(&self) -> Info {
+        Info { name: "Pretty JSON", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.content_type() != Some(ContentType::JSON) {
+            return;
+        }
+        let requested = request.query_value::<bool>("pretty").and_then(Result::ok);
+        if !requested.unwrap_or(self.always) {
+            return;
+        }
+
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                eprintln!("[ERROR] 读取响应体失败，无法格式化: {:?}", e);
+                return;
+            }
+        };
+        let pretty = serde_json::from_slice::<serde_json::Value>(&body)
+            .and_then(|value| serde_json::to_vec_pretty(&value));
+        let body = pretty.unwrap_or(body);
+        response.set_sized_body(body.len(), Cursor::new(body));
+    }
+}
@@ -0,0 +1,78 @@
+// src/request_id.rs
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Data, Request, Response};
+
+// --- 请求 ID ---
+// 每个请求带一个 ID：优先使用请求头 X-Request-Id，缺失或不合法时生成 UUID，并在响应头中原样返回。
+// 在 spawn_blocking 中通过 Db 取得的连接会把 ID 设置到当前线程（见 namespace::RequestPool），
+// 数据库层的日志用 tag() 带上它；异步部分的日志可通过 get(request) 取得。
+pub const HEADER: &str = "X-Request-Id";
+
+// 外部传入的 ID 最长 128 个字符，只允许可见 ASCII（避免伪造日志行）
+const MAX_LEN: usize = 128;
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+// 存放在请求本地缓存中的 ID；没有经过 RequestIdFairing 的请求为 None
+struct Cached(Option<String>);
+
+pub fn get<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    request.local_cache(|| Cached(None)).0.as_deref()
+}
+
+pub struct RequestIdFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info { name: "Request ID", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = match request.headers().get_one(HEADER) {
+            Some(id) if is_valid(id) => id.to_string(),
+            _ => uuid::Uuid::new_v4().to_string(),
+        };
+        request.local_cache(|| Cached(Some(id)));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Some(id) = get(request) {
+            response.set_header(Header::new(HEADER, id.to_string()));
+        }
+    }
+}
+
+// --- 线程上的当前请求 ---
+thread_local! {
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// 在 Scope 存活期间把当前线程的请求 ID 设为 id，结束时恢复原值。
+// Scope 不能跨线程移动（否则会在另一个线程上“恢复”），必须在同一个阻塞任务内进入和结束
+pub struct Scope {
+    previous: Option<String>,
+    _not_send: PhantomData<*const ()>,
+}
+
+pub fn enter(id: Option<String>) -> Scope {
+    Scope { previous: CURRENT.with(|current| current.replace(id)), _not_send: PhantomData }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+// 日志行前缀："[req <id>] "；当前线程不在请求中时为空
+pub fn tag() -> String {
+    CURRENT.with(|current| current.borrow().as_ref().map(|id| format!("[req {}] ", id)).unwrap_or_default())
+}
@@ -0,0 +1,38 @@
+// src/seed.rs
+use std::path::Path;
+
+use crate::config::InboxConfig;
+use crate::models::CreateNotePayload;
+use crate::{db, validation, SharedDb};
+
+// --- 启动时写入示例笔记 ---
+// INBOX_SEED_FILE 指向 CreateNotePayload 的 JSON 数组。只有 notes 表完全为空（包括软删除的笔记）时才写入，
+// 重复启动不会产生重复数据；表非空时不读取文件。
+
+// 返回写入的笔记数（表非空时为 0）；文件无法读取、格式错误或校验失败时返回错误信息
+pub fn seed_if_empty(db: &SharedDb, path: &Path, config: &InboxConfig) -> Result<usize, String> {
+    let mut conn = db.get().map_err(|e| format!("无法获取数据库连接: {}", e))?;
+    if !db::notes_table_is_empty(&conn).map_err(|e| e.to_string())? {
+        return Ok(0);
+    }
+
+    let payloads = load_seed_file(path, config)?;
+    let notes = db::create_notes_bulk_db(&mut conn, payloads, config.auto_link_settings()).map_err(|e| e.to_string())?;
+    Ok(notes.len())
+}
+
+fn load_seed_file(path: &Path, config: &InboxConfig) -> Result<Vec<CreateNotePayload>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("无法读取 {}: {}", path.display(), e))?;
+    let payloads: Vec<CreateNotePayload> =
+        serde_json::from_str(&text).map_err(|e| format!("{} 不是合法的笔记数组: {}", path.display(), e))?;
+    payloads
+        .into_iter()
+        .enumerate()
+        .map(|(index, payload)| {
+            validation::validate_create_payload(payload, config).map_err(|fields| {
+                let messages: Vec<String> = fields.into_iter().map(|f| f.message).collect();
+                format!("{} 第 {} 条: {}", path.display(), index, messages.join("; "))
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,54 @@
+// src/slow_log.rs
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+use crate::request_id;
+
+// --- 慢请求日志 ---
+// 只记录耗时超过阈值 (INBOX_SLOW_MS) 的请求，低于阈值时不输出，避免生产环境日志过多。
+// 耗时从 Rocket 收到请求开始计算，到响应头生成为止（不含响应体传输）。
+pub struct SlowRequestLog {
+    threshold: Duration,
+}
+
+impl SlowRequestLog {
+    pub fn new(threshold_ms: u64) -> Self {
+        SlowRequestLog { threshold: Duration::from_millis(threshold_ms) }
+    }
+}
+
+// 存放在请求本地缓存中的开始时间
+struct RequestStart(Option<Instant>);
+
+#[rocket::async_trait]
+impl Fairing for SlowRequestLog {
+    fn info(&self) -> Info {
+        Info { name: "Slow Request Log", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(start) = request.local_cache(|| RequestStart(None)).0 else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        if elapsed < self.threshold {
+            return;
+        }
+        let route = request.route().map_or_else(|| "unmatched".to_string(), |route| route.uri.to_string());
+        eprintln!(
+            "[WARN] [req {}] 慢请求: {} {} (route {}) -> {}, 耗时 {} ms",
+            request_id::get(request).unwrap_or("-"),
+            request.method(),
+            request.uri(),
+            route,
+            response.status().code,
+            elapsed.as_millis()
+        );
+    }
+}
@@ -0,0 +1,37 @@
+// src/trash.rs
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::task;
+
+use crate::{db, SharedDb};
+
+// --- 回收站自动清理 ---
+// 定期彻底删除软删除时间超过保留天数的笔记。配置了 INBOX_TRASH_RETENTION_DAYS 时在 liftoff 阶段
+// （数据库迁移之后）为每个命名空间启动一个；第一次清理在启动一个周期之后，不与启动过程争用数据库。
+pub async fn run_purge_loop(db: SharedDb, retention_days: u32, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        let db = db.clone();
+        let result = task::spawn_blocking(move || {
+            let mut conn = db.get().map_err(|e| e.to_string())?;
+            db::purge_trash_db(&mut conn, retention_cutoff(retention_days)).map_err(|e| e.to_string())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(summary)) if summary.purged > 0 => {
+                println!("[INFO] 回收站清理：彻底删除 {} 条超过 {} 天的笔记", summary.purged, retention_days)
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("[ERROR] 回收站清理失败: {}", e),
+            Err(e) => eprintln!("[ERROR] 回收站清理任务失败: {:?}", e),
+        }
+    }
+}
+
+// 软删除时间早于该时刻的笔记可以彻底删除
+pub fn retention_cutoff(retention_days: u32) -> chrono::DateTime<Utc> {
+    Utc::now() - chrono::Duration::days(retention_days as i64)
+}
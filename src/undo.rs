@@ -0,0 +1,64 @@
+// src/undo.rs
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::models::{Note, NoteRelation};
+
+// 每个命名空间最多保留的操作数，超出时丢弃最早的
+const UNDO_LOG_CAPACITY: usize = 50;
+
+// --- 撤销日志 ---
+// 记录最近的笔记写操作（创建、更新、删除）以及撤销所需的原状态。
+// 只保存在进程内存中，重启后清空；其它写接口（合并、导入等）不记录。
+pub enum Operation {
+    Created { note: Note },
+    Updated { before: Note },
+    // 删除时笔记的关系随外键级联删除，一并保存以便恢复
+    Deleted { note: Note, relations: Vec<NoteRelation> },
+}
+
+impl Operation {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Operation::Created { .. } => "create",
+            Operation::Updated { .. } => "update",
+            Operation::Deleted { .. } => "delete",
+        }
+    }
+
+    // 操作涉及的笔记（更新时为更新前的状态）
+    pub fn note(&self) -> &Note {
+        match self {
+            Operation::Created { note } => note,
+            Operation::Updated { before } => before,
+            Operation::Deleted { note, .. } => note,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UndoLog {
+    entries: Mutex<VecDeque<Operation>>,
+}
+
+impl UndoLog {
+    pub fn record(&self, operation: Operation) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= UNDO_LOG_CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back(operation);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+
+    // 取出最近的一次操作；撤销失败时该操作也不再保留
+    pub fn pop(&self) -> Option<Operation> {
+        self.entries.lock().ok()?.pop_back()
+    }
+}
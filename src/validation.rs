@@ -0,0 +1,249 @@
+// src/validation.rs
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::InboxConfig;
+use crate::db;
+use crate::lang;
+use crate::models::{CreateCommentPayload, CreateNotePayload, FieldError, UpdateNotePayload, UpsertNotePayload};
+
+// 允许的预设颜色名（与客户端调色板一致）
+const NAMED_COLORS: &[&str] = &["red", "orange", "yellow", "green", "blue", "purple", "pink", "gray"];
+
+// --- 笔记请求体校验 ---
+// create_note / update_note 与 POST /notes/validate 共用同一套规则，避免两边逻辑不一致。
+// 校验通过时返回规范化后的请求体，否则返回全部字段错误。
+
+pub fn validate_create_payload(mut payload: CreateNotePayload, config: &InboxConfig) -> Result<CreateNotePayload, Vec<FieldError>> {
+    let mut errors = Vec::new();
+    // 写入时 db 层同样会规范化；这里先做一遍，长度检查和 #hashtag 提取按规范化后的正文进行
+    if config.normalize_whitespace {
+        payload.content = db::normalize_whitespace(&payload.content);
+    }
+    check_content(&payload.content, config, &mut errors);
+    if config.auto_tag {
+        payload.tags = merge_hashtags(&payload.content, payload.tags);
+    }
+    payload.tags = payload.tags.map(normalize_tags);
+    check_tag_limits(payload.tags.as_deref(), config, &mut errors);
+    payload.color = check_color(payload.color, &mut errors);
+    check_slug(payload.slug.as_deref(), &mut errors);
+    payload.lang = check_lang(payload.lang, &payload.content, config.encryption_key.is_some(), &mut errors);
+    if let Some(created_at) = payload.created_at {
+        check_created_at(created_at, Utc::now(), config, &mut errors);
+    }
+
+    if errors.is_empty() { Ok(payload) } else { Err(errors) }
+}
+
+pub fn validate_update_payload(mut payload: UpdateNotePayload, config: &InboxConfig) -> Result<UpdateNotePayload, Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if config.normalize_whitespace {
+        payload.content = db::normalize_whitespace(&payload.content);
+    }
+    check_content(&payload.content, config, &mut errors);
+    if config.auto_tag {
+        payload.tags = merge_hashtags(&payload.content, payload.tags);
+    }
+    payload.tags = payload.tags.map(normalize_tags);
+    check_tag_limits(payload.tags.as_deref(), config, &mut errors);
+    payload.color = check_color(payload.color, &mut errors);
+
+    if errors.is_empty() { Ok(payload) } else { Err(errors) }
+}
+
+// 外部 ID 去掉首尾空白后不能为空；内容和标签按创建笔记的规则校验
+pub fn validate_upsert_payload(payload: UpsertNotePayload, config: &InboxConfig) -> Result<(String, CreateNotePayload), Vec<FieldError>> {
+    let external_id = payload.external_id.trim().to_string();
+    let note = CreateNotePayload {
+        content: payload.content,
+        tags: payload.tags,
+        created_at: None,
+        color: None,
+        slug: None,
+        lang: None,
+    };
+    let (mut errors, note) = match validate_create_payload(note, config) {
+        Ok(note) => (Vec::new(), Some(note)),
+        Err(errors) => (errors, None),
+    };
+    if external_id.is_empty() {
+        errors.insert(0, FieldError::new("external_id", "external_id cannot be empty"));
+    } else if external_id.chars().count() > EXTERNAL_ID_MAX_LEN {
+        errors.insert(0, FieldError::new("external_id", format!("external_id exceeds {} characters", EXTERNAL_ID_MAX_LEN)));
+    }
+
+    match note {
+        Some(note) if errors.is_empty() => Ok((external_id, note)),
+        _ => Err(errors),
+    }
+}
+
+const EXTERNAL_ID_MAX_LEN: usize = 256;
+
+// 评论也是笔记：内容和标签按创建笔记的规则校验和规范化
+pub fn validate_comment_payload(payload: CreateCommentPayload, config: &InboxConfig) -> Result<CreateCommentPayload, Vec<FieldError>> {
+    let note = CreateNotePayload {
+        content: payload.content,
+        tags: payload.tags,
+        created_at: None,
+        color: None,
+        slug: None,
+        lang: None,
+    };
+    let note = validate_create_payload(note, config)?;
+    Ok(CreateCommentPayload { content: note.content, tags: note.tags })
+}
+
+fn check_content(content: &str, config: &InboxConfig, errors: &mut Vec<FieldError>) {
+    if content.trim().is_empty() {
+        errors.push(FieldError::new("content", "content cannot be empty"));
+    } else if content.chars().count() > config.max_content_len {
+        errors.push(FieldError::new(
+            "content",
+            format!("content exceeds the maximum length of {} characters", config.max_content_len),
+        ));
+    }
+}
+
+// 标签数量和长度的限制由 db::create_note_db / update_note_db 执行，这里提前检查以便与其它字段错误一起返回
+fn check_tag_limits(tags: Option<&[String]>, config: &InboxConfig, errors: &mut Vec<FieldError>) {
+    let tags = match tags {
+        Some(tags) => tags,
+        None => return,
+    };
+    if let Err(e) = db::check_tag_limits(tags, &config.note_write_options()) {
+        errors.push(FieldError::new("tags", e.to_string()));
+    }
+    if let Some(tag) = disallowed_tag(tags, config) {
+        errors.push(FieldError::new("tags", format!("tag {:?} is not in INBOX_ALLOWED_TAGS", tag)));
+    }
+}
+
+// 配置了 INBOX_ALLOWED_TAGS 时，第一个不在允许列表中的标签（标签应已规范化）
+pub fn disallowed_tag<'a>(tags: &'a [String], config: &InboxConfig) -> Option<&'a String> {
+    let allowed = config.allowed_tags.as_ref()?;
+    tags.iter().find(|tag| !allowed.contains(tag))
+}
+
+// 客户端可以回填历史时间，但不能明显超前于服务器时间，也不能早于配置的下限，
+// 否则会打乱按 created_at 排序的列表
+fn check_created_at(created_at: DateTime<Utc>, now: DateTime<Utc>, config: &InboxConfig, errors: &mut Vec<FieldError>) {
+    if created_at > now + Duration::seconds(config.max_clock_skew_secs) {
+        errors.push(FieldError::new(
+            "created_at",
+            format!("created_at {} is in the future (allowed skew {}s)", created_at.to_rfc3339(), config.max_clock_skew_secs),
+        ));
+    } else if created_at < config.min_created_at {
+        errors.push(FieldError::new(
+            "created_at",
+            format!("created_at {} is before {}", created_at.to_rfc3339(), config.min_created_at.to_rfc3339()),
+        ));
+    }
+}
+
+fn check_color(color: Option<String>, errors: &mut Vec<FieldError>) -> Option<String> {
+    let color = color?;
+    let normalized = normalize_color(&color);
+    if normalized.is_none() {
+        errors.push(FieldError::new("color", format!("invalid color {:?}: expected #rgb, #rrggbb or a named color", color)));
+    }
+    normalized
+}
+
+// --- slug ---
+const SLUG_MAX_LEN: usize = 100;
+
+fn check_slug(slug: Option<&str>, errors: &mut Vec<FieldError>) {
+    let Some(slug) = slug else {
+        return;
+    };
+    let valid = !slug.is_empty() && slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        errors.push(FieldError::new("slug", format!("invalid slug {:?}: expected [a-z0-9-]+", slug)));
+    } else if slug.len() > SLUG_MAX_LEN {
+        errors.push(FieldError::new("slug", format!("slug exceeds {} characters", SLUG_MAX_LEN)));
+    }
+}
+
+// --- 语言 ---
+// 指定的代码规范化为小写后校验；未指定时按内容检测（检测不出时为空）。
+// 内容加密存储时不检测，与 slug 一样不让明文内容的特征出现在未加密的列中
+fn check_lang(lang: Option<String>, content: &str, encrypted: bool, errors: &mut Vec<FieldError>) -> Option<String> {
+    let Some(lang) = lang else {
+        return if encrypted { None } else { lang::detect(content) };
+    };
+    let code = lang.trim().to_ascii_lowercase();
+    if !lang::is_valid_code(&code) {
+        errors.push(FieldError::new("lang", format!("invalid language {:?}: expected an ISO 639-1 code", lang)));
+    }
+    Some(code)
+}
+
+// --- 标签 ---
+// 去掉首尾空白和前导 '#'，丢弃空标签，按首次出现的顺序去重
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().trim_start_matches('#').trim();
+        if !tag.is_empty() && !normalized.iter().any(|t| t == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+// 把正文中的 #hashtag 并入标签（正文保持不变），去重交给 normalize_tags
+fn merge_hashtags(content: &str, tags: Option<Vec<String>>) -> Option<Vec<String>> {
+    let hashtags = extract_hashtags(content);
+    if hashtags.is_empty() {
+        return tags;
+    }
+    let mut tags = tags.unwrap_or_default();
+    tags.extend(hashtags);
+    Some(tags)
+}
+
+// 提取 #\w+ 形式的标记；'#' 前必须是开头或非单词字符，避免匹配 URL 片段 (a.html#top) 之类
+fn extract_hashtags(content: &str) -> Vec<String> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut hashtags = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '#' && !prev.is_some_and(|p| is_word(p) || p == '#') {
+            let start = i + c.len_utf8();
+            let mut end = start;
+            while let Some(&(j, next)) = chars.peek() {
+                if !is_word(next) {
+                    break;
+                }
+                end = j + next.len_utf8();
+                chars.next();
+            }
+            if end > start {
+                hashtags.push(content[start..end].to_string());
+                prev = content[..end].chars().next_back();
+                continue;
+            }
+        }
+        prev = Some(c);
+    }
+    hashtags
+}
+
+// --- 颜色 ---
+// 接受 #rgb / #rrggbb 十六进制或预设颜色名，统一转为小写；不合法时返回 None
+pub fn normalize_color(color: &str) -> Option<String> {
+    let color = color.trim().to_ascii_lowercase();
+    if NAMED_COLORS.contains(&color.as_str()) {
+        return Some(color);
+    }
+
+    let hex = color.strip_prefix('#')?;
+    if (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(color)
+    } else {
+        None
+    }
+}
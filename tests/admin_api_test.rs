@@ -0,0 +1,266 @@
+// 管理接口的进程内集成测试
+mod common;
+
+use aw_inbox_rust::config::InboxConfig;
+use rocket::http::{Header, Status};
+use rocket::local::blocking::Client;
+
+const API_KEY: &str = "test-admin-key";
+
+fn admin_client() -> Client {
+    common::client_with_config(InboxConfig { api_key: Some(API_KEY.to_string()), ..Default::default() })
+}
+
+#[test]
+fn test_admin_routes_require_api_key() {
+    let client = admin_client();
+
+    let response = client.post("/inbox/admin/reindex").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/inbox/admin/reindex").header(Header::new("X-API-Key", "wrong")).dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // 未配置密钥时管理接口关闭
+    let keyless_client = common::client();
+    let response = keyless_client.post("/inbox/admin/reindex").header(Header::new("X-API-Key", API_KEY)).dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_admin_reindex() {
+    let client = admin_client();
+    common::create_note(&client, "indexed", &["a"]);
+
+    let response = client
+        .post("/inbox/admin/reindex")
+        .header(Header::new("Authorization", format!("Bearer {}", API_KEY)))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    let indexes: Vec<&str> = body["indexes"].as_array().unwrap().iter().map(|i| i.as_str().unwrap()).collect();
+    assert!(indexes.contains(&"idx_note_relations_source"));
+    assert!(body["fts_tables"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_admin_pool_stats() {
+    let client = admin_client();
+
+    let response = client.get("/inbox/admin/pool").header(Header::new("X-API-Key", API_KEY)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    // 内存数据库的连接池固定为 1 个连接，请求之间处于空闲状态
+    assert_eq!(body, serde_json::json!({ "size": 1, "idle": 1, "in_use": 0 }));
+
+    let response = client.get("/inbox/admin/pool").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_integrity_check() {
+    let client = admin_client();
+    let key = Header::new("X-API-Key", API_KEY);
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+
+    let response = client.get("/inbox/admin/integrity").header(key.clone()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap(), serde_json::json!({ "ok": true }));
+
+    {
+        let namespaces = client.rocket().state::<aw_inbox_rust::namespace::Namespaces>().unwrap();
+        let conn = namespaces.get(aw_inbox_rust::namespace::DEFAULT_NAMESPACE).unwrap().db.get().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = OFF;").unwrap();
+        conn.execute("INSERT INTO note_relations (source_note_id, target_note_id, relation_type) VALUES (?1, 999, 'Link')", [a])
+            .unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+    }
+
+    let response = client.get("/inbox/admin/integrity").header(key).dispatch();
+    assert_eq!(response.status(), Status::InternalServerError);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["ok"], false);
+    assert_eq!(body["foreign_key_violations"][0]["table"], "note_relations");
+    assert_eq!(body["foreign_key_violations"][0]["parent"], "notes");
+
+    assert_eq!(client.get("/inbox/admin/integrity").dispatch().status(), Status::Unauthorized);
+}
+
+#[test]
+fn test_orphan_relations_are_listed_and_pruned() {
+    let client = admin_client();
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+    let response = client
+        .post(format!("/inbox/notes/{}/relations/{}", a, b))
+        .header(rocket::http::ContentType::JSON)
+        .body(serde_json::json!({ "relation_type": "Link" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    // 模拟外键约束关闭时手工删除笔记留下的关系
+    {
+        let namespaces = client.rocket().state::<aw_inbox_rust::namespace::Namespaces>().unwrap();
+        let conn = namespaces.get(aw_inbox_rust::namespace::DEFAULT_NAMESPACE).unwrap().db.get().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = OFF;").unwrap();
+        conn.execute("INSERT INTO note_relations (source_note_id, target_note_id, relation_type) VALUES (?1, 999, 'Link')", [a])
+            .unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+    }
+
+    let key = Header::new("X-API-Key", API_KEY);
+    let response = client.get("/inbox/admin/orphan-relations").header(key.clone()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["count"], 1);
+    assert_eq!(body["relations"][0]["target_note_id"], 999);
+
+    let response = client.post("/inbox/admin/orphan-relations/prune").header(key.clone()).dispatch();
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["pruned"], 1);
+
+    // 正常的关系保留
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", b));
+    assert_eq!(relations.as_array().unwrap().len(), 1);
+    let response = client.get("/inbox/admin/orphan-relations").header(key).dispatch();
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["count"], 0);
+}
+
+#[test]
+fn test_bad_tags_are_reported_and_listing_survives() {
+    let client = admin_client();
+    let good = common::create_note(&client, "good", &["a"])["id"].as_i64().unwrap();
+    let bad = common::create_note(&client, "bad", &["b"])["id"].as_i64().unwrap();
+    {
+        let namespaces = client.rocket().state::<aw_inbox_rust::namespace::Namespaces>().unwrap();
+        let conn = namespaces.get(aw_inbox_rust::namespace::DEFAULT_NAMESPACE).unwrap().db.get().unwrap();
+        conn.execute("UPDATE notes SET tags = '[broken' WHERE id = ?1", [bad]).unwrap();
+    }
+
+    // 损坏的笔记按空标签返回，不影响列表
+    let (status, notes) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(status, Status::Ok);
+    let notes = notes.as_array().unwrap();
+    assert_eq!(notes.len(), 2);
+    let broken = notes.iter().find(|n| n["id"] == bad).unwrap();
+    assert_eq!(broken["tags"], serde_json::json!([]));
+
+    // 按标签过滤时损坏的行不匹配，也不会让查询失败
+    let (status, notes) = common::get_json(&client, "/inbox/notes?tag=a");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+    assert_eq!(notes[0]["id"], good);
+    let (status, count) = common::get_json(&client, "/inbox/notes/count?tag=b");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(count["count"], 0);
+    let (status, count) = common::get_json(&client, "/inbox/count?tag=a");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(count["count"], 1);
+    let (status, notes) = common::get_json(&client, "/inbox/notes?tag=a&prefix=true");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+
+    let response = client.get("/inbox/admin/bad-tags").header(Header::new("X-API-Key", API_KEY)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["count"], 1);
+    assert_eq!(body["notes"][0]["id"], bad);
+    assert_eq!(body["notes"][0]["tags"], "[broken");
+    assert_ne!(body["notes"][0]["id"], good);
+}
+
+#[test]
+fn test_repair_bad_tags() {
+    let client = admin_client();
+    let broken = common::create_note(&client, "broken", &["x"])["id"].as_i64().unwrap();
+    let mixed = common::create_note(&client, "mixed", &["y"])["id"].as_i64().unwrap();
+    let good = common::create_note(&client, "good", &["keep"])["id"].as_i64().unwrap();
+    {
+        let namespaces = client.rocket().state::<aw_inbox_rust::namespace::Namespaces>().unwrap();
+        let conn = namespaces.get(aw_inbox_rust::namespace::DEFAULT_NAMESPACE).unwrap().db.get().unwrap();
+        conn.execute("UPDATE notes SET tags = 'not json' WHERE id = ?1", [broken]).unwrap();
+        conn.execute("UPDATE notes SET tags = '[\"ok\", 1, null]' WHERE id = ?1", [mixed]).unwrap();
+    }
+
+    let key = Header::new("X-API-Key", API_KEY);
+    let response = client.post("/inbox/admin/bad-tags/repair").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = client.post("/inbox/admin/bad-tags/repair").header(key.clone()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["repaired"], 2);
+
+    let (_, note) = common::get_json(&client, &format!("/inbox/notes/{}", broken));
+    assert_eq!(note["tags"], serde_json::json!([]));
+    let (_, note) = common::get_json(&client, &format!("/inbox/notes/{}", mixed));
+    assert_eq!(note["tags"], serde_json::json!(["ok"]));
+    let (_, note) = common::get_json(&client, &format!("/inbox/notes/{}", good));
+    assert_eq!(note["tags"], serde_json::json!(["keep"]));
+
+    let response = client.get("/inbox/admin/bad-tags").header(key.clone()).dispatch();
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["count"], 0);
+    let response = client.post("/inbox/admin/bad-tags/repair").header(key).dispatch();
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["repaired"], 0);
+}
+
+#[test]
+fn test_rebuild_tag_counts() {
+    let client = admin_client();
+    common::create_note(&client, "a", &["x", "y"]);
+    common::create_note(&client, "b", &["x"]);
+    {
+        let namespaces = client.rocket().state::<aw_inbox_rust::namespace::Namespaces>().unwrap();
+        let conn = namespaces.get(aw_inbox_rust::namespace::DEFAULT_NAMESPACE).unwrap().db.get().unwrap();
+        conn.execute("DELETE FROM tag_counts", []).unwrap();
+    }
+    let (_, tags) = common::get_json(&client, "/inbox/tags/detailed");
+    assert_eq!(tags, serde_json::json!([]));
+
+    let response = client.post("/inbox/admin/tag-counts/rebuild").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = client.post("/inbox/admin/tag-counts/rebuild").header(Header::new("X-API-Key", API_KEY)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["tags"], 2);
+
+    let (_, summary) = common::get_json(&client, "/inbox/tags/detailed");
+    let (_, live) = common::get_json(&client, "/inbox/tags/detailed?live=true");
+    assert_eq!(summary, live);
+    assert_eq!(summary[0]["name"], "x");
+    assert_eq!(summary[0]["count"], 2);
+}
+
+#[test]
+fn test_reset_database() {
+    let key = Header::new("X-API-Key", API_KEY);
+    // 未开启 INBOX_ALLOW_RESET 时即使密钥正确也拒绝
+    let client = admin_client();
+    let response = client.post("/inbox/admin/reset?confirm=true").header(key.clone()).dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let client = common::client_with_config(InboxConfig {
+        api_key: Some(API_KEY.to_string()),
+        allow_reset: true,
+        ..Default::default()
+    });
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+    client
+        .post(format!("/inbox/notes/{}/relations/{}", a, b))
+        .header(rocket::http::ContentType::JSON)
+        .body(r#"{"relation_type":"Link"}"#)
+        .dispatch();
+
+    assert_eq!(client.post("/inbox/admin/reset?confirm=true").dispatch().status(), Status::Unauthorized);
+    assert_eq!(client.post("/inbox/admin/reset").header(key.clone()).dispatch().status(), Status::BadRequest);
+
+    let response = client.post("/inbox/admin/reset?confirm=true").header(key).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body, serde_json::json!({ "notes": 2, "relations": 1 }));
+
+    let (_, count) = common::get_json(&client, "/inbox/notes/count");
+    assert_eq!(count["count"], 0);
+    // id 重新从 1 开始，撤销日志也已清空
+    assert_eq!(common::create_note(&client, "fresh", &[])["id"], 1);
+    client.post("/inbox/undo").dispatch();
+    assert_eq!(client.post("/inbox/undo").dispatch().status(), Status::NotFound);
+}
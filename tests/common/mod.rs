@@ -0,0 +1,55 @@
+// 测试公共工具：用内存数据库 + Rocket 本地客户端启动完整应用，无需占用端口
+#![allow(dead_code)]
+
+use aw_inbox_rust::config::InboxConfig;
+use aw_inbox_rust::namespace::Namespaces;
+use aw_inbox_rust::{db, mount_rocket_with_config, mount_rocket_with_namespaces};
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use serde_json::Value;
+
+pub fn client() -> Client {
+    client_with_config(InboxConfig::default())
+}
+
+pub fn client_with_config(config: InboxConfig) -> Client {
+    let pool = memory_pool(&config);
+    let rocket = mount_rocket_with_config(rocket::build(), pool, config);
+    Client::tracked(rocket).expect("valid rocket instance")
+}
+
+// 每个命名空间各用一个内存数据库
+pub fn client_with_namespaces(names: &[&str]) -> Client {
+    let config = InboxConfig::default();
+    let namespaces = Namespaces::new(names.iter().map(|name| (name.to_string(), memory_pool(&config))));
+    let rocket = mount_rocket_with_namespaces(rocket::build(), namespaces, config);
+    Client::tracked(rocket).expect("valid rocket instance")
+}
+
+fn memory_pool(config: &InboxConfig) -> db::DbPool {
+    let manager = db::SqliteConnectionManager::memory()
+        .with_query_timeout(config.query_timeout())
+        .with_encryption_key(config.encryption_key);
+    let pool = db::build_pool(manager, 1).expect("Failed to open in-memory database");
+    db::migrate(&pool.get().unwrap()).expect("Failed to run migrations");
+    pool
+}
+
+// 创建笔记并返回响应体
+pub fn create_note(client: &Client, content: &str, tags: &[&str]) -> Value {
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "content": content, "tags": tags }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created, "Create note failed");
+    response.into_json().expect("Create note response should be JSON")
+}
+
+// 发送 GET 请求，返回状态码和 JSON 响应体（非 JSON 时为 Null）
+pub fn get_json(client: &Client, uri: &str) -> (Status, Value) {
+    let response = client.get(uri.to_string()).dispatch();
+    let status = response.status();
+    let body = response.into_json().unwrap_or(Value::Null);
+    (status, body)
+}
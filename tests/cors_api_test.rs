@@ -0,0 +1,79 @@
+// CORS 配置的进程内集成测试
+mod common;
+
+use aw_inbox_rust::config::InboxConfig;
+use rocket::http::{Header, Status};
+
+#[test]
+fn test_preflight_with_credentials_and_max_age() {
+    let client = common::client_with_config(InboxConfig {
+        cors_origins: Some(vec!["https://app.example.com".to_string()]),
+        cors_credentials: true,
+        cors_max_age: 600,
+        ..Default::default()
+    });
+
+    let response = client
+        .options("/inbox/notes")
+        .header(Header::new("Origin", "https://app.example.com"))
+        .header(Header::new("Access-Control-Request-Method", "POST"))
+        .dispatch();
+    assert!(response.status().class().is_success());
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some("https://app.example.com"));
+    assert_eq!(headers.get_one("Access-Control-Allow-Credentials"), Some("true"));
+    assert_eq!(headers.get_one("Access-Control-Max-Age"), Some("600"));
+
+    // 未列出的来源不会得到 CORS 头
+    let response = client.get("/inbox/livez").header(Header::new("Origin", "https://evil.example.com")).dispatch();
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+}
+
+#[test]
+fn test_credentials_with_wildcard_origin_refuses_to_start() {
+    let pool = aw_inbox_rust::db::build_pool(aw_inbox_rust::db::SqliteConnectionManager::memory(), 1).unwrap();
+    let config = InboxConfig { cors_origins: Some(vec!["*".to_string()]), cors_credentials: true, ..Default::default() };
+    let rocket = aw_inbox_rust::mount_rocket_with_config(rocket::build(), pool, config);
+    match rocket::local::blocking::Client::tracked(rocket) {
+        Err(e) => assert!(matches!(e.kind(), rocket::error::ErrorKind::FailedFairings(_))),
+        Ok(_) => panic!("credentials with a wildcard origin should be rejected"),
+    }
+}
+
+#[test]
+fn test_cors_disabled_by_default() {
+    let client = common::client();
+    let response = client.get("/inbox/livez").header(Header::new("Origin", "https://app.example.com")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+}
+
+#[test]
+fn test_options_lists_allowed_methods() {
+    let client = common::client();
+    let allow = |uri: &str| {
+        let response = client.options(uri.to_string()).dispatch();
+        let status = response.status();
+        (status, response.headers().get_one("Allow").map(str::to_string))
+    };
+
+    assert_eq!(allow("/inbox/notes/1"), (Status::NoContent, Some("GET, HEAD, PUT, DELETE, OPTIONS".to_string())));
+    assert_eq!(allow("/inbox/notes"), (Status::NoContent, Some("GET, HEAD, POST, OPTIONS".to_string())));
+    // 静态路由优先：不把 /notes/<id> 的 PUT、DELETE 算进来
+    assert_eq!(allow("/inbox/notes/popular"), (Status::NoContent, Some("GET, HEAD, OPTIONS".to_string())));
+    assert_eq!(allow("/inbox/undo"), (Status::NoContent, Some("POST, OPTIONS".to_string())));
+    assert_eq!(allow("/inbox/no/such/route").0, Status::NotFound);
+
+    // 开启 CORS 时预检请求仍然有效
+    let client = common::client_with_config(InboxConfig {
+        cors_origins: Some(vec!["https://app.example.com".to_string()]),
+        ..Default::default()
+    });
+    let response = client
+        .options("/inbox/notes/1")
+        .header(Header::new("Origin", "https://app.example.com"))
+        .header(Header::new("Access-Control-Request-Method", "PUT"))
+        .dispatch();
+    assert!(response.status().class().is_success());
+    assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("https://app.example.com"));
+}
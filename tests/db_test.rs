@@ -0,0 +1,303 @@
+// 数据库层的直接测试（不经过 HTTP）
+use std::time::{Duration, Instant};
+
+use aw_inbox_rust::db;
+use r2d2::ManageConnection;
+
+#[test]
+fn test_slow_query_is_interrupted() {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    db::migrate(&conn).unwrap();
+    db::set_query_timeout(&mut conn, Duration::from_millis(50)).unwrap();
+
+    // 无限递归的 CTE，只能靠超时中断
+    let started = Instant::now();
+    let err = conn
+        .query_row(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT COUNT(*) FROM c",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap_err();
+    assert!(db::is_query_timeout(&err), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // 后续的普通查询不受影响
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0)).unwrap();
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_statement_timeout_override() {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    db::set_query_timeout(&mut conn, Duration::from_secs(60)).unwrap();
+    let endless = |conn: &rusqlite::Connection| {
+        conn.query_row(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT COUNT(*) FROM c",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+    };
+
+    // 路由超时短于连接默认值时按路由超时中断
+    let started = Instant::now();
+    let err = db::with_statement_timeout(Some(Duration::from_millis(50)), || endless(&conn)).unwrap_err();
+    assert!(db::is_query_timeout(&err), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // 退出后恢复连接的默认值
+    let one: i64 = conn.query_row("SELECT 1", [], |row| row.get(0)).unwrap();
+    assert_eq!(one, 1);
+}
+
+// 每个测试使用独立的临时数据库文件（内存数据库无法在多个连接间产生锁竞争）
+fn temp_db_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("aw-inbox-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_busy_writes_are_retried() {
+    let path = temp_db_path("busy-retry");
+    let holder = rusqlite::Connection::open(&path).unwrap();
+    db::migrate(&holder).unwrap();
+
+    let mut writer = db::SqliteConnectionManager::file(path.to_str().unwrap()).connect().unwrap();
+    writer.busy_timeout(Duration::ZERO).unwrap();
+
+    // 另一个连接持有写锁一段时间后释放
+    holder.execute_batch("BEGIN IMMEDIATE").unwrap();
+    let releaser = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(30));
+        holder.execute_batch("COMMIT").unwrap();
+    });
+
+    let mut attempts = 0;
+    let note = db::with_busy_retry(5, || {
+        attempts += 1;
+        db::create_note_db(&mut writer, serde_json::from_value(serde_json::json!({ "content": "contended" })).unwrap(), db::NoteWriteOptions::default())
+    })
+    .unwrap();
+    releaser.join().unwrap();
+
+    assert_eq!(note.content, "contended");
+    assert!(attempts > 1, "expected at least one retry, got {} attempts", attempts);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_read_tx_sees_one_snapshot() {
+    let path = temp_db_path("read-tx");
+    let mut writer = db::SqliteConnectionManager::file(path.to_str().unwrap()).connect().unwrap();
+    db::migrate(&writer).unwrap();
+    writer.pragma_update(None, "journal_mode", "WAL").unwrap();
+    let reader = rusqlite::Connection::open(&path).unwrap();
+    let count = |conn: &rusqlite::Connection| conn.query_row("SELECT COUNT(*) FROM notes", [], |row| row.get::<_, i64>(0));
+
+    // 读事务内两次计数之间的写入不可见，事务结束后可见
+    let (before, after) = db::with_read_tx(&reader, |conn| {
+        let before = count(conn)?;
+        db::create_note_db(&mut writer, new_note("interleaved"), db::NoteWriteOptions::default()).unwrap();
+        Ok::<_, rusqlite::Error>((before, count(conn)?))
+    })
+    .unwrap();
+    assert_eq!((before, after), (0, 0));
+    assert_eq!(count(&reader).unwrap(), 1);
+    drop(reader);
+    drop(writer);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_non_busy_errors_are_not_retried() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut attempts = 0;
+    let result = db::with_busy_retry(5, || {
+        attempts += 1;
+        conn.execute("INSERT INTO missing_table VALUES (1)", [])
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+}
+
+fn new_note(content: &str) -> aw_inbox_rust::models::CreateNotePayload {
+    serde_json::from_value(serde_json::json!({ "content": content })).unwrap()
+}
+
+#[test]
+fn test_encrypted_content_round_trip() {
+    let path = temp_db_path("encryption");
+    let key = [7u8; 32];
+    let manager = db::SqliteConnectionManager::file(path.to_str().unwrap()).with_encryption_key(Some(key));
+    let mut conn = manager.connect().unwrap();
+    db::migrate(&conn).unwrap();
+
+    let note = db::create_note_db(&mut conn, new_note("secret plans"), db::NoteWriteOptions::default()).unwrap();
+    assert_eq!(db::get_note_db(&conn, note.id).unwrap().unwrap().content, "secret plans");
+
+    // 磁盘上只有密文和 nonce
+    let (stored, nonce): (Vec<u8>, Vec<u8>) = conn
+        .query_row("SELECT content, content_nonce FROM notes WHERE id = ?1", [note.id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    assert_eq!(nonce.len(), 12);
+    assert!(!String::from_utf8_lossy(&stored).contains("secret plans"));
+
+    // 开启加密前写入的明文行仍可读取
+    conn.execute(
+        "INSERT INTO notes (content, tags, created_at, updated_at) VALUES ('legacy', '[]', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+        [],
+    )
+    .unwrap();
+    let legacy_id = conn.last_insert_rowid();
+    assert_eq!(db::get_note_db(&conn, legacy_id).unwrap().unwrap().content, "legacy");
+
+    // 没有密钥或密钥错误时读取失败，而不是返回密文
+    let plain = db::SqliteConnectionManager::file(path.to_str().unwrap()).connect().unwrap();
+    assert!(db::get_note_db(&plain, note.id).is_err());
+    let wrong = db::SqliteConnectionManager::file(path.to_str().unwrap())
+        .with_encryption_key(Some([8u8; 32]))
+        .connect()
+        .unwrap();
+    assert!(db::get_note_db(&wrong, note.id).is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_tag_limits_enforced_by_write_functions() {
+    let mut conn = db::SqliteConnectionManager::memory().connect().unwrap();
+    db::migrate(&conn).unwrap();
+    let options = db::NoteWriteOptions { max_tags: Some(2), max_tag_len: Some(4), ..Default::default() };
+    let payload = |tags: serde_json::Value| serde_json::from_value(serde_json::json!({ "content": "limited", "tags": tags })).unwrap();
+
+    let err = db::create_note_db(&mut conn, payload(serde_json::json!(["a", "b", "c"])), options).unwrap_err();
+    assert!(matches!(err, db::WriteNoteError::TooManyTags { count: 3, limit: 2 }), "{:?}", err);
+    let note = db::create_note_db(&mut conn, payload(serde_json::json!(["a", "b"])), options).unwrap();
+
+    let update = serde_json::from_value(serde_json::json!({ "content": "limited", "tags": ["toolong"] })).unwrap();
+    let err = db::update_note_db(&mut conn, note.id, update, 0, options).unwrap_err();
+    assert!(matches!(err, db::WriteNoteError::TagTooLong { limit: 4, .. }), "{:?}", err);
+    let stored = db::get_note_db(&conn, note.id).unwrap().unwrap();
+    assert_eq!(stored.tags, vec!["a", "b"]);
+
+    let comment = serde_json::from_value(serde_json::json!({ "content": "c", "tags": ["a", "b", "c"] })).unwrap();
+    let err = db::add_comment_db(&mut conn, note.id, comment, 0, options).unwrap_err();
+    assert!(matches!(err, db::WriteNoteError::TooManyTags { count: 3, limit: 2 }), "{:?}", err);
+}
+
+#[test]
+fn test_whitespace_normalized_by_write_functions() {
+    let mut conn = db::SqliteConnectionManager::memory().connect().unwrap();
+    db::migrate(&conn).unwrap();
+    let options = db::NoteWriteOptions { normalize_whitespace: true, ..Default::default() };
+
+    let note = db::create_note_db(&mut conn, new_note("a  \r\nb\t"), options).unwrap();
+    assert_eq!(note.content, "a\nb");
+    let update = serde_json::from_value(serde_json::json!({ "content": "  c \r\n\r\nd " })).unwrap();
+    let note = db::update_note_db(&mut conn, note.id, update, 0, options).unwrap().unwrap();
+    assert_eq!(note.content, "  c\n\nd");
+    let comment = serde_json::from_value(serde_json::json!({ "content": "reply \r\n" })).unwrap();
+    let (comment, _) = db::add_comment_db(&mut conn, note.id, comment, 0, options).unwrap();
+    assert_eq!(comment.content, "reply\n");
+    let (linked, _) = db::create_linked_note_db(&mut conn, note.id, new_note("linked\t"), aw_inbox_rust::models::NoteRelationType::Link, 10, options).unwrap();
+    assert_eq!(linked.content, "linked");
+
+    // 未开启时原样保存
+    let note = db::create_note_db(&mut conn, new_note("e \r\n"), db::NoteWriteOptions::default()).unwrap();
+    assert_eq!(note.content, "e \r\n");
+}
+
+#[test]
+fn test_migrate_records_schema_version() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    assert_eq!(db::schema_version(&conn).unwrap(), 0);
+    db::migrate(&conn).unwrap();
+    assert_eq!(db::schema_version(&conn).unwrap(), db::SCHEMA_VERSION);
+    // 重复执行不报错
+    db::migrate(&conn).unwrap();
+
+    conn.pragma_update(None, "user_version", db::SCHEMA_VERSION + 1).unwrap();
+    match db::migrate(&conn) {
+        Err(db::MigrateError::SchemaTooNew { db, binary }) => {
+            assert_eq!(db, db::SCHEMA_VERSION + 1);
+            assert_eq!(binary, db::SCHEMA_VERSION);
+        }
+        other => panic!("expected SchemaTooNew, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_migrate_backfills_note_uids() {
+    let path = temp_db_path("uid-backfill");
+    let conn = db::SqliteConnectionManager::file(path.to_str().unwrap()).connect().unwrap();
+    db::migrate(&conn).unwrap();
+    // 旧版本程序写入的行没有 uid，下次启动迁移时补齐
+    conn.execute(
+        "INSERT INTO notes (content, tags, created_at, updated_at) VALUES ('legacy', '[]', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+        [],
+    )
+    .unwrap();
+    let legacy_id = conn.last_insert_rowid();
+    assert!(db::get_note_db(&conn, legacy_id).unwrap().unwrap().uid.is_none());
+
+    db::migrate(&conn).unwrap();
+    let uid = db::get_note_db(&conn, legacy_id).unwrap().unwrap().uid.unwrap();
+    assert!(uuid::Uuid::parse_str(&uid).is_ok());
+    let key = aw_inbox_rust::models::NoteKey::Uid(uid);
+    assert_eq!(db::resolve_note_key(&conn, &key, aw_inbox_rust::config::IdScheme::Uuid).unwrap(), legacy_id);
+    // uuid 模式下不接受整数 id
+    let key = aw_inbox_rust::models::NoteKey::Id(legacy_id);
+    assert!(matches!(
+        db::resolve_note_key(&conn, &key, aw_inbox_rust::config::IdScheme::Uuid),
+        Err(rusqlite::Error::QueryReturnedNoRows)
+    ));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_wal_checkpoint() {
+    let path = temp_db_path("wal-checkpoint");
+    let manager = db::SqliteConnectionManager::file(path.to_str().unwrap());
+    let mut conn = manager.connect().unwrap();
+    db::migrate(&conn).unwrap();
+
+    // 未启用 WAL 时没有可写回的页
+    let checkpoint = db::wal_checkpoint_db(&conn).unwrap();
+    assert_eq!(checkpoint.log_pages, -1);
+
+    conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+    conn.pragma_update(None, "wal_autocheckpoint", 0).unwrap();
+    for i in 0..20 {
+        db::create_note_db(&mut conn, new_note(&format!("note {}", i)), db::NoteWriteOptions::default()).unwrap();
+    }
+    let checkpoint = db::wal_checkpoint_db(&conn).unwrap();
+    assert!(checkpoint.log_pages > 0);
+    assert_eq!(checkpoint.checkpointed_pages, checkpoint.log_pages);
+    assert!(!checkpoint.busy);
+    drop(conn);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+}
+
+#[tokio::test]
+async fn test_purge_loop_waits_one_interval() {
+    let pool = db::build_pool(db::SqliteConnectionManager::memory(), 1).unwrap();
+    {
+        let mut conn = pool.get().unwrap();
+        db::migrate(&conn).unwrap();
+        let note = db::create_note_db(&mut conn, new_note("trashed"), db::NoteWriteOptions::default()).unwrap();
+        db::trash_note_db(&mut conn, note.id).unwrap();
+    }
+    let remaining = |pool: &db::DbPool| -> i64 {
+        pool.get().unwrap().query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0)).unwrap()
+    };
+
+    // 启动时不立即清理，一个周期之后才执行第一次
+    let purge = tokio::spawn(aw_inbox_rust::trash::run_purge_loop(pool.clone(), 0, Duration::from_millis(300)));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(remaining(&pool), 1);
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(remaining(&pool), 0);
+    purge.abort();
+}
@@ -0,0 +1,62 @@
+// 健康检查接口的进程内集成测试
+mod common;
+
+use rocket::http::{Header, Status};
+
+#[test]
+fn test_liveness_and_readiness() {
+    let client = common::client();
+
+    let (status, body) = common::get_json(&client, "/inbox/livez");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], "alive");
+
+    // 挂载时的点火阶段已完成迁移
+    let (status, body) = common::get_json(&client, "/inbox/readyz");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], "ready");
+}
+
+#[test]
+fn test_root_describes_service() {
+    let client = common::client();
+
+    let (status, body) = common::get_json(&client, "/inbox");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["name"], "aw-inbox");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    let routes: Vec<&str> = body["routes"].as_array().unwrap().iter().map(|r| r.as_str().unwrap()).collect();
+    assert!(routes.contains(&"GET /inbox/notes/<id>"));
+    assert!(routes.contains(&"POST /inbox/notes"));
+}
+
+#[test]
+fn test_request_id_is_echoed() {
+    let client = common::client();
+    let request_id = |response: &rocket::local::blocking::LocalResponse| response.headers().get_one("X-Request-Id").map(str::to_string);
+
+    let response = client.get("/inbox/notes").header(Header::new("X-Request-Id", "trace-123")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(request_id(&response).as_deref(), Some("trace-123"));
+
+    // 未携带或不合法时生成 UUID，错误响应同样带上
+    let response = client.get("/inbox/notes/999").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let generated = request_id(&response).unwrap();
+    assert!(uuid::Uuid::parse_str(&generated).is_ok(), "{}", generated);
+    let response = client.get("/inbox/livez").header(Header::new("X-Request-Id", "bad id\nforged")).dispatch();
+    assert_ne!(request_id(&response).as_deref(), Some("bad id\nforged"));
+}
+
+#[test]
+fn test_refuses_to_start_on_newer_schema() {
+    let pool = aw_inbox_rust::db::build_pool(aw_inbox_rust::db::SqliteConnectionManager::memory(), 1).unwrap();
+    let future_version = aw_inbox_rust::db::SCHEMA_VERSION + 1;
+    pool.get().unwrap().pragma_update(None, "user_version", future_version).unwrap();
+
+    let rocket = aw_inbox_rust::mount_rocket_with_config(rocket::build(), pool, Default::default());
+    match rocket::local::blocking::Client::tracked(rocket) {
+        Err(e) => assert!(matches!(e.kind(), rocket::error::ErrorKind::FailedFairings(_))),
+        Ok(_) => panic!("rocket should refuse to ignite on a newer schema"),
+    }
+}
@@ -0,0 +1,33 @@
+// Prometheus 指标接口的进程内集成测试
+mod common;
+
+use rocket::http::Status;
+
+#[test]
+fn test_metrics_exposes_counters() {
+    let client = common::client();
+    let keep = common::create_note(&client, "keep", &[]);
+    let gone = common::create_note(&client, "gone", &[])["id"].as_i64().unwrap();
+    assert_eq!(client.delete(format!("/inbox/notes/{}", gone)).dispatch().status(), Status::NoContent);
+    common::get_json(&client, &format!("/inbox/notes/{}", keep["id"]));
+
+    let response = client.get("/metrics").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.content_type().unwrap().is_text());
+    let body = response.into_string().unwrap();
+
+    assert!(body.contains("inbox_notes_created_total 2"), "{}", body);
+    assert!(body.contains("inbox_notes_deleted_total 1"));
+    assert!(body.contains("inbox_notes{namespace=\"default\"} 1"));
+    // 按路由模板计数，而不是实际路径
+    assert!(body.contains(r#"inbox_http_requests_total{method="POST",route="/inbox/notes",status="201"} 2"#));
+    assert!(body.contains(r#"inbox_http_requests_total{method="GET",route="/inbox/notes/<id>",status="200"} 1"#));
+    // 上面的请求都执行过查询，耗时直方图必须有样本（查询超时的 trace 钩子不能顶掉 profile 钩子）
+    let query_count: u64 = body
+        .lines()
+        .find_map(|line| line.strip_prefix("inbox_db_query_duration_seconds_count "))
+        .expect("query duration histogram missing")
+        .parse()
+        .unwrap();
+    assert!(query_count > 0, "{}", body);
+}
@@ -0,0 +1,56 @@
+// 多命名空间的进程内集成测试
+mod common;
+
+use aw_inbox_rust::namespace;
+use rocket::http::Status;
+
+#[test]
+fn test_namespaces_are_isolated() {
+    let client = common::client_with_namespaces(&["default", "work"]);
+
+    let response = client
+        .post("/inbox/work/notes")
+        .header(rocket::http::ContentType::JSON)
+        .body(serde_json::json!({ "content": "work item", "tags": ["job"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    common::create_note(&client, "personal item", &[]);
+
+    let (status, work) = common::get_json(&client, "/inbox/work/notes");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(work.as_array().unwrap().len(), 1);
+    assert_eq!(work[0]["content"], "work item");
+
+    // 默认命名空间保持原有路径
+    let (_, default) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(default.as_array().unwrap().len(), 1);
+    assert_eq!(default[0]["content"], "personal item");
+
+    let (_, tags) = common::get_json(&client, "/inbox/work/tags");
+    assert_eq!(tags, serde_json::json!(["job"]));
+    let (status, _) = common::get_json(&client, "/inbox/work/readyz");
+    assert_eq!(status, Status::Ok);
+
+    let (status, _) = common::get_json(&client, "/inbox/personal/notes");
+    assert_eq!(status, Status::NotFound);
+}
+
+#[test]
+fn test_route_segments_are_reserved_names() {
+    assert!(namespace::is_valid_name("work"));
+    for name in ["notes", "tags", "admin", "export", "readyz"] {
+        assert!(!namespace::is_valid_name(name), "{}", name);
+    }
+
+    // 默认命名空间下每个路由的第一段都不能用作命名空间名
+    let client = common::client();
+    for route in client.rocket().routes() {
+        let path = route.uri.path();
+        let Some(segment) = path.strip_prefix("/inbox/").and_then(|rest| rest.split('/').next()) else {
+            continue;
+        };
+        if !segment.is_empty() && !segment.starts_with('<') {
+            assert!(!namespace::is_valid_name(segment), "route segment {:?} is not reserved", segment);
+        }
+    }
+}
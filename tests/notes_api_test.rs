@@ -0,0 +1,1714 @@
+// 笔记相关 API 的进程内集成测试（Rocket 本地客户端 + 内存数据库）
+mod common;
+
+use aw_inbox_rust::config::{InboxConfig, LocationId};
+use aw_inbox_rust::db;
+use rocket::local::blocking::Client;
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::json;
+
+#[test]
+fn test_popular_notes_ranked_by_access_count() {
+    let client = common::client_with_config(InboxConfig { track_access: true, ..Default::default() });
+
+    let rarely_read = common::create_note(&client, "rarely read", &[])["id"].as_i64().unwrap();
+    let often_read = common::create_note(&client, "often read", &[])["id"].as_i64().unwrap();
+    common::create_note(&client, "never read", &[]);
+
+    common::get_json(&client, &format!("/inbox/notes/{}", rarely_read));
+    for _ in 0..5 {
+        let (status, _) = common::get_json(&client, &format!("/inbox/notes/{}", often_read));
+        assert_eq!(status, Status::Ok);
+    }
+
+    let (status, body) = common::get_json(&client, "/inbox/notes/popular");
+    assert_eq!(status, Status::Ok);
+    let ids: Vec<i64> = body.as_array().unwrap().iter().map(|n| n["id"].as_i64().unwrap()).collect();
+    // 从未读取的笔记不出现在热门列表中
+    assert_eq!(ids, vec![often_read, rarely_read]);
+}
+
+#[test]
+fn test_popular_notes_limit_is_clamped() {
+    let client = common::client_with_config(InboxConfig { track_access: true, max_limit: 1, ..Default::default() });
+    for content in ["a", "b"] {
+        let id = common::create_note(&client, content, &[])["id"].as_i64().unwrap();
+        common::get_json(&client, &format!("/inbox/notes/{}", id));
+    }
+    for uri in ["/inbox/notes/popular?limit=-1", "/inbox/notes/popular?limit=100"] {
+        let (status, body) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(body.as_array().unwrap().len(), 1, "{}", uri);
+    }
+}
+
+#[test]
+fn test_popular_notes_keeps_pending_counts_when_flush_fails() {
+    let pool = db::build_pool(db::SqliteConnectionManager::memory(), 1).unwrap();
+    db::migrate(&pool.get().unwrap()).unwrap();
+    let config = InboxConfig { track_access: true, ..Default::default() };
+    let client = Client::tracked(aw_inbox_rust::mount_rocket_with_config(rocket::build(), pool.clone(), config)).unwrap();
+    let id = common::create_note(&client, "read once", &[])["id"].as_i64().unwrap();
+    common::get_json(&client, &format!("/inbox/notes/{}", id));
+
+    let fail_writes = "CREATE TRIGGER fail_access BEFORE UPDATE OF access_count ON notes BEGIN SELECT RAISE(ABORT, 'boom'); END";
+    pool.get().unwrap().execute_batch(fail_writes).unwrap();
+    assert_ne!(client.get("/inbox/notes/popular").dispatch().status(), Status::Ok);
+
+    // 写回恢复后，之前的访问仍然计入
+    pool.get().unwrap().execute_batch("DROP TRIGGER fail_access").unwrap();
+    let (status, body) = common::get_json(&client, "/inbox/notes/popular");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body[0]["id"].as_i64(), Some(id));
+}
+
+#[test]
+fn test_access_not_tracked_when_disabled() {
+    let client = common::client();
+
+    let id = common::create_note(&client, "read but untracked", &[])["id"].as_i64().unwrap();
+    common::get_json(&client, &format!("/inbox/notes/{}", id));
+
+    let (status, body) = common::get_json(&client, "/inbox/notes/popular");
+    assert_eq!(status, Status::Ok);
+    assert!(body.as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_feed_is_escaped_atom() {
+    let client = common::client();
+    common::create_note(&client, "a < b & \"c\"", &["rust", "x&y"]);
+
+    let response = client.get("/inbox/feed.xml").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type().unwrap().to_string(), "application/atom+xml");
+
+    let body = response.into_string().unwrap();
+    assert!(body.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+    assert!(body.contains("<content type=\"text\">a &lt; b &amp; &quot;c&quot;</content>"));
+    assert!(body.contains("<category term=\"rust\"/>"));
+    assert!(body.contains("<category term=\"x&amp;y\"/>"));
+}
+
+#[test]
+fn test_feed_limit_is_clamped() {
+    let client = common::client_with_config(InboxConfig { max_limit: 2, ..Default::default() });
+    for i in 0..3 {
+        common::create_note(&client, &format!("feed note {}", i), &[]);
+    }
+    let entries = |uri: &str| client.get(uri).dispatch().into_string().unwrap().matches("<entry>").count();
+    assert_eq!(entries("/inbox/feed.xml?limit=100"), 2);
+    assert_eq!(entries("/inbox/feed.xml?limit=-1"), 1);
+}
+
+#[test]
+fn test_large_responses_are_gzipped() {
+    let client = common::client();
+    for i in 0..50 {
+        common::create_note(&client, &format!("note number {} with some repetitive filler text", i), &["bulk"]);
+    }
+
+    let plain = client.get("/inbox/notes").dispatch();
+    assert!(plain.headers().get_one("Content-Encoding").is_none());
+    let plain_body = plain.into_bytes().unwrap();
+
+    let gzipped = client.get("/inbox/notes").header(Header::new("Accept-Encoding", "gzip, deflate")).dispatch();
+    assert_eq!(gzipped.status(), Status::Ok);
+    assert_eq!(gzipped.headers().get_one("Content-Encoding"), Some("gzip"));
+    let gzipped_body = gzipped.into_bytes().unwrap();
+
+    assert!(gzipped_body.len() * 4 < plain_body.len(), "Expected at least a 4x size reduction");
+
+    let mut decoded = Vec::new();
+    GzDecoder::new(&gzipped_body[..]).read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, plain_body);
+}
+
+#[test]
+fn test_small_responses_are_not_compressed() {
+    let client = common::client();
+    let response = client.get("/inbox/notes/popular").header(Header::new("Accept-Encoding", "gzip")).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("Content-Encoding").is_none());
+}
+
+#[test]
+fn test_fields_projection() {
+    let client = common::client();
+    common::create_note(&client, "projected note", &["a"]);
+
+    let (status, body) = common::get_json(&client, "/inbox/notes?fields=id,tags");
+    assert_eq!(status, Status::Ok);
+    let note = body[0].as_object().unwrap();
+    let mut keys: Vec<&str> = note.keys().map(|k| k.as_str()).collect();
+    keys.sort();
+    assert_eq!(keys, vec!["id", "tags"]);
+
+    // 默认返回所有字段
+    let (_, body) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(body[0].as_object().unwrap().len(), 11);
+
+    let (status, _) = common::get_json(&client, "/inbox/notes?fields=id,password");
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_note_color_is_validated_and_filterable() {
+    let client = common::client();
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "red note", "color": "#FF0000" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let note: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(note["color"], "#ff0000");
+    common::create_note(&client, "plain note", &[]);
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "bad color", "color": "#zzz" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .put(format!("/inbox/notes/{}", note["id"]))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "red note", "color": "not-a-color" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let (status, body) = common::get_json(&client, "/inbox/notes?color=%23ff0000");
+    assert_eq!(status, Status::Ok);
+    let notes = body.as_array().unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0]["content"], "red note");
+
+    let (status, _) = common::get_json(&client, "/inbox/notes?color=chartreuse");
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_validate_endpoint_matches_create_rules() {
+    let client = common::client();
+
+    let response = client
+        .post("/inbox/notes/validate")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "ok", "tags": [" #rust", "rust", ""] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["tags"], json!(["rust"]));
+
+    // 校验接口不应写入数据
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert!(notes.as_array().unwrap().is_empty());
+
+    let invalid = json!({ "content": "  ", "color": "nope" }).to_string();
+    let response = client.post("/inbox/notes/validate").header(ContentType::JSON).body(invalid.clone()).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    let fields: Vec<&str> = body["fields"].as_array().unwrap().iter().map(|f| f["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["content", "color"]);
+
+    // 真正创建时返回同样的错误
+    let response = client.post("/inbox/notes").header(ContentType::JSON).body(invalid).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let create_body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(create_body, body);
+    assert!(create_body["error"].as_str().unwrap().contains("content cannot be empty"));
+}
+
+#[test]
+fn test_created_at_override_is_bounded() {
+    let client = common::client();
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "from the far future", "created_at": "3000-01-01T00:00:00Z" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["fields"][0]["field"], "created_at");
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "before the epoch", "created_at": "1960-01-01T00:00:00Z" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // 回填历史时间仍然允许
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "backfilled", "created_at": "2020-05-01T12:00:00Z" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["created_at"], "2020-05-01T12:00:00+00:00");
+}
+
+#[test]
+fn test_export_then_reimport() {
+    let client = common::client();
+    common::create_note(&client, "exported", &["backup"]);
+
+    let export = client.get("/inbox/export").dispatch().into_string().unwrap();
+    let response = client.post("/inbox/import").header(ContentType::JSON).body(export).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["imported"], 1);
+
+    let (_, notes) = common::get_json(&client, "/inbox/notes?tag=backup");
+    assert_eq!(notes.as_array().unwrap().len(), 2);
+    // 导回同一个库时 slug 已被占用，追加数字后缀
+    let mut slugs: Vec<&str> = notes.as_array().unwrap().iter().map(|n| n["slug"].as_str().unwrap()).collect();
+    slugs.sort();
+    assert_eq!(slugs, vec!["exported", "exported-2"]);
+}
+
+#[test]
+fn test_lenient_import_accepts_json5() {
+    let client = common::client();
+    let hand_edited = r#"[
+        // 手工编辑的备份
+        { "content": "first", "tags": ["edited",], },
+        { content: 'second' },
+    ]"#;
+
+    // 默认严格解析
+    let response = client.post("/inbox/import").header(ContentType::JSON).body(hand_edited).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.post("/inbox/import?lenient=true").header(ContentType::JSON).body(hand_edited).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["imported"], 2);
+
+    // 普通接口不受影响
+    let response = client.post("/inbox/notes").header(ContentType::JSON).body("{ content: 'x', }").dispatch();
+    assert!(response.status().code >= 400);
+}
+
+#[test]
+fn test_errors_are_json() {
+    let client = common::client();
+
+    // 尾部斜杠与不带斜杠的路径等价
+    let (status, body) = common::get_json(&client, "/inbox/notes/");
+    assert_eq!(status, Status::Ok);
+    assert!(body.is_array());
+
+    let (status, body) = common::get_json(&client, "/inbox/notes/999");
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body["status"], 404);
+
+    let (status, body) = common::get_json(&client, "/inbox/no-such-route");
+    assert_eq!(status, Status::NotFound);
+    assert!(body["error"].as_str().unwrap().contains("/inbox/no-such-route"));
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(r#"{"content": 42}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["status"], 422);
+}
+
+#[test]
+fn test_diff_two_notes() {
+    let client = common::client();
+    let a = common::create_note(&client, "line one\nline two\nline three", &["shared", "only-a"])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "line one\nline 2\nline three", &["shared", "only-b"])["id"].as_i64().unwrap();
+
+    let (status, diff) = common::get_json(&client, &format!("/inbox/notes/diff?a={}&b={}", a, b));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(diff["identical"], false);
+    let ops: Vec<(&str, &str)> = diff["lines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| (l["op"].as_str().unwrap(), l["text"].as_str().unwrap()))
+        .collect();
+    assert_eq!(ops, vec![
+        ("equal", "line one"),
+        ("delete", "line two"),
+        ("insert", "line 2"),
+        ("equal", "line three"),
+    ]);
+    assert_eq!(diff["tags"]["only_a"], json!(["only-a"]));
+    assert_eq!(diff["tags"]["only_b"], json!(["only-b"]));
+
+    let (status, _) = common::get_json(&client, &format!("/inbox/notes/diff?a={}&b=9999", a));
+    assert_eq!(status, Status::NotFound);
+}
+
+#[test]
+fn test_pagination_link_header() {
+    let client = common::client();
+    for i in 0..5 {
+        common::create_note(&client, &format!("note {}", i), &["paged"]);
+    }
+
+    let response = client.get("/inbox/notes?tag=paged&limit=2&offset=2").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let link = response.headers().get_one("Link").unwrap().to_string();
+    assert!(link.contains(r#"</inbox/notes?tag=paged&limit=2&offset=4>; rel="next""#), "{}", link);
+    assert!(link.contains(r#"</inbox/notes?tag=paged&limit=2&offset=0>; rel="prev""#), "{}", link);
+    assert!(link.contains(r#"</inbox/notes?tag=paged&limit=2&offset=0>; rel="first""#), "{}", link);
+    assert!(link.contains(r#"</inbox/notes?tag=paged&limit=2&offset=4>; rel="last""#), "{}", link);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 2);
+
+    // 最后一页没有 next
+    let response = client.get("/inbox/notes?limit=2&offset=4").dispatch();
+    assert!(!response.headers().get_one("Link").unwrap().contains("rel=\"next\""));
+
+    // 未分页时不带 Link 头
+    let response = client.get("/inbox/notes").dispatch();
+    assert!(response.headers().get_one("Link").is_none());
+}
+
+#[test]
+fn test_batch_get_preserves_order() {
+    let client = common::client_with_config(InboxConfig { max_batch_size: 4, ..Default::default() });
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+
+    let response = client
+        .post("/inbox/notes/batch-get")
+        .header(ContentType::JSON)
+        .body(json!([b, 9999, a, b]).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    let ids: Vec<i64> = body["notes"].as_array().unwrap().iter().map(|n| n["id"].as_i64().unwrap()).collect();
+    assert_eq!(ids, vec![b, a]);
+    assert_eq!(body["missing"], json!([9999]));
+
+    let response = client
+        .post("/inbox/notes/batch-get")
+        .header(ContentType::JSON)
+        .body(json!([1, 2, 3, 4, 5]).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_merge_notes() {
+    let client = common::client();
+    let primary = common::create_note(&client, "first thought", &["a"])["id"].as_i64().unwrap();
+    let second = common::create_note(&client, "second thought", &["a", "b"])["id"].as_i64().unwrap();
+    let third = common::create_note(&client, "third thought", &["c"])["id"].as_i64().unwrap();
+    let outside = common::create_note(&client, "linked from elsewhere", &[])["id"].as_i64().unwrap();
+    client
+        .post(format!("/inbox/notes/{}/relations/{}", outside, second))
+        .header(ContentType::JSON)
+        .body(json!({ "relation_type": "Link" }).to_string())
+        .dispatch();
+
+    let response = client
+        .post("/inbox/notes/merge")
+        .header(ContentType::JSON)
+        .body(json!({ "primary": primary, "others": [second, third] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let merged: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(merged["content"], "first thought\n\n---\n\nsecond thought\n\n---\n\nthird thought");
+    assert_eq!(merged["tags"], json!(["a", "b", "c"]));
+
+    // 被合并的笔记不可见，关系改指向 primary
+    let (status, _) = common::get_json(&client, &format!("/inbox/notes/{}", second));
+    assert_eq!(status, Status::NotFound);
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(notes.as_array().unwrap().len(), 2);
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", primary));
+    assert_eq!(relations[0]["source_note_id"].as_i64(), Some(outside));
+
+    let response = client
+        .post("/inbox/notes/merge")
+        .header(ContentType::JSON)
+        .body(json!({ "primary": primary, "others": [primary] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .post("/inbox/notes/merge")
+        .header(ContentType::JSON)
+        .body(json!({ "primary": primary, "others": [second] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_triage_notes() {
+    let client = common::client();
+    let ids: Vec<i64> = (0..4)
+        .map(|i| common::create_note(&client, &format!("note {}", i), &[])["id"].as_i64().unwrap())
+        .collect();
+
+    let response = client
+        .post("/inbox/triage")
+        .header(ContentType::JSON)
+        .body(json!({ "archive": [ids[0], ids[1]], "delete": [ids[2], 999], "pin": [ids[1]] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .post("/inbox/triage")
+        .header(ContentType::JSON)
+        .body(json!({ "archive": [ids[0]], "delete": [ids[2], 999], "pin": [ids[1]] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let summary: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(summary, json!({ "archived": 1, "deleted": 1, "pinned": 1 }));
+
+    let (_, archived) = common::get_json(&client, &format!("/inbox/notes/{}", ids[0]));
+    assert!(archived["archived_at"].is_string());
+    assert_eq!(archived["pinned"], false);
+    let (_, pinned) = common::get_json(&client, &format!("/inbox/notes/{}", ids[1]));
+    assert_eq!(pinned["pinned"], true);
+    assert!(pinned["archived_at"].is_null());
+    let (status, _) = common::get_json(&client, &format!("/inbox/notes/{}", ids[2]));
+    assert_eq!(status, Status::NotFound);
+}
+
+#[test]
+fn test_inbox_count() {
+    let client = common::client();
+    let archived = common::create_note(&client, "done", &["work"])["id"].as_i64().unwrap();
+    common::create_note(&client, "todo", &["work"]);
+    common::create_note(&client, "later", &["home"]);
+    client
+        .post("/inbox/triage")
+        .header(ContentType::JSON)
+        .body(json!({ "archive": [archived] }).to_string())
+        .dispatch();
+
+    let response = client.get("/inbox/count").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("Cache-Control").unwrap().contains("max-age"));
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap(), json!({ "count": 2 }));
+
+    let (_, body) = common::get_json(&client, "/inbox/count?tag=work");
+    assert_eq!(body["count"], 1);
+    let (_, body) = common::get_json(&client, "/inbox/count?tag=missing");
+    assert_eq!(body["count"], 0);
+}
+
+#[test]
+fn test_tag_and_content_filters_combine() {
+    let client = common::client();
+    common::create_note(&client, "pay the invoice", &["inbox"]);
+    common::create_note(&client, "invoice archived", &["done"]);
+    common::create_note(&client, "call mom", &["inbox"]);
+    let contents = |uri: &str| -> Vec<String> {
+        let (status, notes) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        notes.as_array().unwrap().iter().map(|n| n["content"].as_str().unwrap().to_string()).collect()
+    };
+
+    assert_eq!(contents("/inbox/notes?tag=inbox&q=invoice"), vec!["pay the invoice"]);
+    assert_eq!(contents("/inbox/notes?tag=inbox&search=invoice"), vec!["pay the invoice"]);
+    assert!(contents("/inbox/notes?tag=done&q=mom").is_empty());
+    let (_, count) = common::get_json(&client, "/inbox/notes/count?tag=inbox&q=invoice");
+    assert_eq!(count["count"], 1);
+
+    let (status, _) = common::get_json(&client, "/inbox/notes?q=invoice&search=mom");
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_content_length_filter() {
+    let client = common::client();
+    common::create_note(&client, "stub", &[]);
+    common::create_note(&client, &"essay ".repeat(100), &[]);
+    // 按字节计：6 个汉字共 18 字节
+    common::create_note(&client, "六个汉字笔记", &[]);
+    let contents = |uri: &str| -> Vec<String> {
+        let (status, notes) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        notes.as_array().unwrap().iter().map(|n| n["content"].as_str().unwrap().to_string()).collect()
+    };
+
+    assert_eq!(contents("/inbox/notes?max_len=10"), vec!["stub"]);
+    assert_eq!(contents("/inbox/notes?min_len=18&max_len=18"), vec!["六个汉字笔记"]);
+    assert_eq!(contents("/inbox/notes?min_len=500").len(), 1);
+    let (_, count) = common::get_json(&client, "/inbox/notes/count?min_len=5");
+    assert_eq!(count["count"], 2);
+
+    assert_eq!(common::get_json(&client, "/inbox/notes?min_len=10&max_len=5").0, Status::BadRequest);
+    assert_eq!(common::get_json(&client, "/inbox/notes?min_len=-1").0, Status::BadRequest);
+}
+
+#[test]
+fn test_create_note_from_plain_text() {
+    let client = common::client_with_config(InboxConfig { max_content_len: 20, ..Default::default() });
+
+    let response = client
+        .post("/inbox/notes/raw?tag=idea&tag=%23inbox")
+        .header(ContentType::Plain)
+        .body("idea from a pipe\n")
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let note: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(note["content"], "idea from a pipe\n");
+    assert_eq!(note["tags"], json!(["idea", "inbox"]));
+
+    let response = client.post("/inbox/notes/raw").header(ContentType::Plain).body("no tags").dispatch();
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["tags"], json!([]));
+
+    // 与 JSON 创建相同的校验
+    let response = client.post("/inbox/notes/raw").header(ContentType::Plain).body("x".repeat(21)).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client.post("/inbox/notes/raw").header(ContentType::Plain).body("   ").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_limit_and_offset_are_applied() {
+    let client = common::client();
+    for i in 0..5 {
+        common::create_note(&client, &format!("note {}", i), &[]);
+    }
+    let contents = |uri: &str| -> Vec<String> {
+        let (status, notes) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        notes.as_array().unwrap().iter().map(|n| n["content"].as_str().unwrap().to_string()).collect()
+    };
+
+    assert_eq!(contents("/inbox/notes?limit=2"), vec!["note 4", "note 3"]);
+    assert_eq!(contents("/inbox/notes?limit=2&offset=3"), vec!["note 1", "note 0"]);
+    // 只有 offset 时不限制条数
+    assert_eq!(contents("/inbox/notes?offset=3"), vec!["note 1", "note 0"]);
+    assert_eq!(contents("/inbox/notes?limit=0"), Vec::<String>::new());
+}
+
+#[test]
+fn test_same_created_at_pages_in_stable_order() {
+    let client = common::client();
+    // 批量导入的笔记 created_at 完全相同，按 id 倒序作为第二排序键
+    let batch: Vec<_> = (0..6)
+        .map(|i| json!({ "content": format!("imported {}", i), "created_at": "2024-05-01T12:00:00Z" }))
+        .collect();
+    let response = client.post("/inbox/import").header(ContentType::JSON).body(json!(batch).to_string()).dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    let ids = |uri: &str| -> Vec<i64> {
+        let (_, notes) = common::get_json(&client, uri);
+        notes.as_array().unwrap().iter().map(|n| n["id"].as_i64().unwrap()).collect()
+    };
+    let all = ids("/inbox/notes");
+    let mut expected = all.clone();
+    expected.sort_unstable_by(|a, b| b.cmp(a));
+    assert_eq!(all, expected);
+    for _ in 0..3 {
+        assert_eq!(ids("/inbox/notes"), all);
+    }
+
+    let paged: Vec<i64> = (0..3).flat_map(|page| ids(&format!("/inbox/notes?limit=2&offset={}", page * 2))).collect();
+    assert_eq!(paged, all);
+    let (_, with_counts) = common::get_json(&client, "/inbox/notes?with_counts=true");
+    let counted: Vec<i64> = with_counts.as_array().unwrap().iter().map(|n| n["id"].as_i64().unwrap()).collect();
+    assert_eq!(counted, all);
+}
+
+#[test]
+fn test_default_and_max_limit() {
+    let client = common::client_with_config(InboxConfig { default_limit: 2, max_limit: 3, ..Default::default() });
+    for i in 0..5 {
+        common::create_note(&client, &format!("note {}", i), &[]);
+    }
+
+    let response = client.get("/inbox/notes").dispatch();
+    // 默认 limit 截断了结果，附带分页链接
+    assert!(response.headers().get_one("Link").unwrap().contains("offset=2>; rel=\"next\""));
+    let notes: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(notes.as_array().unwrap().len(), 2);
+
+    let (_, notes) = common::get_json(&client, "/inbox/notes?limit=50");
+    assert_eq!(notes.as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn test_encrypted_notes_round_trip() {
+    let client = common::client_with_config(InboxConfig { encryption_key: Some([1u8; 32]), ..Default::default() });
+    let created = common::create_note(&client, "encrypted note", &["private"]);
+    let id = created["id"].as_i64().unwrap();
+
+    let response = client
+        .put(format!("/inbox/notes/{}", id))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "edited secret", "tags": ["private"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let (status, note) = common::get_json(&client, &format!("/inbox/notes/{}", id));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(note["content"], "edited secret");
+    let (_, notes) = common::get_json(&client, "/inbox/notes?tag=private");
+    assert_eq!(notes[0]["content"], "edited secret");
+
+    // 加密时不支持按内容搜索
+    let (status, _) = common::get_json(&client, "/inbox/notes?search=secret");
+    assert_eq!(status, Status::BadRequest);
+
+    // 加密时不按内容检测语言，指定的语言照常保存
+    let english = common::create_note(&client, "The quick brown fox jumps over the lazy dog while everyone watches.", &[]);
+    assert!(english["lang"].is_null());
+    let response = client.post("/inbox/notes").header(ContentType::JSON)
+        .body(json!({ "content": "hallo", "lang": "de" }).to_string()).dispatch();
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["lang"], "de");
+}
+
+#[test]
+fn test_whitespace_normalization() {
+    let client = common::client_with_config(InboxConfig { normalize_whitespace: true, ..Default::default() });
+    let note = common::create_note(&client, "line1\r\nline2  \r\n", &[]);
+    assert_eq!(note["content"], "line1\nline2\n");
+
+    // 空行和缩进保留
+    let id = note["id"].as_i64().unwrap();
+    let response = client
+        .put(format!("/inbox/notes/{}", id))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "a \t\r\n\r\n  b\t" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let (_, stored) = common::get_json(&client, &format!("/inbox/notes/{}", id));
+    assert_eq!(stored["content"], "a\n\n  b");
+
+    // 由模板创建的笔记同样规范化
+    let response = client
+        .post("/inbox/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "crlf", "content": "todo  \r\n- item\t\r\n" }).to_string())
+        .dispatch();
+    let template_id = response.into_json::<serde_json::Value>().unwrap()["id"].as_i64().unwrap();
+    let response = client.post(format!("/inbox/notes/from-template/{}", template_id)).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["content"], "todo\n- item\n");
+
+    // 评论同样规范化
+    let response = client
+        .post(format!("/inbox/notes/{}/comments", id))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "reply \r\n" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["content"], "reply\n");
+}
+
+#[test]
+fn test_activity_heatmap() {
+    let client = common::client();
+    for created_at in ["2024-01-05T08:00:00Z", "2024-01-05T23:30:00Z", "2024-03-10T12:00:00Z", "2023-12-31T12:00:00Z"] {
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .body(json!({ "content": "entry", "created_at": created_at }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    let (status, days) = common::get_json(&client, "/inbox/activity?year=2024");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(days, json!([{ "date": "2024-01-05", "count": 2 }, { "date": "2024-03-10", "count": 1 }]));
+
+    let (_, days) = common::get_json(&client, "/inbox/activity?year=2022");
+    assert_eq!(days, json!([]));
+}
+
+#[test]
+fn test_archive_browsing() {
+    let client = common::client();
+    for (content, created_at) in [
+        ("new year", "2024-01-01T00:00:00Z"),
+        ("mid january", "2024-01-15T12:00:00Z"),
+        ("end of january", "2024-01-31T23:59:59Z"),
+        ("march", "2024-03-10T12:00:00Z"),
+        ("last year", "2023-12-31T23:59:59Z"),
+    ] {
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .body(json!({ "content": content, "created_at": created_at }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+    let contents = |uri: &str| -> Vec<String> {
+        let (status, notes) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        notes.as_array().unwrap().iter().map(|n| n["content"].as_str().unwrap().to_string()).collect()
+    };
+
+    assert_eq!(contents("/inbox/archive/2024/1"), vec!["end of january", "mid january", "new year"]);
+    assert_eq!(contents("/inbox/archive/2024/1?limit=1&offset=1"), vec!["mid january"]);
+    assert_eq!(contents("/inbox/archive/2024").len(), 4);
+    assert_eq!(contents("/inbox/archive/2023/12"), vec!["last year"]);
+    assert!(contents("/inbox/archive/2024/2").is_empty());
+    // 2024 年 ISO 第 1 周：2024-01-01（周一）至 2024-01-07
+    assert_eq!(contents("/inbox/archive/2024/week/1"), vec!["new year"]);
+
+    let (status, _) = common::get_json(&client, "/inbox/archive/2024/13");
+    assert_eq!(status, Status::BadRequest);
+    let (status, _) = common::get_json(&client, "/inbox/archive/2024/week/54");
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_pretty_json() {
+    let client = common::client();
+    common::create_note(&client, "pretty", &["a"]);
+
+    let compact = client.get("/inbox/tags").dispatch().into_string().unwrap();
+    assert!(!compact.contains('\n'));
+
+    let pretty = client.get("/inbox/tags?pretty=true").dispatch().into_string().unwrap();
+    assert!(pretty.contains("\n  "));
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&pretty).unwrap(), json!(["a"]));
+
+    // 与 get_notes 的查询参数共存
+    let notes = client.get("/inbox/notes?limit=1&pretty=true").dispatch().into_string().unwrap();
+    assert!(notes.contains("\n    \"content\": \"pretty\""));
+
+    let client = common::client_with_config(InboxConfig { pretty: true, ..Default::default() });
+    let body = client.get("/inbox/livez").dispatch().into_string().unwrap();
+    assert_eq!(body, "{\n  \"status\": \"alive\"\n}");
+    let body = client.get("/inbox/livez?pretty=false").dispatch().into_string().unwrap();
+    assert_eq!(body, "{\"status\":\"alive\"}");
+}
+
+#[test]
+fn test_count_matches_listing() {
+    let client = common::client();
+    for (content, tag, created_at) in [
+        ("old work", "work", "2023-06-01T00:00:00Z"),
+        ("new work", "work", "2024-06-01T00:00:00Z"),
+        ("newer work", "work", "2024-09-01T00:00:00Z"),
+        ("home", "home", "2024-07-01T00:00:00Z"),
+    ] {
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .body(json!({ "content": content, "tags": [tag], "created_at": created_at }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    for filter in [
+        "",
+        "tag=work",
+        "tag=work&created_after=2024-01-01T00:00:00Z",
+        "created_after=2024-01-01T00:00:00Z&created_before=2024-08-01T00:00:00Z",
+        "tag=missing",
+    ] {
+        let (status, count) = common::get_json(&client, &format!("/inbox/notes/count?{}", filter));
+        assert_eq!(status, Status::Ok);
+        let (_, notes) = common::get_json(&client, &format!("/inbox/notes?{}", filter));
+        assert_eq!(count["count"].as_u64().unwrap() as usize, notes.as_array().unwrap().len(), "filter {:?}", filter);
+    }
+    let (_, count) = common::get_json(&client, "/inbox/notes/count?tag=work&created_after=2024-01-01T00:00:00Z");
+    assert_eq!(count["count"], 2);
+
+    let (status, _) = common::get_json(&client, "/inbox/notes/count?created_after=yesterday");
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_time_filters_accept_rfc3339_and_epoch_seconds() {
+    let client = common::client();
+    for created_at in ["2024-01-01T00:00:00Z", "2024-06-01T00:00:00Z"] {
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .body(json!({ "content": created_at, "created_at": created_at }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    // 1709251200 = 2024-03-01T00:00:00Z
+    for after in ["2024-03-01T00:00:00Z", "2024-03-01T08:00:00%2B08:00", "1709251200"] {
+        let (status, notes) = common::get_json(&client, &format!("/inbox/notes?created_after={}", after));
+        assert_eq!(status, Status::Ok, "created_after={}", after);
+        assert_eq!(notes.as_array().unwrap().len(), 1, "created_after={}", after);
+        assert_eq!(notes[0]["content"], "2024-06-01T00:00:00Z");
+    }
+    let (_, notes) = common::get_json(&client, "/inbox/notes?created_before=1709251200");
+    assert_eq!(notes[0]["content"], "2024-01-01T00:00:00Z");
+
+    let (status, _) = common::get_json(&client, "/inbox/notes?created_before=1709251200.5");
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_purge_trash() {
+    let client = common::client();
+    let primary = common::create_note(&client, "primary", &[])["id"].as_i64().unwrap();
+    let other = common::create_note(&client, "other", &[])["id"].as_i64().unwrap();
+    let response = client
+        .post("/inbox/notes/merge")
+        .header(ContentType::JSON)
+        .body(json!({ "primary": primary, "others": [other] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // 未配置保留天数时必须显式指定
+    let response = client.post("/inbox/trash/purge").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // 刚删除的笔记还在保留期内
+    let response = client.post("/inbox/trash/purge?days=30").dispatch();
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["purged"], 0);
+
+    let response = client.post("/inbox/trash/purge?days=0").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["purged"], 1);
+
+    // 未删除的笔记不受影响
+    let (status, _) = common::get_json(&client, &format!("/inbox/notes/{}", primary));
+    assert_eq!(status, Status::Ok);
+}
+
+#[test]
+fn test_schema_describes_note_model() {
+    let client = common::client();
+    let (status, schema) = common::get_json(&client, "/inbox/schema");
+    assert_eq!(status, Status::Ok);
+
+    assert_eq!(schema["create"]["required"], json!(["content"]));
+    assert_eq!(schema["create"]["properties"]["content"]["type"], "string");
+    assert_eq!(schema["create"]["properties"]["created_at"]["format"], "date-time");
+
+    let required = schema["note"]["required"].as_array().unwrap();
+    for field in ["id", "content", "tags", "created_at", "updated_at"] {
+        assert!(required.contains(&json!(field)), "{} should be required", field);
+    }
+    assert_eq!(schema["note"]["properties"]["updated_at"]["format"], "date-time");
+}
+
+#[test]
+fn test_max_body_bytes_applies_to_bulk_import() {
+    const LIMIT: u64 = 4096;
+    let client = common::client_with_config(InboxConfig { max_body_bytes: Some(LIMIT), ..Default::default() });
+
+    // 用一条笔记的内容把请求体补到恰好 limit 字节
+    let body_with_len = |len: u64| {
+        let skeleton = json!([{ "content": "" }, { "content": "second" }]).to_string();
+        let padding = "x".repeat((len - skeleton.len() as u64) as usize);
+        let body = json!([{ "content": padding }, { "content": "second" }]).to_string();
+        assert_eq!(body.len() as u64, len);
+        body
+    };
+
+    let response = client.post("/inbox/import").header(ContentType::JSON).body(body_with_len(LIMIT)).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["imported"], 2);
+
+    let response = client.post("/inbox/import").header(ContentType::JSON).body(body_with_len(LIMIT + 1)).dispatch();
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    // 普通 JSON 接口使用同一限制
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "y".repeat(LIMIT as usize) }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
+
+#[test]
+fn test_next_and_prev_navigation() {
+    let client = common::client();
+    let mut ids = Vec::new();
+    for (content, tag, created_at) in [
+        ("first", "review", "2024-01-01T00:00:00Z"),
+        ("second", "other", "2024-01-02T00:00:00Z"),
+        ("third", "review", "2024-01-03T00:00:00Z"),
+        // 与上一条同一时刻创建，按 id 区分先后
+        ("fourth", "review", "2024-01-03T00:00:00Z"),
+    ] {
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .body(json!({ "content": content, "tags": [tag], "created_at": created_at }).to_string())
+            .dispatch();
+        ids.push(response.into_json::<serde_json::Value>().unwrap()["id"].as_i64().unwrap());
+    }
+    let content = |uri: String| {
+        let (status, note) = common::get_json(&client, &uri);
+        (status, note["content"].as_str().map(str::to_string))
+    };
+
+    assert_eq!(content(format!("/inbox/notes/{}/next", ids[0])), (Status::Ok, Some("second".into())));
+    assert_eq!(content(format!("/inbox/notes/{}/next", ids[2])), (Status::Ok, Some("fourth".into())));
+    assert_eq!(content(format!("/inbox/notes/{}/prev", ids[3])), (Status::Ok, Some("third".into())));
+    assert_eq!(content(format!("/inbox/notes/{}/next?tag=review", ids[0])), (Status::Ok, Some("third".into())));
+    assert_eq!(content(format!("/inbox/notes/{}/prev?tag=review", ids[2])), (Status::Ok, Some("first".into())));
+
+    // 两端
+    assert_eq!(content(format!("/inbox/notes/{}/prev", ids[0])).0, Status::NotFound);
+    assert_eq!(content(format!("/inbox/notes/{}/next", ids[3])).0, Status::NotFound);
+    assert_eq!(content("/inbox/notes/999/next".to_string()).0, Status::NotFound);
+}
+
+#[test]
+fn test_get_note_content_negotiation() {
+    let client = common::client();
+    let id = common::create_note(&client, "hello *world*", &["a", "b"])["id"].as_i64().unwrap();
+    let uri = format!("/inbox/notes/{}", id);
+    let get = |accept: Option<&str>| {
+        let mut request = client.get(uri.clone());
+        if let Some(accept) = accept {
+            request = request.header(Header::new("Accept", accept.to_string()));
+        }
+        let response = request.dispatch();
+        let status = response.status();
+        let content_type = response.content_type();
+        (status, content_type, response.into_string().unwrap_or_default())
+    };
+
+    let (status, content_type, body) = get(None);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::JSON));
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["content"], "hello *world*");
+
+    let (status, content_type, body) = get(Some("text/plain"));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::Plain));
+    assert_eq!(body, "hello *world*");
+
+    let (status, content_type, body) = get(Some("text/markdown"));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::new("text", "markdown")));
+    assert!(body.starts_with(&format!("## Note {} (", id)));
+    assert!(body.contains("hello *world*\n\n#a #b"));
+
+    // 按 q 值选择；不支持的类型被跳过
+    let (_, content_type, _) = get(Some("text/html, text/plain;q=0.5, application/json;q=0.9"));
+    assert_eq!(content_type, Some(ContentType::JSON));
+    let (_, content_type, _) = get(Some("*/*"));
+    assert_eq!(content_type, Some(ContentType::JSON));
+
+    assert_eq!(get(Some("text/html")).0, Status::NotAcceptable);
+    assert_eq!(get(Some("application/json;q=0")).0, Status::NotAcceptable);
+}
+
+fn multipart_body(boundary: &str, parts: &[(&str, Option<&str>, &str)]) -> String {
+    let mut body = String::new();
+    for (name, filename, value) in parts {
+        body.push_str(&format!("--{}\r\n", boundary));
+        match filename {
+            Some(filename) => body.push_str(&format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: text/markdown\r\n\r\n",
+                name, filename
+            )),
+            None => body.push_str(&format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name)),
+        }
+        body.push_str(value);
+        body.push_str("\r\n");
+    }
+    body.push_str(&format!("--{}--\r\n", boundary));
+    body
+}
+
+#[test]
+fn test_import_markdown_files() {
+    let client = common::client();
+    let content_type = ContentType::new("multipart", "form-data").with_params(("boundary", "X-BOUNDARY"));
+    let body = multipart_body(
+        "X-BOUNDARY",
+        &[
+            ("files", Some("2024.01 meeting.md"), "---\ntags: [work, \"#weekly\"]\ntitle: ignored\n---\nAgenda\n"),
+            ("mtime", None, "1700000000"),
+            ("files", Some("idea.markdown"), "---\ntags:\n  - someday\n---\n# idea\n\nbody"),
+            ("mtime", None, ""),
+            ("files", Some("plain.md"), "no front matter"),
+        ],
+    );
+    let response = client.post("/inbox/import/markdown").header(content_type.clone()).body(body).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let summary: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(summary["imported"], 3);
+    let ids: Vec<i64> = summary["ids"].as_array().unwrap().iter().map(|id| id.as_i64().unwrap()).collect();
+
+    let (_, first) = common::get_json(&client, &format!("/inbox/notes/{}", ids[0]));
+    assert_eq!(first["content"], "# 2024.01 meeting\n\nAgenda");
+    assert_eq!(first["tags"], json!(["work", "weekly"]));
+    assert!(first["created_at"].as_str().unwrap().starts_with("2023-11-14T22:13:20"));
+
+    let (_, second) = common::get_json(&client, &format!("/inbox/notes/{}", ids[1]));
+    assert_eq!(second["content"], "# idea\n\nbody");
+    assert_eq!(second["tags"], json!(["someday"]));
+
+    let (_, third) = common::get_json(&client, &format!("/inbox/notes/{}", ids[2]));
+    assert_eq!(third["content"], "# plain\n\nno front matter");
+    assert_eq!(third["tags"], json!([]));
+
+    // 非 Markdown 文件整体拒绝，不写入任何笔记
+    let body = multipart_body("X-BOUNDARY", &[("files", Some("a.md"), "a"), ("files", Some("b.txt"), "b")]);
+    let response = client.post("/inbox/import/markdown").header(content_type).body(body).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let (_, count) = common::get_json(&client, "/inbox/notes/count");
+    assert_eq!(count["count"], 3);
+}
+
+#[test]
+fn test_note_slugs() {
+    let client = common::client();
+    let create = |body: serde_json::Value| {
+        let response = client.post("/inbox/notes").header(ContentType::JSON).body(body.to_string()).dispatch();
+        let status = response.status();
+        (status, response.into_json::<serde_json::Value>().unwrap_or_default())
+    };
+
+    let (_, first) = create(json!({ "content": "# Weekly Review: 2024/W01\nbody" }));
+    assert_eq!(first["slug"], "weekly-review-2024-w01");
+    let (_, second) = create(json!({ "content": "Weekly review -- 2024 w01" }));
+    assert_eq!(second["slug"], "weekly-review-2024-w01-2");
+    let (_, chinese) = create(json!({ "content": "周报" }));
+    assert_eq!(chinese["slug"], "note");
+
+    let (status, custom) = create(json!({ "content": "anything", "slug": "my-post" }));
+    assert_eq!(status, Status::Created);
+    assert_eq!(custom["slug"], "my-post");
+    let (status, _) = create(json!({ "content": "dup", "slug": "my-post" }));
+    assert_eq!(status, Status::Conflict);
+    let (status, _) = create(json!({ "content": "bad", "slug": "My Post" }));
+    assert_eq!(status, Status::BadRequest);
+
+    let (status, note) = common::get_json(&client, "/inbox/n/weekly-review-2024-w01-2");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(note["id"], second["id"]);
+    let response = client.get("/inbox/n/my-post").header(Header::new("Accept", "text/plain")).dispatch();
+    assert_eq!(response.into_string().unwrap(), "anything");
+    assert_eq!(common::get_json(&client, "/inbox/n/missing").0, Status::NotFound);
+}
+
+#[test]
+fn test_location_header_identifier() {
+    let location = |client: &rocket::local::blocking::Client, body: serde_json::Value| {
+        let response = client.post("/inbox/notes").header(ContentType::JSON).body(body.to_string()).dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let location = response.headers().get_one("Location").unwrap().to_string();
+        let note: serde_json::Value = response.into_json().unwrap();
+        (location, note)
+    };
+
+    let client = common::client();
+    let (url, note) = location(&client, json!({ "content": "Plain id" }));
+    assert_eq!(url, format!("/inbox/notes/{}", note["id"]));
+
+    let client = common::client_with_config(InboxConfig { location_id: LocationId::Slug, ..Default::default() });
+    let (url, note) = location(&client, json!({ "content": "Opaque URL" }));
+    assert_eq!(url, "/inbox/n/opaque-url");
+    // slug 与数字 id 两种 URL 都能访问
+    assert_eq!(common::get_json(&client, &url).1["id"], note["id"]);
+    assert_eq!(common::get_json(&client, &format!("/inbox/notes/{}", note["id"])).0, Status::Ok);
+}
+
+#[test]
+fn test_undo_last_operations() {
+    let client = common::client();
+    let undo = || {
+        let response = client.post("/inbox/undo").dispatch();
+        let status = response.status();
+        (status, response.into_json::<serde_json::Value>().unwrap_or_default())
+    };
+    assert_eq!(undo().0, Status::NotFound);
+
+    let keep = common::create_note(&client, "keep", &[])["id"].as_i64().unwrap();
+    let id = common::create_note(&client, "original", &["a"])["id"].as_i64().unwrap();
+    client
+        .post(format!("/inbox/notes/{}/relations/{}", id, keep))
+        .header(ContentType::JSON)
+        .body(json!({ "relation_type": "Link" }).to_string())
+        .dispatch();
+    let response = client
+        .put(format!("/inbox/notes/{}", id))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "edited", "tags": ["b"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(client.delete(format!("/inbox/notes/{}", id)).dispatch().status(), Status::NoContent);
+
+    // 撤销删除：以原 id 恢复，关系一并恢复
+    let (status, body) = undo();
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["undone"], "delete");
+    assert_eq!(body["note"]["id"], id);
+    assert_eq!(body["note"]["content"], "edited");
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", keep));
+    assert_eq!(relations[0]["source_note_id"], id);
+
+    // 撤销更新
+    let (_, body) = undo();
+    assert_eq!(body["undone"], "update");
+    assert_eq!(body["note"]["content"], "original");
+    assert_eq!(body["note"]["tags"], json!(["a"]));
+
+    // 撤销创建
+    let (_, body) = undo();
+    assert_eq!(body["undone"], "create");
+    assert_eq!(body["note_id"], id);
+    assert!(body["note"].is_null());
+    assert_eq!(common::get_json(&client, &format!("/inbox/notes/{}", id)).0, Status::NotFound);
+
+    // 笔记已被合并（软删除，不记录在撤销日志中）时无法恢复更新，返回 409
+    let merged = common::create_note(&client, "merged", &[])["id"].as_i64().unwrap();
+    client
+        .put(format!("/inbox/notes/{}", merged))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "merged edited" }).to_string())
+        .dispatch();
+    let response = client
+        .post("/inbox/notes/merge")
+        .header(ContentType::JSON)
+        .body(json!({ "primary": keep, "others": [merged] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let (status, _) = undo();
+    assert_eq!(status, Status::Conflict);
+}
+
+#[test]
+fn test_note_language() {
+    let client = common::client();
+    let create = |body: serde_json::Value| {
+        let response = client.post("/inbox/notes").header(ContentType::JSON).body(body.to_string()).dispatch();
+        let status = response.status();
+        (status, response.into_json::<serde_json::Value>().unwrap_or_default())
+    };
+
+    let (_, explicit) = create(json!({ "content": "hi", "lang": "DE" }));
+    assert_eq!(explicit["lang"], "de");
+    let (_, english) = create(json!({ "content": "The quick brown fox jumps over the lazy dog while everyone watches." }));
+    assert_eq!(english["lang"], "en");
+    let (_, chinese) = create(json!({ "content": "今天下午三点在会议室讨论下个季度的产品计划和预算安排。" }));
+    assert_eq!(chinese["lang"], "zh");
+    let (_, short) = create(json!({ "content": "ok" }));
+    assert!(short["lang"].is_null());
+
+    let (status, _) = create(json!({ "content": "x", "lang": "english" }));
+    assert_eq!(status, Status::BadRequest);
+    let (status, _) = create(json!({ "content": "x", "lang": "xx" }));
+    assert_eq!(status, Status::BadRequest);
+
+    let (_, notes) = common::get_json(&client, "/inbox/notes?lang=en");
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+    assert_eq!(notes[0]["id"], english["id"]);
+    let (_, count) = common::get_json(&client, "/inbox/notes/count?lang=DE");
+    assert_eq!(count["count"], 1);
+    assert_eq!(common::get_json(&client, "/inbox/notes?lang=zzz").0, Status::BadRequest);
+}
+
+#[test]
+fn test_content_preview() {
+    let client = common::client_with_config(InboxConfig { preview_len: 10, ..Default::default() });
+    let long = common::create_note(&client, "笔记预览应当按字符截断而不是按字节", &[]);
+    assert_eq!(long["preview"], "笔记预览应当按字符截…");
+    assert_eq!(long["content"], "笔记预览应当按字符截断而不是按字节");
+    let short = common::create_note(&client, "short", &[]);
+    assert_eq!(short["preview"], "short");
+    // 截断处的空白不保留
+    let spaced = common::create_note(&client, "123456789 abcdef", &[]);
+    assert_eq!(spaced["preview"], "123456789…");
+
+    // 列表可以只取摘要，不返回全文
+    let (_, notes) = common::get_json(&client, "/inbox/notes?fields=id,preview");
+    let mut keys: Vec<&str> = notes[0].as_object().unwrap().keys().map(|k| k.as_str()).collect();
+    keys.sort();
+    assert_eq!(keys, vec!["id", "preview"]);
+
+    // 默认 140 个字符
+    let client = common::client();
+    let note = common::create_note(&client, &"a".repeat(200), &[]);
+    assert_eq!(note["preview"].as_str().unwrap().chars().count(), 141);
+}
+
+#[test]
+fn test_uuid_id_scheme() {
+    use aw_inbox_rust::config::IdScheme;
+
+    let client = common::client_with_config(InboxConfig { id_scheme: IdScheme::Uuid, ..Default::default() });
+    let note = common::create_note(&client, "hidden counter", &["a"]);
+    let id = note["id"].as_str().expect("id should be a UUID string").to_string();
+    assert!(uuid::Uuid::parse_str(&id).is_ok());
+
+    let (status, fetched) = common::get_json(&client, &format!("/inbox/notes/{}", id));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(fetched["content"], "hidden counter");
+    // 大写形式同样可以访问
+    assert_eq!(common::get_json(&client, &format!("/inbox/notes/{}", id.to_uppercase())).0, Status::Ok);
+    // 整数 id 不可用，避免被逐个枚举
+    assert_eq!(common::get_json(&client, "/inbox/notes/1").0, Status::NotFound);
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(notes[0]["id"], id.as_str());
+
+    let response = client
+        .post(format!("/inbox/notes/{}/comments", id))
+        .header(ContentType::JSON)
+        .body(r#"{"content":"a comment"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let (_, comments) = common::get_json(&client, &format!("/inbox/notes/{}/comments", id));
+    assert_eq!(comments[0]["content"], "a comment");
+
+    let other = common::create_note(&client, "other", &[])["id"].as_str().unwrap().to_string();
+    let response = client
+        .post(format!("/inbox/notes/{}/relations/{}", id, other))
+        .header(ContentType::JSON)
+        .body(r#"{"relation_type":"Link"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let response = client
+        .post(format!("/inbox/notes/{}/relations/{}", id, uuid::Uuid::new_v4()))
+        .header(ContentType::JSON)
+        .body(r#"{"relation_type":"Link"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .put(format!("/inbox/notes/{}", id))
+        .header(ContentType::JSON)
+        .body(r#"{"content":"updated"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(client.delete(format!("/inbox/notes/{}", id)).dispatch().status(), Status::NoContent);
+    assert_eq!(common::get_json(&client, &format!("/inbox/notes/{}", id)).0, Status::NotFound);
+
+    let response = client.post("/inbox/undo").dispatch();
+    let undo: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(undo["note_id"], id.as_str());
+    assert_eq!(common::get_json(&client, &format!("/inbox/notes/{}", id)).0, Status::Ok);
+}
+
+#[test]
+fn test_uuid_id_scheme_hides_integer_ids_everywhere() {
+    use aw_inbox_rust::config::IdScheme;
+
+    let client = common::client_with_config(InboxConfig { id_scheme: IdScheme::Uuid, ..Default::default() });
+    let is_uuid = |value: &serde_json::Value| value.as_str().is_some_and(|s| uuid::Uuid::parse_str(s).is_ok());
+    let a = common::create_note(&client, "a\nsame", &["x"])["id"].as_str().unwrap().to_string();
+    let b = common::create_note(&client, "b\nsame [[1]]", &["y"])["id"].as_str().unwrap().to_string();
+    let c = common::create_note(&client, "c", &[])["id"].as_str().unwrap().to_string();
+
+    // 关系：id 与两端笔记 id 都是 UUID
+    let response = client
+        .post(format!("/inbox/notes/{}/relations/{}", a, b))
+        .header(ContentType::JSON)
+        .body(r#"{"relation_type":"Link"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let relation: serde_json::Value = response.into_json().unwrap();
+    assert!(is_uuid(&relation["id"]));
+    assert_eq!(relation["source_note_id"], a.as_str());
+    assert_eq!(relation["target_note_id"], b.as_str());
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", b));
+    assert_eq!(relations[0]["id"], relation["id"]);
+    assert_eq!(relations[0]["source_note_id"], a.as_str());
+    let (_, full) = common::get_json(&client, &format!("/inbox/notes/{}/full", a));
+    assert_eq!(full["relations"][0]["target_note_id"], b.as_str());
+
+    let (_, graph) = common::get_json(&client, &format!("/inbox/notes/{}/graph", a));
+    let nodes: Vec<&str> = graph["nodes"].as_array().unwrap().iter().map(|node| node["id"].as_str().unwrap()).collect();
+    assert_eq!(nodes, [a.as_str(), b.as_str()]);
+    assert_eq!(graph["edges"][0]["id"], relation["id"]);
+
+    // 请求体中的 id：UUID 可用，整数 id 视为不存在
+    let response = client
+        .post("/inbox/relations/bulk")
+        .header(ContentType::JSON)
+        .body(json!([{ "source_id": b, "target_id": c, "relation_type": "Reference" }]).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let created: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(created[0]["target_note_id"], c.as_str());
+    let response = client
+        .post("/inbox/relations/bulk")
+        .header(ContentType::JSON)
+        .body(json!([{ "source_id": 1, "target_id": c, "relation_type": "Reference" }]).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .post("/inbox/notes/batch-get")
+        .header(ContentType::JSON)
+        .body(json!([a, 2]).to_string())
+        .dispatch();
+    let batch: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(batch["notes"][0]["id"], a.as_str());
+    assert_eq!(batch["missing"], json!([2]));
+
+    let (status, diff) = common::get_json(&client, &format!("/inbox/notes/diff?a={}&b={}", a, b));
+    assert_eq!(status, Status::Ok);
+    assert_eq!((diff["a"].as_str(), diff["b"].as_str()), (Some(a.as_str()), Some(b.as_str())));
+    assert_eq!(common::get_json(&client, &format!("/inbox/notes/diff?a=1&b={}", b)).0, Status::NotFound);
+
+    // [[1]] 在 uuid 模式下不指向任何笔记
+    let (_, broken) = common::get_json(&client, "/inbox/notes/broken-links");
+    assert_eq!(broken, json!([{ "note_id": b, "missing_refs": [1] }]));
+
+    let response = client
+        .post("/inbox/tags/rename/preview")
+        .header(ContentType::JSON)
+        .body(r#"{"from":"x","to":"z"}"#)
+        .dispatch();
+    let preview: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(preview["note_ids"], json!([a]));
+
+    let response = client
+        .post(format!("/inbox/notes/{}/comments", c))
+        .header(ContentType::JSON)
+        .body(r#"{"content":"a comment"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", c));
+    let comment = relations.as_array().unwrap().iter().find(|relation| relation["relation_type"] == "Comment").unwrap();
+    assert_eq!(client.post("/inbox/comments/1/promote").dispatch().status(), Status::NotFound);
+    let response = client.post(format!("/inbox/comments/{}/promote", comment["id"].as_str().unwrap())).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/inbox/triage")
+        .header(ContentType::JSON)
+        .body(json!({ "pin": [c, 1] }).to_string())
+        .dispatch();
+    let summary: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(summary["pinned"], 1);
+
+    let response = client
+        .post("/inbox/notes/merge")
+        .header(ContentType::JSON)
+        .body(json!({ "primary": a, "others": [3] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let response = client
+        .post("/inbox/notes/merge")
+        .header(ContentType::JSON)
+        .body(json!({ "primary": a, "others": [c] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let merged: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(merged["id"], a.as_str());
+}
+
+#[test]
+fn test_stale_untagged_notes() {
+    let client = common::client();
+    let create = |content: &str, tags: &[&str], days_ago: i64| {
+        let created_at = chrono::Utc::now() - chrono::Duration::days(days_ago);
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .body(json!({ "content": content, "tags": tags, "created_at": created_at }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    };
+    create("old untagged", &[], 40);
+    create("oldest untagged", &[], 90);
+    create("old tagged", &["work"], 60);
+    create("recent untagged", &[], 1);
+
+    let (status, notes) = common::get_json(&client, "/inbox/notes/stale-untagged");
+    assert_eq!(status, Status::Ok);
+    let contents: Vec<&str> = notes.as_array().unwrap().iter().map(|n| n["content"].as_str().unwrap()).collect();
+    assert_eq!(contents, vec!["oldest untagged", "old untagged"]);
+
+    let (_, notes) = common::get_json(&client, "/inbox/notes/stale-untagged?days=0");
+    assert_eq!(notes.as_array().unwrap().len(), 3);
+    let (_, notes) = common::get_json(&client, "/inbox/notes/stale-untagged?days=60");
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+    let (_, notes) = common::get_json(&client, "/inbox/notes/stale-untagged?days=0&limit=1");
+    assert_eq!(notes[0]["content"], "oldest untagged");
+}
+
+#[test]
+fn test_note_meta_tracks_updates() {
+    let client = common::client();
+    let note = common::create_note(&client, "first draft", &[]);
+    let id = note["id"].as_i64().unwrap();
+
+    let (status, meta) = common::get_json(&client, &format!("/inbox/notes/{}/meta", id));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(meta["update_count"], 0);
+    let initial_hash = meta["content_hash"].as_str().unwrap().to_string();
+    assert_eq!(initial_hash.len(), 64);
+    assert!(meta.get("content").is_none());
+
+    for content in ["second draft", "first draft"] {
+        let response = client
+            .put(format!("/inbox/notes/{}", id))
+            .header(ContentType::JSON)
+            .body(json!({ "content": content }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let (_, meta) = common::get_json(&client, &format!("/inbox/notes/{}/meta", id));
+    assert_eq!(meta["update_count"], 2);
+    // 内容改回原样后哈希相同
+    assert_eq!(meta["content_hash"], initial_hash.as_str());
+    assert_ne!(meta["updated_at"], meta["created_at"]);
+
+    assert_eq!(common::get_json(&client, "/inbox/notes/999999/meta").0, Status::NotFound);
+    client.delete(format!("/inbox/notes/{}", id)).dispatch();
+    assert_eq!(common::get_json(&client, &format!("/inbox/notes/{}/meta", id)).0, Status::NotFound);
+}
+
+#[test]
+fn test_identical_update_is_a_no_op() {
+    let client = common::client();
+    let note = common::create_note(&client, "unchanged", &["keep"]);
+    let id = note["id"].as_i64().unwrap();
+    let put = |body: serde_json::Value| {
+        let response = client.put(format!("/inbox/notes/{}", id)).header(ContentType::JSON).body(body.to_string()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<serde_json::Value>().unwrap()
+    };
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let same = put(json!({ "content": "unchanged", "tags": ["keep"] }));
+    assert_eq!(same["updated_at"], note["updated_at"]);
+    let (_, meta) = common::get_json(&client, &format!("/inbox/notes/{}/meta", id));
+    assert_eq!(meta["update_count"], 0);
+    let (_, history) = common::get_json(&client, &format!("/inbox/notes/{}/history", id));
+    assert_eq!(history, json!([]));
+
+    // 只改标签也算修改
+    let changed = put(json!({ "content": "unchanged", "tags": ["keep", "new"] }));
+    assert_ne!(changed["updated_at"], note["updated_at"]);
+}
+
+#[test]
+fn test_note_history_and_restore() {
+    let client = common::client();
+    let id = common::create_note(&client, "v1", &["draft"])["id"].as_i64().unwrap();
+    let update = |content: &str, tags: &[&str]| {
+        let response = client
+            .put(format!("/inbox/notes/{}", id))
+            .header(ContentType::JSON)
+            .body(json!({ "content": content, "tags": tags }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    };
+    update("v2", &["draft"]);
+    update("v3", &["final"]);
+
+    let (status, history) = common::get_json(&client, &format!("/inbox/notes/{}/history", id));
+    assert_eq!(status, Status::Ok);
+    let contents: Vec<&str> = history.as_array().unwrap().iter().map(|v| v["content"].as_str().unwrap()).collect();
+    assert_eq!(contents, vec!["v2", "v1"]);
+    assert_eq!(history[1]["tags"], json!(["draft"]));
+
+    let v1 = history[1]["version_id"].as_i64().unwrap();
+    let response = client.post(format!("/inbox/notes/{}/restore/{}", id, v1)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let restored: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(restored["content"], "v1");
+    assert_eq!(restored["tags"], json!(["draft"]));
+    // 恢复前的内容成为最新的历史版本
+    let (_, history) = common::get_json(&client, &format!("/inbox/notes/{}/history", id));
+    assert_eq!(history[0]["content"], "v3");
+    assert_eq!(history.as_array().unwrap().len(), 3);
+
+    // 版本必须属于该笔记
+    let other = common::create_note(&client, "other", &[])["id"].as_i64().unwrap();
+    assert_eq!(client.post(format!("/inbox/notes/{}/restore/{}", other, v1)).dispatch().status(), Status::NotFound);
+    assert_eq!(client.post(format!("/inbox/notes/{}/restore/999999", id)).dispatch().status(), Status::NotFound);
+    assert_eq!(common::get_json(&client, "/inbox/notes/999999/history").0, Status::NotFound);
+}
+
+#[test]
+fn test_note_history_is_capped() {
+    let client = common::client_with_config(InboxConfig { history_limit: 2, ..Default::default() });
+    let id = common::create_note(&client, "v0", &[])["id"].as_i64().unwrap();
+    for i in 1..=4 {
+        client
+            .put(format!("/inbox/notes/{}", id))
+            .header(ContentType::JSON)
+            .body(json!({ "content": format!("v{}", i) }).to_string())
+            .dispatch();
+    }
+    let (_, history) = common::get_json(&client, &format!("/inbox/notes/{}/history", id));
+    let contents: Vec<&str> = history.as_array().unwrap().iter().map(|v| v["content"].as_str().unwrap()).collect();
+    assert_eq!(contents, vec!["v3", "v2"]);
+
+    // 0 表示不记录历史
+    let client = common::client_with_config(InboxConfig { history_limit: 0, ..Default::default() });
+    let id = common::create_note(&client, "v0", &[])["id"].as_i64().unwrap();
+    client.put(format!("/inbox/notes/{}", id)).header(ContentType::JSON).body(r#"{"content":"v1"}"#).dispatch();
+    let (status, history) = common::get_json(&client, &format!("/inbox/notes/{}/history", id));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(history, json!([]));
+}
+
+#[test]
+fn test_streaming_export_ndjson() {
+    let client = common::client();
+    // 超过一批 (500 条)，覆盖分批读取的边界
+    let batch: Vec<_> = (0..1001).map(|i| json!({ "content": format!("note {}", i), "tags": ["bulk"] })).collect();
+    let response = client.post("/inbox/import").header(ContentType::JSON).body(json!(batch).to_string()).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let deleted = common::create_note(&client, "deleted", &[])["id"].as_i64().unwrap();
+    client.delete(format!("/inbox/notes/{}", deleted)).dispatch();
+
+    let response = client.get("/inbox/export/stream").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::new("application", "x-ndjson")));
+    let body = response.into_string().unwrap();
+    assert!(body.ends_with('\n'));
+    let notes: Vec<serde_json::Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(notes.len(), 1001);
+    assert_eq!(notes[0]["content"], "note 0");
+    assert_eq!(notes[1000]["content"], "note 1000");
+    assert_eq!(notes[0]["tags"], json!(["bulk"]));
+    let ids: Vec<i64> = notes.iter().map(|n| n["id"].as_i64().unwrap()).collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+    // 空库时返回空响应体
+    let client = common::client();
+    assert_eq!(client.get("/inbox/export/stream").dispatch().into_string().unwrap(), "");
+}
+
+#[test]
+fn test_streaming_export_does_not_hold_a_connection() {
+    // 测试连接池只有一个连接：导出流尚未读完时，其它请求仍能取得连接
+    let client = common::client();
+    let batch: Vec<_> = (0..600).map(|i| json!({ "content": format!("note {}", i) })).collect();
+    client.post("/inbox/import").header(ContentType::JSON).body(json!(batch).to_string()).dispatch();
+
+    let stream = client.get("/inbox/export/stream").dispatch();
+    assert_eq!(stream.status(), Status::Ok);
+    let (status, count) = common::get_json(&client, "/inbox/notes/count");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(count["count"], 600);
+    assert_eq!(stream.into_string().unwrap().lines().count(), 600);
+}
+
+#[test]
+fn test_streaming_import_ndjson() {
+    let source = common::client();
+    let batch: Vec<_> = (0..600).map(|i| json!({ "content": format!("note {}", i), "tags": ["moved"] })).collect();
+    source.post("/inbox/import").header(ContentType::JSON).body(json!(batch).to_string()).dispatch();
+    let export = source.get("/inbox/export/stream").dispatch().into_string().unwrap();
+
+    // 导出结果可以原样导入，跨越多个批次
+    let target = common::client();
+    let response = target.post("/inbox/import/stream").body(export).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let summary: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(summary, json!({ "imported": 600, "skipped": 0, "skipped_lines": [] }));
+    let (_, count) = common::get_json(&target, "/inbox/notes/count?tag=moved");
+    assert_eq!(count["count"], 600);
+
+    // 无法解析或校验失败的行被跳过，空行不计
+    let body = [
+        r#"{"content":"good 1"}"#,
+        "not json",
+        "",
+        r#"{"tags":["no content"]}"#,
+        r#"{"content":"   "}"#,
+        r#"{"content":"good 2","tags":["x"]}"#,
+    ]
+    .join("\n");
+    let response = target.post("/inbox/import/stream").body(body).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let summary: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(summary, json!({ "imported": 2, "skipped": 3, "skipped_lines": [2, 4, 5] }));
+    let (_, notes) = common::get_json(&target, "/inbox/notes?tag=x");
+    assert_eq!(notes[0]["content"], "good 2");
+}
+
+#[test]
+fn test_upsert_note_by_external_id() {
+    let client = common::client();
+    let upsert = |body: serde_json::Value, expected: Status| {
+        let response = client.post("/inbox/notes/upsert").header(ContentType::JSON).body(body.to_string()).dispatch();
+        assert_eq!(response.status(), expected);
+        let location = response.headers().get_one("Location").map(str::to_string);
+        let body = response.into_json::<serde_json::Value>().unwrap();
+        // 只有新建时带 Location，指向新笔记
+        let expected_location = (expected == Status::Created).then(|| format!("/inbox/notes/{}", body["id"]));
+        assert_eq!(location, expected_location);
+        body
+    };
+
+    let first = upsert(json!({ "external_id": "gh-42", "content": "from github", "tags": ["sync"] }), Status::Created);
+    assert_eq!(first["created"], true);
+    let id = first["id"].as_i64().unwrap();
+
+    let second = upsert(json!({ "external_id": "gh-42", "content": "edited on github", "tags": ["sync", "edited"] }), Status::Ok);
+    assert_eq!(second["created"], false);
+    assert_eq!(second["id"], id);
+    assert_eq!(second["content"], "edited on github");
+    assert_eq!(second["tags"], json!(["sync", "edited"]));
+
+    // 不同的外部 ID 新建另一条笔记
+    let other = upsert(json!({ "external_id": "gh-43", "content": "another issue" }), Status::Created);
+    assert_ne!(other["id"], id);
+
+    // 原笔记移入回收站后，同一外部 ID 创建新笔记
+    assert_eq!(client.delete(format!("/inbox/notes/{}", id)).dispatch().status(), Status::NoContent);
+    let recreated = upsert(json!({ "external_id": "gh-42", "content": "reopened" }), Status::Created);
+    assert_ne!(recreated["id"], id);
+
+    let response = client.post("/inbox/notes/upsert").header(ContentType::JSON)
+        .body(json!({ "external_id": "  ", "content": "no id" }).to_string()).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_head_note() {
+    let client = common::client();
+    let id = common::create_note(&client, "still here", &["monitored"])["id"].as_i64().unwrap();
+
+    let get = client.get(format!("/inbox/notes/{}", id)).dispatch();
+    let etag = get.headers().get_one("ETag").unwrap().to_string();
+
+    // Content-Length 由服务器按去掉响应体前的大小填写，本地测试客户端看不到，这里只检查其余的头
+    let head = client.head(format!("/inbox/notes/{}", id)).dispatch();
+    assert_eq!(head.status(), Status::Ok);
+    assert_eq!(head.headers().get_one("ETag"), Some(etag.as_str()));
+    assert_eq!(head.content_type(), Some(ContentType::JSON));
+    assert!(head.into_bytes().unwrap_or_default().is_empty());
+
+    // 笔记变化后 ETag 随之变化
+    let response = client.put(format!("/inbox/notes/{}", id)).header(ContentType::JSON)
+        .body(json!({ "content": "edited", "tags": ["monitored"] }).to_string()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let head = client.head(format!("/inbox/notes/{}", id)).dispatch();
+    assert_ne!(head.headers().get_one("ETag"), Some(etag.as_str()));
+
+    let missing = client.head("/inbox/notes/999999").dispatch();
+    assert_eq!(missing.status(), Status::NotFound);
+    assert!(missing.into_bytes().unwrap_or_default().is_empty());
+}
+
+#[test]
+fn test_head_note_goes_through_compression_like_get() {
+    // HEAD 的响应体与 GET 一样经过格式化和压缩后才被去掉，Content-Length 因此与 GET 一致
+    let client = common::client_with_config(InboxConfig { compression: true, compress_min_bytes: 1, pretty: true, ..Default::default() });
+    let id = common::create_note(&client, &"compressible ".repeat(50), &[])["id"].as_i64().unwrap();
+
+    for accept_encoding in ["gzip", "identity"] {
+        let get = client.get(format!("/inbox/notes/{}", id)).header(Header::new("Accept-Encoding", accept_encoding)).dispatch();
+        let head = client.head(format!("/inbox/notes/{}", id)).header(Header::new("Accept-Encoding", accept_encoding)).dispatch();
+        for name in ["Content-Encoding", "Vary", "ETag"] {
+            let get_values: Vec<&str> = get.headers().get(name).collect();
+            let head_values: Vec<&str> = head.headers().get(name).collect();
+            assert_eq!(head_values, get_values, "{} with Accept-Encoding: {}", name, accept_encoding);
+        }
+        assert!(head.into_bytes().unwrap_or_default().is_empty());
+    }
+}
@@ -0,0 +1,653 @@
+// 笔记关系相关 API 的进程内集成测试
+mod common;
+
+use aw_inbox_rust::config::InboxConfig;
+use rocket::http::{ContentType, Status};
+use serde_json::json;
+
+#[test]
+fn test_bulk_relations_created_in_one_call() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+    let c = common::create_note(&client, "c", &[])["id"].as_i64().unwrap();
+
+    let payload = json!([
+        { "source_id": a, "target_id": b, "relation_type": "Link" },
+        { "source_id": a, "target_id": c, "relation_type": "Reference" },
+    ]);
+    let response = client.post("/inbox/relations/bulk").header(ContentType::JSON).body(payload.to_string()).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let body: serde_json::Value = response.into_json().unwrap();
+    let relations = body.as_array().unwrap();
+    assert_eq!(relations.len(), 2);
+    assert!(relations.iter().all(|r| r["id"].as_i64().is_some()));
+
+    let (_, relations_b) = common::get_json(&client, &format!("/inbox/notes/{}/relations", b));
+    assert_eq!(relations_b.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_bulk_relations_roll_back_on_missing_note() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+
+    let payload = json!([
+        { "source_id": a, "target_id": b, "relation_type": "Link" },
+        { "source_id": a, "target_id": 9999, "relation_type": "Link" },
+    ]);
+    let response = client.post("/inbox/relations/bulk").header(ContentType::JSON).body(payload.to_string()).dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"], "entry 1: target note 9999 does not exist");
+
+    // 第一条也应回滚
+    let (_, relations_b) = common::get_json(&client, &format!("/inbox/notes/{}/relations", b));
+    assert!(relations_b.as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_comments_validated_like_notes() {
+    let client = common::client_with_config(InboxConfig { max_tags: 2, ..Default::default() });
+    let parent = common::create_note(&client, "parent", &[])["id"].as_i64().unwrap();
+    let comment = |body: serde_json::Value| {
+        client.post(format!("/inbox/notes/{}/comments", parent)).header(ContentType::JSON).body(body.to_string()).dispatch()
+    };
+
+    assert_eq!(comment(json!({ "content": "   " })).status(), Status::BadRequest);
+    let response = comment(json!({ "content": "c", "tags": ["a", "b", "c"] }));
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(response.into_json::<serde_json::Value>().unwrap()["error"].as_str().unwrap().contains("INBOX_MAX_TAGS"));
+
+    // 标签与笔记一样规范化去重
+    let response = comment(json!({ "content": "c", "tags": ["x", "x", "#x"] }));
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["tags"], json!(["x"]));
+
+    let response = client.post("/inbox/notes/9999/comments").header(ContentType::JSON).body(json!({ "content": "c" }).to_string()).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_promote_comment_to_note() {
+    let client = common::client();
+    let parent = common::create_note(&client, "parent", &[])["id"].as_i64().unwrap();
+
+    let response = client
+        .post(format!("/inbox/notes/{}/comments", parent))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "grew into its own thought" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let comment_id = response.into_json::<serde_json::Value>().unwrap()["id"].as_i64().unwrap();
+
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", parent));
+    let relation_id = relations[0]["id"].as_i64().unwrap();
+
+    let response = client.post(format!("/inbox/comments/{}/promote", relation_id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let promoted: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(promoted["id"].as_i64(), Some(comment_id));
+
+    let (_, comments) = common::get_json(&client, &format!("/inbox/notes/{}/comments", parent));
+    assert!(comments.as_array().unwrap().is_empty());
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert!(notes.as_array().unwrap().iter().any(|n| n["id"].as_i64() == Some(comment_id)));
+
+    // 关系已删除
+    let response = client.post(format!("/inbox/comments/{}/promote", relation_id)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_promote_rejects_non_comment_relation() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+
+    let response = client
+        .post(format!("/inbox/notes/{}/relations/{}", a, b))
+        .header(ContentType::JSON)
+        .body(json!({ "relation_type": "Link" }).to_string())
+        .dispatch();
+    let relation_id = response.into_json::<serde_json::Value>().unwrap()["id"].as_i64().unwrap();
+
+    let response = client.post(format!("/inbox/comments/{}/promote", relation_id)).dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[test]
+fn test_notes_with_relation_counts() {
+    let client = common::client();
+    let parent = common::create_note(&client, "parent", &[])["id"].as_i64().unwrap();
+    let other = common::create_note(&client, "other", &[])["id"].as_i64().unwrap();
+
+    for content in ["first", "second"] {
+        client
+            .post(format!("/inbox/notes/{}/comments", parent))
+            .header(ContentType::JSON)
+            .body(json!({ "content": content }).to_string())
+            .dispatch();
+    }
+    client
+        .post(format!("/inbox/notes/{}/relations/{}", parent, other))
+        .header(ContentType::JSON)
+        .body(json!({ "relation_type": "Link" }).to_string())
+        .dispatch();
+
+    let (_, notes) = common::get_json(&client, "/inbox/notes?with_counts=true&fields=id");
+    let find = |id: i64| notes.as_array().unwrap().iter().find(|n| n["id"].as_i64() == Some(id)).unwrap().clone();
+    assert_eq!(find(parent)["relation_count"], 3);
+    assert_eq!(find(parent)["comment_count"], 2);
+    assert_eq!(find(other)["relation_count"], 1);
+    assert_eq!(find(other)["comment_count"], 0);
+    assert!(find(parent).get("content").is_none());
+
+    // 默认不附带计数
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert!(notes[0].get("relation_count").is_none());
+}
+
+#[test]
+fn test_relation_types_with_counts() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+    for _ in 0..2 {
+        client
+            .post(format!("/inbox/notes/{}/relations/{}", a, b))
+            .header(ContentType::JSON)
+            .body(json!({ "relation_type": "Link" }).to_string())
+            .dispatch();
+    }
+
+    let (status, types) = common::get_json(&client, "/inbox/relations/types");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(types, json!([
+        { "type": "Link", "count": 2 },
+        { "type": "Comment", "count": 0 },
+        { "type": "Reference", "count": 0 },
+        { "type": "Parent", "count": 0 },
+    ]));
+}
+
+#[test]
+fn test_comment_count() {
+    let client = common::client();
+    let parent = common::create_note(&client, "parent", &[])["id"].as_i64().unwrap();
+
+    let (status, body) = common::get_json(&client, &format!("/inbox/notes/{}/comments/count", parent));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body, json!({ "count": 0 }));
+
+    for content in ["one", "two", "three"] {
+        client
+            .post(format!("/inbox/notes/{}/comments", parent))
+            .header(ContentType::JSON)
+            .body(json!({ "content": content }).to_string())
+            .dispatch();
+    }
+    let (_, body) = common::get_json(&client, &format!("/inbox/notes/{}/comments/count", parent));
+    assert_eq!(body, json!({ "count": 3 }));
+}
+
+#[test]
+fn test_full_note() {
+    let client = common::client();
+    let note = common::create_note(&client, "detail", &[])["id"].as_i64().unwrap();
+    let other = common::create_note(&client, "other", &[])["id"].as_i64().unwrap();
+    client
+        .post(format!("/inbox/notes/{}/comments", note))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "a comment" }).to_string())
+        .dispatch();
+    for (source, target) in [(note, other), (other, note)] {
+        client
+            .post(format!("/inbox/notes/{}/relations/{}", source, target))
+            .header(ContentType::JSON)
+            .body(json!({ "relation_type": "Link" }).to_string())
+            .dispatch();
+    }
+
+    let (status, full) = common::get_json(&client, &format!("/inbox/notes/{}/full", note));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(full["note"]["content"], "detail");
+    assert_eq!(full["comments"][0]["content"], "a comment");
+    assert_eq!(full["relations"].as_array().unwrap().len(), 1);
+    assert_eq!(full["relations"][0]["target_note_id"].as_i64(), Some(other));
+    assert_eq!(full["backlinks"].as_array().unwrap().len(), 1);
+    assert_eq!(full["backlinks"][0]["source_note_id"].as_i64(), Some(other));
+
+    let (status, _) = common::get_json(&client, "/inbox/notes/9999/full");
+    assert_eq!(status, Status::NotFound);
+}
+
+#[test]
+fn test_search_linked_notes() {
+    let client = common::client();
+    let id = |content: &str| common::create_note(&client, content, &[])["id"].as_i64().unwrap();
+    let rust_a = id("learning rust");
+    let rust_b = id("rust macros");
+    let outgoing = id("book list");
+    let incoming = id("weekly review");
+    id("groceries");
+
+    let payload = json!([
+        { "source_id": rust_a, "target_id": outgoing, "relation_type": "Reference" },
+        { "source_id": rust_b, "target_id": outgoing, "relation_type": "Link" },
+        { "source_id": incoming, "target_id": rust_b, "relation_type": "Link" },
+        // 匹配的笔记之间的关系不会把它们列入 linked
+        { "source_id": rust_a, "target_id": rust_b, "relation_type": "Link" }
+    ]);
+    let response = client.post("/inbox/relations/bulk").header(ContentType::JSON).body(payload.to_string()).dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    let (status, body) = common::get_json(&client, "/inbox/search/linked?q=rust");
+    assert_eq!(status, Status::Ok);
+    let ids = |key: &str| -> Vec<i64> { body[key].as_array().unwrap().iter().map(|n| n["id"].as_i64().unwrap()).collect() };
+    assert_eq!(ids("matched"), vec![rust_b, rust_a]);
+    assert_eq!(ids("linked"), vec![incoming, outgoing]);
+
+    let (_, body) = common::get_json(&client, "/inbox/search/linked?q=rust&limit=1");
+    assert_eq!(body["matched"].as_array().unwrap().len(), 1);
+
+    let (status, _) = common::get_json(&client, "/inbox/search/linked?q=");
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_create_linked_note() {
+    let client = common::client();
+    let source = common::create_note(&client, "source", &[])["id"].as_i64().unwrap();
+
+    let response = client
+        .post(format!("/inbox/notes/{}/link", source))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "follow-up", "tags": ["x"], "relation_type": "Reference" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let body: serde_json::Value = response.into_json().unwrap();
+    let target = body["note"]["id"].as_i64().unwrap();
+    assert_eq!(body["note"]["content"], "follow-up");
+    assert_eq!(body["note"]["tags"], json!(["x"]));
+    assert_eq!(body["relation"]["source_note_id"], source);
+    assert_eq!(body["relation"]["target_note_id"], target);
+    assert_eq!(body["relation"]["relation_type"], "Reference");
+
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", target));
+    assert_eq!(relations.as_array().unwrap().len(), 1);
+
+    // 源笔记不存在：整体回滚，不会留下新笔记
+    let response = client
+        .post("/inbox/notes/999/link")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "orphan", "relation_type": "Link" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let (_, count) = common::get_json(&client, "/inbox/notes/count");
+    assert_eq!(count["count"], 2);
+}
+
+#[test]
+fn test_create_relation_reports_missing_note() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let create = |source: i64, target: i64| {
+        let response = client
+            .post(format!("/inbox/notes/{}/relations/{}", source, target))
+            .header(ContentType::JSON)
+            .body(json!({ "relation_type": "Link" }).to_string())
+            .dispatch();
+        let status = response.status();
+        (status, response.into_json::<serde_json::Value>().unwrap())
+    };
+
+    let (status, body) = create(999, a);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(body["error"], "source note 999 does not exist");
+
+    let (status, body) = create(a, 998);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(body["error"], "target note 998 does not exist");
+
+    // 两者都不存在时先报告源笔记
+    let (_, body) = create(997, 996);
+    assert_eq!(body["error"], "source note 997 does not exist");
+}
+
+#[test]
+fn test_parent_cycles_are_rejected_and_graph_terminates() {
+    let client = common::client();
+    let ids: Vec<i64> = ["a", "b", "c"].iter().map(|c| common::create_note(&client, c, &[])["id"].as_i64().unwrap()).collect();
+    let (a, b, c) = (ids[0], ids[1], ids[2]);
+    let relate = |source: i64, target: i64, relation_type: &str| {
+        client
+            .post(format!("/inbox/notes/{}/relations/{}", source, target))
+            .header(ContentType::JSON)
+            .body(json!({ "relation_type": relation_type }).to_string())
+            .dispatch()
+            .status()
+    };
+
+    // a 的父笔记是 b，b 的父笔记是 c；再让 c 以 a 为父会成环
+    assert_eq!(relate(a, b, "Parent"), Status::Created);
+    assert_eq!(relate(b, c, "Parent"), Status::Created);
+    assert_eq!(relate(c, a, "Parent"), Status::Conflict);
+    assert_eq!(relate(a, a, "Parent"), Status::Conflict);
+    let response = client
+        .post("/inbox/relations/bulk")
+        .header(ContentType::JSON)
+        .body(json!([{ "source_id": c, "target_id": b, "relation_type": "Parent" }]).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+
+    // 其它类型允许成环，遍历仍然终止
+    assert_eq!(relate(c, a, "Link"), Status::Created);
+    let (status, graph) = common::get_json(&client, &format!("/inbox/notes/{}/graph?depth=10", a));
+    assert_eq!(status, Status::Ok);
+    let nodes: Vec<(i64, i64)> = graph["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|n| (n["id"].as_i64().unwrap(), n["depth"].as_i64().unwrap()))
+        .collect();
+    assert_eq!(nodes.len(), 3);
+    assert!(nodes.contains(&(a, 0)) && nodes.contains(&(b, 1)) && nodes.contains(&(c, 1)));
+    assert_eq!(graph["edges"].as_array().unwrap().len(), 3);
+
+    let (_, graph) = common::get_json(&client, &format!("/inbox/notes/{}/graph?depth=0", a));
+    assert_eq!(graph["nodes"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_trash_hides_and_restores_relations() {
+    let client = common::client();
+    let parent = common::create_note(&client, "parent", &[])["id"].as_i64().unwrap();
+    let other = common::create_note(&client, "other", &[])["id"].as_i64().unwrap();
+    let response = client
+        .post(format!("/inbox/notes/{}/comments", parent))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "a comment" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let response = client
+        .post(format!("/inbox/notes/{}/relations/{}", other, parent))
+        .header(ContentType::JSON)
+        .body(json!({ "relation_type": "Link" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    let response = client.post(format!("/inbox/notes/{}/trash", parent)).dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    let (status, _) = common::get_json(&client, &format!("/inbox/notes/{}", parent));
+    assert_eq!(status, Status::NotFound);
+    let (_, comments) = common::get_json(&client, &format!("/inbox/notes/{}/comments", parent));
+    assert_eq!(comments.as_array().unwrap().len(), 0);
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", parent));
+    assert_eq!(relations.as_array().unwrap().len(), 0);
+    let (_, types) = common::get_json(&client, "/inbox/relations/types");
+    assert!(types.as_array().unwrap().iter().all(|t| t["count"] == 0));
+    let response = client.post(format!("/inbox/notes/{}/trash", parent)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.post(format!("/inbox/trash/{}/restore", parent)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<serde_json::Value>().unwrap()["content"], "parent");
+    let (_, comments) = common::get_json(&client, &format!("/inbox/notes/{}/comments", parent));
+    assert_eq!(comments.as_array().unwrap().len(), 1);
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", parent));
+    assert_eq!(relations.as_array().unwrap().len(), 2);
+
+    let response = client.post(format!("/inbox/trash/{}/restore", parent)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+// 依次读出 zip 中各文件的 (文件名, 解压后的内容)
+fn unzip(bytes: &[u8]) -> Vec<(String, String)> {
+    use std::io::Read;
+    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize;
+    let mut files = Vec::new();
+    let mut at = 0;
+    while u32_at(at) == 0x0403_4b50 {
+        let (size, name_len) = (u32_at(at + 18), u16_at(at + 26));
+        let name = String::from_utf8(bytes[at + 30..at + 30 + name_len].to_vec()).unwrap();
+        let data_start = at + 30 + name_len;
+        let mut content = String::new();
+        flate2::read::DeflateDecoder::new(&bytes[data_start..data_start + size]).read_to_string(&mut content).unwrap();
+        files.push((name, content));
+        at = data_start + size;
+    }
+    files
+}
+
+#[test]
+fn test_note_bundle() {
+    let client = common::client();
+    let note = common::create_note(&client, "shared note", &["share"])["id"].as_i64().unwrap();
+    client
+        .post(format!("/inbox/notes/{}/comments", note))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "first comment" }).to_string())
+        .dispatch();
+
+    let response = client.get(format!("/inbox/notes/{}/bundle", note)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::ZIP));
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some(format!("attachment; filename=\"note-{}.zip\"", note).as_str())
+    );
+    let files = unzip(&response.into_bytes().unwrap());
+    let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["note.md", "comments.md"]);
+    assert!(files[0].1.contains("shared note") && files[0].1.contains("#share"));
+    assert!(files[1].1.contains("first comment"));
+
+    assert_eq!(client.get("/inbox/notes/9999/bundle").dispatch().status(), Status::NotFound);
+}
+
+#[test]
+fn test_relations_per_note_are_capped() {
+    let client = common::client_with_config(InboxConfig { max_relations_per_note: 2, ..Default::default() });
+    let ids: Vec<i64> = (0..4).map(|i| common::create_note(&client, &format!("n{}", i), &[])["id"].as_i64().unwrap()).collect();
+    let link = |source: i64, target: i64| {
+        client
+            .post(format!("/inbox/notes/{}/relations/{}", source, target))
+            .header(ContentType::JSON)
+            .body(json!({ "relation_type": "Link" }).to_string())
+            .dispatch()
+    };
+
+    assert_eq!(link(ids[0], ids[1]).status(), Status::Created);
+    assert_eq!(link(ids[2], ids[0]).status(), Status::Created);
+    // ids[0] 作为源或目标都已达到上限
+    let response = link(ids[0], ids[3]);
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert!(body["error"].as_str().unwrap().contains("INBOX_MAX_RELATIONS_PER_NOTE"));
+    assert_eq!(link(ids[3], ids[0]).status(), Status::BadRequest);
+    // 评论不计入
+    let response = client
+        .post(format!("/inbox/notes/{}/comments", ids[0]))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "still fine" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    // 创建关联笔记同样受限，且不留下新笔记
+    let response = client
+        .post(format!("/inbox/notes/{}/link", ids[0]))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "linked", "relation_type": "Link" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let (_, count) = common::get_json(&client, "/inbox/notes/count");
+    assert_eq!(count["count"], 5);
+
+    let response = client
+        .post("/inbox/relations/bulk")
+        .header(ContentType::JSON)
+        .body(json!([{ "source_id": ids[1], "target_id": ids[3], "relation_type": "Link" }, { "source_id": ids[3], "target_id": ids[0], "relation_type": "Link" }]).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    // 整批回滚
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", ids[3]));
+    assert_eq!(relations, json!([]));
+}
+
+#[test]
+fn test_broken_links() {
+    let client = common::client();
+    let target = common::create_note(&client, "target", &[])["id"].as_i64().unwrap();
+    let ok = common::create_note(&client, &format!("see [[{}]]", target), &[])["id"].as_i64().unwrap();
+    let broken = common::create_note(&client, &format!("see [[{}]], [[99999]] and [[99998]], again [[99999]]", target), &[])["id"].as_i64().unwrap();
+    // 不是纯数字的写法不算引用
+    common::create_note(&client, "[[wiki page]] [[ 99997 ]] [[-5]]", &[]);
+
+    let (status, body) = common::get_json(&client, "/inbox/notes/broken-links");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body, json!([{ "note_id": broken, "missing_refs": [99999, 99998] }]));
+
+    // 被引用的笔记移入回收站后，引用它的笔记也出现在结果中
+    assert_eq!(client.delete(format!("/inbox/notes/{}", target)).dispatch().status(), Status::NoContent);
+    let (_, body) = common::get_json(&client, "/inbox/notes/broken-links");
+    let ids: Vec<i64> = body.as_array().unwrap().iter().map(|entry| entry["note_id"].as_i64().unwrap()).collect();
+    assert_eq!(ids, vec![broken, ok]);
+}
+
+#[test]
+fn test_auto_link_tracks_inline_references() {
+    let client = common::client_with_config(InboxConfig { auto_link: true, ..Default::default() });
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+    // GET /notes/<id>/relations 列出指向该笔记的关系，因此逐个检查可能的目标
+    let links = |source: i64| -> Vec<i64> {
+        [a, b].into_iter().filter(|target| {
+            let (status, body) = common::get_json(&client, &format!("/inbox/notes/{}/relations", target));
+            assert_eq!(status, Status::Ok);
+            body.as_array().unwrap().iter().any(|r| r["relation_type"] == "Link" && r["source_note_id"] == source)
+        }).collect()
+    };
+    let put = |id: i64, content: String| {
+        let response = client.put(format!("/inbox/notes/{}", id)).header(ContentType::JSON)
+            .body(json!({ "content": content }).to_string()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    };
+
+    // 引用不存在的笔记时跳过，不影响其它引用
+    let source = common::create_note(&client, &format!("links to [[{}]] and [[99999]]", a), &[])["id"].as_i64().unwrap();
+    assert_eq!(links(source), vec![a]);
+
+    put(source, format!("links to [[{}]] and [[{}]]", a, b));
+    assert_eq!(links(source), vec![a, b]);
+
+    put(source, format!("only [[{}]] now", b));
+    assert_eq!(links(source), vec![b]);
+
+    // 手动创建的 Link 关系不受正文变化影响
+    let response = client.post(format!("/inbox/notes/{}/relations/{}", source, a)).header(ContentType::JSON)
+        .body(json!({ "relation_type": "Link" }).to_string()).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    put(source, "no references".to_string());
+    assert_eq!(links(source), vec![a]);
+}
+
+#[test]
+fn test_auto_link_keeps_manual_links_and_follows_restores() {
+    let client = common::client_with_config(InboxConfig { auto_link: true, ..Default::default() });
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+    let links_to = |target: i64, source: i64| -> bool {
+        let (_, body) = common::get_json(&client, &format!("/inbox/notes/{}/relations", target));
+        body.as_array().unwrap().iter().any(|r| r["relation_type"] == "Link" && r["source_note_id"] == source)
+    };
+    let put = |id: i64, content: String| {
+        let response = client.put(format!("/inbox/notes/{}", id)).header(ContentType::JSON)
+            .body(json!({ "content": content }).to_string()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    };
+
+    // 先手动创建 Link，之后正文才出现并删掉引用：关系不是同步创建的，保持不变
+    let source = common::create_note(&client, "no references", &[])["id"].as_i64().unwrap();
+    let response = client.post(format!("/inbox/notes/{}/relations/{}", source, a)).header(ContentType::JSON)
+        .body(json!({ "relation_type": "Link" }).to_string()).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    put(source, format!("see [[{}]]", a));
+    put(source, "no references again".to_string());
+    assert!(links_to(a, source));
+
+    // 恢复历史版本与撤销更新同样同步关系
+    put(source, format!("see [[{}]]", b));
+    assert!(links_to(b, source));
+    put(source, "dropped".to_string());
+    assert!(!links_to(b, source));
+    let (_, history) = common::get_json(&client, &format!("/inbox/notes/{}/history", source));
+    let version = history[0]["version_id"].as_i64().unwrap();
+    let response = client.post(format!("/inbox/notes/{}/restore/{}", source, version)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(links_to(b, source));
+    assert_eq!(client.post("/inbox/undo").dispatch().status(), Status::Ok);
+    assert!(!links_to(b, source));
+    assert!(links_to(a, source));
+}
+
+#[test]
+fn test_auto_link_respects_relation_cap() {
+    let client = common::client_with_config(InboxConfig { auto_link: true, max_relations_per_note: 1, ..Default::default() });
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &[])["id"].as_i64().unwrap();
+
+    // 达到上限后其余引用跳过，笔记照常保存
+    let source = common::create_note(&client, &format!("[[{}]] and [[{}]]", a, b), &[])["id"].as_i64().unwrap();
+    let (_, to_a) = common::get_json(&client, &format!("/inbox/notes/{}/relations", a));
+    let (_, to_b) = common::get_json(&client, &format!("/inbox/notes/{}/relations", b));
+    assert_eq!(to_a.as_array().unwrap().len() + to_b.as_array().unwrap().len(), 1);
+    let (status, _) = common::get_json(&client, &format!("/inbox/notes/{}", source));
+    assert_eq!(status, Status::Ok);
+}
+
+#[test]
+fn test_auto_link_covers_every_write_path() {
+    let client = common::client_with_config(InboxConfig { auto_link: true, ..Default::default() });
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    let link_sources = |target: i64| -> Vec<i64> {
+        let (_, body) = common::get_json(&client, &format!("/inbox/notes/{}/relations", target));
+        body.as_array().unwrap().iter().filter(|r| r["relation_type"] == "Link").map(|r| r["source_note_id"].as_i64().unwrap()).collect()
+    };
+    let post = |uri: &str, body: String| {
+        let response = client.post(uri).header(ContentType::JSON).body(body).dispatch();
+        assert_eq!(response.status(), Status::Created, "{}", uri);
+        response.into_json::<serde_json::Value>().unwrap()
+    };
+
+    let imported = post("/inbox/import", json!([{ "content": format!("imported [[{}]]", a) }]).to_string())["ids"][0].as_i64().unwrap();
+    assert_eq!(link_sources(a), vec![imported]);
+    post("/inbox/import/stream", json!({ "content": format!("streamed [[{}]]", a) }).to_string());
+    assert_eq!(link_sources(a).len(), 2);
+
+    let comment = post(&format!("/inbox/notes/{}/comments", imported), json!({ "content": format!("see [[{}]]", a) }).to_string());
+    assert!(link_sources(a).contains(&comment["id"].as_i64().unwrap()));
+    let linked = post(&format!("/inbox/notes/{}/link", imported), json!({ "content": format!("also [[{}]]", a), "relation_type": "Reference" }).to_string());
+    assert!(link_sources(a).contains(&linked["note"]["id"].as_i64().unwrap()));
+
+    // 合并后按新正文同步：被合并笔记引用的笔记当时还不存在，合并时才关联上
+    let primary = common::create_note(&client, "primary", &[])["id"].as_i64().unwrap();
+    let other = common::create_note(&client, &format!("later [[{}]]", primary + 2), &[])["id"].as_i64().unwrap();
+    let later = common::create_note(&client, "later", &[])["id"].as_i64().unwrap();
+    assert_eq!(later, primary + 2);
+    assert!(link_sources(later).is_empty());
+    let response = client.post("/inbox/notes/merge").header(ContentType::JSON)
+        .body(json!({ "primary": primary, "others": [other] }).to_string()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(link_sources(later), vec![primary]);
+}
+
+#[test]
+fn test_auto_link_disabled_by_default() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &[])["id"].as_i64().unwrap();
+    common::create_note(&client, &format!("see [[{}]]", a), &[]);
+    let (_, relations) = common::get_json(&client, &format!("/inbox/notes/{}/relations", a));
+    assert_eq!(relations, json!([]));
+}
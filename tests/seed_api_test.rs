@@ -0,0 +1,63 @@
+// 启动时写入示例笔记 (INBOX_SEED_FILE) 的进程内集成测试
+mod common;
+
+use std::path::PathBuf;
+
+use aw_inbox_rust::config::InboxConfig;
+use aw_inbox_rust::db;
+use rocket::local::blocking::Client;
+use serde_json::json;
+
+fn seed_file(name: &str, contents: &serde_json::Value) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("aw-inbox-seed-{}-{}.json", name, std::process::id()));
+    std::fs::write(&path, contents.to_string()).unwrap();
+    path
+}
+
+fn seeded_config(path: PathBuf) -> InboxConfig {
+    InboxConfig { seed_file: Some(path), ..Default::default() }
+}
+
+#[test]
+fn test_seeds_empty_database() {
+    let path = seed_file("empty", &json!([
+        { "content": "Welcome to the inbox", "tags": ["demo"] },
+        { "content": "Second sample", "created_at": "2024-01-01T00:00:00Z" },
+    ]));
+    let client = common::client_with_config(seeded_config(path.clone()));
+
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    let mut contents: Vec<&str> = notes.as_array().unwrap().iter().map(|n| n["content"].as_str().unwrap()).collect();
+    contents.sort();
+    assert_eq!(contents, vec!["Second sample", "Welcome to the inbox"]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_skips_seeding_when_notes_exist() {
+    let path = seed_file("existing", &json!([{ "content": "sample" }]));
+    let pool = db::build_pool(db::SqliteConnectionManager::memory(), 1).unwrap();
+    {
+        let mut conn = pool.get().unwrap();
+        db::migrate(&conn).unwrap();
+        db::create_note_db(&mut conn, serde_json::from_value(json!({ "content": "mine" })).unwrap(), db::NoteWriteOptions::default()).unwrap();
+    }
+
+    let client = Client::tracked(aw_inbox_rust::mount_rocket_with_config(rocket::build(), pool, seeded_config(path.clone()))).unwrap();
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+    assert_eq!(notes[0]["content"], "mine");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_invalid_seed_file_refuses_to_start() {
+    let path = seed_file("invalid", &json!([{ "content": "" }]));
+    let pool = db::build_pool(db::SqliteConnectionManager::memory(), 1).unwrap();
+    let rocket = aw_inbox_rust::mount_rocket_with_config(rocket::build(), pool, seeded_config(path.clone()));
+    match Client::tracked(rocket) {
+        Err(e) => assert!(matches!(e.kind(), rocket::error::ErrorKind::FailedFairings(_))),
+        Ok(_) => panic!("rocket should refuse to ignite with an invalid seed file"),
+    }
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,592 @@
+// 标签相关 API 的进程内集成测试
+mod common;
+
+use aw_inbox_rust::config::InboxConfig;
+use aw_inbox_rust::db;
+use rocket::local::blocking::Client;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::json;
+
+#[test]
+fn test_tag_notes_route_uses_exact_match() {
+    let client = common::client();
+    common::create_note(&client, "first", &["a_b"]);
+    common::create_note(&client, "second", &["axb"]);
+    common::create_note(&client, "third", &["a_b", "other"]);
+
+    let (status, body) = common::get_json(&client, "/inbox/tags/a_b/notes");
+    assert_eq!(status, Status::Ok);
+    let contents: Vec<&str> = body.as_array().unwrap().iter().map(|n| n["content"].as_str().unwrap()).collect();
+    assert_eq!(contents.len(), 2);
+    assert!(!contents.contains(&"second"));
+
+    // 与 /notes?tag= 结果一致
+    let (_, via_query) = common::get_json(&client, "/inbox/notes?tag=a_b");
+    assert_eq!(via_query, body);
+
+    let (_, page) = common::get_json(&client, "/inbox/tags/a_b/notes?limit=1&offset=1");
+    assert_eq!(page.as_array().unwrap().len(), 1);
+
+    let (status, _) = common::get_json(&client, "/inbox/tags/missing/notes");
+    assert_eq!(status, Status::NotFound);
+}
+
+#[test]
+fn test_tag_notes_formats() {
+    let client = common::client();
+    common::create_note(&client, "hello, \"csv\"", &["fmt"]);
+
+    let response = client.get("/inbox/tags/fmt/notes?format=csv").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let csv = response.into_string().unwrap();
+    assert!(csv.starts_with("id,content,tags,created_at,updated_at\r\n"));
+    assert!(csv.contains("\"hello, \"\"csv\"\"\",fmt,"));
+
+    let response = client.get("/inbox/tags/fmt/notes?format=md").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_string().unwrap().contains("#fmt"));
+
+    let response = client.get("/inbox/tags/fmt/notes?format=xml").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_tag_notes_route_is_paginated_like_notes() {
+    let client = common::client_with_config(InboxConfig { default_limit: 2, max_limit: 3, ..Default::default() });
+    for i in 0..4 {
+        common::create_note(&client, &format!("note {}", i), &["paged"]);
+    }
+    let count = |uri: &str| {
+        let (status, notes) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok, "{}", uri);
+        notes.as_array().unwrap().len()
+    };
+    assert_eq!(count("/inbox/tags/paged/notes"), 2);
+    assert_eq!(count("/inbox/tags/paged/notes?limit=100"), 3);
+    assert_eq!(count("/inbox/tags/paged/notes?limit=-1"), 0);
+    assert_eq!(count("/inbox/tags/paged/notes?limit=3&offset=3"), 1);
+}
+
+#[test]
+fn test_tag_count_limit() {
+    let client = common::client_with_config(InboxConfig { max_tags: 3, ..Default::default() });
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "too many", "tags": ["a", "b", "c", "d"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert!(body["error"].as_str().unwrap().contains("INBOX_MAX_TAGS"));
+
+    // 去重后未超限
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "duplicates", "tags": ["a", "a", "b", "c"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    let id = common::create_note(&client, "update me", &["a"])["id"].as_i64().unwrap();
+    let response = client
+        .put(format!("/inbox/notes/{}", id))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "update me", "tags": ["a", "b", "c", "d"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_tag_length_limit() {
+    let client = common::client();
+
+    let long_tag = "x".repeat(65);
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "long tag", "tags": [long_tag] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert!(body["error"].as_str().unwrap().contains("INBOX_MAX_TAG_LEN"));
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "ok tag", "tags": ["x".repeat(64)] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+}
+
+#[test]
+fn test_tag_limits_apply_to_template_notes() {
+    // 模板不校验标签数量，由模板创建的笔记同样受 INBOX_MAX_TAGS 限制
+    let client = common::client_with_config(InboxConfig { max_tags: 2, ..Default::default() });
+    let response = client
+        .post("/inbox/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "tagged", "content": "x", "tags": ["a", "b", "c"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let template_id = response.into_json::<serde_json::Value>().unwrap()["id"].as_i64().unwrap();
+
+    let response = client.post(format!("/inbox/notes/from-template/{}", template_id)).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert!(body["error"].as_str().unwrap().contains("INBOX_MAX_TAGS"));
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(notes, json!([]));
+}
+
+#[test]
+fn test_allowed_tags_enforced() {
+    let client = common::client_with_config(InboxConfig {
+        allowed_tags: Some(vec!["home".into(), "work".into()]),
+        ..Default::default()
+    });
+
+    let response = client
+        .post("/inbox/notes")
+        .header(ContentType::JSON)
+        .body(json!({ "content": "bad tag", "tags": ["work", "misc"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert!(body["error"].as_str().unwrap().contains("\"misc\""));
+
+    let id = common::create_note(&client, "allowed", &["work"])["id"].as_i64().unwrap();
+    let response = client
+        .put(format!("/inbox/notes/{}", id))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "allowed", "tags": ["home", "other"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.get("/inbox/tags/allowed").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body, json!({ "restricted": true, "tags": ["home", "work"] }));
+}
+
+#[test]
+fn test_allowed_tags_apply_to_templates() {
+    let client = common::client_with_config(InboxConfig {
+        allowed_tags: Some(vec!["work".into()]),
+        ..Default::default()
+    });
+
+    let response = client
+        .post("/inbox/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "bad", "content": "x", "tags": ["misc"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert!(body["error"].as_str().unwrap().contains("\"misc\""));
+
+    let response = client
+        .post("/inbox/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "ok", "content": "x", "tags": [" #work"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let template_id = response.into_json::<serde_json::Value>().unwrap()["id"].as_i64().unwrap();
+
+    let response = client
+        .put(format!("/inbox/templates/{}", template_id))
+        .header(ContentType::JSON)
+        .body(json!({ "name": "ok", "content": "x", "tags": ["work", "other"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.post(format!("/inbox/notes/from-template/{}", template_id)).dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+}
+
+#[test]
+fn test_allowed_tags_checked_when_instantiating_old_templates() {
+    // 设置允许列表之前创建的模板在实例化时被拒绝
+    let pool = db::build_pool(db::SqliteConnectionManager::memory(), 1).unwrap();
+    let template = {
+        let mut conn = pool.get().unwrap();
+        db::migrate(&conn).unwrap();
+        let payload = serde_json::from_value(json!({ "name": "old", "content": "x", "tags": ["misc"] })).unwrap();
+        db::create_template_db(&mut conn, payload).unwrap()
+    };
+    let config = InboxConfig { allowed_tags: Some(vec!["work".into()]), ..Default::default() };
+    let client = Client::tracked(aw_inbox_rust::mount_rocket_with_config(rocket::build(), pool, config)).unwrap();
+
+    let response = client.post(format!("/inbox/notes/from-template/{}", template.id)).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    assert_eq!(notes, json!([]));
+}
+
+#[test]
+fn test_allowed_tags_unrestricted() {
+    let client = common::client();
+
+    common::create_note(&client, "anything goes", &["misc", "whatever"]);
+
+    let response = client.get("/inbox/tags/allowed").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body, json!({ "restricted": false, "tags": [] }));
+}
+
+#[test]
+fn test_source_header_adds_tag() {
+    let create = |client: &rocket::local::blocking::Client| {
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Inbox-Source", " Shortcuts "))
+            .body(json!({ "content": "captured", "tags": ["idea"] }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        response.into_json::<serde_json::Value>().unwrap()["tags"].clone()
+    };
+
+    let client = common::client_with_config(InboxConfig { source_tagging: true, ..Default::default() });
+    assert_eq!(create(&client), json!(["idea", "source:shortcuts"]));
+    let (_, notes) = common::get_json(&client, "/inbox/notes?tag=source:shortcuts");
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+
+    // 未开启时忽略该请求头
+    assert_eq!(create(&common::client()), json!(["idea"]));
+}
+
+#[test]
+fn test_auto_tag_from_hashtags() {
+    let client = common::client_with_config(InboxConfig { auto_tag: true, ..Default::default() });
+
+    let note = common::create_note(&client, "call mom #family #urgent, see a.html#anchor", &["family"]);
+    assert_eq!(note["tags"], json!(["family", "urgent"]));
+    assert_eq!(note["content"], "call mom #family #urgent, see a.html#anchor");
+
+    // 默认关闭
+    let client = common::client();
+    let note = common::create_note(&client, "call mom #family", &[]);
+    assert_eq!(note["tags"], json!([]));
+}
+
+#[test]
+fn test_detailed_tags_scoped_to_filter() {
+    let client = common::client();
+    for (tags, created_at) in [
+        (json!(["work", "project-a"]), "2024-01-10T00:00:00Z"),
+        (json!(["work", "project-b"]), "2024-02-10T00:00:00Z"),
+        (json!(["home"]), "2024-02-20T00:00:00Z"),
+    ] {
+        let response = client
+            .post("/inbox/notes")
+            .header(ContentType::JSON)
+            .body(json!({ "content": "n", "tags": tags, "created_at": created_at }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+    }
+    let counts = |uri: &str| {
+        let (status, body) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        let mut counts: Vec<(String, i64)> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["name"].as_str().unwrap().to_string(), t["count"].as_i64().unwrap()))
+            .collect();
+        counts.sort();
+        counts
+    };
+    let pairs = |items: &[(&str, i64)]| items.iter().map(|(n, c)| (n.to_string(), *c)).collect::<Vec<_>>();
+
+    assert_eq!(
+        counts("/inbox/tags/detailed"),
+        pairs(&[("home", 1), ("project-a", 1), ("project-b", 1), ("work", 2)])
+    );
+    assert_eq!(
+        counts("/inbox/tags/detailed?created_after=2024-02-01T00:00:00Z"),
+        pairs(&[("home", 1), ("project-b", 1), ("work", 1)])
+    );
+    assert_eq!(
+        counts("/inbox/tags/detailed?created_after=2024-02-01T00:00:00Z&created_before=2024-02-15T00:00:00Z&prefix=project-"),
+        pairs(&[("project-b", 1)])
+    );
+    assert_eq!(common::get_json(&client, "/inbox/tags/detailed?created_after=yesterday").0, Status::BadRequest);
+}
+
+#[test]
+fn test_detailed_tags_paginated_and_sorted() {
+    let client = common::client();
+    common::create_note(&client, "one", &["beta", "alpha"]);
+    common::create_note(&client, "two", &["beta", "gamma"]);
+    common::create_note(&client, "three", &["beta", "gamma", "delta"]);
+    let names = |uri: &str| -> Vec<String> {
+        let (status, tags) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        tags.as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap().to_string()).collect()
+    };
+
+    // 汇总表与实时扫描的分页、排序一致
+    for live in ["false", "true"] {
+        assert_eq!(names(&format!("/inbox/tags/detailed?live={}&limit=2", live)), ["beta", "gamma"]);
+        assert_eq!(names(&format!("/inbox/tags/detailed?live={}&limit=2&offset=2", live)), ["alpha", "delta"]);
+        assert_eq!(names(&format!("/inbox/tags/detailed?live={}&sort=name&limit=3", live)), ["alpha", "beta", "delta"]);
+    }
+    assert_eq!(names("/inbox/tags/detailed").len(), 4);
+    assert_eq!(common::get_json(&client, "/inbox/tags/detailed?sort=size").0, Status::BadRequest);
+}
+
+#[test]
+fn test_related_tags() {
+    let client = common::client();
+    common::create_note(&client, "1", &["rust", "web", "async"]);
+    common::create_note(&client, "2", &["rust", "web"]);
+    common::create_note(&client, "3", &["rust", "cli"]);
+    common::create_note(&client, "4", &["web", "css"]);
+
+    let (status, body) = common::get_json(&client, "/inbox/tags/rust/related");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        body,
+        json!([
+            { "name": "web", "count": 2 },
+            { "name": "async", "count": 1 },
+            { "name": "cli", "count": 1 },
+        ])
+    );
+
+    let (_, body) = common::get_json(&client, "/inbox/tags/rust/related?limit=1");
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    let (_, body) = common::get_json(&client, "/inbox/tags/unknown/related");
+    assert_eq!(body, json!([]));
+}
+
+#[test]
+fn test_related_tags_limit_capped_by_max_limit() {
+    let client = common::client_with_config(InboxConfig { max_limit: 2, ..Default::default() });
+    common::create_note(&client, "1", &["rust", "web", "async", "cli"]);
+    let (status, body) = common::get_json(&client, "/inbox/tags/rust/related?limit=9223372036854775807");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_tag_feed() {
+    let client = common::client();
+    common::create_note(&client, "rust note", &["rust"]);
+    common::create_note(&client, "rustacean note", &["rustacean"]);
+    common::create_note(&client, "c++ note", &["c++"]);
+
+    let response = client.get("/inbox/tags/rust/feed.xml").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type().unwrap().to_string(), "application/atom+xml");
+    let body = response.into_string().unwrap();
+    assert!(body.contains("<id>urn:aw-inbox:feed:tag:rust</id>"));
+    assert!(body.contains("<title>aw-inbox #rust</title>"));
+    assert!(body.contains("rust note"));
+    // 精确匹配，不包含前缀相同的标签
+    assert!(!body.contains("rustacean note"));
+
+    // 特殊字符的标签名编码后作为稳定的 id
+    let body = client.get("/inbox/tags/c%2B%2B/feed.xml").dispatch().into_string().unwrap();
+    assert!(body.contains("<id>urn:aw-inbox:feed:tag:c%2B%2B</id>"), "{}", body);
+    assert!(body.contains("c++ note"));
+
+    assert_eq!(client.get("/inbox/tags/never-used/feed.xml").dispatch().status(), Status::NotFound);
+}
+
+#[test]
+fn test_tag_feed_limit_capped_by_max_limit() {
+    let client = common::client_with_config(InboxConfig { max_limit: 2, ..Default::default() });
+    for i in 0..3 {
+        common::create_note(&client, &format!("feed note {}", i), &["rust"]);
+    }
+    let body = client.get("/inbox/tags/rust/feed.xml?limit=100").dispatch().into_string().unwrap();
+    assert_eq!(body.matches("<entry>").count(), 2);
+}
+
+#[test]
+fn test_tag_usage_ordered_by_bytes() {
+    let client = common::client();
+    common::create_note(&client, &"x".repeat(1000), &["bulky"]);
+    common::create_note(&client, "small", &["tiny", "bulky"]);
+    common::create_note(&client, "汉字", &["tiny"]);
+
+    let (status, usage) = common::get_json(&client, "/inbox/tags/usage");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        usage,
+        json!([
+            { "name": "bulky", "count": 2, "bytes": 1005 },
+            // 按 UTF-8 字节计：汉字各 3 字节
+            { "name": "tiny", "count": 2, "bytes": 11 },
+        ])
+    );
+}
+
+#[test]
+fn test_overview_latest_notes_per_tag() {
+    let client = common::client();
+    common::create_note(&client, "a1", &["alpha"]);
+    for content in ["w1", "w2", "w3"] {
+        common::create_note(&client, content, &["work"]);
+    }
+    common::create_note(&client, "both", &["work", "home"]);
+    common::create_note(&client, "untagged", &[]);
+
+    let (status, overview) = common::get_json(&client, "/inbox/overview?per_tag=2");
+    assert_eq!(status, Status::Ok);
+    let columns: Vec<(String, Vec<String>)> = overview.as_array().unwrap().iter().map(|column| {
+        let notes = column["notes"].as_array().unwrap().iter().map(|note| note["content"].as_str().unwrap().to_string()).collect();
+        (column["tag"].as_str().unwrap().to_string(), notes)
+    }).collect();
+    // 按最新一条笔记的时间倒序（home 与 work 同为 both，按名称），而不是按标签名
+    assert_eq!(columns, vec![
+        ("home".to_string(), vec!["both".to_string()]),
+        ("work".to_string(), vec!["both".to_string(), "w3".to_string()]),
+        ("alpha".to_string(), vec!["a1".to_string()]),
+    ]);
+
+    // 默认每个标签 3 条
+    let (_, overview) = common::get_json(&client, "/inbox/overview");
+    assert_eq!(overview[1]["notes"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn test_tag_merge_preview_matches_merge() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &["todo", "work"])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &["to-do", "later", "todo"])["id"].as_i64().unwrap();
+    common::create_note(&client, "c", &["other"]);
+    let post = |uri: &str, body: serde_json::Value| {
+        let response = client.post(uri).header(ContentType::JSON).body(body.to_string()).dispatch();
+        (response.status(), response.into_json::<serde_json::Value>())
+    };
+    let merge = json!({ "sources": ["to-do", "later"], "target": "todo" });
+
+    // 预览不写入
+    let (status, preview) = post("/inbox/tags/merge/preview", merge.clone());
+    assert_eq!(status, Status::Ok);
+    assert_eq!(preview.clone().unwrap(), json!({ "count": 1, "note_ids": [b] }));
+    let (_, note) = common::get_json(&client, &format!("/inbox/notes/{}", b));
+    assert_eq!(note["tags"], json!(["to-do", "later", "todo"]));
+
+    let (_, merged) = post("/inbox/tags/merge", merge);
+    assert_eq!(merged, preview);
+    let (_, note) = common::get_json(&client, &format!("/inbox/notes/{}", b));
+    assert_eq!(note["tags"], json!(["todo"]));
+
+    let (_, preview) = post("/inbox/tags/rename/preview", json!({ "from": "todo", "to": "#task" }));
+    assert_eq!(preview.unwrap(), json!({ "count": 2, "note_ids": [a, b] }));
+    let (_, renamed) = post("/inbox/tags/rename", json!({ "from": "todo", "to": "#task" }));
+    assert_eq!(renamed.unwrap()["count"], 2);
+    let (_, note) = common::get_json(&client, &format!("/inbox/notes/{}", a));
+    assert_eq!(note["tags"], json!(["task", "work"]));
+
+    let (status, _) = post("/inbox/tags/rename/preview", json!({ "from": "work", "to": "work" }));
+    assert_eq!(status, Status::BadRequest);
+    let (status, _) = post("/inbox/tags/merge", json!({ "sources": [], "target": "x" }));
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[test]
+fn test_tag_counts_summary_matches_live_scan() {
+    let client = common::client();
+    let a = common::create_note(&client, "a", &["work", "urgent"])["id"].as_i64().unwrap();
+    let b = common::create_note(&client, "b", &["work"])["id"].as_i64().unwrap();
+    let c = common::create_note(&client, "c", &["home"])["id"].as_i64().unwrap();
+    common::create_note(&client, "d", &["work", "home"]);
+
+    let response = client
+        .put(format!("/inbox/notes/{}", a))
+        .header(ContentType::JSON)
+        .body(json!({ "content": "a", "tags": ["work", "later"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(client.delete(format!("/inbox/notes/{}", b)).dispatch().status(), Status::NoContent);
+    assert_eq!(client.post(format!("/inbox/notes/{}/trash", c)).dispatch().status(), Status::NoContent);
+
+    let counts = |uri: &str| -> Vec<(String, i64)> {
+        let (status, tags) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        let mut counts: Vec<(String, i64)> = tags
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["name"].as_str().unwrap().to_string(), t["count"].as_i64().unwrap()))
+            .collect();
+        counts.sort();
+        counts
+    };
+    let expected = vec![("home".to_string(), 1), ("later".to_string(), 1), ("work".to_string(), 2)];
+    assert_eq!(counts("/inbox/tags/detailed"), expected);
+    assert_eq!(counts("/inbox/tags/detailed?live=true"), expected);
+    assert_eq!(counts("/inbox/tags/detailed?prefix=wo"), vec![("work".to_string(), 2)]);
+
+    // 从回收站恢复后重新计入
+    client.post(format!("/inbox/trash/{}/restore", c)).dispatch();
+    assert_eq!(counts("/inbox/tags/detailed")[0], ("home".to_string(), 2));
+}
+
+#[test]
+fn test_tag_counts_ignore_duplicate_tags() {
+    // 旧数据或外部工具写入的重复标签：同一笔记只计一次，删除后不留下残余计数
+    let pool = db::build_pool(db::SqliteConnectionManager::memory(), 1).unwrap();
+    {
+        let conn = pool.get().unwrap();
+        db::migrate(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO notes (content, tags, created_at, updated_at) VALUES ('dup', '[\"x\",\"x\"]', '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+    }
+    let client = Client::tracked(aw_inbox_rust::mount_rocket_with_config(rocket::build(), pool, InboxConfig::default())).unwrap();
+
+    for uri in ["/inbox/tags/detailed", "/inbox/tags/detailed?live=true"] {
+        let (_, tags) = common::get_json(&client, uri);
+        assert_eq!(tags[0]["count"], 1, "{}", uri);
+    }
+    let (_, notes) = common::get_json(&client, "/inbox/notes");
+    let id = notes[0]["id"].as_i64().unwrap();
+    assert_eq!(client.delete(format!("/inbox/notes/{}", id)).dispatch().status(), Status::NoContent);
+    for uri in ["/inbox/tags/detailed", "/inbox/tags/detailed?live=true"] {
+        let (_, tags) = common::get_json(&client, uri);
+        assert_eq!(tags, json!([]), "{}", uri);
+    }
+}
+
+#[test]
+fn test_hierarchical_tags() {
+    let client = common::client();
+    let project = common::create_note(&client, "project x", &["work/projects/x"])["id"].as_i64().unwrap();
+    let work = common::create_note(&client, "plain work", &["work"])["id"].as_i64().unwrap();
+    let meeting = common::create_note(&client, "standup", &["work/meetings"])["id"].as_i64().unwrap();
+    common::create_note(&client, "not work", &["workshop"]);
+
+    let ids = |uri: &str| -> Vec<i64> {
+        let (status, body) = common::get_json(&client, uri);
+        assert_eq!(status, Status::Ok);
+        let mut ids: Vec<i64> = body.as_array().unwrap().iter().map(|n| n["id"].as_i64().unwrap()).collect();
+        ids.sort();
+        ids
+    };
+    // 不带 prefix 时仍是精确匹配
+    assert_eq!(ids("/inbox/notes?tag=work"), vec![work]);
+    assert_eq!(ids("/inbox/notes?tag=work&prefix=true"), vec![project, work, meeting]);
+    assert_eq!(ids("/inbox/notes?tag=work/projects&prefix=true"), vec![project]);
+
+    let (status, tree) = common::get_json(&client, "/inbox/tags/tree");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(tree, json!([
+        { "name": "work", "path": "work", "count": 1, "children": [
+            { "name": "meetings", "path": "work/meetings", "count": 1, "children": [] },
+            { "name": "projects", "path": "work/projects", "count": 0, "children": [
+                { "name": "x", "path": "work/projects/x", "count": 1, "children": [] },
+            ] },
+        ] },
+        { "name": "workshop", "path": "workshop", "count": 1, "children": [] },
+    ]));
+}
@@ -0,0 +1,78 @@
+// 笔记模板 API 的进程内集成测试
+mod common;
+
+use rocket::http::{ContentType, Status};
+use serde_json::json;
+
+#[test]
+fn test_template_crud_and_instantiation() {
+    let client = common::client();
+
+    let response = client
+        .post("/inbox/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "daily", "content": "Daily log {{date}}", "tags": ["journal"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let template: serde_json::Value = response.into_json().unwrap();
+    let template_id = template["id"].as_i64().unwrap();
+
+    // 重名模板返回 409
+    let response = client
+        .post("/inbox/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "name": "daily", "content": "dup" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client.post(format!("/inbox/notes/from-template/{}", template_id)).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let note: serde_json::Value = response.into_json().unwrap();
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    assert_eq!(note["content"], format!("Daily log {}", today));
+    assert_eq!(note["tags"], json!(["journal"]));
+
+    let response = client
+        .put(format!("/inbox/templates/{}", template_id))
+        .header(ContentType::JSON)
+        .body(json!({ "name": "daily", "content": "Updated" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let (status, templates) = common::get_json(&client, "/inbox/templates");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(templates[0]["content"], "Updated");
+
+    let response = client.delete(format!("/inbox/templates/{}", template_id)).dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    let response = client.post(format!("/inbox/notes/from-template/{}", template_id)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_template_notes_follow_create_rules() {
+    let client = common::client();
+    let create_template = |body: serde_json::Value| {
+        let response = client.post("/inbox/templates").header(ContentType::JSON).body(body.to_string()).dispatch();
+        assert_eq!(response.status(), Status::Created);
+        response.into_json::<serde_json::Value>().unwrap()
+    };
+
+    // 标签保存时即规范化去重
+    let template = create_template(json!({ "name": "tagged", "content": "note", "tags": [" #work", "work", ""] }));
+    assert_eq!(template["tags"], json!(["work"]));
+
+    // 实例化的笔记可以撤销
+    let response = client.post(format!("/inbox/notes/from-template/{}", template["id"])).dispatch();
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(client.post("/inbox/undo").dispatch().status(), Status::Ok);
+    let (_, count) = common::get_json(&client, "/inbox/notes/count");
+    assert_eq!(count["count"], 0);
+
+    // 渲染结果为空时按字段错误返回 400
+    let template = create_template(json!({ "name": "blank", "content": "   " }));
+    let response = client.post(format!("/inbox/notes/from-template/{}", template["id"])).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"], "content cannot be empty");
+}